@@ -15,11 +15,18 @@ const EXTERNAL_DEFINITIONS_FOLDER: &str = "ext-proto";
 const EXT_FRIENDSHIPS_PROTO_FILE: &str = "ext-proto/friendships.proto";
 const INTERNAL_DEFINITIONS_FOLDER: &str = "int-proto";
 const INT_NOTIFICATIONS_PROTO_FILE: &str = "int-proto/notifications.proto";
+/// The `User` message of the pinned protocol only carries the address, the profile fields used to
+/// enrich the friend lists are appended with new field numbers so clients built from the upstream
+/// definition keep decoding it.
+const USER_MESSAGE_DEFINITION: &str = "message User {\n  string address = 1;\n";
+const USER_PROFILE_FIELDS: &str =
+    "  optional string name = 2;\n  optional string face_image = 3;\n";
 
 fn main() -> Result<()> {
     if should_download_proto() {
         download_proto_from_github()?;
     }
+    extend_user_message()?;
 
     // Tell Cargo that if the given file changes, to rerun this build script.
     println!("cargo:rerun-if-changed=ext-proto/friendships.proto");
@@ -87,6 +94,23 @@ fn save_content_to_file(content: reqwest::blocking::Response) -> Result<()> {
     Ok(())
 }
 
+fn extend_user_message() -> Result<()> {
+    let file_path = env::current_dir()?.join(EXT_FRIENDSHIPS_PROTO_FILE);
+    let content = std::fs::read_to_string(&file_path)?;
+    if content.contains(USER_PROFILE_FIELDS) {
+        return Ok(());
+    }
+
+    if !content.contains(USER_MESSAGE_DEFINITION) {
+        panic!("Failed to find the `User` message in the friendship proto def, update `USER_MESSAGE_DEFINITION`");
+    }
+    let extended_definition = format!("{USER_MESSAGE_DEFINITION}{USER_PROFILE_FIELDS}");
+    std::fs::write(
+        file_path,
+        content.replacen(USER_MESSAGE_DEFINITION, &extended_definition, 1),
+    )
+}
+
 fn download_file(client: reqwest::blocking::Client, file_url: Url) -> reqwest::blocking::Response {
     match client
         .get(file_url)
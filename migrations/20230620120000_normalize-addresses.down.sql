@@ -0,0 +1,12 @@
+-- Lowercasing and merging the addresses can't be undone, only the schema changes are reverted.
+ALTER TABLE user_features DROP CONSTRAINT IF EXISTS lowercase_user;
+
+ALTER TABLE friendship_history DROP CONSTRAINT IF EXISTS lowercase_acting_user;
+
+ALTER TABLE friendships DROP CONSTRAINT IF EXISTS lowercase_addresses;
+
+DROP INDEX IF EXISTS friendships_address_1;
+DROP INDEX IF EXISTS friendships_address_2;
+
+CREATE INDEX IF NOT EXISTS friendships_address_1_lower ON friendships (LOWER(address_1) text_pattern_ops);
+CREATE INDEX IF NOT EXISTS friendships_address_2_lower ON friendships (LOWER(address_2) text_pattern_ops);
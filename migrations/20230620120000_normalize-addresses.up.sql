@@ -0,0 +1,60 @@
+-- Addresses used to be stored in whatever case the client sent them, so the same pair of users
+-- could end up with more than one friendship. Keep the friendship with the most recent history
+-- for each case-insensitive pair and move the history of the others into it.
+CREATE TEMPORARY TABLE normalized_friendships AS
+SELECT
+  f.id,
+  LEAST(LOWER(f.address_1), LOWER(f.address_2)) AS address_1,
+  GREATEST(LOWER(f.address_1), LOWER(f.address_2)) AS address_2,
+  FIRST_VALUE(f.id) OVER (
+    PARTITION BY
+      LEAST(LOWER(f.address_1), LOWER(f.address_2)),
+      GREATEST(LOWER(f.address_1), LOWER(f.address_2))
+    ORDER BY
+      (SELECT MAX(fh.timestamp) FROM friendship_history fh WHERE fh.friendship_id = f.id) DESC NULLS LAST,
+      f.id
+  ) AS canonical_id
+FROM friendships f;
+
+UPDATE friendship_history fh
+  SET friendship_id = nf.canonical_id
+FROM normalized_friendships nf
+WHERE fh.friendship_id = nf.id AND nf.id <> nf.canonical_id;
+
+DELETE FROM friendships f
+USING normalized_friendships nf
+WHERE f.id = nf.id AND nf.id <> nf.canonical_id;
+
+UPDATE friendships f
+  SET address_1 = nf.address_1, address_2 = nf.address_2
+FROM normalized_friendships nf
+WHERE f.id = nf.id AND (f.address_1 <> nf.address_1 OR f.address_2 <> nf.address_2);
+
+DROP TABLE normalized_friendships;
+
+UPDATE friendship_history SET acting_user = LOWER(acting_user) WHERE acting_user <> LOWER(acting_user);
+
+-- Merge the features of the same user stored with different casing.
+DELETE FROM user_features uf
+USING user_features other
+WHERE LOWER(uf."user") = LOWER(other."user")
+  AND uf.feature_name = other.feature_name
+  AND uf.ctid < other.ctid;
+
+UPDATE user_features SET "user" = LOWER("user") WHERE "user" <> LOWER("user");
+
+-- Addresses are now always lowercase, so plain indexes are enough.
+DROP INDEX IF EXISTS friendships_address_1_lower;
+DROP INDEX IF EXISTS friendships_address_2_lower;
+
+CREATE INDEX IF NOT EXISTS friendships_address_1 ON friendships (address_1);
+CREATE INDEX IF NOT EXISTS friendships_address_2 ON friendships (address_2);
+
+ALTER TABLE friendships
+  ADD CONSTRAINT lowercase_addresses CHECK (address_1 = LOWER(address_1) AND address_2 = LOWER(address_2));
+
+ALTER TABLE friendship_history
+  ADD CONSTRAINT lowercase_acting_user CHECK (acting_user = LOWER(acting_user));
+
+ALTER TABLE user_features
+  ADD CONSTRAINT lowercase_user CHECK ("user" = LOWER("user"));
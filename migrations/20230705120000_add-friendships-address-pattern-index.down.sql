@@ -0,0 +1,2 @@
+DROP INDEX IF EXISTS friendships_address_1_pattern;
+DROP INDEX IF EXISTS friendships_address_2_pattern;
@@ -0,0 +1,4 @@
+-- Addresses are stored in lowercase (see `lowercase_addresses` constraint), and searches only look
+-- for address prefixes (`LIKE 'prefix%'`), which a btree with `text_pattern_ops` serves.
+CREATE INDEX IF NOT EXISTS friendships_address_1_pattern ON friendships (address_1 text_pattern_ops);
+CREATE INDEX IF NOT EXISTS friendships_address_2_pattern ON friendships (address_2 text_pattern_ops);
@@ -0,0 +1,34 @@
+ALTER TABLE friendships
+  ADD COLUMN IF NOT EXISTS created_at TIMESTAMP,
+  ADD COLUMN IF NOT EXISTS updated_at TIMESTAMP,
+  ADD COLUMN IF NOT EXISTS friends_since TIMESTAMP;
+
+-- Friendships created before these columns existed take their dates from the history:
+-- the first event as creation date, since that's when the friendship was requested, the latest
+-- event as the last status change, and the last ACCEPT as when they became friends.
+WITH history AS (
+  SELECT
+    friendship_id,
+    MIN(timestamp) AS first_event,
+    MAX(timestamp) AS last_event,
+    MAX(timestamp) FILTER (WHERE event = 'accept' OR event = '"accept"') AS last_accept
+  FROM friendship_history
+  GROUP BY friendship_id
+)
+UPDATE friendships f
+  SET
+    created_at = h.first_event,
+    updated_at = h.last_event,
+    friends_since = CASE WHEN f.is_active THEN COALESCE(h.last_accept, h.last_event) END
+FROM history h
+WHERE h.friendship_id = f.id;
+
+UPDATE friendships SET created_at = CURRENT_TIMESTAMP WHERE created_at IS NULL;
+UPDATE friendships SET updated_at = created_at WHERE updated_at IS NULL OR updated_at < created_at;
+UPDATE friendships SET friends_since = updated_at WHERE is_active AND friends_since IS NULL;
+
+ALTER TABLE friendships
+  ALTER COLUMN created_at SET DEFAULT CURRENT_TIMESTAMP,
+  ALTER COLUMN created_at SET NOT NULL,
+  ALTER COLUMN updated_at SET DEFAULT CURRENT_TIMESTAMP,
+  ALTER COLUMN updated_at SET NOT NULL;
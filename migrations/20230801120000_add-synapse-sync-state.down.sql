@@ -0,0 +1 @@
+DROP TABLE IF EXISTS synapse_sync_state;
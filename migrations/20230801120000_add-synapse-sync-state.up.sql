@@ -0,0 +1,5 @@
+CREATE TABLE IF NOT EXISTS synapse_sync_state (
+  name VARCHAR PRIMARY KEY,
+  next_batch VARCHAR NOT NULL,
+  updated_at TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
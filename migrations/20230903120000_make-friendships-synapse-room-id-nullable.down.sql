@@ -0,0 +1,3 @@
+UPDATE friendships SET synapse_room_id = '' WHERE synapse_room_id IS NULL;
+
+ALTER TABLE friendships ALTER COLUMN synapse_room_id SET NOT NULL;
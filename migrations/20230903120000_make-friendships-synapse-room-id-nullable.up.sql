@@ -0,0 +1,4 @@
+-- A friendship whose Synapse room was cleaned up has no room, it was marked with an empty id.
+ALTER TABLE friendships ALTER COLUMN synapse_room_id DROP NOT NULL;
+
+UPDATE friendships SET synapse_room_id = NULL WHERE synapse_room_id = '';
@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS webhook_deliveries_event_id_url_idx;
+
+ALTER TABLE webhook_deliveries DROP COLUMN IF EXISTS event_id;
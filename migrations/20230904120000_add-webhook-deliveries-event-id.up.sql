@@ -0,0 +1,4 @@
+ALTER TABLE webhook_deliveries ADD COLUMN IF NOT EXISTS event_id VARCHAR;
+
+-- Each event is delivered once to each URL, even by several instances
+CREATE UNIQUE INDEX IF NOT EXISTS webhook_deliveries_event_id_url_idx ON webhook_deliveries (event_id, url);
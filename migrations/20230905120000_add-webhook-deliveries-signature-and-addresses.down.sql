@@ -0,0 +1,6 @@
+DROP INDEX IF EXISTS webhook_deliveries_to_address_idx;
+DROP INDEX IF EXISTS webhook_deliveries_from_address_idx;
+
+ALTER TABLE webhook_deliveries DROP COLUMN IF EXISTS to_address;
+ALTER TABLE webhook_deliveries DROP COLUMN IF EXISTS from_address;
+ALTER TABLE webhook_deliveries DROP COLUMN IF EXISTS signature;
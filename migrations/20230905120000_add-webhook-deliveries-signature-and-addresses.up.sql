@@ -0,0 +1,12 @@
+ALTER TABLE webhook_deliveries ADD COLUMN IF NOT EXISTS signature VARCHAR;
+ALTER TABLE webhook_deliveries ADD COLUMN IF NOT EXISTS from_address VARCHAR;
+ALTER TABLE webhook_deliveries ADD COLUMN IF NOT EXISTS to_address VARCHAR;
+
+-- The users of the deliveries saved until now are only in their payload
+UPDATE webhook_deliveries
+SET from_address = payload::jsonb ->> 'from', to_address = payload::jsonb ->> 'to'
+WHERE from_address IS NULL;
+
+-- The deliveries of a user are deleted along with their data
+CREATE INDEX IF NOT EXISTS webhook_deliveries_from_address_idx ON webhook_deliveries (from_address);
+CREATE INDEX IF NOT EXISTS webhook_deliveries_to_address_idx ON webhook_deliveries (to_address);
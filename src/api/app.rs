@@ -1,38 +1,63 @@
 use actix_web::body::MessageBody;
 use actix_web::dev::{Server, ServiceFactory};
 use actix_web::middleware;
-use actix_web::{web::Data, App, HttpServer};
+use actix_web::{
+    web::{Data, ServiceConfig},
+    App, HttpServer,
+};
 use dcl_http_prom_metrics::HttpMetricsCollector;
 use tracing_actix_web::TracingLogger;
 
 use crate::components::app::AppComponents;
 use crate::components::configuration::Config;
 use crate::components::tracing::init_telemetry;
+use crate::ws::app::SocialContext;
 
-use super::middlewares::check_auth::CheckAuthToken;
-use super::middlewares::metrics_token::CheckMetricsToken;
+use super::middlewares::bearer_token::{CheckBearerToken, ADMIN_ROUTES_PREFIX, METRICS_ROUTE};
+use super::middlewares::check_auth::{AuthScope, CheckAuthToken};
+use super::routes::admin::friendships::force_delete_friendship;
+use super::routes::admin::users::{
+    get_user_friendships, get_user_pending_requests, purge_user, revoke_user_tokens,
+};
+use super::routes::admin::webhooks::{get_webhook_deliveries, replay_webhook_delivery};
 use super::routes::health::handlers::health;
 use super::routes::health::handlers::live;
-use super::routes::synapse::handlers::{login, version};
+use super::routes::internal::friendships::{
+    check_friendships, get_friends_for_service, get_mutual_friends_count,
+};
+use super::routes::synapse::handlers::{login, logout, version};
 use super::routes::synapse::room_events::room_event_handler;
+use super::routes::v1::friendships::bulk::bulk_update_friendships;
 use super::routes::v1::friendships::get::get_user_friends;
 use super::routes::v1::friendships::mutuals::get_mutual_friends;
+use super::routes::v1::sessions::refresh::refresh_session;
+use super::routes::v1::users::data::{erase_user_data, export_user_data};
+use super::routes::v1::users::notifications::{
+    get_notification_settings, update_notification_settings,
+};
 
 #[derive(Clone)]
 pub struct AppOptions {
     pub auth_routes: Option<Vec<String>>,
 }
 
-pub fn run_service(data: Data<AppComponents>) -> Result<Server, std::io::Error> {
+/// Runs the HTTP server, the routes updating friendships share the `social_context` of the RPC
+/// server.
+pub fn run_service(
+    data: Data<AppComponents>,
+    social_context: Data<SocialContext>,
+) -> Result<Server, std::io::Error> {
     init_telemetry();
     let server_port = data.config.server.port;
 
     let http_metrics_collector =
         Data::new(dcl_http_prom_metrics::HttpMetricsCollectorBuilder::default().build());
 
-    let server = HttpServer::new(move || get_app_router(&data, &http_metrics_collector))
-        .bind(("0.0.0.0", server_port))?
-        .run();
+    let server = HttpServer::new(move || {
+        get_app_router(&data, &http_metrics_collector).app_data(social_context.clone())
+    })
+    .bind(("0.0.0.0", server_port))?
+    .run();
 
     Ok(server)
 }
@@ -42,11 +67,44 @@ pub async fn get_app_data(custom_config: Option<Config>) -> Data<AppComponents>
     Data::new(app_data)
 }
 
-const ROUTES_NEED_AUTH_TOKEN: [&str; 3] = [
-    "/v1/friendships/{userId}",
-    "/v1/friendships/{userId}/mutuals",
-    "/_matrix/client/r0/rooms/{room_id}/state/org.decentraland.friendship",
-]; // should fill this array to protect routes
+/// Who can call each route, every route needs a policy. Routes under `/v1/` without one are only
+/// available to logged in users.
+pub const ROUTE_POLICIES: [(&str, AuthScope); 22] = [
+    ("/health/live", AuthScope::Public),
+    ("/health/ready", AuthScope::Public),
+    ("/_matrix/client/versions", AuthScope::Public),
+    ("/_matrix/client/r0/login", AuthScope::Public),
+    ("/_matrix/client/r0/logout", AuthScope::User),
+    (
+        "/_matrix/client/r0/rooms/{room_id}/state/org.decentraland.friendship",
+        AuthScope::User,
+    ),
+    ("/v1/sessions/refresh", AuthScope::Public),
+    ("/v1/friendships/bulk", AuthScope::User),
+    ("/v1/friendships/{userId}", AuthScope::User),
+    ("/v1/friendships/{userId}/mutuals", AuthScope::User),
+    ("/v1/me/data", AuthScope::User),
+    ("/v1/me/notifications", AuthScope::User),
+    ("/admin/v1/users/{address}/friendships", AuthScope::Admin),
+    ("/admin/v1/users/{address}/requests", AuthScope::Admin),
+    ("/admin/v1/users/{address}", AuthScope::Admin),
+    ("/admin/v1/users/{address}/tokens", AuthScope::Admin),
+    (
+        "/admin/v1/friendships/{address_1}/{address_2}",
+        AuthScope::Admin,
+    ),
+    ("/admin/v1/webhooks/deliveries", AuthScope::Admin),
+    (
+        "/admin/v1/webhooks/deliveries/{id}/replay",
+        AuthScope::Admin,
+    ),
+    ("/internal/v1/friendships/check", AuthScope::Service),
+    ("/internal/v1/users/{address}/friends", AuthScope::Service),
+    (
+        "/internal/v1/users/{address}/mutuals/{other}/count",
+        AuthScope::Service,
+    ),
+];
 
 pub fn get_app_router(
     data: &Data<AppComponents>,
@@ -60,26 +118,61 @@ pub fn get_app_router(
         InitError = (),
     >,
 > {
-    let protected_routes = ROUTES_NEED_AUTH_TOKEN
-        .iter()
-        .map(|s| String::from(*s))
-        .collect::<Vec<String>>();
-
     App::new()
         .app_data(data.clone())
         .app_data(http_metrics_collector.clone())
-        .wrap(CheckAuthToken::new(protected_routes))
+        .wrap(CheckAuthToken::new(ROUTE_POLICIES))
         .wrap(dcl_http_prom_metrics::metrics())
-        .wrap(CheckMetricsToken::new(
+        .wrap(CheckBearerToken::new(
             data.config.wkc_metrics_bearer_token.clone(),
+            METRICS_ROUTE,
+        ))
+        .wrap(CheckBearerToken::new(
+            data.config.admin_bearer_token.clone(),
+            ADMIN_ROUTES_PREFIX,
         ))
         .wrap(middleware::NormalizePath::trim())
         .wrap(TracingLogger::default())
-        .service(live)
-        .service(health)
-        .service(version)
-        .service(get_user_friends)
-        .service(get_mutual_friends)
-        .service(login)
-        .service(room_event_handler)
+        .configure(configure_routes)
+}
+
+/// Registers the services and lists their names, which actix gives the resources after their
+/// handlers, so the tests can resolve the pattern of every registered route.
+macro_rules! routes {
+    ($($service:ident),* $(,)?) => {
+        /// Names of the registered routes, the ones of their handlers.
+        pub const ROUTE_NAMES: &[&str] = &[$(stringify!($service)),*];
+
+        /// Registers every route, each of them needs a policy in `ROUTE_POLICIES`.
+        pub fn configure_routes(config: &mut ServiceConfig) {
+            $(config.service($service);)*
+        }
+    };
 }
+
+routes!(
+    live,
+    health,
+    version,
+    bulk_update_friendships,
+    get_user_friends,
+    get_mutual_friends,
+    export_user_data,
+    erase_user_data,
+    get_notification_settings,
+    update_notification_settings,
+    login,
+    logout,
+    refresh_session,
+    room_event_handler,
+    get_user_friendships,
+    get_user_pending_requests,
+    force_delete_friendship,
+    purge_user,
+    revoke_user_tokens,
+    get_webhook_deliveries,
+    replay_webhook_delivery,
+    check_friendships,
+    get_friends_for_service,
+    get_mutual_friends_count,
+);
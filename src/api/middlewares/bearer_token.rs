@@ -6,20 +6,29 @@ use actix_web::{
     Error, HttpResponse,
 };
 use futures_util::future::LocalBoxFuture;
+use subtle::ConstantTimeEq;
 
-pub struct CheckMetricsToken {
+/// Every route under this prefix requires the admin bearer token.
+pub const ADMIN_ROUTES_PREFIX: &str = "/admin/";
+/// The metrics route requires the metrics bearer token.
+pub const METRICS_ROUTE: &str = "/metrics";
+
+/// Requires the given bearer token on every route whose path starts with `path_prefix`.
+pub struct CheckBearerToken {
     bearer_token: String,
+    path_prefix: &'static str,
 }
 
-impl CheckMetricsToken {
-    pub fn new(token: String) -> Self {
-        CheckMetricsToken {
+impl CheckBearerToken {
+    pub fn new(token: String, path_prefix: &'static str) -> Self {
+        CheckBearerToken {
             bearer_token: token,
+            path_prefix,
         }
     }
 }
 
-impl<S, B> Transform<S, ServiceRequest> for CheckMetricsToken
+impl<S, B> Transform<S, ServiceRequest> for CheckBearerToken
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
@@ -28,21 +37,23 @@ where
     type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type InitError = ();
-    type Transform = CheckMetricsTokenMiddleware<S>;
+    type Transform = CheckBearerTokenMiddleware<S>;
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(CheckMetricsTokenMiddleware {
+        ready(Ok(CheckBearerTokenMiddleware {
             service,
             bearer_token: self.bearer_token.clone(),
+            path_prefix: self.path_prefix,
         }))
     }
 }
-pub struct CheckMetricsTokenMiddleware<S> {
+pub struct CheckBearerTokenMiddleware<S> {
     service: S,
     bearer_token: String,
+    path_prefix: &'static str,
 }
-impl<S, B> Service<ServiceRequest> for CheckMetricsTokenMiddleware<S>
+impl<S, B> Service<ServiceRequest> for CheckBearerTokenMiddleware<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
     S::Future: 'static,
@@ -55,9 +66,12 @@ where
     dev::forward_ready!(service);
 
     fn call(&self, request: ServiceRequest) -> Self::Future {
-        if request.path() == "/metrics" {
+        if request.path().starts_with(self.path_prefix) {
             if self.bearer_token.is_empty() {
-                log::error!("missing wkc_metrics_bearer_token in configuration component");
+                log::error!(
+                    "missing bearer token of the {} routes in configuration component",
+                    self.path_prefix
+                );
                 let (request, _pl) = request.into_parts();
 
                 let response = HttpResponse::InternalServerError()
@@ -80,7 +94,7 @@ where
                 _ => "",
             };
 
-            if token.is_empty() || token != self.bearer_token {
+            if token.is_empty() || !is_expected_token(token, &self.bearer_token) {
                 let (request, _pl) = request.into_parts();
 
                 let response = HttpResponse::Unauthorized().finish().map_into_right_body();
@@ -94,3 +108,20 @@ where
         Box::pin(async move { res.await.map(ServiceResponse::map_into_left_body) })
     }
 }
+
+/// Compares the tokens in constant time, so the time taken doesn't tell how much of it matched.
+fn is_expected_token(token: &str, expected: &str) -> bool {
+    token.as_bytes().ct_eq(expected.as_bytes()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_expected_token;
+
+    #[test]
+    fn test_only_the_expected_token_is_accepted() {
+        assert!(is_expected_token("a-token", "a-token"));
+        assert!(!is_expected_token("a-token-", "a-token"));
+        assert!(!is_expected_token("b-token", "a-token"));
+    }
+}
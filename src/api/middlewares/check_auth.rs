@@ -1,4 +1,5 @@
 use std::{
+    collections::HashMap,
     future::{ready, Ready},
     rc::Rc,
     sync::Arc,
@@ -6,24 +7,51 @@ use std::{
 
 use actix_web::{
     body::EitherBody,
-    dev::{self, Service, ServiceRequest, ServiceResponse, Transform},
+    dev::{self, Payload, Service, ServiceRequest, ServiceResponse, Transform},
+    http::header::HeaderMap,
     web::Data,
-    Error, HttpMessage, HttpResponse,
+    Error, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError,
 };
 use futures_util::future::LocalBoxFuture;
 
 use crate::{
-    components::{app::AppComponents, users_cache::get_user_id_from_token},
+    components::{
+        app::AppComponents, session_tokens::get_user_id_from_any_token, users_cache::UserId,
+    },
     domain::error::CommonError,
 };
 
+/// Who can call a route.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthScope {
+    /// Anyone, without a token
+    Public,
+    /// A logged in user, with a Synapse or a session token
+    User,
+    /// The admin, its token is checked by `CheckBearerToken` on every route under `ADMIN_ROUTES_PREFIX`
+    Admin,
+    /// Other backends, with one of the service keys and within their rate limit
+    Service,
+}
+
+/// Routes under this prefix without a policy are only available to logged in users, so forgetting
+/// to declare the policy of a new route doesn't make it public.
+pub const DEFAULT_DENY_PREFIX: &str = "/v1/";
+
+/// Authenticates the requests according to the scope of the matched route, the `UserId` and
+/// `Token` of the logged in user are then available to the handlers as extractors.
 pub struct CheckAuthToken {
-    auth_routes: Vec<String>,
+    route_policies: HashMap<String, AuthScope>,
 }
 
 impl CheckAuthToken {
-    pub fn new(auth_routes: Vec<String>) -> Self {
-        CheckAuthToken { auth_routes }
+    pub fn new<R: Into<String>>(route_policies: impl IntoIterator<Item = (R, AuthScope)>) -> Self {
+        CheckAuthToken {
+            route_policies: route_policies
+                .into_iter()
+                .map(|(route, scope)| (route.into(), scope))
+                .collect(),
+        }
     }
 }
 
@@ -42,24 +70,69 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(CheckAuthTokenMiddleware {
             service: Rc::new(service),
-            auth_routes: self.auth_routes.clone(),
+            route_policies: self.route_policies.clone(),
         }))
     }
 }
 pub struct CheckAuthTokenMiddleware<S> {
     service: Rc<S>,
-    auth_routes: Vec<String>,
+    route_policies: HashMap<String, AuthScope>,
 }
 
 const AUTH_TOKEN_HEADER: &str = "authorization";
 
-fn is_auth_route(routes: &[String], path: &str) -> bool {
-    routes.iter().any(|x| *x == path)
+fn route_scope(route_policies: &HashMap<String, AuthScope>, route: &str) -> AuthScope {
+    match route_policies.get(route) {
+        Some(scope) => *scope,
+        None if route.starts_with(DEFAULT_DENY_PREFIX) => {
+            log::warn!("Route {route} has no auth policy, only logged in users can call it");
+            AuthScope::User
+        }
+        None => AuthScope::Public,
+    }
+}
+
+/// Token of the `Authorization` header, whatever its scheme is.
+fn get_bearer_token(headers: &HeaderMap) -> Option<String> {
+    let header = headers.get(AUTH_TOKEN_HEADER)?.to_str().ok()?;
+    let (_, token) = header.split_once(' ')?;
+    let token = token.trim();
+
+    (!token.is_empty()).then(|| token.to_string())
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token(pub String);
 
+impl FromRequest for Token {
+    type Error = CommonError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<Token>()
+                .cloned()
+                .ok_or_else(|| CommonError::Unauthorized("".to_owned())),
+        )
+    }
+}
+
+/// The logged in user, only available on the routes with the `User` scope.
+impl FromRequest for UserId {
+    type Error = CommonError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<UserId>()
+                .cloned()
+                .ok_or_else(|| CommonError::Unauthorized("".to_owned())),
+        )
+    }
+}
+
 impl<S: 'static, B> Service<ServiceRequest> for CheckAuthTokenMiddleware<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
@@ -73,40 +146,32 @@ where
     dev::forward_ready!(service);
 
     fn call(&self, request: ServiceRequest) -> Self::Future {
-        let matched_route = request.match_pattern();
         let is_metrics_call = request.path().eq_ignore_ascii_case("/metrics");
-        if matched_route.is_none() && !is_metrics_call {
-            let (request, _pl) = request.into_parts();
-            let response = HttpResponse::from_error(CommonError::NotFound("".to_owned()))
-                .map_into_right_body();
-            return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
-        }
-
-        if is_metrics_call
-            || !is_auth_route(&self.auth_routes, request.match_pattern().unwrap().as_str())
-        {
-            let res = self.service.call(request);
-            return Box::pin(async { res.await.map(ServiceResponse::map_into_left_body) });
-        }
-
-        let token = if let Some(header) = request.headers().get(AUTH_TOKEN_HEADER) {
-            match header.to_str() {
-                Ok(header) => {
-                    let split_header_bearer = header.split(' ').collect::<Vec<&str>>();
-                    let token = split_header_bearer.get(1);
-                    if let Some(token) = token {
-                        token.to_owned()
-                    } else {
-                        ""
-                    }
-                }
-                Err(_) => "",
+        let route = request.match_pattern();
+        let scope = match &route {
+            Some(route) => route_scope(&self.route_policies, route),
+            None if is_metrics_call => AuthScope::Public,
+            None => {
+                let (request, _pl) = request.into_parts();
+                let response = HttpResponse::from_error(CommonError::NotFound("".to_owned()))
+                    .map_into_right_body();
+                return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
             }
-        } else {
-            ""
         };
 
-        if token.is_empty() {
+        match scope {
+            AuthScope::Public | AuthScope::Admin => {
+                let res = self.service.call(request);
+                return Box::pin(async { res.await.map(ServiceResponse::map_into_left_body) });
+            }
+            AuthScope::Service => {
+                let route = route.unwrap_or_default();
+                return Box::pin(call_as_service(self.service.clone(), request, route));
+            }
+            AuthScope::User => {}
+        }
+
+        let Some(token) = get_bearer_token(request.headers()) else {
             let (request, _pl) = request.into_parts();
 
             let response = HttpResponse::from_error(CommonError::BadRequest(
@@ -115,17 +180,16 @@ where
             .map_into_right_body();
 
             return Box::pin(async { Ok(ServiceResponse::new(request, response)) });
-        }
-
-        let token = token.to_string();
+        };
 
         let svc = self.service.clone();
         Box::pin(async move {
             match request.app_data::<Data<AppComponents>>() {
                 Some(components) => {
-                    let user_id = get_user_id_from_token(
+                    let user_id = get_user_id_from_any_token(
                         components.synapse.clone(),
                         Arc::clone(&components.users_cache),
+                        components.session_tokens.as_ref(),
                         &token,
                     )
                     .await;
@@ -156,3 +220,40 @@ where
         })
     }
 }
+
+/// Authenticates the request with the key of a service and counts it within its rate limit.
+async fn call_as_service<S, B>(
+    service: Rc<S>,
+    request: ServiceRequest,
+    route: String,
+) -> Result<ServiceResponse<EitherBody<B>>, Error>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+{
+    let components = request.app_data::<Data<AppComponents>>().cloned();
+    let service_keys = components
+        .as_ref()
+        .and_then(|components| components.service_keys.as_ref());
+    let caller = get_bearer_token(request.headers())
+        .zip(service_keys)
+        .and_then(|(key, service_keys)| Some((service_keys.authenticate(&key)?, service_keys)));
+
+    let Some((caller, service_keys)) = caller else {
+        let (request, _pl) = request.into_parts();
+        let response = HttpResponse::from_error(CommonError::Unauthorized("".to_owned()))
+            .map_into_right_body();
+        return Ok(ServiceResponse::new(request, response));
+    };
+
+    if let Err(err) = service_keys.check_rate_limit(&caller).await {
+        service_keys.record_request(&caller, &route, err.status_code().as_u16());
+        let (request, _pl) = request.into_parts();
+        let response = HttpResponse::from_error(err).map_into_right_body();
+        return Ok(ServiceResponse::new(request, response));
+    }
+
+    let response = service.call(request).await?;
+    service_keys.record_request(&caller, &route, response.status().as_u16());
+
+    Ok(response.map_into_left_body())
+}
@@ -1,2 +1,2 @@
+pub mod bearer_token;
 pub mod check_auth;
-pub mod metrics_token;
@@ -0,0 +1,86 @@
+pub use crate::domain::friendship_event::ADMIN_ACTING_USER;
+use crate::{
+    domain::address::Address,
+    friendships::{
+        friendship_event_response, CancelResponse, DeleteResponse, FriendshipEventResponse,
+        RejectResponse, User,
+    },
+    generate_uuid_v4, instance_id,
+    notifications::Event,
+};
+
+pub fn admin_acting_user() -> Address {
+    Address::parse(ADMIN_ACTING_USER).expect("the admin acting user to be a valid address")
+}
+
+/// Builds the event that lets `to` know their relationship with `from` was removed.
+///
+/// * `from` - The user the removal is attributed to.
+/// * `to` - The other user of the relationship.
+/// * `requester` - The user who sent the pending request, `None` if the users were friends.
+///
+/// Friends receive a DELETE. For a pending request, the receiver gets a CANCEL from the requester
+/// and the requester gets a REJECT from the receiver. A single event is built per relationship,
+/// set `notify_from` for `from` to receive the mirrored one as well.
+pub fn removed_relationship_event(from: &str, to: &str, requester: Option<&str>) -> Event {
+    let body = match requester {
+        None => friendship_event_response::Body::Delete(DeleteResponse { user: user(from) }),
+        Some(requester) if requester == from => {
+            friendship_event_response::Body::Cancel(CancelResponse { user: user(from) })
+        }
+        Some(_) => friendship_event_response::Body::Reject(RejectResponse { user: user(from) }),
+    };
+
+    Event {
+        friendship_event: Some(FriendshipEventResponse { body: Some(body) }),
+        from: from.to_string(),
+        to: to.to_string(),
+        id: generate_uuid_v4(),
+        origin: instance_id().to_string(),
+        notify_from: false,
+    }
+}
+
+fn user(address: &str) -> Option<User> {
+    Some(User {
+        address: address.to_string(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::friendships::friendship_event_response::Body;
+
+    use super::removed_relationship_event;
+
+    const PIZARNIK: &str = "0x0000000000000000000000000000000000000001";
+    const MARTHA: &str = "0x0000000000000000000000000000000000000002";
+
+    #[test]
+    fn test_friends_receive_a_delete() {
+        let event = removed_relationship_event(PIZARNIK, MARTHA, None);
+
+        match event.friendship_event.unwrap().body.unwrap() {
+            Body::Delete(delete) => assert_eq!(delete.user.unwrap().address, PIZARNIK),
+            _ => panic!("Expected Delete body"),
+        }
+        assert_eq!(event.to, MARTHA);
+        assert!(!event.notify_from);
+    }
+
+    #[test]
+    fn test_pending_request_is_cancelled_or_rejected() {
+        let to_receiver = removed_relationship_event(MARTHA, PIZARNIK, Some(MARTHA));
+        let to_requester = removed_relationship_event(PIZARNIK, MARTHA, Some(MARTHA));
+
+        assert!(matches!(
+            to_receiver.friendship_event.unwrap().body,
+            Some(Body::Cancel(_))
+        ));
+        assert!(matches!(
+            to_requester.friendship_event.unwrap().body,
+            Some(Body::Reject(_))
+        ));
+    }
+}
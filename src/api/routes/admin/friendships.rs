@@ -0,0 +1,185 @@
+use actix_web::{
+    delete,
+    web::{self, Data},
+    HttpResponse,
+};
+
+use crate::{
+    api::routes::v1::friendships::errors::FriendshipsError,
+    components::database::{DBRepositories, DatabaseTransaction},
+    components::{app::AppComponents, notifications::ChannelPublisher},
+    db::{friendships_handler::update_friendship_status, types::FriendshipDbRepositories},
+    domain::{
+        address::Address, error::CommonError, friendship_event::FriendshipEvent,
+        friendship_status::FriendshipStatus, room::RoomInfo,
+    },
+    entities::friendships::Friendship,
+    notifications::Event,
+    synapse::synapse_handler::spawn_room_cleanup,
+};
+
+use super::events::{admin_acting_user, removed_relationship_event};
+
+/// Removes the friendship, or the pending request, between two users.
+///
+/// The removal is recorded in the history as a DELETE (or a CANCEL for pending requests)
+/// made by the admin acting user, and both users are notified.
+#[delete("/admin/v1/friendships/{address_1}/{address_2}")]
+pub async fn force_delete_friendship(
+    addresses: web::Path<(String, String)>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let (address_1, address_2) = addresses.into_inner();
+    let address_1 =
+        Address::parse(&address_1).map_err(|err| FriendshipsError::CommonError(err.into()))?;
+    let address_2 =
+        Address::parse(&address_2).map_err(|err| FriendshipsError::CommonError(err.into()))?;
+
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let transaction = app_data.db.start_transaction().await.map_err(|err| {
+        log::error!("[Admin] Couldn't start transaction to remove friendship {err}");
+        FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+    })?;
+
+    let (result, transaction) =
+        get_removed_relationship(repos, (&address_1, &address_2), transaction).await;
+    let (current_friendship, room_event, requester) = match result {
+        Ok(relationship) => relationship,
+        Err(err) => {
+            let _ = transaction.rollback().await;
+            return Err(err);
+        }
+    };
+    let friendship = Some(current_friendship.clone());
+
+    let admin = admin_acting_user();
+    let transaction = update_friendship_status(
+        &friendship,
+        &admin,
+        &address_2,
+        FriendshipStatus::NotFriends,
+        RoomInfo {
+            room_event,
+            room_message_body: None,
+            room_id: current_friendship
+                .synapse_room_id
+                .as_deref()
+                .unwrap_or_default(),
+        },
+        FriendshipDbRepositories {
+            db: &app_data.db,
+            friendships_repository: &repos.friendships,
+            friendship_history_repository: &repos.friendship_history,
+        },
+        transaction,
+    )
+    .await
+    .map_err(FriendshipsError::CommonError)?;
+
+    transaction.commit().await.map_err(|err| {
+        log::error!("[Admin] Couldn't commit friendship removal {err}");
+        FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+    })?;
+
+    spawn_room_cleanup(
+        app_data.config.synapse.room_cleanup,
+        &app_data.config.synapse_admin_access_token,
+        room_event,
+        friendship.as_ref(),
+        None,
+        &app_data.synapse,
+        repos.friendships.clone(),
+    );
+
+    log::info!(
+        "[Admin] Removed relationship between {} and {}",
+        address_1,
+        address_2
+    );
+
+    // A single event for the relationship, the requester of a pending request is the one it's
+    // attributed to, and both users are notified as neither of them made the change
+    let from = requester
+        .as_deref()
+        .unwrap_or(current_friendship.address_1.as_str());
+    let to = if from == current_friendship.address_1 {
+        current_friendship.address_2.as_str()
+    } else {
+        current_friendship.address_1.as_str()
+    };
+    let event = Event {
+        notify_from: true,
+        ..removed_relationship_event(from, to, requester.as_deref())
+    };
+    if let Some(friends_cache) = &app_data.friends_cache {
+        friends_cache.invalidate_event(&event).await;
+    }
+    app_data.events_publisher.publish(event).await;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+/// Reads the relationship between the users within the transaction, after locking their
+/// friendship so it can't change until the removal is committed.
+///
+/// Returns the friendship along with the event that removes it and, for pending requests, the
+/// user who sent the request.
+async fn get_removed_relationship(
+    repos: &DBRepositories,
+    addresses: (&Address, &Address),
+    transaction: DatabaseTransaction,
+) -> (
+    Result<(Friendship, FriendshipEvent, Option<String>), FriendshipsError>,
+    DatabaseTransaction,
+) {
+    let unknown_error = |err: sqlx::Error| {
+        log::error!("[Admin] Couldn't read friendship to remove {err}");
+        FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+    };
+
+    let (result, transaction) = repos
+        .friendships
+        .lock_users_friendships(addresses, transaction)
+        .await;
+    if let Err(err) = result {
+        return (Err(unknown_error(err)), transaction);
+    }
+
+    let (friendship, transaction) = repos
+        .friendships
+        .get_friendship(addresses, Some(transaction))
+        .await;
+    let transaction = transaction.unwrap();
+    let friendship = match friendship {
+        Ok(Some(friendship)) => friendship,
+        Ok(None) => return (Err(FriendshipsError::FriendshipNotFound), transaction),
+        Err(err) => return (Err(unknown_error(err)), transaction),
+    };
+
+    if friendship.is_active {
+        return (Ok((friendship, FriendshipEvent::DELETE, None)), transaction);
+    }
+
+    let (last_history, transaction) = repos
+        .friendship_history
+        .get_last_history_for_friendship(friendship.id, Some(transaction))
+        .await;
+    let transaction = transaction.unwrap();
+    let last_history = match last_history {
+        Ok(last_history) => last_history,
+        Err(err) => return (Err(unknown_error(err)), transaction),
+    };
+
+    match FriendshipStatus::from_history_event(last_history) {
+        FriendshipStatus::Requested(requester) => (
+            Ok((friendship, FriendshipEvent::CANCEL, Some(requester))),
+            transaction,
+        ),
+        _ => (Err(FriendshipsError::FriendshipNotFound), transaction),
+    }
+}
@@ -0,0 +1,5 @@
+pub mod events;
+pub mod friendships;
+pub mod types;
+pub mod users;
+pub mod webhooks;
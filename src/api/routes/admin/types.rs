@@ -0,0 +1,123 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::entities::{
+    friendship_history::FriendshipRequestEvent,
+    friendships::Friendship,
+    webhook_deliveries::{DeliveryStatus, WebhookDelivery},
+};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminFriendship {
+    pub id: Uuid,
+    pub address_1: String,
+    pub address_2: String,
+    pub is_active: bool,
+    pub synapse_room_id: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<Friendship> for AdminFriendship {
+    fn from(friendship: Friendship) -> Self {
+        Self {
+            id: friendship.id,
+            address_1: friendship.address_1,
+            address_2: friendship.address_2,
+            is_active: friendship.is_active,
+            synapse_room_id: friendship.synapse_room_id,
+            created_at: friendship.created_at.timestamp(),
+            updated_at: friendship.updated_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AdminFriendshipsResponse {
+    pub friendships: Vec<AdminFriendship>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminRequest {
+    pub from: String,
+    pub to: String,
+    pub created_at: i64,
+    pub message: Option<String>,
+}
+
+impl From<FriendshipRequestEvent> for AdminRequest {
+    fn from(request: FriendshipRequestEvent) -> Self {
+        let to = if request.acting_user == request.address_1 {
+            request.address_2
+        } else {
+            request.address_1
+        };
+
+        Self {
+            message: request.metadata.and_then(|metadata| metadata.0.message),
+            from: request.acting_user,
+            to,
+            created_at: request.timestamp.timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AdminRequestsResponse {
+    pub incoming: Vec<AdminRequest>,
+    pub outgoing: Vec<AdminRequest>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct RevokeUserTokensResponse {
+    pub tokens_revoked: usize,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PurgeUserResponse {
+    pub friendships_removed: u64,
+    pub events_published: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AdminWebhookDelivery {
+    pub id: Uuid,
+    pub url: String,
+    pub event: String,
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<WebhookDelivery> for AdminWebhookDelivery {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            url: delivery.url,
+            event: delivery.event,
+            payload: delivery.payload,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            last_status_code: delivery.last_status_code,
+            last_error: delivery.last_error,
+            created_at: delivery.created_at.timestamp(),
+            updated_at: delivery.updated_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AdminWebhookDeliveriesResponse {
+    pub deliveries: Vec<AdminWebhookDelivery>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WebhookDeliveriesQuery {
+    /// `failed` by default
+    pub status: Option<DeliveryStatus>,
+    pub limit: Option<i64>,
+}
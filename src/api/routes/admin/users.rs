@@ -0,0 +1,248 @@
+use actix_web::{
+    delete, get,
+    web::{self, Data},
+    HttpResponse,
+};
+
+use crate::{
+    api::routes::v1::friendships::errors::FriendshipsError,
+    components::{app::AppComponents, notifications::ChannelPublisher},
+    domain::{address::Address, error::CommonError},
+    notifications::Event,
+};
+
+use super::{
+    events::removed_relationship_event,
+    types::{
+        AdminFriendshipsResponse, AdminRequest, AdminRequestsResponse, PurgeUserResponse,
+        RevokeUserTokensResponse,
+    },
+};
+
+/// Lists all the friendships, past and current, of the given user.
+#[get("/admin/v1/users/{address}/friendships")]
+pub async fn get_user_friendships(
+    address: web::Path<String>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address =
+        Address::parse(&address).map_err(|err| FriendshipsError::CommonError(err.into()))?;
+
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let (friendships, _) = repos
+        .friendships
+        .get_user_friends(&address, false, None, None)
+        .await;
+
+    match friendships {
+        Ok(friendships) => Ok(HttpResponse::Ok().json(AdminFriendshipsResponse {
+            friendships: friendships.into_iter().map(Into::into).collect(),
+        })),
+        Err(_) => Err(FriendshipsError::CommonError(CommonError::Unknown(
+            "".to_owned(),
+        ))),
+    }
+}
+
+/// Lists the pending requests sent and received by the given user.
+#[get("/admin/v1/users/{address}/requests")]
+pub async fn get_user_pending_requests(
+    address: web::Path<String>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address =
+        Address::parse(&address).map_err(|err| FriendshipsError::CommonError(err.into()))?;
+
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let (requests, _) = repos
+        .friendship_history
+        .get_user_pending_request_events(&address, None)
+        .await;
+    let requests =
+        requests.map_err(|_| FriendshipsError::CommonError(CommonError::Unknown("".to_owned())))?;
+
+    let (outgoing, incoming): (Vec<AdminRequest>, Vec<AdminRequest>) = requests
+        .into_iter()
+        .map(AdminRequest::from)
+        .partition(|request| request.from == address.as_str());
+
+    Ok(HttpResponse::Ok().json(AdminRequestsResponse { incoming, outgoing }))
+}
+
+/// Removes all the social data of the given user: friendships, their history and features.
+///
+/// Friends and users with pending requests are notified as if the relationship had been
+/// deleted, cancelled or rejected by the purged user.
+#[delete("/admin/v1/users/{address}")]
+pub async fn purge_user(
+    address: web::Path<String>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address =
+        Address::parse(&address).map_err(|err| FriendshipsError::CommonError(err.into()))?;
+
+    let response = purge_user_data(&app_data, &address).await?;
+
+    Ok(HttpResponse::Ok().json(response))
+}
+
+/// Revokes every token of the given user cached by the service, so each of them has to be
+/// authenticated by Synapse again on its next request, and their session refresh tokens.
+#[delete("/admin/v1/users/{address}/tokens")]
+pub async fn revoke_user_tokens(
+    address: web::Path<String>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address =
+        Address::parse(&address).map_err(|err| FriendshipsError::CommonError(err.into()))?;
+
+    let tokens_revoked = app_data
+        .users_cache
+        .revoke_user_tokens(address.as_str())
+        .await
+        .map_err(|_| FriendshipsError::CommonError(CommonError::Unknown("".to_owned())))?;
+
+    if let Some(session_tokens) = &app_data.session_tokens {
+        session_tokens
+            .revoke(address.as_str())
+            .await
+            .map_err(|_| FriendshipsError::CommonError(CommonError::Unknown("".to_owned())))?;
+    }
+
+    Ok(HttpResponse::Ok().json(RevokeUserTokensResponse { tokens_revoked }))
+}
+
+/// Removes every friendship, history entry, feature and webhook delivery of the given user in a
+/// single transaction, then lets the counterparties know through the events channel.
+///
+/// The relationships to notify are read within the transaction, with the friendships of the user
+/// locked, so a request or an accept arriving meanwhile isn't removed without being notified.
+pub async fn purge_user_data(
+    app_data: &AppComponents,
+    address: &Address,
+) -> Result<PurgeUserResponse, FriendshipsError> {
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let unknown_error = |err: sqlx::Error| {
+        log::error!("Couldn't purge user {}: {}", address, err);
+        FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+    };
+
+    let transaction = app_data
+        .db
+        .start_transaction()
+        .await
+        .map_err(unknown_error)?;
+
+    let (result, transaction) = repos
+        .friendships
+        .lock_users_friendships((address, address), transaction)
+        .await;
+    result.map_err(unknown_error)?;
+
+    let (friends, transaction) = repos
+        .friendships
+        .get_user_friends(address, true, None, Some(transaction))
+        .await;
+    let friends = friends.map_err(unknown_error)?;
+
+    let (requests, transaction) = repos
+        .friendship_history
+        .get_user_pending_request_events(address, transaction)
+        .await;
+    let requests = requests.map_err(unknown_error)?;
+
+    // The purged user isn't notified, so there's a single event to the other user of each
+    let events: Vec<Event> = friends
+        .iter()
+        .map(|friendship| {
+            let other = other_user((&friendship.address_1, &friendship.address_2), address);
+            removed_relationship_event(address.as_str(), other, None)
+        })
+        .chain(requests.iter().map(|request| {
+            let other = other_user((&request.address_1, &request.address_2), address);
+            removed_relationship_event(address.as_str(), other, Some(&request.acting_user))
+        }))
+        .collect();
+
+    let (result, transaction) = repos
+        .friendship_history
+        .delete_user_history(address, transaction)
+        .await;
+    result.map_err(unknown_error)?;
+
+    let (result, transaction) = repos
+        .friendships
+        .delete_user_friendships(address, transaction)
+        .await;
+    let friendships_removed = result.map_err(unknown_error)?;
+
+    let (result, transaction) = repos
+        .user_features
+        .delete_all_user_features(address, transaction)
+        .await;
+    result.map_err(unknown_error)?;
+
+    // Push notifications keep no record of the user besides the opt out, which is a feature
+    let (result, transaction) = repos
+        .webhook_deliveries
+        .delete_user_deliveries(address, transaction)
+        .await;
+    result.map_err(unknown_error)?;
+
+    match transaction {
+        Some(transaction) => transaction.commit().await.map_err(unknown_error)?,
+        None => {
+            log::error!("Couldn't purge user {}, transaction was lost", address);
+            return Err(FriendshipsError::CommonError(CommonError::Unknown(
+                "".to_owned(),
+            )));
+        }
+    }
+
+    log::info!(
+        "Purged user {}, {} friendships removed",
+        address,
+        friendships_removed
+    );
+
+    if let Some(friends_cache) = &app_data.friends_cache {
+        friends_cache.invalidate(&[address]).await;
+    }
+
+    let events_published = events.len();
+    for event in events {
+        if let Some(friends_cache) = &app_data.friends_cache {
+            friends_cache.invalidate_event(&event).await;
+        }
+        app_data.events_publisher.publish(event).await;
+    }
+
+    Ok(PurgeUserResponse {
+        friendships_removed,
+        events_published,
+    })
+}
+
+/// The user of the relationship that isn't `address`.
+fn other_user<'a>(addresses: (&'a str, &'a str), address: &Address) -> &'a str {
+    if addresses.0 == address.as_str() {
+        addresses.1
+    } else {
+        addresses.0
+    }
+}
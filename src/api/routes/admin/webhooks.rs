@@ -0,0 +1,72 @@
+use actix_web::{
+    get, post,
+    web::{self, Data},
+    HttpResponse,
+};
+use uuid::Uuid;
+
+use crate::{
+    api::routes::v1::friendships::errors::FriendshipsError, components::app::AppComponents,
+    domain::error::CommonError, entities::webhook_deliveries::DeliveryStatus,
+};
+
+use super::types::{AdminWebhookDeliveriesResponse, AdminWebhookDelivery, WebhookDeliveriesQuery};
+
+const DEFAULT_DELIVERIES_LIMIT: i64 = 100;
+const MAX_DELIVERIES_LIMIT: i64 = 1000;
+
+/// Lists the most recent webhook deliveries with the given status, the failed ones by default.
+#[get("/admin/v1/webhooks/deliveries")]
+pub async fn get_webhook_deliveries(
+    query: web::Query<WebhookDeliveriesQuery>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let status = query.status.unwrap_or(DeliveryStatus::Failed);
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_DELIVERIES_LIMIT)
+        .clamp(1, MAX_DELIVERIES_LIMIT);
+
+    let deliveries = repos
+        .webhook_deliveries
+        .get_by_status(status, limit)
+        .await
+        .map_err(|err| {
+            log::error!("[Admin] Couldn't read the webhook deliveries {err}");
+            FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+        })?;
+
+    Ok(HttpResponse::Ok().json(AdminWebhookDeliveriesResponse {
+        deliveries: deliveries.into_iter().map(Into::into).collect(),
+    }))
+}
+
+/// Posts a webhook delivery again with its original payload and signature.
+#[post("/admin/v1/webhooks/deliveries/{id}/replay")]
+pub async fn replay_webhook_delivery(
+    id: web::Path<String>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let not_found = || FriendshipsError::CommonError(CommonError::NotFound("".to_owned()));
+
+    let Some(webhooks) = &app_data.webhooks else {
+        return Err(not_found());
+    };
+    let id = Uuid::parse_str(&id).map_err(|_| {
+        FriendshipsError::CommonError(CommonError::BadRequest("Invalid delivery id".to_owned()))
+    })?;
+
+    let delivery = webhooks
+        .replay(id)
+        .await
+        .map_err(FriendshipsError::CommonError)?
+        .ok_or_else(not_found)?;
+
+    Ok(HttpResponse::Ok().json(AdminWebhookDelivery::from(delivery)))
+}
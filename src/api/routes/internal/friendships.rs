@@ -0,0 +1,99 @@
+use actix_web::{
+    get, post,
+    web::{self, Data},
+    HttpResponse,
+};
+
+use super::types::{
+    AreFriendsRequest, AreFriendsResponse, AreFriendsResult, MutualFriendsCountResponse,
+    ServiceFriendsResponse,
+};
+use crate::{
+    components::{
+        app::AppComponents,
+        database::DBRepositories,
+        friends_cache::{load_friends, load_mutual_friends},
+    },
+    db::friendships_handler::get_friendship,
+    domain::{address::Address, error::CommonError},
+};
+
+/// Most pairs a service can check in a single request
+const MAX_PAIRS_PER_CHECK: usize = 100;
+
+fn repos(app_data: &AppComponents) -> Result<&DBRepositories, CommonError> {
+    app_data
+        .db
+        .get_repos()
+        .as_ref()
+        .ok_or_else(|| CommonError::NotFound("".to_owned()))
+}
+
+#[post("/internal/v1/friendships/check")]
+pub async fn check_friendships(
+    request: web::Json<AreFriendsRequest>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, CommonError> {
+    let pairs = request.into_inner().pairs;
+    if pairs.len() > MAX_PAIRS_PER_CHECK {
+        return Err(CommonError::BadRequest(format!(
+            "At most {MAX_PAIRS_PER_CHECK} pairs can be checked at once"
+        )));
+    }
+
+    let repos = repos(&app_data)?;
+    let mut results = Vec::with_capacity(pairs.len());
+    for pair in pairs {
+        let address_1 = Address::parse(&pair.address_1)?;
+        let address_2 = Address::parse(&pair.address_2)?;
+        let friendship = get_friendship(&repos.friendships, &address_1, &address_2).await?;
+
+        results.push(AreFriendsResult {
+            address_1: pair.address_1,
+            address_2: pair.address_2,
+            are_friends: friendship.map_or(false, |friendship| friendship.is_active),
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(AreFriendsResponse { results }))
+}
+
+#[get("/internal/v1/users/{address}/friends")]
+pub async fn get_friends_for_service(
+    address: web::Path<String>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, CommonError> {
+    let address = Address::parse(&address)?;
+    let repos = repos(&app_data)?;
+
+    let friends = match &app_data.friends_cache {
+        Some(friends_cache) => friends_cache.get_friends(&address, repos).await?,
+        None => load_friends(&address, repos).await?,
+    };
+
+    Ok(HttpResponse::Ok().json(ServiceFriendsResponse { friends }))
+}
+
+#[get("/internal/v1/users/{address}/mutuals/{other}/count")]
+pub async fn get_mutual_friends_count(
+    addresses: web::Path<(String, String)>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, CommonError> {
+    let (address_1, address_2) = addresses.into_inner();
+    let address_1 = Address::parse(&address_1)?;
+    let address_2 = Address::parse(&address_2)?;
+    let repos = repos(&app_data)?;
+
+    let mutuals = match &app_data.friends_cache {
+        Some(friends_cache) => {
+            friends_cache
+                .get_mutual_friends(&address_1, &address_2, repos)
+                .await?
+        }
+        None => load_mutual_friends(&address_1, &address_2, repos).await?,
+    };
+
+    Ok(HttpResponse::Ok().json(MutualFriendsCountResponse {
+        count: mutuals.len(),
+    }))
+}
@@ -0,0 +1,2 @@
+pub mod friendships;
+pub mod types;
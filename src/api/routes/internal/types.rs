@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AddressPair {
+    pub address_1: String,
+    pub address_2: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AreFriendsRequest {
+    pub pairs: Vec<AddressPair>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AreFriendsResult {
+    pub address_1: String,
+    pub address_2: String,
+    pub are_friends: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AreFriendsResponse {
+    pub results: Vec<AreFriendsResult>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ServiceFriendsResponse {
+    pub friends: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MutualFriendsCountResponse {
+    pub count: usize,
+}
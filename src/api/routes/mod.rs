@@ -1,3 +1,5 @@
+pub mod admin;
 pub mod health;
+pub mod internal;
 pub mod synapse;
 pub mod v1;
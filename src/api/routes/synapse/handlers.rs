@@ -1,7 +1,11 @@
+use super::types::LoginResponse;
 use crate::{
+    api::middlewares::check_auth::Token,
     components::{
         app::AppComponents,
-        synapse::{clean_synapse_user_id, SynapseLoginRequest},
+        session_tokens::SessionTokensComponent,
+        synapse::{clean_synapse_user_id, EmptyResponse, SynapseComponent, SynapseLoginRequest},
+        users_cache::UserId,
     },
     domain::error::CommonError,
 };
@@ -22,6 +26,7 @@ pub async fn version(app_data: Data<AppComponents>) -> HttpResponse {
     }
 }
 
+/// Logs the user in Synapse, along with the session tokens of the service when they are enabled.
 #[post("/_matrix/client/r0/login")]
 pub async fn login(
     app_data: Data<AppComponents>,
@@ -29,10 +34,10 @@ pub async fn login(
 ) -> HttpResponse {
     match app_data.synapse.login(payload.0).await {
         Ok(ok_response) => {
-            let mut users_cache = app_data.users_cache.lock().await;
             let social_id = clean_synapse_user_id(&ok_response.user_id);
 
-            if users_cache
+            if app_data
+                .users_cache
                 .add_user(
                     &ok_response.access_token,
                     &social_id,
@@ -42,7 +47,20 @@ pub async fn login(
                 .await
                 .is_ok()
             {
-                HttpResponse::Ok().json(ok_response)
+                let session = app_data.session_tokens.as_ref().map(|session_tokens| {
+                    session_tokens.issue(
+                        &UserId {
+                            social_id,
+                            synapse_id: ok_response.user_id.clone(),
+                        },
+                        &ok_response.device_id,
+                    )
+                });
+
+                HttpResponse::Ok().json(LoginResponse {
+                    synapse: ok_response,
+                    session,
+                })
             } else {
                 log::error!(
                     "login handler: Error on storing hashed token and user id into users redis cache"
@@ -54,3 +72,50 @@ pub async fn login(
         Err(err_response) => err_response.error_response(),
     }
 }
+
+/// Logs the token out of Synapse and drops it from the users cache, so it's rejected right away
+/// instead of once its cache entry expires. The session issued for its Synapse device is revoked
+/// as well, and the RPC transports bound to either token are closed on their next check.
+#[post("/_matrix/client/r0/logout")]
+pub async fn logout(Token(token): Token, app_data: Data<AppComponents>) -> HttpResponse {
+    if let Some(session_tokens) = &app_data.session_tokens {
+        if let Err(err) = revoke_device_session(session_tokens, &app_data.synapse, &token).await {
+            return err.error_response();
+        }
+    }
+
+    let logout_response = app_data.synapse.logout(&token).await;
+
+    // Dropped even if Synapse failed, the next request is authenticated by Synapse again
+    if let Err(err) = app_data.users_cache.invalidate_token(&token).await {
+        log::error!("logout handler: Error on dropping the token from the users cache: {err}");
+        return CommonError::Unknown("".to_owned()).error_response();
+    }
+
+    match logout_response {
+        Ok(()) => HttpResponse::Ok().json(EmptyResponse {}),
+        Err(err_response) => err_response.error_response(),
+    }
+}
+
+/// Revokes the session issued for the Synapse device of the token, it must be read before logging
+/// the token out.
+async fn revoke_device_session(
+    session_tokens: &SessionTokensComponent,
+    synapse: &SynapseComponent,
+    token: &str,
+) -> Result<(), CommonError> {
+    let who_am_i = synapse.who_am_i(token).await?;
+    let Some(device_id) = who_am_i.device_id else {
+        log::warn!(
+            "logout handler: Synapse didn't tell the device of the token, its session stays valid"
+        );
+        return Ok(());
+    };
+    let social_id = clean_synapse_user_id(&who_am_i.user_id);
+
+    session_tokens
+        .revoke_session(&social_id, &device_id)
+        .await
+        .map_err(|_| CommonError::Unknown("".to_owned()))
+}
@@ -1,3 +1,4 @@
 pub mod errors;
 pub mod handlers;
 pub mod room_events;
+pub mod types;
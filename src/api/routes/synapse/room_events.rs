@@ -1,27 +1,31 @@
 use actix_web::{
     put,
     web::{self, Data},
-    HttpMessage, HttpRequest, HttpResponse,
+    HttpResponse,
 };
 use serde::{Deserialize, Serialize};
-use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
     api::middlewares::check_auth::Token,
     components::{
         app::AppComponents,
-        database::{DatabaseComponent, DatabaseComponentImplementation},
+        database::{DatabaseComponentImplementation, DatabaseTransaction},
         synapse::{RoomMembersResponse, SynapseComponent},
         users_cache::UserId,
     },
+    db::{friendships_handler::check_friendship_limits, types::FriendshipDbRepositories},
     domain::{
-        error::CommonError, friendship_event::FriendshipEvent, friendship_status::FriendshipStatus,
+        address::Address, error::CommonError, friendship_event::FriendshipEvent,
+        friendship_limits::FriendshipLimits, friendship_status::FriendshipStatus,
     },
     entities::{
-        friendship_history::{FriendshipHistory, FriendshipHistoryRepository, FriendshipMetadata},
-        friendships::{Friendship, FriendshipRepositoryImplementation, FriendshipsRepository},
+        friendship_history::{
+            FriendshipHistory, FriendshipHistoryRepositoryImplementation, FriendshipMetadata,
+        },
+        friendships::{Friendship, FriendshipRepositoryImplementation},
     },
+    synapse::synapse_handler::spawn_room_cleanup,
 };
 
 use super::errors::SynapseError;
@@ -49,37 +53,24 @@ pub struct RoomEventRequestBody {
 
 #[put("/_matrix/client/r0/rooms/{room_id}/state/org.decentraland.friendship")]
 pub async fn room_event_handler(
-    req: HttpRequest,
+    logged_in_user: UserId,
+    Token(token): Token,
     body: web::Json<RoomEventRequestBody>,
     room_id: web::Path<String>,
     app_data: Data<AppComponents>,
 ) -> Result<HttpResponse, SynapseError> {
-    let (logged_in_user, token) = {
-        let extensions = req.extensions();
-        let logged_in_user = extensions
-            .get::<UserId>()
-            .expect("to have a UserId")
-            .clone();
-
-        let token = extensions
-            .get::<Token>()
-            .expect("To have an authentication token")
-            .0
-            .clone();
-
-        (logged_in_user, token)
-    };
-
     let room_message_body = body.message.as_deref();
 
+    let acting_user = Address::parse(&logged_in_user.social_id)
+        .map_err(|err| SynapseError::CommonError(err.into()))?;
+
     let response = process_room_event(
-        &logged_in_user.social_id,
+        &acting_user,
         &token,
         room_id.as_str(),
         body.r#type,
         room_message_body,
-        &app_data.db,
-        &app_data.synapse,
+        &app_data,
     )
     .await;
 
@@ -90,32 +81,38 @@ pub async fn room_event_handler(
 }
 
 async fn process_room_event<'a>(
-    acting_user: &str,
+    acting_user: &Address,
     token: &str,
     room_id: &str,
     room_event: FriendshipEvent,
     room_message_body: Option<&str>,
-    db: &DatabaseComponent,
-    synapse: &SynapseComponent,
+    app_data: &AppComponents,
 ) -> Result<RoomEventResponse, SynapseError> {
+    let db = app_data.db.as_ref();
+    let synapse = &app_data.synapse;
+    let default_limits = FriendshipLimits {
+        max_friends: app_data.config.friendships_max_friends,
+        max_outgoing_requests: app_data.config.friendships_max_outgoing_requests,
+    };
+
     // GET MEMBERS FROM SYNAPSE
     let members_result = synapse.get_room_members(token, room_id).await;
     let (address_0, address_1) = get_room_members(members_result).await?;
 
-    let second_user = if address_0.eq_ignore_ascii_case(acting_user) {
+    let second_user = if &address_0 == acting_user {
         address_1
     } else {
         address_0
     };
 
     // GET LAST STATUS FROM DB
-    let repos = db.db_repos.as_ref().unwrap();
+    let repos = db.get_repos().as_ref().unwrap();
     let friendship = get_friendship_from_db(&repos.friendships, acting_user, &second_user).await?;
 
     let last_history = get_last_history_from_db(&friendship, &repos.friendship_history).await?;
 
     // PROCESS NEW STATUS OF FRIENDSHIP
-    let new_status = process_friendship_status(acting_user, &last_history, room_event)?;
+    let new_status = process_friendship_status(acting_user.as_str(), &last_history, room_event)?;
 
     let current_status = FriendshipStatus::from_history_event(last_history);
 
@@ -148,6 +145,23 @@ async fn process_room_event<'a>(
         }
     };
 
+    // CHECK THE LIMITS OF THE USERS WITHIN THE TRANSACTION
+    let transaction = check_friendship_limits(
+        room_event,
+        acting_user,
+        &second_user,
+        default_limits,
+        repos.user_features.as_ref(),
+        &FriendshipDbRepositories {
+            db,
+            friendships_repository: &repos.friendships,
+            friendship_history_repository: &repos.friendship_history,
+        },
+        transaction,
+    )
+    .await
+    .map_err(SynapseError::CommonError)?;
+
     // UPDATE FRIENDSHIP ACCORDINGLY IN DB
     let transaction = update_friendship_status(
         &friendship,
@@ -174,7 +188,23 @@ async fn process_room_event<'a>(
             let transaction_result = transaction.commit().await;
 
             match transaction_result {
-                Ok(_) => Ok(value),
+                Ok(_) => {
+                    if let Some(friends_cache) = &app_data.friends_cache {
+                        friends_cache.invalidate(&[acting_user, &second_user]).await;
+                    }
+
+                    spawn_room_cleanup(
+                        app_data.config.synapse.room_cleanup,
+                        &app_data.config.synapse_admin_access_token,
+                        room_event,
+                        friendship.as_ref(),
+                        Some((acting_user, token)),
+                        synapse,
+                        repos.friendships.clone(),
+                    );
+
+                    Ok(value)
+                }
                 Err(_) => Err(SynapseError::CommonError(CommonError::Unknown(
                     "".to_owned(),
                 ))),
@@ -186,7 +216,7 @@ async fn process_room_event<'a>(
 
 async fn get_room_members(
     room_members_response: Result<RoomMembersResponse, CommonError>,
-) -> Result<(String, String), SynapseError> {
+) -> Result<(Address, Address), SynapseError> {
     match room_members_response {
         Ok(response) => {
             let members = response
@@ -202,19 +232,20 @@ async fn get_room_members(
                 return Err(SynapseError::FriendshipNotFound);
             }
 
-            Ok((
-                members.get(0).unwrap().to_string(),
-                members.get(1).unwrap().to_string(),
-            ))
+            let parse_member = |member: &String| {
+                Address::parse(member).map_err(|err| SynapseError::CommonError(err.into()))
+            };
+
+            Ok((parse_member(&members[0])?, parse_member(&members[1])?))
         }
         Err(err) => Err(SynapseError::CommonError(err)),
     }
 }
 
 async fn get_friendship_from_db(
-    friendships_repository: &FriendshipsRepository,
-    address_0: &str,
-    address_1: &str,
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
+    address_0: &Address,
+    address_1: &Address,
 ) -> Result<Option<Friendship>, SynapseError> {
     let (friendship_result, _) = friendships_repository
         .get_friendship((address_0, address_1), None)
@@ -234,7 +265,7 @@ async fn get_friendship_from_db(
 
 async fn get_last_history_from_db(
     friendship: &Option<Friendship>,
-    friendship_history_repository: &FriendshipHistoryRepository,
+    friendship_history_repository: &dyn FriendshipHistoryRepositoryImplementation,
 ) -> Result<Option<FriendshipHistory>, SynapseError> {
     let friendship = {
         match friendship {
@@ -280,7 +311,7 @@ fn process_friendship_status(
         }
         FriendshipEvent::CANCEL => {
             if let Some(last_history) = last_history {
-                if last_history.acting_user.eq_ignore_ascii_case(acting_user) {
+                if last_history.acting_user == acting_user {
                     return Ok(FriendshipStatus::NotFriends);
                 }
             }
@@ -289,7 +320,7 @@ fn process_friendship_status(
         }
         FriendshipEvent::REJECT => {
             if let Some(last_history) = last_history {
-                if !last_history.acting_user.eq_ignore_ascii_case(acting_user) {
+                if last_history.acting_user != acting_user {
                     return Ok(FriendshipStatus::NotFriends);
                 }
             }
@@ -318,7 +349,7 @@ fn calculate_new_friendship_status(
     match last_history.event {
         FriendshipEvent::REQUEST => {
             // since the room event should only be accept or request it can only be done by the second user
-            if last_history.acting_user.eq_ignore_ascii_case(acting_user) {
+            if last_history.acting_user == acting_user {
                 return Err(SynapseError::InvalidEvent);
             }
 
@@ -342,20 +373,20 @@ pub struct RoomInfo<'a> {
 }
 
 pub struct FriendshipPorts<'a> {
-    db: &'a DatabaseComponent,
-    friendships_repository: &'a FriendshipsRepository,
-    friendship_history_repository: &'a FriendshipHistoryRepository,
+    db: &'a dyn DatabaseComponentImplementation,
+    friendships_repository: &'a dyn FriendshipRepositoryImplementation,
+    friendship_history_repository: &'a dyn FriendshipHistoryRepositoryImplementation,
 }
 
 async fn update_friendship_status<'a>(
     friendship: &'a Option<Friendship>,
-    acting_user: &'a str,
-    second_user: &'a str,
+    acting_user: &'a Address,
+    second_user: &'a Address,
     new_status: FriendshipStatus,
     room_info: RoomInfo<'a>,
     friendship_ports: FriendshipPorts<'a>,
-    transaction: Transaction<'static, Postgres>,
-) -> Result<Transaction<'static, Postgres>, SynapseError> {
+    transaction: DatabaseTransaction,
+) -> Result<DatabaseTransaction, SynapseError> {
     // store friendship update
     let is_active = new_status == FriendshipStatus::Friends;
     let (friendship_id_result, transaction) = store_friendship_update(
@@ -396,6 +427,7 @@ async fn update_friendship_status<'a>(
         message: room_info.room_message_body.map(|m| m.to_string()),
         synapse_room_id: Some(room_info.room_id.to_string()),
         migrated_from_synapse: None,
+        forced_by_admin: None,
     });
 
     // store history
@@ -427,18 +459,28 @@ async fn update_friendship_status<'a>(
 async fn store_friendship_update(
     friendship: &Option<Friendship>,
     is_active: bool,
-    address_0: &str,
-    address_1: &str,
+    address_0: &Address,
+    address_1: &Address,
     synapse_room_id: &str,
-    friendships_repository: &FriendshipsRepository,
-    transaction: Transaction<'static, Postgres>,
-) -> (Result<Uuid, SynapseError>, Transaction<'static, Postgres>) {
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
+    transaction: DatabaseTransaction,
+) -> (Result<Uuid, SynapseError>, DatabaseTransaction) {
     match friendship {
         Some(friendship) => {
             let (res, transaction) = friendships_repository
                 .update_friendship_status(&friendship.id, is_active, Some(transaction))
                 .await;
 
+            // The previous room was cleaned up when the friendship ended, so a new one is used
+            let (res, transaction) = match res {
+                Ok(_) if friendship.synapse_room_id.as_deref() != Some(synapse_room_id) => {
+                    friendships_repository
+                        .update_synapse_room_id(&friendship.id, Some(synapse_room_id), transaction)
+                        .await
+                }
+                res => (res, transaction),
+            };
+
             let res = match res {
                 Ok(_) => Ok(friendship.id),
                 Err(err) => {
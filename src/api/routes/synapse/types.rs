@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+
+use crate::components::{session_tokens::SessionTokens, synapse::SynapseLoginResponse};
+
+/// Synapse login response along with the session tokens issued by the service, if enabled.
+#[derive(Serialize, Deserialize)]
+pub struct LoginResponse {
+    #[serde(flatten)]
+    pub synapse: SynapseLoginResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionTokens>,
+}
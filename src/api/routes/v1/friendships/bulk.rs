@@ -0,0 +1,58 @@
+use actix_web::{
+    post,
+    web::{self, Data},
+    HttpResponse,
+};
+
+use super::{
+    errors::FriendshipsError,
+    types::{BulkFriendshipUpdateRequest, BulkFriendshipUpdateResponse},
+};
+use crate::{
+    api::middlewares::check_auth::Token,
+    components::{session_tokens::is_session_token, users_cache::UserId},
+    domain::{address::Address, error::CommonError},
+    ws::{
+        app::SocialContext,
+        service::bulk_friendship_updates::{handle_bulk_friendship_update, BulkTarget},
+    },
+};
+
+/// Applies the action to the pending requests of the logged in user with the given users, or
+/// with all of them when no users are given.
+///
+/// Each update is stored on its own, so the response has the outcome of each of them rather than
+/// failing as a whole. This is the only way to make bulk updates, there are no RPC procedures for
+/// them.
+#[post("/v1/friendships/bulk")]
+pub async fn bulk_update_friendships(
+    logged_in_user: UserId,
+    Token(token): Token,
+    body: web::Json<BulkFriendshipUpdateRequest>,
+    context: Data<SocialContext>,
+) -> Result<HttpResponse, FriendshipsError> {
+    // The updates are done in Synapse on behalf of the user
+    if is_session_token(&token) {
+        return Err(FriendshipsError::CommonError(CommonError::BadRequest(
+            "A Synapse token is needed, session tokens can't update Synapse".to_owned(),
+        )));
+    }
+
+    let acting_user = Address::parse(&logged_in_user.social_id)
+        .map_err(|err| FriendshipsError::CommonError(err.into()))?;
+
+    let BulkFriendshipUpdateRequest { action, users } = body.into_inner();
+    let target = match users {
+        Some(users) => BulkTarget::Users(users),
+        None => BulkTarget::All,
+    };
+
+    let results =
+        handle_bulk_friendship_update(token, action, target, context.into_inner(), acting_user)
+            .await
+            .map_err(FriendshipsError::CommonError)?;
+
+    Ok(HttpResponse::Ok().json(BulkFriendshipUpdateResponse {
+        results: results.into_iter().map(Into::into).collect(),
+    }))
+}
@@ -0,0 +1,32 @@
+use std::collections::HashMap;
+
+use crate::{components::profiles::ProfilesComponent, domain::address::Address};
+
+use super::types::{FriendshipFriend, FriendshipsResponse};
+
+/// Builds the friendships response, enriching it with the friends' profiles when the
+/// profiles component is enabled.
+///
+/// Profiles are fetched in batches of `page_size` addresses, the same page size used to
+/// stream friends through the RPC service.
+pub async fn build_friendships_response(
+    profiles_component: Option<&ProfilesComponent>,
+    friends: Vec<FriendshipFriend>,
+    page_size: usize,
+) -> FriendshipsResponse {
+    let Some(profiles_component) = profiles_component else {
+        return FriendshipsResponse::new(friends);
+    };
+
+    let addresses: Vec<Address> = friends
+        .iter()
+        .filter_map(|friend| Address::parse(&friend.address).ok())
+        .collect();
+
+    let mut profiles = HashMap::new();
+    for page in addresses.chunks(page_size.max(1)) {
+        profiles.extend(profiles_component.get_profiles(page).await);
+    }
+
+    FriendshipsResponse::with_profiles(friends, &profiles)
+}
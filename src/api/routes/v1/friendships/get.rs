@@ -1,13 +1,20 @@
 use actix_web::{
     get,
     web::{self, Data},
-    HttpMessage, HttpRequest, HttpResponse,
+    HttpResponse,
 };
 
-use super::{errors::FriendshipsError, types::FriendshipsResponse};
+use super::{
+    enrichment::build_friendships_response,
+    errors::FriendshipsError,
+    types::{FriendsQuery, FriendshipFriend, FriendshipsResponse},
+};
 use crate::{
-    components::{app::AppComponents, synapse::clean_synapse_user_id, users_cache::UserId},
-    domain::error::CommonError,
+    components::{
+        app::AppComponents, profiles::ProfilesComponent, synapse::clean_synapse_user_id,
+        users_cache::UserId,
+    },
+    domain::{address::Address, error::CommonError, friends_search::FriendsSearch},
     entities::friendships::{Friendship, FriendshipRepositoryImplementation},
 };
 
@@ -15,67 +22,141 @@ const ME: &str = "me";
 
 #[get("/v1/friendships/{userId}")]
 pub async fn get_user_friends(
-    req: HttpRequest,
+    logged_in_user: UserId,
     user_id: web::Path<String>,
+    query: web::Query<FriendsQuery>,
     app_data: Data<AppComponents>,
 ) -> Result<HttpResponse, FriendshipsError> {
-    let logged_in_user = {
-        let extensions = req.extensions();
-        extensions
-            .get::<UserId>()
-            .expect("to have a UserId")
-            .clone()
-    };
-
     let clean_user_id: String = if ME.eq_ignore_ascii_case(&user_id) {
         logged_in_user.social_id.clone()
     } else {
         clean_synapse_user_id(user_id.as_str())
     };
 
+    let logged_in_address = Address::parse(&logged_in_user.social_id)
+        .map_err(|err| FriendshipsError::CommonError(err.into()))?;
+    let address =
+        Address::parse(&clean_user_id).map_err(|err| FriendshipsError::CommonError(err.into()))?;
+
     // Return error when user has no permission
-    if !has_permission(logged_in_user.social_id.as_str(), clean_user_id.as_str()) {
+    if !has_permission(&logged_in_address, &address) {
         return Err(FriendshipsError::CommonError(CommonError::Forbidden(
-            format!("You don't have permission to view {clean_user_id} friends"),
+            format!("You don't have permission to view {address} friends"),
         )));
     }
 
-    // Look for friendships and build friend addresses list
-    match &app_data.db.db_repos {
-        Some(repos) => {
-            let (friendships, _) = repos
-                .friendships
-                .get_user_friends(&clean_user_id, true, None)
-                .await;
-            match friendships {
-                Err(_) => Err(FriendshipsError::CommonError(CommonError::Unknown(
-                    "".to_owned(),
-                ))),
-                Ok(friendships) => {
-                    let response =
-                        FriendshipsResponse::new(get_friends(&clean_user_id, friendships));
-                    Ok(HttpResponse::Ok().json(response))
-                }
-            }
-        }
-        None => Err(FriendshipsError::CommonError(CommonError::NotFound(
+    let search = query
+        .search
+        .as_deref()
+        .map(FriendsSearch::parse)
+        .transpose()
+        .map_err(FriendshipsError::CommonError)?;
+
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
             "".to_owned(),
-        ))),
+        )));
+    };
+
+    // Addresses are always filtered by the DB, the term can only match names if there are profiles
+    let address_prefix = search.as_ref().and_then(FriendsSearch::address_prefix);
+    if search.is_some() && address_prefix.is_none() && app_data.profiles.is_none() {
+        return Ok(HttpResponse::Ok().json(FriendshipsResponse::default()));
+    }
+
+    let page_size = app_data.config.friends_stream_page_size as usize;
+    let mut response = match (&search, &address_prefix) {
+        (Some(_), None) => FriendshipsResponse::default(),
+        _ => {
+            let friends = get_active_friends(
+                repos.friendships.as_ref(),
+                &address,
+                address_prefix.as_deref(),
+            )
+            .await?;
+            build_friendships_response(app_data.profiles.as_ref(), friends, page_size).await
+        }
+    };
+
+    if let (Some(search), Some(profiles)) = (&search, app_data.profiles.as_ref()) {
+        let by_name = search_by_name(
+            repos.friendships.as_ref(),
+            profiles,
+            &address,
+            search,
+            &response,
+            page_size,
+        )
+        .await?;
+        response.friendships.extend(by_name.friendships);
+    }
+
+    if let Some(sort) = query.sort {
+        response.sort(sort);
     }
+    Ok(HttpResponse::Ok().json(response))
+}
+
+async fn get_active_friends(
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
+    address: &Address,
+    address_prefix: Option<&str>,
+) -> Result<Vec<FriendshipFriend>, FriendshipsError> {
+    let (friendships, _) = friendships_repository
+        .get_user_friends(address, true, address_prefix, None)
+        .await;
+
+    friendships
+        .map(|friendships| get_friends(address, friendships))
+        .map_err(|_| FriendshipsError::CommonError(CommonError::Unknown("".to_owned())))
+}
+
+/// Friends whose display name matches the search, other than the ones already found by address.
+///
+/// Names are only matched against the page of most recent friends, so a search doesn't fetch
+/// the profile of every friend of the user.
+async fn search_by_name(
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
+    profiles: &ProfilesComponent,
+    address: &Address,
+    search: &FriendsSearch,
+    found_by_address: &FriendshipsResponse,
+    page_size: usize,
+) -> Result<FriendshipsResponse, FriendshipsError> {
+    let mut friends = get_active_friends(friendships_repository, address, None).await?;
+    friends.retain(|friend| {
+        !found_by_address
+            .friendships
+            .iter()
+            .any(|found| found.address == friend.address)
+    });
+    friends.sort_by(|a, b| b.friends_since.cmp(&a.friends_since));
+    friends.truncate(page_size.max(1));
+
+    let mut response = build_friendships_response(Some(profiles), friends, page_size).await;
+    response.retain_matching(search);
+    Ok(response)
 }
 
-fn has_permission(logged_user_id: &str, user_id: &str) -> bool {
-    user_id.eq_ignore_ascii_case(logged_user_id)
+fn has_permission(logged_address: &Address, address: &Address) -> bool {
+    address == logged_address
 }
 
-fn get_friends(user_id: &str, friendships: Vec<Friendship>) -> Vec<String> {
+fn get_friends(address: &Address, friendships: Vec<Friendship>) -> Vec<FriendshipFriend> {
     friendships
         .iter()
-        .map(
-            |friendship| match friendship.address_1.eq_ignore_ascii_case(user_id) {
+        .map(|friendship| {
+            let friend_address = match friendship.address_1 == address.as_str() {
                 true => friendship.address_2.to_string(),
                 false => friendship.address_1.to_string(),
-            },
-        )
+            };
+
+            FriendshipFriend {
+                friends_since: friendship
+                    .friends_since
+                    .map(|friends_since| friends_since.timestamp()),
+                ..FriendshipFriend::from(friend_address)
+            }
+        })
         .collect()
 }
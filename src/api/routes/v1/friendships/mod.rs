@@ -1,3 +1,5 @@
+pub mod bulk;
+pub mod enrichment;
 pub mod errors;
 pub mod get;
 pub mod mutuals;
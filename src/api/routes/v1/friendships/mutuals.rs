@@ -1,45 +1,57 @@
 use actix_web::{
     get,
     web::{self, Data},
-    HttpMessage, HttpRequest, HttpResponse,
+    HttpResponse,
 };
 
-use super::{errors::FriendshipsError, types::FriendshipsResponse};
+use super::{
+    enrichment::build_friendships_response, errors::FriendshipsError, types::FriendshipFriend,
+};
 use crate::{
     components::{app::AppComponents, synapse::clean_synapse_user_id, users_cache::UserId},
-    domain::error::CommonError,
-    entities::friendships::FriendshipRepositoryImplementation,
+    domain::{address::Address, error::CommonError},
 };
 
 #[get("/v1/friendships/{userId}/mutuals")]
 pub async fn get_mutual_friends(
-    req: HttpRequest,
+    logged_in_user: UserId,
     user_id: web::Path<String>,
     app_data: Data<AppComponents>,
 ) -> Result<HttpResponse, FriendshipsError> {
-    let logged_in_user = req
-        .extensions()
-        .get::<UserId>()
-        .expect("to have a UserId")
-        .clone();
+    let logged_in_address = Address::parse(&logged_in_user.social_id)
+        .map_err(|err| FriendshipsError::CommonError(err.into()))?;
+    let address = Address::parse(&clean_synapse_user_id(&user_id))
+        .map_err(|err| FriendshipsError::CommonError(err.into()))?;
 
     // Look for friendships and build friend addresses list
-    match &app_data.db.db_repos {
+    match app_data.db.get_repos() {
         Some(repos) => {
-            let (friendships, _) = repos
-                .friendships
-                .get_mutual_friends(
-                    &logged_in_user.social_id,
-                    &clean_synapse_user_id(&user_id),
-                    None,
-                )
-                .await;
+            let friendships = match &app_data.friends_cache {
+                Some(friends_cache) => {
+                    friends_cache
+                        .get_mutual_friends(&logged_in_address, &address, repos)
+                        .await
+                }
+                None => {
+                    let (friendships, _) = repos
+                        .friendships
+                        .get_mutual_friends(&logged_in_address, &address, None)
+                        .await;
+                    friendships.map_err(|_| CommonError::Unknown("".to_owned()))
+                }
+            };
             match friendships {
-                Err(_) => Err(FriendshipsError::CommonError(CommonError::Unknown(
-                    "".to_owned(),
-                ))),
+                Err(err) => Err(FriendshipsError::CommonError(err)),
                 Ok(friendships) => {
-                    let response = FriendshipsResponse::new(friendships);
+                    let response = build_friendships_response(
+                        app_data.profiles.as_ref(),
+                        friendships
+                            .into_iter()
+                            .map(FriendshipFriend::from)
+                            .collect(),
+                        app_data.config.friends_stream_page_size as usize,
+                    )
+                    .await;
                     Ok(HttpResponse::Ok().json(response))
                 }
             }
@@ -1,18 +1,83 @@
+use std::collections::HashMap;
+
+use actix_web::ResponseError;
 use serde::{Deserialize, Serialize};
 
+use crate::{
+    components::profiles::Profile,
+    domain::{address::Address, error::ErrorResponse, friends_search::FriendsSearch},
+    ws::service::bulk_friendship_updates::{BulkFriendshipAction, BulkItemResult},
+};
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FriendsQuery {
+    /// Prefix of the friend's address or display name. Display names are only matched against
+    /// the page of most recent friends.
+    pub search: Option<String>,
+    pub sort: Option<FriendsSort>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FriendsSort {
+    /// Most recent friendships first
+    Recent,
+}
+
 #[derive(Debug, Default, Serialize, Deserialize)]
 pub struct FriendshipsResponse {
     pub friendships: Vec<FriendshipFriend>,
 }
 
 impl FriendshipsResponse {
-    pub fn new(addresses: Vec<String>) -> Self {
-        let friends = addresses.iter().map(|address| FriendshipFriend {
-            address: address.to_string(),
+    pub fn new(friends: Vec<FriendshipFriend>) -> Self {
+        Self {
+            friendships: friends,
+        }
+    }
+
+    /// Builds the response adding the profile data of the friends found in `profiles`.
+    pub fn with_profiles(
+        friends: Vec<FriendshipFriend>,
+        profiles: &HashMap<Address, Profile>,
+    ) -> Self {
+        let friends = friends.into_iter().map(|friend| {
+            let profile = Address::parse(&friend.address)
+                .ok()
+                .and_then(|address| profiles.get(&address));
+
+            FriendshipFriend {
+                name: profile.and_then(|profile| profile.name.clone()),
+                face_image: profile.and_then(|profile| profile.face_image.clone()),
+                ..friend
+            }
         });
 
-        Self {
-            friendships: friends.collect(),
+        Self::new(friends.collect())
+    }
+
+    /// Keeps the friends whose address or display name matches the given search.
+    pub fn retain_matching(&mut self, search: &FriendsSearch) {
+        let address_prefix = search.address_prefix();
+
+        self.friendships.retain(|friend| {
+            let address_matches = address_prefix
+                .as_deref()
+                .is_some_and(|prefix| friend.address.starts_with(prefix));
+            let name_matches = friend
+                .name
+                .as_deref()
+                .is_some_and(|name| search.matches_name(name));
+
+            address_matches || name_matches
+        });
+    }
+
+    pub fn sort(&mut self, sort: FriendsSort) {
+        match sort {
+            FriendsSort::Recent => self
+                .friendships
+                .sort_by(|a, b| b.friends_since.cmp(&a.friends_since)),
         }
     }
 }
@@ -20,4 +85,54 @@ impl FriendshipsResponse {
 #[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct FriendshipFriend {
     pub address: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub face_image: Option<String>,
+    /// Unix timestamp (in seconds) of when the users became friends
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub friends_since: Option<i64>,
+}
+
+impl From<String> for FriendshipFriend {
+    fn from(address: String) -> Self {
+        Self {
+            address,
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkFriendshipUpdateRequest {
+    pub action: BulkFriendshipAction,
+    /// Other users of the requests, every pending request in the direction of the action when
+    /// missing
+    pub users: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct BulkFriendshipUpdateResponse {
+    /// Outcome of the update with each user, in the order of the request
+    pub results: Vec<BulkFriendshipUpdateResult>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BulkFriendshipUpdateResult {
+    pub user: String,
+    /// Why the update with the user failed, `None` when it went through
+    pub error: Option<ErrorResponse>,
+}
+
+impl From<BulkItemResult> for BulkFriendshipUpdateResult {
+    fn from(item: BulkItemResult) -> Self {
+        Self {
+            user: item.user,
+            error: item.result.err().map(|err| ErrorResponse {
+                code: err.status_code().as_u16(),
+                message: err.to_string(),
+                error: err.name(),
+            }),
+        }
+    }
 }
@@ -1 +1,3 @@
 pub mod friendships;
+pub mod sessions;
+pub mod users;
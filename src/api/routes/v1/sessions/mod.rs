@@ -0,0 +1,2 @@
+pub mod refresh;
+pub mod types;
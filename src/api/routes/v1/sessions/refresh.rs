@@ -0,0 +1,23 @@
+use actix_web::{
+    post,
+    web::{self, Data},
+    HttpResponse,
+};
+
+use super::types::RefreshSessionRequest;
+use crate::{components::app::AppComponents, domain::error::CommonError};
+
+/// Exchanges a refresh token for a new pair of session tokens, the refresh token is rotated too.
+#[post("/v1/sessions/refresh")]
+pub async fn refresh_session(
+    app_data: Data<AppComponents>,
+    payload: web::Json<RefreshSessionRequest>,
+) -> Result<HttpResponse, CommonError> {
+    let Some(session_tokens) = &app_data.session_tokens else {
+        return Err(CommonError::NotFound("".to_owned()));
+    };
+
+    let tokens = session_tokens.refresh(&payload.refresh_token).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
@@ -0,0 +1,85 @@
+use actix_web::{delete, get, web::Data, HttpResponse};
+
+use super::types::UserDataExport;
+use crate::{
+    api::routes::{admin::users::purge_user_data, v1::friendships::errors::FriendshipsError},
+    components::{app::AppComponents, users_cache::UserId},
+    domain::{address::Address, error::CommonError},
+};
+
+/// Exports, as a downloadable JSON file, everything the service holds about the logged in user:
+/// friendships, their whole history (request messages included) and features.
+#[get("/v1/me/data")]
+pub async fn export_user_data(
+    logged_in_user: UserId,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address = get_logged_in_address(&logged_in_user)?;
+
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let unknown_error = |err: sqlx::Error| {
+        log::error!("Couldn't export user {} data: {}", address, err);
+        FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+    };
+
+    let (friendships, _) = repos
+        .friendships
+        .get_user_friends(&address, false, None, None)
+        .await;
+    let friendships = friendships.map_err(unknown_error)?;
+
+    let history = repos
+        .friendship_history
+        .get_user_history(&address)
+        .await
+        .map_err(unknown_error)?;
+
+    let features = repos
+        .user_features
+        .get_all_user_features(&address)
+        .await
+        .map_err(unknown_error)?;
+
+    let export = UserDataExport {
+        address: address.to_string(),
+        exported_at: chrono::Utc::now().timestamp(),
+        friendships: friendships.into_iter().map(Into::into).collect(),
+        friendship_history: history.into_iter().map(Into::into).collect(),
+        features: features
+            .map(|features| features.features.into_iter().map(Into::into).collect())
+            .unwrap_or_default(),
+    };
+
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"social-data-{address}.json\""),
+        ))
+        .json(export))
+}
+
+/// Erases all the social data of the logged in user.
+///
+/// Friendships and pending requests are removed for both users, so the counterparties are
+/// notified as if the relationship had been deleted, cancelled or rejected.
+#[delete("/v1/me/data")]
+pub async fn erase_user_data(
+    logged_in_user: UserId,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address = get_logged_in_address(&logged_in_user)?;
+
+    purge_user_data(&app_data, &address).await?;
+
+    Ok(HttpResponse::NoContent().finish())
+}
+
+pub(super) fn get_logged_in_address(logged_in_user: &UserId) -> Result<Address, FriendshipsError> {
+    Address::parse(&logged_in_user.social_id)
+        .map_err(|err| FriendshipsError::CommonError(err.into()))
+}
@@ -0,0 +1,87 @@
+use actix_web::{
+    get, put,
+    web::{self, Data},
+    HttpResponse,
+};
+
+use super::{data::get_logged_in_address, types::NotificationSettings};
+use crate::{
+    api::routes::v1::friendships::errors::FriendshipsError,
+    components::{
+        app::AppComponents,
+        push_notifications::{
+            PUSH_NOTIFICATIONS_DISABLED, PUSH_NOTIFICATIONS_ENABLED, PUSH_NOTIFICATIONS_FEATURE,
+        },
+        users_cache::UserId,
+    },
+    domain::error::CommonError,
+};
+
+/// Notification preferences of the logged in user, push notifications are enabled by default.
+#[get("/v1/me/notifications")]
+pub async fn get_notification_settings(
+    logged_in_user: UserId,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address = get_logged_in_address(&logged_in_user)?;
+
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let push = repos
+        .user_features
+        .get_user_feature(&address, PUSH_NOTIFICATIONS_FEATURE)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "Couldn't read user {} notification settings: {}",
+                address,
+                err
+            );
+            FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+        })?;
+
+    Ok(HttpResponse::Ok().json(NotificationSettings {
+        push_enabled: push.as_deref() != Some(PUSH_NOTIFICATIONS_DISABLED),
+    }))
+}
+
+/// Opts the logged in user in or out of the push notifications.
+#[put("/v1/me/notifications")]
+pub async fn update_notification_settings(
+    logged_in_user: UserId,
+    settings: web::Json<NotificationSettings>,
+    app_data: Data<AppComponents>,
+) -> Result<HttpResponse, FriendshipsError> {
+    let address = get_logged_in_address(&logged_in_user)?;
+
+    let Some(repos) = app_data.db.get_repos() else {
+        return Err(FriendshipsError::CommonError(CommonError::NotFound(
+            "".to_owned(),
+        )));
+    };
+
+    let push = if settings.push_enabled {
+        PUSH_NOTIFICATIONS_ENABLED
+    } else {
+        PUSH_NOTIFICATIONS_DISABLED
+    };
+
+    repos
+        .user_features
+        .set_user_feature(&address, PUSH_NOTIFICATIONS_FEATURE, push)
+        .await
+        .map_err(|err| {
+            log::error!(
+                "Couldn't save user {} notification settings: {}",
+                address,
+                err
+            );
+            FriendshipsError::CommonError(CommonError::Unknown("".to_owned()))
+        })?;
+
+    Ok(HttpResponse::Ok().json(settings.into_inner()))
+}
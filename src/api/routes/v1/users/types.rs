@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::entities::{
+    friendship_history::UserFriendshipHistory, friendships::Friendship, user_features::UserFeature,
+};
+
+/// Everything the service holds about a user.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserDataExport {
+    pub address: String,
+    /// Unix timestamp (in seconds) of when the export was generated
+    pub exported_at: i64,
+    pub friendships: Vec<ExportedFriendship>,
+    pub friendship_history: Vec<ExportedFriendshipHistory>,
+    pub features: Vec<ExportedFeature>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedFriendship {
+    pub id: Uuid,
+    pub address_1: String,
+    pub address_2: String,
+    pub is_active: bool,
+    pub synapse_room_id: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl From<Friendship> for ExportedFriendship {
+    fn from(friendship: Friendship) -> Self {
+        Self {
+            id: friendship.id,
+            address_1: friendship.address_1,
+            address_2: friendship.address_2,
+            is_active: friendship.is_active,
+            synapse_room_id: friendship.synapse_room_id,
+            created_at: friendship.created_at.timestamp(),
+            updated_at: friendship.updated_at.timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedFriendshipHistory {
+    pub friendship_id: Uuid,
+    pub address_1: String,
+    pub address_2: String,
+    pub event: String,
+    pub acting_user: String,
+    pub timestamp: i64,
+    pub message: Option<String>,
+    pub synapse_room_id: Option<String>,
+    pub migrated_from_synapse: Option<bool>,
+}
+
+impl From<UserFriendshipHistory> for ExportedFriendshipHistory {
+    fn from(history: UserFriendshipHistory) -> Self {
+        let metadata = history.metadata.map(|metadata| metadata.0);
+
+        Self {
+            friendship_id: history.friendship_id,
+            address_1: history.address_1,
+            address_2: history.address_2,
+            // Older entries were stored without quotes
+            event: history.event.trim_matches('"').to_string(),
+            acting_user: history.acting_user,
+            timestamp: history.timestamp.timestamp(),
+            message: metadata
+                .as_ref()
+                .and_then(|metadata| metadata.message.clone()),
+            synapse_room_id: metadata
+                .as_ref()
+                .and_then(|metadata| metadata.synapse_room_id.clone()),
+            migrated_from_synapse: metadata.and_then(|metadata| metadata.migrated_from_synapse),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedFeature {
+    pub name: String,
+    pub value: String,
+}
+
+impl From<UserFeature> for ExportedFeature {
+    fn from(feature: UserFeature) -> Self {
+        Self {
+            name: feature.feature_name,
+            value: feature.feature_value,
+        }
+    }
+}
+
+/// Notification preferences of the logged in user.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NotificationSettings {
+    /// Whether the friendship requests and their acceptance are pushed while offline
+    pub push_enabled: bool,
+}
@@ -1,24 +1,45 @@
-use std::sync::Arc;
-
-use tokio::sync::Mutex;
+use std::{sync::Arc, time::Duration};
 
 use super::configuration::Database;
 use super::{
-    configuration::Config, database::DatabaseComponent, database::DatabaseComponentImplementation,
-    health::HealthComponent, synapse::SynapseComponent,
+    configuration::Config,
+    database::{DatabaseComponent, DatabaseComponentImplementation, InMemoryDatabaseComponent},
+    health::HealthComponent,
+    synapse::SynapseComponent,
 };
 
 use super::{
+    friends_cache::FriendsCacheComponent,
+    notifications::{init_configured_events_channel_publisher, EventsChannelPublisher},
+    profiles::{LambdasProfileProvider, ProfilesComponent},
+    push_notifications::{HttpPushProvider, PushNotificationsComponent},
     redis::Redis,
+    service_keys::{parse_service_keys, ServiceKeysComponent},
+    session_tokens::{parse_signing_keys, SessionTokensComponent},
     users_cache::{self, UsersCacheComponent},
+    webhooks::{parse_webhook_events, WebhooksComponent},
 };
 
 pub struct AppComponents {
     pub health: HealthComponent,
     pub synapse: SynapseComponent,
     pub config: Config,
-    pub db: DatabaseComponent,
-    pub users_cache: Arc<Mutex<UsersCacheComponent>>,
+    pub db: Arc<dyn DatabaseComponentImplementation>,
+    pub users_cache: Arc<UsersCacheComponent>,
+    /// Enriches friend lists with profile data, `None` when no profiles URL is configured
+    pub profiles: Option<ProfilesComponent>,
+    /// Caches the friends of each user, `None` when the cache is disabled
+    pub friends_cache: Option<FriendsCacheComponent>,
+    /// Issues the session tokens on login, `None` when no signing keys are configured
+    pub session_tokens: Option<SessionTokensComponent>,
+    /// Authenticates the other backends on the internal API, `None` when no service keys are configured
+    pub service_keys: Option<ServiceKeysComponent>,
+    /// Pushes the friendship events to offline users, `None` when no push service is configured
+    pub push_notifications: Option<PushNotificationsComponent>,
+    /// Posts the friendship events to the subscribers, `None` when no webhook URLs are configured
+    pub webhooks: Option<WebhooksComponent>,
+    /// Publishes the friendship events triggered from the REST API, e.g. admin actions
+    pub events_publisher: Arc<EventsChannelPublisher>,
 }
 
 impl AppComponents {
@@ -27,58 +48,251 @@ impl AppComponents {
         let config = custom_config
             .unwrap_or_else(|| Config::new().expect("Couldn't read the configuration"));
 
-        Self::with_config(config).await
+        Self::init_logger();
+        let db = Self::init_db_component(&config.db).await;
+
+        Self::with_config(config, db).await
     }
 
-    async fn with_config(config: Config) -> Self {
+    /// Same as `new` but the repositories are kept in memory instead of Postgres
+    pub async fn new_in_memory(custom_config: Option<Config>) -> Self {
+        let config = custom_config
+            .unwrap_or_else(|| Config::new().expect("Couldn't read the configuration"));
+
+        Self::init_logger();
+        Self::with_config(config, Arc::new(InMemoryDatabaseComponent::default())).await
+    }
+
+    async fn with_config(config: Config, db: Arc<dyn DatabaseComponentImplementation>) -> Self {
+        let synapse = Self::init_synapse_component(&config);
+        let redis = Self::init_redis(&config).await;
+        let health = Self::init_health_component(db.clone(), redis.clone(), synapse.clone());
+        let profiles = Self::init_profiles_component(&config, redis.clone());
+        let friends_cache = Self::init_friends_cache(&config, redis.clone());
+        let session_tokens = Self::init_session_tokens(&config, redis.clone());
+        let service_keys = Self::init_service_keys(&config, redis.clone());
+        let webhooks = Self::init_webhooks(&config, db.as_ref(), redis.clone());
+        let push_notifications = Self::init_push_notifications(&config, db.as_ref(), redis.clone());
+        let events_publisher = Arc::new(
+            init_configured_events_channel_publisher(
+                config.events_channel,
+                redis.clone().map(Arc::new),
+                &db,
+            )
+            .await,
+        );
+        let users_cache = Self::init_users_cache(&config, redis);
+
+        Self {
+            health,
+            db,
+            synapse,
+            users_cache: Arc::new(users_cache),
+            profiles,
+            friends_cache,
+            session_tokens,
+            service_keys,
+            push_notifications,
+            webhooks,
+            events_publisher,
+            config,
+        }
+    }
+
+    fn init_logger() {
         if env_logger::try_init().is_err() {
             log::debug!("Logger already init")
         }
+    }
 
-        let synapse = Self::init_synapse_component(config.synapse.url.clone());
-        let db = Self::init_db_component(&config.db).await;
-        let redis = Redis::new_and_run(&config.redis).await;
-        match redis {
-            Ok(redis) => {
-                let health = Self::init_health_component(db.clone(), redis.clone());
-                let users_cache = Self::init_users_cache(redis, config.cache_hashing_key.clone());
-
-                Self {
-                    health,
-                    db,
-                    synapse,
-                    users_cache: Arc::new(Mutex::new(users_cache)),
-                    config,
-                }
-            }
-            Err(er) => {
-                log::error!("There was an error initiliazing Redis: {}", er);
+    /// Redis is optional, without a host the components keep their state in-process or in
+    /// Postgres instead.
+    async fn init_redis(config: &Config) -> Option<Redis> {
+        if config.redis.host.is_empty() {
+            log::info!("No Redis host configured, caches and rate limits will be kept in-process");
+            return None;
+        }
+
+        match Redis::new_and_run(&config.redis).await {
+            Ok(redis) => Some(redis),
+            Err(err) => {
+                log::error!("There was an error initiliazing Redis: {}", err);
                 panic!("There was an error initializing Redis");
             }
         }
     }
 
-    fn init_health_component(db: DatabaseComponent, redis: Redis) -> HealthComponent {
+    fn init_health_component(
+        db: Arc<dyn DatabaseComponentImplementation>,
+        redis: Option<Redis>,
+        synapse: SynapseComponent,
+    ) -> HealthComponent {
         let mut health = HealthComponent::default();
         health.register_component(Box::new(db), "database".to_string());
-        health.register_component(Box::new(redis), "redis".to_string());
+        if let Some(redis) = redis {
+            health.register_component(Box::new(redis), "redis".to_string());
+        }
+        health.register_component(Box::new(synapse), "synapse".to_string());
         health
     }
 
-    async fn init_db_component(db_config: &Database) -> DatabaseComponent {
+    async fn init_db_component(db_config: &Database) -> Arc<dyn DatabaseComponentImplementation> {
         let mut db = DatabaseComponent::new(db_config);
         if let Err(err) = db.run().await {
             log::debug!("Error on running the DB: {:?}", err);
             panic!("Unable to run the DB")
         }
-        db
+        Arc::new(db)
+    }
+
+    fn init_synapse_component(config: &Config) -> SynapseComponent {
+        let synapse = SynapseComponent::from_config(&config.synapse);
+        if config.synapse_sync_access_token.is_empty() {
+            return synapse;
+        }
+
+        if config.synapse_sync_user_id.is_empty() {
+            log::warn!("No Synapse sync user id configured, the sync listener won't get the events of new rooms");
+            return synapse;
+        }
+
+        synapse.with_service_user(config.synapse_sync_user_id.clone())
+    }
+
+    fn init_profiles_component(config: &Config, redis: Option<Redis>) -> Option<ProfilesComponent> {
+        if config.profiles.url.is_empty() {
+            log::info!("No profiles URL configured, friend lists won't be enriched");
+            return None;
+        }
+
+        let provider = LambdasProfileProvider::new(config.profiles.url.clone());
+        Some(ProfilesComponent::new(
+            Arc::new(provider),
+            redis,
+            config.profiles_cache_ttl_seconds,
+            config.profiles_missing_cache_ttl_seconds,
+        ))
     }
 
-    fn init_synapse_component(url: String) -> SynapseComponent {
-        SynapseComponent::new(url)
+    fn init_friends_cache(config: &Config, redis: Option<Redis>) -> Option<FriendsCacheComponent> {
+        if !config.friends_cache_enabled {
+            log::info!("Friends cache disabled, friends will be read from the DB");
+            return None;
+        }
+
+        let Some(redis) = redis else {
+            log::warn!("The friends cache needs Redis, friends will be read from the DB");
+            return None;
+        };
+
+        Some(FriendsCacheComponent::new(
+            redis,
+            config.friends_cache_ttl_seconds,
+        ))
+    }
+
+    fn init_session_tokens(
+        config: &Config,
+        redis: Option<Redis>,
+    ) -> Option<SessionTokensComponent> {
+        let keys = parse_signing_keys(&config.session_signing_keys)
+            .expect("Couldn't read the session signing keys");
+        if keys.is_empty() {
+            log::info!("No session signing keys configured, session tokens won't be issued");
+            return None;
+        }
+
+        Some(SessionTokensComponent::new(
+            keys,
+            config.session_access_ttl_seconds,
+            config.session_refresh_ttl_seconds,
+            redis,
+        ))
+    }
+
+    fn init_service_keys(config: &Config, redis: Option<Redis>) -> Option<ServiceKeysComponent> {
+        let keys =
+            parse_service_keys(&config.service_keys).expect("Couldn't read the service keys");
+        if keys.is_empty() {
+            log::info!("No service keys configured, the internal API is disabled");
+            return None;
+        }
+
+        Some(ServiceKeysComponent::new(
+            keys,
+            redis,
+            config.service_requests_per_minute,
+        ))
+    }
+
+    fn init_push_notifications(
+        config: &Config,
+        db: &dyn DatabaseComponentImplementation,
+        redis: Option<Redis>,
+    ) -> Option<PushNotificationsComponent> {
+        if config.push_notifications_url.is_empty() {
+            log::info!("No push notifications URL configured, offline users won't be notified");
+            return None;
+        }
+
+        let token = Some(config.push_notifications_token.clone()).filter(|token| !token.is_empty());
+        let provider = HttpPushProvider::new(config.push_notifications_url.clone(), token);
+        let repos = db.get_repos().as_ref()?;
+
+        Some(PushNotificationsComponent::new(
+            Arc::new(provider),
+            repos.user_features.clone(),
+            redis,
+            config.cache_hashing_key.clone(),
+            Duration::from_millis(config.push_notifications_grace_ms),
+        ))
+    }
+
+    fn init_webhooks(
+        config: &Config,
+        db: &dyn DatabaseComponentImplementation,
+        redis: Option<Redis>,
+    ) -> Option<WebhooksComponent> {
+        let urls: Vec<String> = config
+            .webhooks_urls
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_string)
+            .collect();
+        if urls.is_empty() {
+            log::info!("No webhook URLs configured, the friendship events won't be posted");
+            return None;
+        }
+        if config.webhooks_signing_key.is_empty() {
+            panic!("A webhooks signing key is required to post the friendship events");
+        }
+
+        let events = parse_webhook_events(&config.webhooks_events)
+            .expect("Couldn't read the webhook events");
+        let repos = db.get_repos().as_ref()?;
+
+        Some(
+            WebhooksComponent::new(
+                urls,
+                config.webhooks_signing_key.clone(),
+                events,
+                repos.webhook_deliveries.clone(),
+                redis,
+            )
+            .with_retries(
+                config.webhooks_max_attempts,
+                Duration::from_millis(config.webhooks_retry_backoff_ms),
+            ),
+        )
     }
 
-    fn init_users_cache(redis: Redis, config_hash_key: String) -> UsersCacheComponent {
-        users_cache::UsersCacheComponent::new(redis, config_hash_key)
+    fn init_users_cache(config: &Config, redis: Option<Redis>) -> UsersCacheComponent {
+        users_cache::UsersCacheComponent::new(redis, config.cache_hashing_key.clone())
+            .with_local_cache(
+                config.users_cache_local_capacity,
+                Duration::from_secs(config.users_cache_local_ttl_seconds),
+            )
+            .with_negative_cache(Duration::from_secs(config.users_cache_negative_ttl_seconds))
     }
 }
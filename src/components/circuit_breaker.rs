@@ -0,0 +1,182 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Calls go through
+    Closed,
+    /// Calls fail fast until the open period is over
+    Open,
+    /// The open period is over and a single call is let through to probe the service
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// Value reported in the metrics
+    pub fn as_gauge_value(&self) -> i64 {
+        match self {
+            Self::Closed => 0,
+            Self::HalfOpen => 1,
+            Self::Open => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum BreakerState {
+    Closed { consecutive_failures: u32 },
+    Open { until: Instant },
+    HalfOpen { probing: bool },
+}
+
+/// Stops calling a service after `failure_threshold` consecutive failures,
+/// and waits `open_duration` before trying it again.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    open_duration: Duration,
+    state: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, open_duration: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            open_duration,
+            state: Mutex::new(BreakerState::Closed {
+                consecutive_failures: 0,
+            }),
+        }
+    }
+
+    /// Whether a call can be made. When the open period is over, only the first caller is let through.
+    pub fn try_acquire(&self) -> bool {
+        let mut state = self.lock();
+        match *state {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } => {
+                if Instant::now() < until {
+                    return false;
+                }
+                *state = BreakerState::HalfOpen { probing: true };
+                true
+            }
+            BreakerState::HalfOpen { probing } => {
+                if probing {
+                    return false;
+                }
+                *state = BreakerState::HalfOpen { probing: true };
+                true
+            }
+        }
+    }
+
+    pub fn record_success(&self) {
+        *self.lock() = BreakerState::Closed {
+            consecutive_failures: 0,
+        };
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.lock();
+        let consecutive_failures = match *state {
+            BreakerState::Closed {
+                consecutive_failures,
+            } => consecutive_failures + 1,
+            // The probe failed, so the service is still down
+            BreakerState::HalfOpen { .. } | BreakerState::Open { .. } => self.failure_threshold,
+        };
+
+        *state = if consecutive_failures >= self.failure_threshold {
+            log::warn!(
+                "[CircuitBreaker] opening the circuit for {:?} after {} consecutive failures",
+                self.open_duration,
+                consecutive_failures
+            );
+            BreakerState::Open {
+                until: Instant::now() + self.open_duration,
+            }
+        } else {
+            BreakerState::Closed {
+                consecutive_failures,
+            }
+        };
+    }
+
+    pub fn state(&self) -> CircuitState {
+        match *self.lock() {
+            BreakerState::Closed { .. } => CircuitState::Closed,
+            BreakerState::Open { until } if Instant::now() < until => CircuitState::Open,
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => CircuitState::HalfOpen,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BreakerState> {
+        self.state
+            .lock()
+            .expect("the circuit breaker state not to be poisoned")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{CircuitBreaker, CircuitState};
+
+    #[test]
+    fn should_open_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire());
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn should_reset_the_failures_on_success() {
+        let breaker = CircuitBreaker::new(2, Duration::from_secs(60));
+
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn should_let_a_single_probe_through_once_the_open_period_is_over() {
+        let breaker = CircuitBreaker::new(1, Duration::ZERO);
+
+        breaker.record_failure();
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+
+        breaker.record_success();
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn should_open_again_when_the_probe_fails() {
+        let breaker = CircuitBreaker::new(3, Duration::ZERO);
+        for _ in 0..3 {
+            breaker.record_failure();
+        }
+
+        assert!(breaker.try_acquire());
+        breaker.record_failure();
+
+        // A single failure is enough to open it again
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+    }
+}
@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::{ConfigError, File};
 use serde::Deserialize;
 
@@ -24,6 +24,39 @@ pub struct Args {
     /// RPC WS Ping interval in seconds
     #[clap(long, value_parser)]
     rpc_ping_interval_seconds: Option<u64>,
+
+    /// Runs a maintenance task instead of the service
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compares the friendships with their Synapse rooms and reports the mismatches as JSON
+    CheckConsistency {
+        /// Friendships fetched from the database at once
+        #[clap(long, value_parser, default_value_t = 100)]
+        batch_size: i64,
+
+        /// Repairs the mismatches that can be fixed, without it nothing is changed
+        #[clap(long, action)]
+        apply: bool,
+
+        /// Token of a Synapse account that is a member of the friendship rooms,
+        /// the sync listener one is used by default
+        #[clap(long, value_parser)]
+        synapse_access_token: Option<String>,
+    },
+    /// Recomputes the friendships from their history, reporting the invalid sequences as JSON
+    RebuildProjection {
+        /// Friendships replayed at once
+        #[clap(long, value_parser, default_value_t = 100)]
+        batch_size: i64,
+
+        /// Overwrites the friendships that don't match their history, without it nothing is changed
+        #[clap(long, action)]
+        apply: bool,
+    },
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -35,11 +68,35 @@ pub struct ServerConfig {
 pub struct RpcServerConfig {
     pub port: u16,
     pub ping_interval_seconds: u64,
+    /// How long a transport can stay connected without authenticating
+    pub auth_grace_period_seconds: u64,
+    /// How often the token of an authenticated transport is checked again, so it's closed once the
+    /// token is revoked, logged out or expired, 0 to not check it
+    pub auth_recheck_interval_seconds: u64,
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Synapse {
     pub url: String,
+    pub connect_timeout_ms: u64,
+    pub request_timeout_ms: u64,
+    /// Retries of idempotent requests after a connection or server error
+    pub max_retries: u32,
+    pub retry_base_delay_ms: u64,
+    /// Consecutive failures after which requests to Synapse fail fast
+    pub circuit_breaker_failure_threshold: u32,
+    pub circuit_breaker_open_seconds: u64,
+    pub room_cleanup: RoomCleanupPolicy,
+}
+
+/// What to do with the Synapse room of a friendship after it's deleted or rejected.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RoomCleanupPolicy {
+    /// The room is kept, so it can be used again if the users become friends later
+    Keep,
+    /// The room is left and forgotten by both users, and removed from their direct chats
+    LeaveAndForget,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -47,6 +104,25 @@ pub struct RedisConfig {
     pub host: String,
 }
 
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProfilesConfig {
+    /// Catalyst lambdas URL used to enrich friend lists, empty to disable the enrichment
+    pub url: String,
+}
+
+/// Backend used to publish and listen to the friendship events.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EventsChannelBackend {
+    /// Redis Pub/Sub
+    Redis,
+    /// Postgres LISTEN/NOTIFY, so the events don't go through Redis. The REST API still needs
+    /// Redis for the users cache and the rate limits.
+    Postgres,
+    /// Tokio broadcast channel, only valid when a single node is running
+    InProcess,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Database {
     pub host: String,
@@ -63,14 +139,70 @@ pub struct Config {
     pub db: Database,
     pub env: String, // prd / stg / dev / biz
     pub wkc_metrics_bearer_token: String,
+    pub admin_bearer_token: String,
     pub redis: RedisConfig,
     pub cache_hashing_key: String,
     pub friends_stream_page_size: u16,
+    pub profiles: ProfilesConfig,
+    pub profiles_cache_ttl_seconds: u64,
+    /// How long an address without a profile is cached, so it isn't requested on every page
+    pub profiles_missing_cache_ttl_seconds: u64,
+    /// Whether to cache the friends of each user in Redis
+    pub friends_cache_enabled: bool,
+    pub friends_cache_ttl_seconds: u64,
+    /// Entries of the in-process tier of the users cache, 0 to only cache the users in Redis
+    pub users_cache_local_capacity: usize,
+    pub users_cache_local_ttl_seconds: u64,
+    /// How long a token rejected by Synapse is rejected without asking it again, 0 to disable it
+    pub users_cache_negative_ttl_seconds: u64,
+    /// Comma separated `id:secret` keys to sign the session tokens with, the first one signs the
+    /// new tokens. Empty to not issue session tokens
+    pub session_signing_keys: String,
+    pub session_access_ttl_seconds: u64,
+    pub session_refresh_ttl_seconds: u64,
+    /// Comma separated `service:key` entries of the backends allowed to call the internal API
+    pub service_keys: String,
+    pub service_requests_per_minute: u64,
+    /// Comma separated URLs the friendship events are posted to, empty to disable the webhooks
+    pub webhooks_urls: String,
+    /// Key of the HMAC signature sent along with each webhook
+    pub webhooks_signing_key: String,
+    /// Comma separated friendship events posted to the webhooks, e.g. `accept,delete`
+    pub webhooks_events: String,
+    pub webhooks_max_attempts: u32,
+    pub webhooks_retry_backoff_ms: u64,
+    /// How often the pending deliveries left behind by a stopped instance are resumed, 0 to not
+    /// resume them
+    pub webhooks_sweep_interval_seconds: u64,
+    /// URL of the service the notifications for offline users are pushed to, empty to disable them
+    pub push_notifications_url: String,
+    /// Bearer token of the push notifications service, empty if it needs none
+    pub push_notifications_token: String,
+    /// Wait for an instance to deliver the event to a live subscription before pushing it
+    pub push_notifications_grace_ms: u64,
+    /// Maximum number of friends of each user, 0 for no limit
+    pub friendships_max_friends: u64,
+    /// Maximum number of requests of each user waiting for an answer, 0 for no limit
+    pub friendships_max_outgoing_requests: u64,
+    /// Backend of the friendship events channel, see [`EventsChannelBackend`]
+    pub events_channel: EventsChannelBackend,
+    /// Token of the Synapse account used to sync friendship events created directly in Synapse,
+    /// empty to disable the sync listener
+    pub synapse_sync_access_token: String,
+    /// Synapse user id of the sync account, e.g. `@friendships:decentraland.org`. It's invited to the
+    /// rooms created for new friendships, so the sync listener gets their events
+    pub synapse_sync_user_id: String,
+    /// How long each `/sync` request waits for new events
+    pub synapse_sync_timeout_ms: u64,
+    /// Token of a Synapse admin used to clean up the rooms on behalf of the users whose token
+    /// isn't at hand, empty to only clean them up for the user that ended the friendship
+    pub synapse_admin_access_token: String,
 }
 
 const SYNAPSE_URL_ENV: &str = "SYNAPSE_URL";
 const ENV_VAR: &str = "ENV";
 const METRICS_TOKEN: &str = "WKC_METRICS_BEARER_TOKEN";
+const ADMIN_TOKEN: &str = "ADMIN_BEARER_TOKEN";
 const DB_HOST: &str = "DB_HOST";
 const DB_USER: &str = "DB_USER";
 const DB_PWD: &str = "DB_PASSWORD";
@@ -82,6 +214,46 @@ const CACHE_HASHING_KEY: &str = "CACHE_HASHING_KEY";
 
 const FRIENDS_STREAM_PAGE_SIZE: &str = "FRIENDS_STREAM_PAGE_SIZE";
 
+const PROFILES_URL: &str = "PROFILES_URL";
+const PROFILES_CACHE_TTL_SECONDS: &str = "PROFILES_CACHE_TTL_SECONDS";
+const PROFILES_MISSING_CACHE_TTL_SECONDS: &str = "PROFILES_MISSING_CACHE_TTL_SECONDS";
+
+const FRIENDS_CACHE_ENABLED: &str = "FRIENDS_CACHE_ENABLED";
+const FRIENDS_CACHE_TTL_SECONDS: &str = "FRIENDS_CACHE_TTL_SECONDS";
+
+const USERS_CACHE_LOCAL_CAPACITY: &str = "USERS_CACHE_LOCAL_CAPACITY";
+const USERS_CACHE_LOCAL_TTL_SECONDS: &str = "USERS_CACHE_LOCAL_TTL_SECONDS";
+const USERS_CACHE_NEGATIVE_TTL_SECONDS: &str = "USERS_CACHE_NEGATIVE_TTL_SECONDS";
+
+const SESSION_SIGNING_KEYS: &str = "SESSION_SIGNING_KEYS";
+const SESSION_ACCESS_TTL_SECONDS: &str = "SESSION_ACCESS_TTL_SECONDS";
+const SESSION_REFRESH_TTL_SECONDS: &str = "SESSION_REFRESH_TTL_SECONDS";
+
+const SERVICE_KEYS: &str = "SERVICE_KEYS";
+const SERVICE_REQUESTS_PER_MINUTE: &str = "SERVICE_REQUESTS_PER_MINUTE";
+
+const WEBHOOKS_URLS: &str = "WEBHOOKS_URLS";
+const WEBHOOKS_SIGNING_KEY: &str = "WEBHOOKS_SIGNING_KEY";
+const WEBHOOKS_EVENTS: &str = "WEBHOOKS_EVENTS";
+const WEBHOOKS_MAX_ATTEMPTS: &str = "WEBHOOKS_MAX_ATTEMPTS";
+const WEBHOOKS_RETRY_BACKOFF_MS: &str = "WEBHOOKS_RETRY_BACKOFF_MS";
+const WEBHOOKS_SWEEP_INTERVAL_SECONDS: &str = "WEBHOOKS_SWEEP_INTERVAL_SECONDS";
+
+const PUSH_NOTIFICATIONS_URL: &str = "PUSH_NOTIFICATIONS_URL";
+const PUSH_NOTIFICATIONS_TOKEN: &str = "PUSH_NOTIFICATIONS_TOKEN";
+const PUSH_NOTIFICATIONS_GRACE_MS: &str = "PUSH_NOTIFICATIONS_GRACE_MS";
+
+const FRIENDSHIPS_MAX_FRIENDS: &str = "FRIENDSHIPS_MAX_FRIENDS";
+const FRIENDSHIPS_MAX_OUTGOING_REQUESTS: &str = "FRIENDSHIPS_MAX_OUTGOING_REQUESTS";
+
+const EVENTS_CHANNEL: &str = "EVENTS_CHANNEL";
+
+const SYNAPSE_SYNC_ACCESS_TOKEN: &str = "SYNAPSE_SYNC_ACCESS_TOKEN";
+const SYNAPSE_SYNC_USER_ID: &str = "SYNAPSE_SYNC_USER_ID";
+const SYNAPSE_SYNC_TIMEOUT_MS: &str = "SYNAPSE_SYNC_TIMEOUT_MS";
+
+const SYNAPSE_ADMIN_ACCESS_TOKEN: &str = "SYNAPSE_ADMIN_ACCESS_TOKEN";
+
 impl Config {
     pub fn new() -> Result<Self, ConfigError> {
         let args = Args::parse();
@@ -98,6 +270,7 @@ impl Config {
                     .with_list_parse_key(DB_NAME)
                     .with_list_parse_key(REDIS_HOST)
                     .with_list_parse_key(FRIENDS_STREAM_PAGE_SIZE)
+                    .with_list_parse_key(PROFILES_URL)
                     .try_parsing(true)
                     .separator("_"),
             )
@@ -105,7 +278,36 @@ impl Config {
                 config::Environment::default()
                     .with_list_parse_key(CACHE_HASHING_KEY)
                     .with_list_parse_key(METRICS_TOKEN)
+                    .with_list_parse_key(ADMIN_TOKEN)
                     .with_list_parse_key(ENV_VAR)
+                    .with_list_parse_key(PROFILES_CACHE_TTL_SECONDS)
+                    .with_list_parse_key(PROFILES_MISSING_CACHE_TTL_SECONDS)
+                    .with_list_parse_key(FRIENDS_CACHE_ENABLED)
+                    .with_list_parse_key(FRIENDS_CACHE_TTL_SECONDS)
+                    .with_list_parse_key(USERS_CACHE_LOCAL_CAPACITY)
+                    .with_list_parse_key(USERS_CACHE_LOCAL_TTL_SECONDS)
+                    .with_list_parse_key(USERS_CACHE_NEGATIVE_TTL_SECONDS)
+                    .with_list_parse_key(SESSION_SIGNING_KEYS)
+                    .with_list_parse_key(SESSION_ACCESS_TTL_SECONDS)
+                    .with_list_parse_key(SESSION_REFRESH_TTL_SECONDS)
+                    .with_list_parse_key(SERVICE_KEYS)
+                    .with_list_parse_key(SERVICE_REQUESTS_PER_MINUTE)
+                    .with_list_parse_key(WEBHOOKS_URLS)
+                    .with_list_parse_key(WEBHOOKS_SIGNING_KEY)
+                    .with_list_parse_key(WEBHOOKS_EVENTS)
+                    .with_list_parse_key(WEBHOOKS_MAX_ATTEMPTS)
+                    .with_list_parse_key(WEBHOOKS_RETRY_BACKOFF_MS)
+                    .with_list_parse_key(WEBHOOKS_SWEEP_INTERVAL_SECONDS)
+                    .with_list_parse_key(PUSH_NOTIFICATIONS_URL)
+                    .with_list_parse_key(PUSH_NOTIFICATIONS_TOKEN)
+                    .with_list_parse_key(PUSH_NOTIFICATIONS_GRACE_MS)
+                    .with_list_parse_key(FRIENDSHIPS_MAX_FRIENDS)
+                    .with_list_parse_key(FRIENDSHIPS_MAX_OUTGOING_REQUESTS)
+                    .with_list_parse_key(EVENTS_CHANNEL)
+                    .with_list_parse_key(SYNAPSE_SYNC_ACCESS_TOKEN)
+                    .with_list_parse_key(SYNAPSE_SYNC_USER_ID)
+                    .with_list_parse_key(SYNAPSE_SYNC_TIMEOUT_MS)
+                    .with_list_parse_key(SYNAPSE_ADMIN_ACCESS_TOKEN)
                     .try_parsing(true),
             )
             .set_override_option("server.port", args.port)?
@@ -115,9 +317,19 @@ impl Config {
                 args.rpc_ping_interval_seconds,
             )?
             .set_default("rpc_server.ping_interval_seconds", 30)?
+            .set_default("rpc_server.auth_grace_period_seconds", 30)?
+            .set_default("rpc_server.auth_recheck_interval_seconds", 60)?
             .set_default("synapse.url", "https://synapse.decentraland.zone")?
+            .set_default("synapse.connect_timeout_ms", 2000)?
+            .set_default("synapse.request_timeout_ms", 10000)?
+            .set_default("synapse.max_retries", 2)?
+            .set_default("synapse.retry_base_delay_ms", 100)?
+            .set_default("synapse.circuit_breaker_failure_threshold", 5)?
+            .set_default("synapse.circuit_breaker_open_seconds", 30)?
+            .set_default("synapse.room_cleanup", "leave_and_forget")?
             .set_default("env", "dev")?
             .set_default("wkc_metrics_bearer_token", "")?
+            .set_default("admin_bearer_token", "")?
             .set_default("db.host", "0.0.0.0:3500")? // docker-compose -> local env
             .set_default("db.user", "postgres")? // docker-compose -> local env
             .set_default("db.password", "postgres")? // docker-compose -> local env
@@ -125,6 +337,35 @@ impl Config {
             .set_default("redis.host", "0.0.0.0")? // docker-compose -> local env
             .set_default("cache_hashing_key", "test_key")? // docker-compose -> local env
             .set_default("friends_stream_page_size", 20)?
+            .set_default("profiles.url", "")?
+            .set_default("profiles_cache_ttl_seconds", 3600)?
+            .set_default("profiles_missing_cache_ttl_seconds", 60)?
+            .set_default("friends_cache_enabled", false)?
+            .set_default("friends_cache_ttl_seconds", 3600)?
+            .set_default("users_cache_local_capacity", 10000)?
+            .set_default("users_cache_local_ttl_seconds", 60)?
+            .set_default("users_cache_negative_ttl_seconds", 30)?
+            .set_default("session_signing_keys", "")?
+            .set_default("session_access_ttl_seconds", 900)?
+            .set_default("session_refresh_ttl_seconds", 2592000)?
+            .set_default("service_keys", "")?
+            .set_default("service_requests_per_minute", 6000)?
+            .set_default("webhooks_urls", "")?
+            .set_default("webhooks_signing_key", "")?
+            .set_default("webhooks_events", "accept,delete")?
+            .set_default("webhooks_max_attempts", 5)?
+            .set_default("webhooks_retry_backoff_ms", 1000)?
+            .set_default("webhooks_sweep_interval_seconds", 60)?
+            .set_default("push_notifications_url", "")?
+            .set_default("push_notifications_token", "")?
+            .set_default("push_notifications_grace_ms", 2000)?
+            .set_default("friendships_max_friends", 1000)?
+            .set_default("friendships_max_outgoing_requests", 100)?
+            .set_default("events_channel", "redis")?
+            .set_default("synapse_sync_access_token", "")?
+            .set_default("synapse_sync_user_id", "")?
+            .set_default("synapse_sync_timeout_ms", 30000)?
+            .set_default("synapse_admin_access_token", "")?
             .build()?;
 
         config.try_deserialize()
@@ -5,7 +5,6 @@ use chrono::DateTime;
 
 use futures_util::stream::BoxStream;
 use log::LevelFilter;
-use mockall::automock;
 use sqlx::{
     postgres::{PgArguments, PgConnectOptions, PgPoolOptions, PgQueryResult, PgRow},
     query::Query,
@@ -16,29 +15,81 @@ use super::configuration::Database as DatabaseConfig;
 use super::health::Healthy;
 
 use crate::entities::{
-    friendship_history::FriendshipHistoryRepository, friendships::FriendshipsRepository,
-    user_features::UserFeaturesRepository,
+    friendship_history::{FriendshipHistoryRepository, FriendshipHistoryRepositoryImplementation},
+    friendships::{FriendshipRepositoryImplementation, FriendshipsRepository},
+    in_memory::{InMemoryStore, InMemoryTransaction},
+    sync_state::{SyncStateRepository, SyncStateRepositoryImplementation},
+    user_features::{UserFeaturesRepository, UserFeaturesRepositoryImplementation},
+    webhook_deliveries::{WebhookDeliveriesRepository, WebhookDeliveriesRepositoryImplementation},
 };
 
 pub type DBConnection = Pool<Postgres>;
 
 #[derive(Clone)]
 pub struct DBRepositories {
-    pub friendships: FriendshipsRepository,
-    pub friendship_history: FriendshipHistoryRepository,
-    pub user_features: UserFeaturesRepository,
+    pub friendships: Arc<dyn FriendshipRepositoryImplementation>,
+    pub friendship_history: Arc<dyn FriendshipHistoryRepositoryImplementation>,
+    pub user_features: Arc<dyn UserFeaturesRepositoryImplementation>,
+    pub sync_state: Arc<dyn SyncStateRepositoryImplementation>,
+    pub webhook_deliveries: Arc<dyn WebhookDeliveriesRepositoryImplementation>,
 }
 
 impl DBRepositories {
     pub fn new(
-        friendships: FriendshipsRepository,
-        friendship_history: FriendshipHistoryRepository,
-        user_features: UserFeaturesRepository,
+        friendships: Arc<dyn FriendshipRepositoryImplementation>,
+        friendship_history: Arc<dyn FriendshipHistoryRepositoryImplementation>,
+        user_features: Arc<dyn UserFeaturesRepositoryImplementation>,
+        sync_state: Arc<dyn SyncStateRepositoryImplementation>,
+        webhook_deliveries: Arc<dyn WebhookDeliveriesRepositoryImplementation>,
     ) -> Self {
         Self {
             friendships,
             friendship_history,
             user_features,
+            sync_state,
+            webhook_deliveries,
+        }
+    }
+
+    /// Repositories sharing a new empty in-memory store, no database needed.
+    ///
+    /// They only accept the transactions started on their own store, use an
+    /// [`InMemoryDatabaseComponent`] to also start them.
+    pub fn in_memory() -> Self {
+        Self::from_store(&InMemoryStore::default())
+    }
+
+    fn from_store(store: &InMemoryStore) -> Self {
+        Self::new(
+            Arc::new(store.friendships()),
+            Arc::new(store.friendship_history()),
+            Arc::new(store.user_features()),
+            Arc::new(store.sync_state()),
+            Arc::new(store.webhook_deliveries()),
+        )
+    }
+}
+
+/// Groups the changes done through several repositories, so they're committed or discarded together.
+pub enum DatabaseTransaction {
+    Postgres(Transaction<'static, Postgres>),
+    /// The in-memory repositories stage the changes in the transaction until it's committed
+    InMemory(InMemoryTransaction),
+}
+
+impl DatabaseTransaction {
+    pub async fn commit(self) -> Result<(), Error> {
+        match self {
+            Self::Postgres(transaction) => transaction.commit().await,
+            Self::InMemory(transaction) => transaction.commit(),
+        }
+    }
+
+    pub async fn rollback(self) -> Result<(), Error> {
+        match self {
+            Self::Postgres(transaction) => transaction.rollback().await,
+            // Dropping the staged changes and the locks is enough
+            Self::InMemory(_) => Ok(()),
         }
     }
 }
@@ -48,6 +99,35 @@ pub enum Executor<'a> {
     Pool(Pool<Postgres>),
 }
 
+impl Executor<'static> {
+    /// Runs on the given transaction, or on the pool if there is none.
+    ///
+    /// An in-memory transaction can't be run on Postgres, so it's handed back with the error to be
+    /// rolled back instead of silently running outside of it.
+    pub fn from_transaction(
+        transaction: Option<DatabaseTransaction>,
+        db_connection: &Arc<Option<DBConnection>>,
+    ) -> Result<Self, (Error, DatabaseTransaction)> {
+        match transaction {
+            Some(DatabaseTransaction::Postgres(transaction)) => {
+                Ok(Executor::Transaction(transaction))
+            }
+            Some(transaction @ DatabaseTransaction::InMemory(_)) => Err((
+                Error::Protocol(
+                    "an in-memory transaction can't be used by a Postgres repository".to_string(),
+                ),
+                transaction,
+            )),
+            None => Ok(Self::from_pool(db_connection)),
+        }
+    }
+
+    pub fn from_pool(db_connection: &Arc<Option<DBConnection>>) -> Self {
+        // choose to Clone because it's cheap and the pool use an Arc internally
+        Executor::Pool(DatabaseComponent::get_connection(db_connection).clone())
+    }
+}
+
 #[derive(Clone)]
 pub struct DatabaseComponent {
     db_host: String,
@@ -125,22 +205,27 @@ impl DatabaseComponent {
 }
 
 #[async_trait]
-pub trait DatabaseComponentImplementation {
+pub trait DatabaseComponentImplementation: Healthy + Send + Sync {
     fn get_repos(&self) -> &Option<DBRepositories>;
+    /// Returns the connections pool, `None` until the component runs or if there's no Postgres.
+    fn get_pool(&self) -> Option<DBConnection>;
     async fn run(&mut self) -> Result<(), sqlx::Error>;
     fn is_connected(&self) -> bool;
-    async fn start_transaction(&self) -> Result<Transaction<'static, Postgres>, Error>;
+    async fn start_transaction(&self) -> Result<DatabaseTransaction, Error>;
 
     async fn close(&self);
 }
 
-#[automock]
 #[async_trait]
 impl DatabaseComponentImplementation for DatabaseComponent {
     fn get_repos(&self) -> &Option<DBRepositories> {
         &self.db_repos
     }
 
+    fn get_pool(&self) -> Option<DBConnection> {
+        self.db_connection.as_ref().clone()
+    }
+
     async fn run(&mut self) -> Result<(), sqlx::Error> {
         if !self.is_connected() {
             let url = format!(
@@ -176,9 +261,11 @@ impl DatabaseComponentImplementation for DatabaseComponent {
 
             self.db_connection = Arc::new(Some(db_connection));
             self.db_repos = Some(DBRepositories::new(
-                FriendshipsRepository::new(self.db_connection.clone()),
-                FriendshipHistoryRepository::new(self.db_connection.clone()),
-                UserFeaturesRepository::new(self.db_connection.clone()),
+                Arc::new(FriendshipsRepository::new(self.db_connection.clone())),
+                Arc::new(FriendshipHistoryRepository::new(self.db_connection.clone())),
+                Arc::new(UserFeaturesRepository::new(self.db_connection.clone())),
+                Arc::new(SyncStateRepository::new(self.db_connection.clone())),
+                Arc::new(WebhookDeliveriesRepository::new(self.db_connection.clone())),
             ));
 
             Ok(())
@@ -198,10 +285,13 @@ impl DatabaseComponentImplementation for DatabaseComponent {
         }
     }
 
-    async fn start_transaction(&self) -> Result<Transaction<'static, Postgres>, Error> {
+    async fn start_transaction(&self) -> Result<DatabaseTransaction, Error> {
         let db_connection = self.db_connection.as_ref().as_ref().unwrap();
 
-        db_connection.begin().await
+        db_connection
+            .begin()
+            .await
+            .map(DatabaseTransaction::Postgres)
     }
 }
 
@@ -219,3 +309,58 @@ impl Healthy for DatabaseComponent {
         }
     }
 }
+
+/// A component backed by in-memory repositories, meant for tests and trying the service out.
+#[derive(Clone)]
+pub struct InMemoryDatabaseComponent {
+    store: InMemoryStore,
+    db_repos: Option<DBRepositories>,
+}
+
+impl Default for InMemoryDatabaseComponent {
+    fn default() -> Self {
+        let store = InMemoryStore::default();
+        let db_repos = Some(DBRepositories::from_store(&store));
+
+        Self { store, db_repos }
+    }
+}
+
+#[async_trait]
+impl DatabaseComponentImplementation for InMemoryDatabaseComponent {
+    fn get_repos(&self) -> &Option<DBRepositories> {
+        &self.db_repos
+    }
+
+    fn get_pool(&self) -> Option<DBConnection> {
+        None
+    }
+
+    async fn run(&mut self) -> Result<(), sqlx::Error> {
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    async fn start_transaction(&self) -> Result<DatabaseTransaction, Error> {
+        Ok(DatabaseTransaction::InMemory(self.store.begin()))
+    }
+
+    async fn close(&self) {}
+}
+
+#[async_trait]
+impl Healthy for Arc<dyn DatabaseComponentImplementation> {
+    async fn is_healthy(&self) -> bool {
+        self.as_ref().is_healthy().await
+    }
+}
+
+#[async_trait]
+impl Healthy for InMemoryDatabaseComponent {
+    async fn is_healthy(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,366 @@
+use deadpool_redis::{
+    redis::{cmd, pipe, RedisResult},
+    Connection,
+};
+use prometheus::{IntCounterVec, Opts};
+
+use crate::{
+    components::database::DBRepositories,
+    domain::{address::Address, error::CommonError},
+    notifications::Event,
+};
+
+use super::redis::Redis;
+
+const FRIENDS_CACHE_KEY_PREFIX: &str = "friends";
+const FRIENDS_GENERATION_KEY_PREFIX: &str = "friends_generation";
+
+/// Caches the friends only if they weren't invalidated since the generation was read, so a set
+/// loaded from the database before an update can't be cached after the update dropped it.
+///
+/// KEYS: the friends set and its generation. ARGV: the generation read, the TTL and the members.
+const CACHE_FRIENDS_SCRIPT: &str = r#"
+if (redis.call('GET', KEYS[2]) or '0') ~= ARGV[1] then
+    return 0
+end
+redis.call('DEL', KEYS[1])
+redis.call('SADD', KEYS[1], unpack(ARGV, 3))
+redis.call('EXPIRE', KEYS[1], ARGV[2])
+return 1
+"#;
+
+/// Member added to every cached set, so a user without friends can be cached too and a set that
+/// expired in the middle of a `SINTER` can be told apart from an empty intersection.
+const CACHED_SET_MARKER: &str = "*";
+
+const FRIENDS_CACHE_LOOKUPS: (&str, &str) = (
+    "dcl_social_service_friends_cache_lookups_total",
+    "Social Service Friend Sets Cache Lookups",
+);
+const CACHE_HIT: &str = "hit";
+const CACHE_MISS: &str = "miss";
+
+#[derive(Clone)]
+struct FriendsCacheMetrics {
+    lookups_total: IntCounterVec,
+}
+
+impl FriendsCacheMetrics {
+    fn new() -> Self {
+        let lookups_total = IntCounterVec::new(
+            Opts::new(FRIENDS_CACHE_LOOKUPS.0, FRIENDS_CACHE_LOOKUPS.1),
+            &["result"],
+        )
+        .expect("Metrics definition is correct, so the friends cache lookups metric should be created successfully");
+
+        Self { lookups_total }
+    }
+
+    fn record_lookup(&self, hit: bool) {
+        let result = if hit { CACHE_HIT } else { CACHE_MISS };
+        self.lookups_total.with_label_values(&[result]).inc();
+    }
+}
+
+/// Read-through cache of the active friends of each user, kept as a Redis set so the mutual
+/// friends are a `SINTER`.
+///
+/// The sets are dropped whenever a friendship event is published for one of their users, the TTL
+/// only bounds the staleness left by updates that don't go through this service. Dropping a set
+/// also bumps the generation of the user, and a set is only cached if the generation didn't
+/// change while it was read from the database. Any error on Redis is logged and the friends are
+/// read from the database.
+#[derive(Clone)]
+pub struct FriendsCacheComponent {
+    redis: Redis,
+    ttl_seconds: u64,
+    metrics: FriendsCacheMetrics,
+}
+
+impl std::fmt::Debug for FriendsCacheComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FriendsCacheComponent")
+            .field("redis", &self.redis)
+            .field("ttl_seconds", &self.ttl_seconds)
+            .finish()
+    }
+}
+
+impl FriendsCacheComponent {
+    pub fn new(redis: Redis, ttl_seconds: u64) -> Self {
+        Self {
+            redis,
+            ttl_seconds,
+            metrics: FriendsCacheMetrics::new(),
+        }
+    }
+
+    /// Collectors to expose the cache hits and misses along with the rest of the metrics
+    pub fn metrics_collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![Box::new(self.metrics.lookups_total.clone())]
+    }
+
+    /// Returns the addresses of the active friends of the user.
+    #[tracing::instrument(name = "get friends > Friends cache component", skip(self, repos))]
+    pub async fn get_friends(
+        &self,
+        address: &Address,
+        repos: &DBRepositories,
+    ) -> Result<Vec<String>, CommonError> {
+        let Some(mut connection) = self.redis.get_async_connection().await else {
+            return load_friends(address, repos).await;
+        };
+
+        let cached: RedisResult<(Vec<String>, Option<u64>)> = pipe()
+            .cmd("SMEMBERS")
+            .arg(friends_cache_key(address))
+            .cmd("GET")
+            .arg(friends_generation_key(address))
+            .query_async(&mut connection)
+            .await;
+
+        match cached {
+            // A cached set always has the marker, an empty one means it's not cached
+            Ok((cached, _)) if !cached.is_empty() => {
+                self.metrics.record_lookup(true);
+                Ok(without_marker(cached))
+            }
+            Ok((_, generation)) => {
+                self.metrics.record_lookup(false);
+                let friends = load_friends(address, repos).await?;
+                self.cache_friends(&mut connection, address, &friends, generation)
+                    .await;
+                Ok(friends)
+            }
+            Err(err) => {
+                log::warn!("[Friends Cache] Couldn't read the friends of {address}: {err}");
+                load_friends(address, repos).await
+            }
+        }
+    }
+
+    /// Returns the addresses of the users that are friends of both users, caching their friends first
+    /// when needed.
+    #[tracing::instrument(
+        name = "get mutual friends > Friends cache component",
+        skip(self, repos)
+    )]
+    pub async fn get_mutual_friends(
+        &self,
+        address_1: &Address,
+        address_2: &Address,
+        repos: &DBRepositories,
+    ) -> Result<Vec<String>, CommonError> {
+        let Some(mut connection) = self.redis.get_async_connection().await else {
+            return load_mutual_friends(address_1, address_2, repos).await;
+        };
+
+        let cached: RedisResult<(bool, bool, Option<u64>, Option<u64>)> = pipe()
+            .cmd("EXISTS")
+            .arg(friends_cache_key(address_1))
+            .cmd("EXISTS")
+            .arg(friends_cache_key(address_2))
+            .cmd("GET")
+            .arg(friends_generation_key(address_1))
+            .cmd("GET")
+            .arg(friends_generation_key(address_2))
+            .query_async(&mut connection)
+            .await;
+
+        let cached = match cached {
+            Ok(cached) => cached,
+            Err(err) => {
+                log::warn!("[Friends Cache] Couldn't check the cached friends: {err}");
+                return load_mutual_friends(address_1, address_2, repos).await;
+            }
+        };
+
+        for (address, is_cached, generation) in [
+            (address_1, cached.0, cached.2),
+            (address_2, cached.1, cached.3),
+        ] {
+            self.metrics.record_lookup(is_cached);
+            if !is_cached {
+                let friends = load_friends(address, repos).await?;
+                self.cache_friends(&mut connection, address, &friends, generation)
+                    .await;
+            }
+        }
+
+        let mutuals: RedisResult<Vec<String>> = cmd("SINTER")
+            .arg(friends_cache_key(address_1))
+            .arg(friends_cache_key(address_2))
+            .query_async(&mut connection)
+            .await;
+
+        match mutuals {
+            Ok(mutuals) if mutuals.iter().any(|member| member == CACHED_SET_MARKER) => {
+                Ok(without_marker(mutuals))
+            }
+            // One of the sets expired or was dropped in the meantime
+            Ok(_) => load_mutual_friends(address_1, address_2, repos).await,
+            Err(err) => {
+                log::warn!("[Friends Cache] Couldn't intersect the cached friends: {err}");
+                load_mutual_friends(address_1, address_2, repos).await
+            }
+        }
+    }
+
+    /// Drops the cached friends of the users, to be called whenever one of their friendships changes.
+    pub async fn invalidate(&self, addresses: &[&Address]) {
+        if addresses.is_empty() {
+            return;
+        }
+
+        let Some(mut connection) = self.redis.get_async_connection().await else {
+            return;
+        };
+
+        let keys: Vec<String> = addresses
+            .iter()
+            .map(|address| friends_cache_key(address))
+            .collect();
+        let mut pipeline = pipe();
+        pipeline.atomic().cmd("DEL").arg(keys).ignore();
+        for address in addresses {
+            let generation_key = friends_generation_key(address);
+            pipeline
+                .cmd("INCR")
+                .arg(&generation_key)
+                .ignore()
+                .cmd("EXPIRE")
+                .arg(&generation_key)
+                .arg(self.ttl_seconds)
+                .ignore();
+        }
+
+        let result: RedisResult<()> = pipeline.query_async(&mut connection).await;
+        if let Err(err) = result {
+            log::warn!("[Friends Cache] Couldn't drop the cached friends: {err}");
+        }
+    }
+
+    /// Drops the cached friends of both users of a friendship event.
+    pub async fn invalidate_event(&self, event: &Event) {
+        let addresses: Vec<Address> = [&event.from, &event.to]
+            .into_iter()
+            .filter_map(|address| Address::parse(address).ok())
+            .collect();
+
+        self.invalidate(&addresses.iter().collect::<Vec<_>>()).await;
+    }
+
+    /// Caches the friends unless the generation changed since it was read, `None` if it wasn't set.
+    async fn cache_friends(
+        &self,
+        connection: &mut Connection,
+        address: &Address,
+        friends: &[String],
+        generation: Option<u64>,
+    ) {
+        let result: RedisResult<bool> = cmd("EVAL")
+            .arg(CACHE_FRIENDS_SCRIPT)
+            .arg(2)
+            .arg(friends_cache_key(address))
+            .arg(friends_generation_key(address))
+            .arg(generation.unwrap_or(0))
+            .arg(self.ttl_seconds)
+            .arg(CACHED_SET_MARKER)
+            .arg(friends)
+            .query_async(connection)
+            .await;
+
+        match result {
+            Ok(true) => {}
+            Ok(false) => log::debug!(
+                "[Friends Cache] The friends of {address} changed while being read, they aren't cached"
+            ),
+            Err(err) => {
+                log::warn!("[Friends Cache] Couldn't cache the friends of {address}: {err}")
+            }
+        }
+    }
+}
+
+/// Reads the active friends of the user from the database, skipping the cache.
+pub(crate) async fn load_friends(
+    address: &Address,
+    repos: &DBRepositories,
+) -> Result<Vec<String>, CommonError> {
+    let (friendships, _) = repos
+        .friendships
+        .get_user_friends(address, true, None, None)
+        .await;
+
+    let friendships = friendships.map_err(|err| {
+        log::error!("[Friends Cache] Couldn't fetch the friends of {address}: {err}");
+        CommonError::Unknown("".to_owned())
+    })?;
+
+    Ok(friendships
+        .into_iter()
+        .map(|friendship| {
+            if friendship.address_1 == address.as_str() {
+                friendship.address_2
+            } else {
+                friendship.address_1
+            }
+        })
+        .collect())
+}
+
+/// Reads the mutual friends of both users from the database, skipping the cache.
+pub(crate) async fn load_mutual_friends(
+    address_1: &Address,
+    address_2: &Address,
+    repos: &DBRepositories,
+) -> Result<Vec<String>, CommonError> {
+    let (mutuals, _) = repos
+        .friendships
+        .get_mutual_friends(address_1, address_2, None)
+        .await;
+
+    mutuals.map_err(|err| {
+        log::error!("[Friends Cache] Couldn't fetch the mutual friends: {err}");
+        CommonError::Unknown("".to_owned())
+    })
+}
+
+fn without_marker(members: Vec<String>) -> Vec<String> {
+    members
+        .into_iter()
+        .filter(|member| member != CACHED_SET_MARKER)
+        .collect()
+}
+
+fn friends_cache_key(address: &Address) -> String {
+    format!("{FRIENDS_CACHE_KEY_PREFIX}:{address}")
+}
+
+fn friends_generation_key(address: &Address) -> String {
+    format!("{FRIENDS_GENERATION_KEY_PREFIX}:{address}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::address::Address;
+
+    use super::{friends_cache_key, without_marker, CACHED_SET_MARKER};
+
+    #[test]
+    fn test_friends_cache_key() {
+        let address = Address::parse("0xAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA").unwrap();
+
+        assert_eq!(
+            friends_cache_key(&address),
+            "friends:0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+        );
+    }
+
+    #[test]
+    fn test_without_marker() {
+        let members = vec![CACHED_SET_MARKER.to_string(), "0xaa".to_string()];
+
+        assert_eq!(without_marker(members), vec!["0xaa".to_string()]);
+    }
+}
@@ -0,0 +1,177 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{Duration, Instant},
+};
+
+struct LruEntry<V> {
+    value: V,
+    expires_at: Instant,
+    /// Tick of the last time the entry was used, its key in the recency index
+    last_used: u64,
+}
+
+/// Bounded in-process cache whose entries expire after a TTL. Once full, the least recently used
+/// entry is evicted to make room for a new one.
+///
+/// It's not synchronized, the owner is expected to wrap it in a lock that isn't held across awaits.
+pub struct LruCache<V> {
+    capacity: usize,
+    entries: HashMap<String, LruEntry<V>>,
+    /// Keys by the tick of their last use, the first one is the least recently used
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl<V: Clone> LruCache<V> {
+    /// A cache with no capacity doesn't store anything.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: HashMap::new(),
+            recency: BTreeMap::new(),
+            tick: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<V> {
+        let is_expired = self.entries.get(key)?.expires_at <= Instant::now();
+        if is_expired {
+            self.remove(key);
+            return None;
+        }
+
+        let tick = self.next_tick();
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.last_used);
+        self.recency.insert(tick, key.to_string());
+        entry.last_used = tick;
+
+        Some(entry.value.clone())
+    }
+
+    pub fn insert(&mut self, key: String, value: V, ttl: Duration) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        self.remove(&key);
+        while self.entries.len() >= self.capacity {
+            let Some(least_recently_used) = self.recency.keys().next().copied() else {
+                break;
+            };
+            if let Some(evicted) = self.recency.remove(&least_recently_used) {
+                self.entries.remove(&evicted);
+            }
+        }
+
+        let tick = self.next_tick();
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            LruEntry {
+                value,
+                expires_at: Instant::now() + ttl,
+                last_used: tick,
+            },
+        );
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.last_used);
+        Some(entry.value)
+    }
+
+    /// Removes the entries whose value matches, returns how many of them were removed.
+    pub fn remove_matching(&mut self, matches: impl Fn(&V) -> bool) -> usize {
+        let keys: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| matches(&entry.value))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in &keys {
+            self.remove(key);
+        }
+        keys.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::LruCache;
+
+    const TTL: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn test_evicts_the_least_recently_used_entry() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a".to_string(), 1, TTL);
+        cache.insert("b".to_string(), 2, TTL);
+
+        // `a` is used, so `b` is the least recently used one
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c".to_string(), 3, TTL);
+
+        assert_eq!(cache.len(), 2);
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("c"), Some(3));
+    }
+
+    #[test]
+    fn test_expired_entries_are_not_returned() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a".to_string(), 1, Duration::ZERO);
+
+        assert_eq!(cache.get("a"), None);
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_reinserting_a_key_replaces_its_value() {
+        let mut cache = LruCache::new(2);
+        cache.insert("a".to_string(), 1, TTL);
+        cache.insert("a".to_string(), 2, TTL);
+
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get("a"), Some(2));
+        assert_eq!(cache.remove("a"), Some(2));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_removes_the_matching_entries() {
+        let mut cache = LruCache::new(3);
+        cache.insert("a".to_string(), 1, TTL);
+        cache.insert("b".to_string(), 2, TTL);
+        cache.insert("c".to_string(), 1, TTL);
+
+        assert_eq!(cache.remove_matching(|value| *value == 1), 2);
+        assert_eq!(cache.len(), 1);
+        assert_eq!(cache.get("b"), Some(2));
+    }
+
+    #[test]
+    fn test_a_cache_without_capacity_stores_nothing() {
+        let mut cache = LruCache::new(0);
+        cache.insert("a".to_string(), 1, TTL);
+
+        assert!(cache.is_empty());
+    }
+}
@@ -1,9 +1,17 @@
 pub mod app;
+pub mod circuit_breaker;
 pub mod configuration;
 pub mod database;
+pub mod friends_cache;
 pub mod health;
+pub mod lru_cache;
 pub mod notifications;
+pub mod profiles;
+pub mod push_notifications;
 pub mod redis;
+pub mod service_keys;
+pub mod session_tokens;
 pub mod synapse;
 pub mod tracing;
 pub mod users_cache;
+pub mod webhooks;
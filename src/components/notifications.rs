@@ -1,11 +1,16 @@
 use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine as _};
 use deadpool_redis::redis::AsyncCommands;
 use futures_util::{Future, StreamExt as _};
 use log::{debug, error};
 pub use prost::Message as ProtocolMessage;
+use sqlx::postgres::PgListener;
 use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
 
-use crate::components::redis::Redis;
+use crate::components::{
+    configuration::EventsChannelBackend, database::DatabaseComponentImplementation, redis::Redis,
+};
 
 pub trait ChannelSubscriber: Send + Sync {
     fn subscribe<NewPublishment: ProtocolMessage + Default, U: Future<Output = ()> + Send + Sync>(
@@ -123,6 +128,279 @@ impl<Publishment: ProtocolMessage + 'static> ChannelPublisher<Publishment>
     }
 }
 
+/// Payloads of Postgres notifications must be shorter than this, in bytes
+const NOTIFY_PAYLOAD_LIMIT: usize = 8000;
+
+/// Listens to Postgres notifications (LISTEN/NOTIFY) carrying base64-encoded protobuf payloads,
+/// since a NOTIFY payload must be text.
+pub struct PostgresChannelSubscriber {
+    db: Arc<dyn DatabaseComponentImplementation>,
+}
+
+impl PostgresChannelSubscriber {
+    pub(crate) fn new(db: Arc<dyn DatabaseComponentImplementation>) -> Self {
+        Self { db }
+    }
+}
+
+impl ChannelSubscriber for PostgresChannelSubscriber {
+    /// Listens to a specific channel for new notifications
+    fn subscribe<
+        NewPublishment: ProtocolMessage + Default,
+        U: Future<Output = ()> + Send + Sync,
+    >(
+        &self,
+        channel_name: &str,
+        on_update_fn: impl Fn(NewPublishment) -> U + Send + Sync + 'static,
+    ) {
+        let db = self.db.clone();
+        let channel_name = channel_name.to_string();
+        tokio::spawn(async move {
+            debug!("Listening to channel {channel_name}");
+            let Some(pool) = db.get_pool() else {
+                error!("Couldn't listen to channel {channel_name}, the database is not running");
+                return;
+            };
+            let mut listener = match PgListener::connect_with(&pool).await {
+                Ok(listener) => listener,
+                Err(err) => {
+                    error!("Couldn't listen to channel {channel_name}, failed to connect: {err}");
+                    return;
+                }
+            };
+
+            if let Err(err) = listener.listen(&channel_name).await {
+                error!("Couldn't listen to channel {channel_name}: {err}");
+                return;
+            }
+
+            debug!("Listening to channel {channel_name}!");
+
+            loop {
+                // The listener reconnects by itself when the connection is lost
+                match listener.recv().await {
+                    Ok(notification) => match BASE64.decode(notification.payload()) {
+                        Ok(payload) => {
+                            debug!("New notification received from channel");
+                            match NewPublishment::decode(&*payload) {
+                                Ok(update) => {
+                                    debug!("New publishment parsed {update:?}");
+                                    on_update_fn(update).await;
+                                }
+                                Err(_) => error!("Couldn't deserialize update"),
+                            }
+                        }
+                        Err(_) => error!("Couldn't retrieve payload"),
+                    },
+                    Err(err) => error!("Couldn't read a notification from channel: {err}"),
+                }
+            }
+        });
+    }
+}
+
+pub struct PostgresChannelPublisher {
+    db: Arc<dyn DatabaseComponentImplementation>,
+    channel_name: String,
+}
+
+impl PostgresChannelPublisher {
+    pub fn new(db: Arc<dyn DatabaseComponentImplementation>, channel_name: &str) -> Self {
+        Self {
+            db,
+            channel_name: channel_name.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl<Publishment: ProtocolMessage + 'static> ChannelPublisher<Publishment>
+    for PostgresChannelPublisher
+{
+    async fn publish(&self, publishment: Publishment) {
+        let Some(pool) = self.db.get_pool() else {
+            error!("Couldn't publish message, the database is not running");
+            return;
+        };
+
+        debug!("Publish > Encoding message...");
+        let publishment_base64 = BASE64.encode(publishment.encode_to_vec());
+        if publishment_base64.len() >= NOTIFY_PAYLOAD_LIMIT {
+            error!(
+                "Couldn't publish message, its {} bytes are over the notifications limit",
+                publishment_base64.len()
+            );
+            return;
+        }
+
+        debug!("Publish > Notifying...");
+        let result = sqlx::query("SELECT pg_notify($1, $2)")
+            .bind(&self.channel_name)
+            .bind(publishment_base64)
+            .execute(&pool)
+            .await;
+        match result {
+            Ok(_) => debug!("Publish > Done"),
+            Err(e) => error!("Couldn't publish message with error: {e:?}"),
+        }
+    }
+}
+
+const IN_PROCESS_CHANNEL_CAPACITY: usize = 1024;
+
+lazy_static::lazy_static! {
+    /// Channel shared by every in-process publisher and subscriber built from the configuration
+    static ref IN_PROCESS_EVENTS_CHANNEL: InProcessChannel = InProcessChannel::new(IN_PROCESS_CHANNEL_CAPACITY);
+}
+
+/// A message sent through an [`InProcessChannel`], tagged with the channel name it was published to.
+#[derive(Clone, Debug)]
+struct InProcessMessage {
+    channel_name: String,
+    payload: Vec<u8>,
+}
+
+/// Tokio broadcast channel to publish and listen to messages without any external service.
+///
+/// Messages are only delivered to the subscribers of the same process, so it's meant for
+/// single-node deployments and tests.
+#[derive(Clone)]
+pub struct InProcessChannel {
+    sender: broadcast::Sender<InProcessMessage>,
+}
+
+impl InProcessChannel {
+    /// * `capacity` - Messages kept for slow subscribers before they start missing them.
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    pub fn publisher(&self, channel_name: &str) -> InProcessChannelPublisher {
+        InProcessChannelPublisher {
+            channel: self.clone(),
+            channel_name: channel_name.to_string(),
+        }
+    }
+
+    pub fn subscriber(&self) -> InProcessChannelSubscriber {
+        InProcessChannelSubscriber {
+            channel: self.clone(),
+        }
+    }
+}
+
+pub struct InProcessChannelSubscriber {
+    channel: InProcessChannel,
+}
+
+impl ChannelSubscriber for InProcessChannelSubscriber {
+    /// Listens to a specific channel for new messages, the subscription is ready once it returns
+    fn subscribe<
+        NewPublishment: ProtocolMessage + Default,
+        U: Future<Output = ()> + Send + Sync,
+    >(
+        &self,
+        channel_name: &str,
+        on_update_fn: impl Fn(NewPublishment) -> U + Send + Sync + 'static,
+    ) {
+        let mut receiver = self.channel.sender.subscribe();
+        let channel_name = channel_name.to_string();
+        tokio::spawn(async move {
+            debug!("Subscribed to in-process channel {channel_name}!");
+            loop {
+                match receiver.recv().await {
+                    Ok(message) if message.channel_name == channel_name => {
+                        debug!("New message received from channel");
+                        match NewPublishment::decode(&*message.payload) {
+                            Ok(update) => {
+                                debug!("New publishment parsed {update:?}");
+                                on_update_fn(update).await;
+                            }
+                            Err(_) => error!("Couldn't deserialize update"),
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(skipped)) => {
+                        error!("Subscriber lagged behind, {skipped} messages were missed")
+                    }
+                    Err(RecvError::Closed) => {
+                        debug!("In-process channel {channel_name} was closed");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+}
+
+pub struct InProcessChannelPublisher {
+    channel: InProcessChannel,
+    channel_name: String,
+}
+
+#[async_trait]
+impl<Publishment: ProtocolMessage + 'static> ChannelPublisher<Publishment>
+    for InProcessChannelPublisher
+{
+    async fn publish(&self, publishment: Publishment) {
+        let message = InProcessMessage {
+            channel_name: self.channel_name.clone(),
+            payload: publishment.encode_to_vec(),
+        };
+
+        match self.channel.sender.send(message) {
+            Ok(receivers) => debug!("Publish > Done, sent to {receivers} subscribers"),
+            // It only fails when there are no subscribers
+            Err(_) => debug!("Publish > No subscribers for {}", self.channel_name),
+        }
+    }
+}
+
+/// Publisher of the configured events channel backend.
+pub enum EventsChannelPublisher {
+    Redis(RedisChannelPublisher),
+    Postgres(PostgresChannelPublisher),
+    InProcess(InProcessChannelPublisher),
+}
+
+#[async_trait]
+impl<Publishment: ProtocolMessage + 'static> ChannelPublisher<Publishment>
+    for EventsChannelPublisher
+{
+    async fn publish(&self, publishment: Publishment) {
+        match self {
+            Self::Redis(publisher) => publisher.publish(publishment).await,
+            Self::Postgres(publisher) => publisher.publish(publishment).await,
+            Self::InProcess(publisher) => publisher.publish(publishment).await,
+        }
+    }
+}
+
+/// Subscriber of the configured events channel backend.
+pub enum EventsChannelSubscriber {
+    Redis(RedisChannelSubscriber),
+    Postgres(PostgresChannelSubscriber),
+    InProcess(InProcessChannelSubscriber),
+}
+
+impl ChannelSubscriber for EventsChannelSubscriber {
+    fn subscribe<
+        NewPublishment: ProtocolMessage + Default,
+        U: Future<Output = ()> + Send + Sync,
+    >(
+        &self,
+        channel_name: &str,
+        on_update_fn: impl Fn(NewPublishment) -> U + Send + Sync + 'static,
+    ) {
+        match self {
+            Self::Redis(subscriber) => subscriber.subscribe(channel_name, on_update_fn),
+            Self::Postgres(subscriber) => subscriber.subscribe(channel_name, on_update_fn),
+            Self::InProcess(subscriber) => subscriber.subscribe(channel_name, on_update_fn),
+        }
+    }
+}
+
 pub const EVENT_UPDATES_CHANNEL_NAME: &str = "FRIENDSHIP_EVENTS_UPDATES";
 
 pub fn init_events_channel_subscriber(redis: Arc<Redis>) -> RedisChannelSubscriber {
@@ -132,3 +410,59 @@ pub fn init_events_channel_subscriber(redis: Arc<Redis>) -> RedisChannelSubscrib
 pub async fn init_events_channel_publisher(redis: Arc<Redis>) -> RedisChannelPublisher {
     RedisChannelPublisher::new(redis, EVENT_UPDATES_CHANNEL_NAME)
 }
+
+pub fn init_postgres_events_channel_subscriber(
+    db: Arc<dyn DatabaseComponentImplementation>,
+) -> PostgresChannelSubscriber {
+    PostgresChannelSubscriber::new(db)
+}
+
+pub fn init_postgres_events_channel_publisher(
+    db: Arc<dyn DatabaseComponentImplementation>,
+) -> PostgresChannelPublisher {
+    PostgresChannelPublisher::new(db, EVENT_UPDATES_CHANNEL_NAME)
+}
+
+/// Builds the events channel publisher for the configured backend.
+///
+/// * `redis` - Required for the Redis backend.
+pub async fn init_configured_events_channel_publisher(
+    backend: EventsChannelBackend,
+    redis: Option<Arc<Redis>>,
+    db: &Arc<dyn DatabaseComponentImplementation>,
+) -> EventsChannelPublisher {
+    match (backend, redis) {
+        (EventsChannelBackend::Redis, Some(redis)) => {
+            EventsChannelPublisher::Redis(init_events_channel_publisher(redis).await)
+        }
+        (EventsChannelBackend::Redis, None) => panic!("Redis is required for the events channel"),
+        (EventsChannelBackend::Postgres, _) => {
+            EventsChannelPublisher::Postgres(init_postgres_events_channel_publisher(db.clone()))
+        }
+        (EventsChannelBackend::InProcess, _) => EventsChannelPublisher::InProcess(
+            IN_PROCESS_EVENTS_CHANNEL.publisher(EVENT_UPDATES_CHANNEL_NAME),
+        ),
+    }
+}
+
+/// Builds the events channel subscriber for the configured backend.
+///
+/// * `redis` - Required for the Redis backend.
+pub fn init_configured_events_channel_subscriber(
+    backend: EventsChannelBackend,
+    redis: Option<Arc<Redis>>,
+    db: &Arc<dyn DatabaseComponentImplementation>,
+) -> EventsChannelSubscriber {
+    match (backend, redis) {
+        (EventsChannelBackend::Redis, Some(redis)) => {
+            EventsChannelSubscriber::Redis(init_events_channel_subscriber(redis))
+        }
+        (EventsChannelBackend::Redis, None) => panic!("Redis is required for the events channel"),
+        (EventsChannelBackend::Postgres, _) => {
+            EventsChannelSubscriber::Postgres(init_postgres_events_channel_subscriber(db.clone()))
+        }
+        (EventsChannelBackend::InProcess, _) => {
+            EventsChannelSubscriber::InProcess(IN_PROCESS_EVENTS_CHANNEL.subscriber())
+        }
+    }
+}
@@ -0,0 +1,348 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use deadpool_redis::redis::{cmd, pipe, RedisResult};
+use serde::{Deserialize, Serialize};
+
+use crate::domain::{address::Address, error::CommonError};
+
+use super::{lru_cache::LruCache, redis::Redis};
+
+pub const PROFILES_URI: &str = "/lambdas/profiles";
+
+const PROFILE_CACHE_KEY_PREFIX: &str = "profile";
+/// Cached in place of the profile of the addresses the provider has no profile for.
+const MISSING_PROFILE_CACHE_VALUE: &str = "missing";
+const REQUEST_TIMEOUT_SECONDS: u64 = 5;
+/// Profiles kept in-process when there's no Redis
+const LOCAL_CACHE_CAPACITY: usize = 10000;
+
+/// Public profile data used to enrich friend lists.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Profile {
+    pub address: Address,
+    pub name: Option<String>,
+    pub face_image: Option<String>,
+}
+
+/// Source of profiles. Implementations may return fewer profiles than requested,
+/// addresses without a profile are simply left out.
+#[async_trait]
+pub trait ProfileProvider: Send + Sync {
+    async fn get_profiles(&self, addresses: &[Address]) -> Result<Vec<Profile>, CommonError>;
+}
+
+#[derive(Serialize)]
+struct LambdasProfilesRequest<'a> {
+    ids: Vec<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct LambdasProfileResponse {
+    avatars: Vec<LambdasAvatar>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LambdasAvatar {
+    eth_address: String,
+    name: Option<String>,
+    avatar: Option<LambdasAvatarData>,
+}
+
+#[derive(Deserialize)]
+struct LambdasAvatarData {
+    snapshots: Option<LambdasSnapshots>,
+}
+
+#[derive(Deserialize)]
+struct LambdasSnapshots {
+    face256: Option<String>,
+}
+
+/// Fetches profiles from a catalyst through the lambdas `/profiles` endpoint.
+#[derive(Debug, Clone)]
+pub struct LambdasProfileProvider {
+    lambdas_url: String,
+    client: reqwest::Client,
+}
+
+impl LambdasProfileProvider {
+    pub fn new(lambdas_url: String) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECONDS))
+            .build()
+            .expect("to build the profiles http client");
+
+        Self {
+            lambdas_url,
+            client,
+        }
+    }
+}
+
+#[async_trait]
+impl ProfileProvider for LambdasProfileProvider {
+    #[tracing::instrument(name = "get profiles > Lambdas profile provider", skip(self))]
+    async fn get_profiles(&self, addresses: &[Address]) -> Result<Vec<Profile>, CommonError> {
+        let url = format!("{}{}", self.lambdas_url, PROFILES_URI);
+        let body = LambdasProfilesRequest {
+            ids: addresses.iter().map(Address::as_str).collect(),
+        };
+
+        let response = self
+            .client
+            .post(url)
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        let response = match response {
+            Ok(response) => response,
+            Err(err) => {
+                log::warn!("[Profiles] error fetching profiles {}", err);
+                return Err(CommonError::Unknown("".to_owned()));
+            }
+        };
+
+        let profiles = match response.json::<Vec<LambdasProfileResponse>>().await {
+            Ok(profiles) => profiles,
+            Err(err) => {
+                log::warn!("[Profiles] error parsing profiles response {}", err);
+                return Err(CommonError::Unknown("".to_owned()));
+            }
+        };
+
+        Ok(profiles
+            .into_iter()
+            .filter_map(|profile| profile.avatars.into_iter().next())
+            .filter_map(|avatar| {
+                let address = Address::parse(&avatar.eth_address).ok()?;
+                let face_image = avatar
+                    .avatar
+                    .and_then(|data| data.snapshots)
+                    .and_then(|snapshots| snapshots.face256);
+
+                Some(Profile {
+                    address,
+                    name: avatar.name,
+                    face_image,
+                })
+            })
+            .collect())
+    }
+}
+
+/// Read-through cache in front of a `ProfileProvider`.
+///
+/// Enrichment is best effort: any error on Redis or on the provider is logged and the
+/// affected addresses are returned without a profile. Addresses without a profile are cached
+/// for `missing_cache_ttl_seconds` so they aren't requested to the provider on every page.
+/// Without Redis the profiles are cached in-process instead.
+#[derive(Clone)]
+pub struct ProfilesComponent {
+    provider: Arc<dyn ProfileProvider>,
+    redis: Option<Redis>,
+    /// Cache used when there's no Redis, `None` for the addresses without a profile
+    local: Arc<Mutex<LruCache<Option<Profile>>>>,
+    cache_ttl_seconds: u64,
+    missing_cache_ttl_seconds: u64,
+}
+
+impl std::fmt::Debug for ProfilesComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ProfilesComponent")
+            .field("redis", &self.redis)
+            .field("cache_ttl_seconds", &self.cache_ttl_seconds)
+            .field("missing_cache_ttl_seconds", &self.missing_cache_ttl_seconds)
+            .finish()
+    }
+}
+
+impl ProfilesComponent {
+    pub fn new(
+        provider: Arc<dyn ProfileProvider>,
+        redis: Option<Redis>,
+        cache_ttl_seconds: u64,
+        missing_cache_ttl_seconds: u64,
+    ) -> Self {
+        Self {
+            provider,
+            redis,
+            local: Arc::new(Mutex::new(LruCache::new(LOCAL_CACHE_CAPACITY))),
+            cache_ttl_seconds,
+            missing_cache_ttl_seconds,
+        }
+    }
+
+    /// Returns the profiles found for the given addresses, fetching the missing ones
+    /// from the provider in a single batch.
+    #[tracing::instrument(name = "get profiles > Profiles component", skip(self, addresses))]
+    pub async fn get_profiles(&self, addresses: &[Address]) -> HashMap<Address, Profile> {
+        if addresses.is_empty() {
+            return HashMap::new();
+        }
+
+        let cached = self.get_cached_profiles(addresses).await;
+
+        let missing: Vec<Address> = addresses
+            .iter()
+            .filter(|address| !cached.contains_key(*address))
+            .cloned()
+            .collect();
+
+        let mut profiles: HashMap<Address, Profile> = cached
+            .into_iter()
+            .filter_map(|(address, profile)| Some((address, profile?)))
+            .collect();
+
+        if missing.is_empty() {
+            return profiles;
+        }
+
+        match self.provider.get_profiles(&missing).await {
+            Ok(fetched) => {
+                let not_found: Vec<&Address> = missing
+                    .iter()
+                    .filter(|address| !fetched.iter().any(|profile| &profile.address == *address))
+                    .collect();
+                self.cache_profiles(&fetched, &not_found).await;
+                for profile in fetched {
+                    profiles.insert(profile.address.clone(), profile);
+                }
+            }
+            Err(err) => {
+                log::warn!("[Profiles] Couldn't fetch profiles: {:?}", err);
+            }
+        }
+
+        profiles
+    }
+
+    /// Cached entries of the given addresses, `None` for the ones cached as missing.
+    async fn get_cached_profiles(
+        &self,
+        addresses: &[Address],
+    ) -> HashMap<Address, Option<Profile>> {
+        let Some(redis) = &self.redis else {
+            let mut local = self.local();
+            return addresses
+                .iter()
+                .filter_map(|address| {
+                    let profile = local.get(&profile_cache_key(address))?;
+                    Some((address.clone(), profile))
+                })
+                .collect();
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            return HashMap::new();
+        };
+
+        let keys: Vec<String> = addresses.iter().map(profile_cache_key).collect();
+        let cached: RedisResult<Vec<Option<String>>> =
+            cmd("MGET").arg(keys).query_async(&mut connection).await;
+
+        match cached {
+            Ok(cached) => addresses
+                .iter()
+                .zip(cached)
+                .filter_map(|(address, value)| match value?.as_str() {
+                    MISSING_PROFILE_CACHE_VALUE => Some((address.clone(), None)),
+                    profile => {
+                        let profile = serde_json::from_str::<Profile>(profile).ok()?;
+                        Some((address.clone(), Some(profile)))
+                    }
+                })
+                .collect(),
+            Err(err) => {
+                log::warn!("[Profiles] Couldn't read cached profiles: {}", err);
+                HashMap::new()
+            }
+        }
+    }
+
+    async fn cache_profiles(&self, profiles: &[Profile], not_found: &[&Address]) {
+        if profiles.is_empty() && not_found.is_empty() {
+            return;
+        }
+
+        let Some(redis) = &self.redis else {
+            let mut local = self.local();
+            for profile in profiles {
+                let ttl = Duration::from_secs(self.cache_ttl_seconds);
+                local.insert(
+                    profile_cache_key(&profile.address),
+                    Some(profile.clone()),
+                    ttl,
+                );
+            }
+            for address in not_found {
+                let ttl = Duration::from_secs(self.missing_cache_ttl_seconds);
+                local.insert(profile_cache_key(address), None, ttl);
+            }
+            return;
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            return;
+        };
+
+        let mut pipeline = pipe();
+        for profile in profiles {
+            let Ok(value) = serde_json::to_string(profile) else {
+                continue;
+            };
+            pipeline
+                .cmd("SET")
+                .arg(profile_cache_key(&profile.address))
+                .arg(value)
+                .arg("EX")
+                .arg(self.cache_ttl_seconds)
+                .ignore();
+        }
+        for address in not_found {
+            pipeline
+                .cmd("SET")
+                .arg(profile_cache_key(address))
+                .arg(MISSING_PROFILE_CACHE_VALUE)
+                .arg("EX")
+                .arg(self.missing_cache_ttl_seconds)
+                .ignore();
+        }
+
+        if let Err(err) = pipeline.query_async::<_, ()>(&mut connection).await {
+            log::warn!("[Profiles] Couldn't cache profiles: {}", err);
+        }
+    }
+
+    fn local(&self) -> MutexGuard<'_, LruCache<Option<Profile>>> {
+        self.local
+            .lock()
+            .expect("the local profiles cache not to be poisoned")
+    }
+}
+
+fn profile_cache_key(address: &Address) -> String {
+    format!("{PROFILE_CACHE_KEY_PREFIX}:{address}")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::address::Address;
+
+    use super::profile_cache_key;
+
+    #[test]
+    fn test_profile_cache_key() {
+        let address = Address::parse("0xAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA").unwrap();
+
+        assert_eq!(
+            profile_cache_key(&address),
+            "profile:0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+        );
+    }
+}
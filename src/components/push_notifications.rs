@@ -0,0 +1,327 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use deadpool_redis::redis::{cmd, RedisResult};
+use prometheus::{IntCounterVec, Opts};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{address::Address, error::CommonError, friendship_event::FriendshipEvent},
+    entities::user_features::UserFeaturesRepositoryImplementation,
+    friendships::friendship_event_response::Body,
+    instance_id,
+    notifications::Event,
+};
+
+use super::{redis::Redis, users_cache::hash_with_key};
+
+/// Feature of the user to opt out of the push notifications
+pub const PUSH_NOTIFICATIONS_FEATURE: &str = "push_notifications";
+pub const PUSH_NOTIFICATIONS_DISABLED: &str = "disabled";
+pub const PUSH_NOTIFICATIONS_ENABLED: &str = "enabled";
+
+/// Marks an event as sent to a live subscription by one of the instances
+const DELIVERED_LIVE_KEY_PREFIX: &str = "event_delivered_live";
+/// Marks an event as taken by an instance, so only one of them pushes it
+const PUSH_NOTIFICATION_KEY_PREFIX: &str = "push_notification";
+const EVENT_MARKS_TTL_SECONDS: u64 = 60;
+
+const REQUEST_TIMEOUT_SECONDS: u64 = 5;
+
+const PUSH_NOTIFICATIONS: (&str, &str) = (
+    "dcl_social_service_push_notifications_total",
+    "Social Service Push Notifications",
+);
+const PUSH_SENT: &str = "sent";
+const PUSH_FAILED: &str = "failed";
+const PUSH_OPTED_OUT: &str = "opted_out";
+
+#[derive(Clone)]
+struct PushNotificationsMetrics {
+    notifications_total: IntCounterVec,
+}
+
+impl PushNotificationsMetrics {
+    fn new() -> Self {
+        let notifications_total = IntCounterVec::new(
+            Opts::new(PUSH_NOTIFICATIONS.0, PUSH_NOTIFICATIONS.1),
+            &["event", "result"],
+        )
+        .expect("Metrics definition is correct, so the push notifications metric should be created successfully");
+
+        Self {
+            notifications_total,
+        }
+    }
+}
+
+/// A friendship event for a user who wasn't connected when it happened.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PushNotification {
+    pub event: FriendshipEvent,
+    /// User who triggered the event
+    pub from: String,
+    pub to: String,
+    /// Message of the friendship request, if any
+    pub message: Option<String>,
+}
+
+/// Service the notifications are pushed through.
+#[async_trait]
+pub trait PushProvider: Send + Sync {
+    async fn send(&self, notification: &PushNotification) -> Result<(), CommonError>;
+}
+
+/// Posts the notifications as JSON to a notifier service.
+#[derive(Debug, Clone)]
+pub struct HttpPushProvider {
+    url: String,
+    /// Bearer token of the notifier, if it needs one
+    token: Option<String>,
+    client: reqwest::Client,
+}
+
+impl HttpPushProvider {
+    pub fn new(url: String, token: Option<String>) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECONDS))
+            .build()
+            .expect("to build the push notifications http client");
+
+        Self { url, token, client }
+    }
+}
+
+#[async_trait]
+impl PushProvider for HttpPushProvider {
+    async fn send(&self, notification: &PushNotification) -> Result<(), CommonError> {
+        let mut request = self.client.post(&self.url).json(notification);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        match request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+        {
+            Ok(_) => Ok(()),
+            Err(err) => {
+                log::warn!("[Push Notifications] Couldn't push the notification: {err}");
+                Err(CommonError::Unknown("".to_owned()))
+            }
+        }
+    }
+}
+
+/// Pushes the REQUEST and ACCEPT events to the users without a live subscription to the friendship
+/// events, unless they opted out.
+///
+/// Every instance receives the events, but only the one holding the subscription of the user
+/// sends it to the WebSocket. So that instance marks the event as delivered live, and the others
+/// wait for `grace_period` before pushing the event if nobody did. Without Redis, or when it's
+/// unavailable, only the instance that published the event pushes it.
+#[derive(Clone)]
+pub struct PushNotificationsComponent {
+    provider: Arc<dyn PushProvider>,
+    user_features: Arc<dyn UserFeaturesRepositoryImplementation>,
+    redis: Option<Redis>,
+    /// Key to hash the ids of the events in the Redis keys
+    hashing_key: String,
+    grace_period: Duration,
+    metrics: PushNotificationsMetrics,
+}
+
+impl PushNotificationsComponent {
+    pub fn new(
+        provider: Arc<dyn PushProvider>,
+        user_features: Arc<dyn UserFeaturesRepositoryImplementation>,
+        redis: Option<Redis>,
+        hashing_key: String,
+        grace_period: Duration,
+    ) -> Self {
+        Self {
+            provider,
+            user_features,
+            redis,
+            hashing_key,
+            grace_period,
+            metrics: PushNotificationsMetrics::new(),
+        }
+    }
+
+    /// Collectors to expose the pushed notifications along with the rest of the metrics
+    pub fn metrics_collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![Box::new(self.metrics.notifications_total.clone())]
+    }
+
+    /// Whether the event may be pushed, only friendship requests and their acceptance are.
+    pub fn is_pushed(event: &Event) -> bool {
+        pushed_event_of(event).is_some()
+    }
+
+    /// Lets the other instances know the recipient got the event through its subscription.
+    pub async fn mark_delivered_live(&self, event: &Event) {
+        let Some(redis) = self.redis.as_ref().filter(|_| !event.id.is_empty()) else {
+            return;
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            log::warn!("[Push Notifications] Couldn't mark the event as delivered, redis has no connection available");
+            return;
+        };
+
+        let result: RedisResult<()> = cmd("SET")
+            .arg(self.event_key(DELIVERED_LIVE_KEY_PREFIX, event))
+            .arg(1)
+            .arg("EX")
+            .arg(EVENT_MARKS_TTL_SECONDS)
+            .query_async(&mut connection)
+            .await;
+
+        if let Err(err) = result {
+            log::warn!("[Push Notifications] Couldn't mark the event as delivered: {err}");
+        }
+    }
+
+    /// Pushes the event to its recipient after the grace period, unless an instance sent it to a
+    /// live subscription meanwhile or the recipient opted out.
+    pub async fn notify_if_offline(&self, event: &Event) {
+        let Some(friendship_event) = pushed_event_of(event) else {
+            return;
+        };
+        if event.id.is_empty() {
+            // Its marks would be shared with every other event without id
+            log::warn!(
+                "[Push Notifications] Skipping an event without id, published by an older version"
+            );
+            return;
+        }
+
+        tokio::time::sleep(self.grace_period).await;
+
+        if self.was_delivered_live(event).await {
+            return;
+        }
+
+        let Ok(to) = Address::parse(&event.to) else {
+            log::error!(
+                "[Push Notifications] Invalid recipient address: {}",
+                event.to
+            );
+            return;
+        };
+
+        if !self.claim(event).await {
+            return;
+        }
+
+        if !self.is_enabled(&to).await {
+            self.record(friendship_event, PUSH_OPTED_OUT);
+            return;
+        }
+
+        let notification = PushNotification {
+            event: friendship_event,
+            from: event.from.clone(),
+            to: to.to_string(),
+            message: request_message_of(event),
+        };
+        match self.provider.send(&notification).await {
+            Ok(()) => self.record(friendship_event, PUSH_SENT),
+            Err(_) => self.record(friendship_event, PUSH_FAILED),
+        }
+    }
+
+    /// Whether the user gets push notifications, they do unless they opted out.
+    pub async fn is_enabled(&self, user: &Address) -> bool {
+        match self
+            .user_features
+            .get_user_feature(user, PUSH_NOTIFICATIONS_FEATURE)
+            .await
+        {
+            Ok(value) => value.as_deref() != Some(PUSH_NOTIFICATIONS_DISABLED),
+            Err(err) => {
+                // Better not to bother a user who may have opted out
+                log::error!("[Push Notifications] Couldn't read the preference of {user}: {err}");
+                false
+            }
+        }
+    }
+
+    async fn was_delivered_live(&self, event: &Event) -> bool {
+        let Some(redis) = &self.redis else {
+            return false;
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            return false;
+        };
+
+        let delivered: RedisResult<bool> = cmd("EXISTS")
+            .arg(self.event_key(DELIVERED_LIVE_KEY_PREFIX, event))
+            .query_async(&mut connection)
+            .await;
+
+        delivered.unwrap_or_else(|err| {
+            log::warn!("[Push Notifications] Couldn't check if the event was delivered: {err}");
+            false
+        })
+    }
+
+    /// Whether this instance should push the event. Without Redis, or when it's unavailable, only
+    /// the instance that published it does.
+    async fn claim(&self, event: &Event) -> bool {
+        let Some(redis) = &self.redis else {
+            return is_published_here(event);
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            return is_published_here(event);
+        };
+
+        let claimed: RedisResult<Option<String>> = cmd("SET")
+            .arg(self.event_key(PUSH_NOTIFICATION_KEY_PREFIX, event))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(EVENT_MARKS_TTL_SECONDS)
+            .query_async(&mut connection)
+            .await;
+
+        match claimed {
+            Ok(claimed) => claimed.is_some(),
+            Err(err) => {
+                log::warn!("[Push Notifications] Couldn't claim the event: {err}");
+                is_published_here(event)
+            }
+        }
+    }
+
+    fn event_key(&self, prefix: &str, event: &Event) -> String {
+        format!("{prefix}:{}", hash_with_key(&event.id, &self.hashing_key))
+    }
+
+    fn record(&self, event: FriendshipEvent, result: &str) {
+        self.metrics
+            .notifications_total
+            .with_label_values(&[event.as_str(), result])
+            .inc();
+    }
+}
+
+fn is_published_here(event: &Event) -> bool {
+    event.origin == instance_id()
+}
+
+fn pushed_event_of(event: &Event) -> Option<FriendshipEvent> {
+    match event.friendship_event.as_ref()?.body.as_ref()? {
+        Body::Request(_) => Some(FriendshipEvent::REQUEST),
+        Body::Accept(_) => Some(FriendshipEvent::ACCEPT),
+        _ => None,
+    }
+}
+
+fn request_message_of(event: &Event) -> Option<String> {
+    match event.friendship_event.as_ref()?.body.as_ref()? {
+        Body::Request(request) => request.message.clone(),
+        _ => None,
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use deadpool_redis::redis::{pipe, RedisResult};
+use prometheus::{IntCounterVec, Opts};
+
+use crate::domain::error::CommonError;
+
+use super::redis::Redis;
+
+/// Requests of each service in the current minute
+const SERVICE_REQUESTS_KEY_PREFIX: &str = "service_requests";
+
+const SERVICE_REQUESTS: (&str, &str) = (
+    "dcl_social_service_service_requests_total",
+    "Social Service Requests from Other Services",
+);
+
+#[derive(Clone)]
+struct ServiceKeysMetrics {
+    requests_total: IntCounterVec,
+}
+
+impl ServiceKeysMetrics {
+    fn new() -> Self {
+        let requests_total = IntCounterVec::new(
+            Opts::new(SERVICE_REQUESTS.0, SERVICE_REQUESTS.1),
+            &["service", "route", "code"],
+        )
+        .expect("Metrics definition is correct, so the service requests metric should be created successfully");
+
+        Self { requests_total }
+    }
+}
+
+/// Parses a comma separated list of `service:key` entries, e.g. `comms:a-key,places:another-key`.
+pub fn parse_service_keys(keys: &str) -> Result<Vec<(String, String)>, String> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| match entry.split_once(':') {
+            Some((service, key)) if !service.is_empty() && !key.is_empty() => {
+                Ok((service.to_string(), key.to_string()))
+            }
+            // The key isn't part of the error, so it doesn't end up in the logs
+            Some((service, _)) => Err(format!("Invalid key for service {service}")),
+            None => Err("Invalid service key, expected `service:key`".to_string()),
+        })
+        .collect()
+}
+
+/// Authenticates the other backends by their shared key and limits how many requests each of them
+/// makes per minute, apart from the user traffic.
+#[derive(Clone)]
+pub struct ServiceKeysComponent {
+    /// Name of the service by its key
+    services: HashMap<String, String>,
+    /// `None` when there's no Redis, the requests are counted in `local_requests` instead
+    redis: Option<Redis>,
+    /// Minute and requests in it of each service, only counted on this instance
+    local_requests: Arc<Mutex<HashMap<String, (i64, u64)>>>,
+    requests_per_minute: u64,
+    metrics: ServiceKeysMetrics,
+}
+
+impl std::fmt::Debug for ServiceKeysComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServiceKeysComponent")
+            .field("services", &self.services.values().collect::<Vec<_>>())
+            .field("requests_per_minute", &self.requests_per_minute)
+            .finish()
+    }
+}
+
+impl ServiceKeysComponent {
+    pub fn new(
+        keys: Vec<(String, String)>,
+        redis: Option<Redis>,
+        requests_per_minute: u64,
+    ) -> Self {
+        Self {
+            services: keys
+                .into_iter()
+                .map(|(service, key)| (key, service))
+                .collect(),
+            redis,
+            local_requests: Arc::new(Mutex::new(HashMap::new())),
+            requests_per_minute,
+            metrics: ServiceKeysMetrics::new(),
+        }
+    }
+
+    /// Collectors to expose the requests of each service along with the rest of the metrics
+    pub fn metrics_collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![Box::new(self.metrics.requests_total.clone())]
+    }
+
+    /// Name of the service the key belongs to.
+    pub fn authenticate(&self, key: &str) -> Option<String> {
+        self.services.get(key).cloned()
+    }
+
+    /// Counts the request of the service in the current minute, failing once it's over the limit.
+    ///
+    /// Without Redis the requests are counted by each instance. Requests are let through when Redis
+    /// is unavailable, so the other backends don't depend on it.
+    pub async fn check_rate_limit(&self, service: &str) -> Result<(), CommonError> {
+        let minute = chrono::Utc::now().timestamp() / 60;
+        let Some(redis) = &self.redis else {
+            return self.check_local_rate_limit(service, minute);
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            log::warn!("[Service Keys] Couldn't check the rate limit of {service}, redis has no connection available");
+            return Ok(());
+        };
+
+        let key = format!("{SERVICE_REQUESTS_KEY_PREFIX}:{service}:{minute}");
+        let requests: RedisResult<(u64,)> = pipe()
+            .atomic()
+            .cmd("INCR")
+            .arg(&key)
+            .cmd("EXPIRE")
+            .arg(&key)
+            .arg(60)
+            .ignore()
+            .query_async(&mut connection)
+            .await;
+
+        match requests {
+            Ok((requests,)) if requests > self.requests_per_minute => {
+                log::warn!("[Service Keys] {service} is over its rate limit");
+                Err(CommonError::TooManyRequests("".to_owned()))
+            }
+            Ok(_) => Ok(()),
+            Err(err) => {
+                log::warn!("[Service Keys] Couldn't check the rate limit of {service}: {err}");
+                Ok(())
+            }
+        }
+    }
+
+    fn check_local_rate_limit(&self, service: &str, minute: i64) -> Result<(), CommonError> {
+        let mut local_requests = self
+            .local_requests
+            .lock()
+            .expect("the local service requests not to be poisoned");
+        let requests = local_requests
+            .entry(service.to_string())
+            .or_insert((minute, 0));
+        if requests.0 != minute {
+            *requests = (minute, 0);
+        }
+        requests.1 += 1;
+
+        if requests.1 > self.requests_per_minute {
+            log::warn!("[Service Keys] {service} is over its rate limit");
+            return Err(CommonError::TooManyRequests("".to_owned()));
+        }
+        Ok(())
+    }
+
+    pub fn record_request(&self, service: &str, route: &str, status_code: u16) {
+        self.metrics
+            .requests_total
+            .with_label_values(&[service, route, &status_code.to_string()])
+            .inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_service_keys, ServiceKeysComponent};
+
+    #[test]
+    fn test_parse_service_keys() {
+        assert_eq!(
+            parse_service_keys("comms:a-key, places:another-key").unwrap(),
+            vec![
+                ("comms".to_string(), "a-key".to_string()),
+                ("places".to_string(), "another-key".to_string())
+            ]
+        );
+
+        assert!(parse_service_keys("").unwrap().is_empty());
+        assert!(parse_service_keys("comms").is_err());
+        assert!(parse_service_keys("comms:").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limit_without_redis() {
+        let component =
+            ServiceKeysComponent::new(vec![("comms".to_string(), "a-key".to_string())], None, 2);
+
+        assert!(component.check_rate_limit("comms").await.is_ok());
+        assert!(component.check_rate_limit("comms").await.is_ok());
+        assert!(component.check_rate_limit("comms").await.is_err());
+        // Each service has its own limit
+        assert!(component.check_rate_limit("places").await.is_ok());
+    }
+}
@@ -0,0 +1,541 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use deadpool_redis::redis::{cmd, pipe, RedisResult};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    components::{
+        synapse::SynapseComponent,
+        users_cache::{get_user_id_from_token, UserId, UsersCacheComponent},
+    },
+    domain::error::CommonError,
+    generate_uuid_v4,
+};
+
+use super::{lru_cache::LruCache, redis::Redis};
+
+const ALGORITHM: Algorithm = Algorithm::HS256;
+
+/// Unix timestamp of the last time the sessions of a social id were revoked
+const SESSIONS_REVOKED_AT_KEY_PREFIX: &str = "sessions_revoked_at";
+/// Sessions ended by logging out of their Synapse device
+const REVOKED_SESSION_KEY_PREFIX: &str = "revoked_session";
+/// Refresh tokens already exchanged for a new pair, by their id
+const USED_REFRESH_TOKEN_KEY_PREFIX: &str = "used_refresh_token";
+
+/// How long the revocations of a session are cached when verifying its access tokens, the
+/// revocations made by other instances are applied once it's over
+const REVOCATIONS_CACHE_TTL: Duration = Duration::from_secs(30);
+const REVOCATIONS_CACHE_CAPACITY: usize = 10_000;
+
+/// Key the session tokens are signed with, told apart by its id so keys can be rotated.
+#[derive(Clone)]
+pub struct SigningKey {
+    pub id: String,
+    secret: Vec<u8>,
+}
+
+impl SigningKey {
+    pub fn new(id: &str, secret: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            secret: secret.as_bytes().to_vec(),
+        }
+    }
+
+    fn encoding_key(&self) -> EncodingKey {
+        EncodingKey::from_secret(&self.secret)
+    }
+
+    fn decoding_key(&self) -> DecodingKey {
+        DecodingKey::from_secret(&self.secret)
+    }
+}
+
+/// Parses a comma separated list of `id:secret` keys, e.g. `2024-02:a-secret,2024-01:an-old-secret`.
+pub fn parse_signing_keys(keys: &str) -> Result<Vec<SigningKey>, String> {
+    keys.split(',')
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+        .map(|key| match key.split_once(':') {
+            Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+                Ok(SigningKey::new(id, secret))
+            }
+            // The secret isn't part of the error, so it doesn't end up in the logs
+            Some((id, _)) => Err(format!("Invalid session signing key {id}")),
+            None => Err("Invalid session signing key, expected `id:secret`".to_string()),
+        })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum SessionTokenType {
+    Access,
+    Refresh,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SessionClaims {
+    /// Social id of the user
+    sub: String,
+    synapse_id: String,
+    /// Synapse device the session was issued for, so logging out of it ends the session
+    sid: String,
+    token_type: SessionTokenType,
+    /// Id of the token, so a refresh token is only exchanged once
+    jti: String,
+    iat: i64,
+    exp: i64,
+}
+
+/// What rejects the tokens of a session, cached so verifying an access token doesn't hit Redis.
+#[derive(Clone)]
+struct SessionRevocations {
+    social_id: String,
+    /// Unix timestamp of the last time the sessions of the user were revoked
+    revoked_at: Option<i64>,
+    /// Whether the session was logged out
+    session_revoked: bool,
+}
+
+impl SessionRevocations {
+    fn rejects(&self, claims: &SessionClaims) -> bool {
+        self.session_revoked
+            || self
+                .revoked_at
+                .map_or(false, |revoked_at| claims.iat <= revoked_at)
+    }
+}
+
+/// Tokens handed to the user on login and on each refresh.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionTokens {
+    pub access_token: String,
+    pub refresh_token: String,
+    /// Seconds until the access token expires
+    pub expires_in: u64,
+}
+
+/// Issues and verifies the session tokens, JWTs signed with HMAC-SHA256, so the identity of a user
+/// is resolved without asking Synapse.
+///
+/// The first key signs the new tokens and every key is accepted when verifying them, so a key is
+/// rotated by adding the new one first and dropping the old one once its refresh tokens expired.
+/// Both the access and the refresh tokens are rejected once the sessions of the user or the
+/// session itself are revoked, which is the only lookup in Redis. A refresh token is exchanged
+/// once and always checks the revocations, while the access tokens, short lived, use them cached
+/// for `REVOCATIONS_CACHE_TTL`. Without Redis the revocations are kept in-process, so they're only
+/// applied by the instance that made them.
+#[derive(Clone)]
+pub struct SessionTokensComponent {
+    keys: Vec<SigningKey>,
+    access_ttl_seconds: u64,
+    refresh_ttl_seconds: u64,
+    redis: Option<Redis>,
+    /// Revocations used when there's no Redis, their value and when they expire by their key
+    local_revocations: Arc<Mutex<HashMap<String, (i64, i64)>>>,
+    /// Revocations of the sessions whose access tokens were verified, by user and device
+    revocations_cache: Arc<Mutex<LruCache<SessionRevocations>>>,
+}
+
+impl std::fmt::Debug for SessionTokensComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SessionTokensComponent")
+            .field(
+                "keys",
+                &self.keys.iter().map(|key| &key.id).collect::<Vec<_>>(),
+            )
+            .field("access_ttl_seconds", &self.access_ttl_seconds)
+            .field("refresh_ttl_seconds", &self.refresh_ttl_seconds)
+            .finish()
+    }
+}
+
+impl SessionTokensComponent {
+    /// Panics if there are no keys, the first one is the signing key.
+    pub fn new(
+        keys: Vec<SigningKey>,
+        access_ttl_seconds: u64,
+        refresh_ttl_seconds: u64,
+        redis: Option<Redis>,
+    ) -> Self {
+        if keys.is_empty() {
+            panic!("missing session signing keys")
+        }
+
+        Self {
+            keys,
+            access_ttl_seconds,
+            refresh_ttl_seconds,
+            redis,
+            local_revocations: Arc::new(Mutex::new(HashMap::new())),
+            revocations_cache: Arc::new(Mutex::new(LruCache::new(REVOCATIONS_CACHE_CAPACITY))),
+        }
+    }
+
+    /// Issues a new pair of tokens for the user logged in on the given Synapse device.
+    pub fn issue(&self, user_id: &UserId, device_id: &str) -> SessionTokens {
+        let sign = |token_type, ttl_seconds| self.sign(user_id, device_id, token_type, ttl_seconds);
+
+        SessionTokens {
+            access_token: sign(SessionTokenType::Access, self.access_ttl_seconds),
+            refresh_token: sign(SessionTokenType::Refresh, self.refresh_ttl_seconds),
+            expires_in: self.access_ttl_seconds,
+        }
+    }
+
+    /// Returns the user of a valid access token, unless its session was revoked.
+    pub async fn verify_access_token(&self, token: &str) -> Result<UserId, CommonError> {
+        let claims = self.verify(token, SessionTokenType::Access)?;
+
+        let revocations = self.cached_session_revocations(&claims).await?;
+        if revocations.rejects(&claims) {
+            return Err(CommonError::Unauthorized("".to_owned()));
+        }
+
+        Ok(UserId {
+            social_id: claims.sub,
+            synapse_id: claims.synapse_id,
+        })
+    }
+
+    /// Exchanges a valid refresh token for a new pair of tokens, unless the sessions of the user
+    /// were revoked after it was issued or its session was logged out. The refresh token is
+    /// revoked once exchanged, the new one replaces it.
+    pub async fn refresh(&self, refresh_token: &str) -> Result<SessionTokens, CommonError> {
+        let claims = self.verify(refresh_token, SessionTokenType::Refresh)?;
+
+        let revocations = self.session_revocations(&claims).await?;
+        if revocations.rejects(&claims) {
+            return Err(CommonError::Unauthorized("".to_owned()));
+        }
+
+        if !self.use_refresh_token(&claims).await? {
+            log::warn!("Refresh token of {} was already used", claims.sub);
+            return Err(CommonError::Unauthorized("".to_owned()));
+        }
+
+        Ok(self.issue(
+            &UserId {
+                social_id: claims.sub,
+                synapse_id: claims.synapse_id,
+            },
+            &claims.sid,
+        ))
+    }
+
+    /// Rejects the access and refresh tokens issued to the user until now.
+    pub async fn revoke(&self, social_id: &str) -> Result<(), String> {
+        let now = chrono::Utc::now().timestamp();
+        self.forget_cached_revocations(social_id);
+        let Some(redis) = &self.redis else {
+            self.revoke_locally(sessions_revoked_at_key(social_id), now);
+            return Ok(());
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            let error = "Couldn't revoke sessions, redis has no connection available".to_string();
+            log::error!("{}", error);
+            return Err(error);
+        };
+
+        // Kept as long as the refresh tokens it rejects
+        let result: RedisResult<()> = cmd("SET")
+            .arg(sessions_revoked_at_key(social_id))
+            .arg(now)
+            .arg("EX")
+            .arg(self.refresh_ttl_seconds.max(1))
+            .query_async(&mut connection)
+            .await;
+
+        result.map_err(|err| {
+            let error = format!("Couldn't revoke the sessions of {social_id} {err}");
+            log::error!("{}", error);
+            error
+        })
+    }
+
+    /// Rejects the tokens of the session issued for the Synapse device, once it's logged out.
+    pub async fn revoke_session(&self, social_id: &str, device_id: &str) -> Result<(), String> {
+        self.forget_cached_revocations(social_id);
+        let Some(redis) = &self.redis else {
+            self.revoke_locally(revoked_session_key(social_id, device_id), 1);
+            return Ok(());
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            let error = "Couldn't revoke session, redis has no connection available".to_string();
+            log::error!("{}", error);
+            return Err(error);
+        };
+
+        // Kept as long as the refresh tokens it rejects
+        let result: RedisResult<()> = cmd("SET")
+            .arg(revoked_session_key(social_id, device_id))
+            .arg(1)
+            .arg("EX")
+            .arg(self.refresh_ttl_seconds.max(1))
+            .query_async(&mut connection)
+            .await;
+
+        result.map_err(|err| {
+            let error = format!("Couldn't revoke the session of {social_id} {err}");
+            log::error!("{}", error);
+            error
+        })
+    }
+
+    /// The revocations of the session of the token, cached for `REVOCATIONS_CACHE_TTL`.
+    async fn cached_session_revocations(
+        &self,
+        claims: &SessionClaims,
+    ) -> Result<SessionRevocations, CommonError> {
+        let key = revoked_session_key(&claims.sub, &claims.sid);
+        if let Some(revocations) = self.revocations_cache().get(&key) {
+            return Ok(revocations);
+        }
+
+        let revocations = self.session_revocations(claims).await?;
+        self.revocations_cache()
+            .insert(key, revocations.clone(), REVOCATIONS_CACHE_TTL);
+
+        Ok(revocations)
+    }
+
+    /// When the sessions of the user were last revoked, and whether the session of the token was
+    /// logged out.
+    async fn session_revocations(
+        &self,
+        claims: &SessionClaims,
+    ) -> Result<SessionRevocations, CommonError> {
+        let Some(redis) = &self.redis else {
+            return Ok(SessionRevocations {
+                social_id: claims.sub.clone(),
+                revoked_at: self.local_revocation(&sessions_revoked_at_key(&claims.sub)),
+                session_revoked: self
+                    .local_revocation(&revoked_session_key(&claims.sub, &claims.sid))
+                    .is_some(),
+            });
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            log::error!("Couldn't check the revoked sessions, redis has no connection available");
+            return Err(CommonError::ServiceUnavailable("".to_owned()));
+        };
+
+        let revoked: RedisResult<(Option<i64>, bool)> = pipe()
+            .cmd("GET")
+            .arg(sessions_revoked_at_key(&claims.sub))
+            .cmd("EXISTS")
+            .arg(revoked_session_key(&claims.sub, &claims.sid))
+            .query_async(&mut connection)
+            .await;
+
+        match revoked {
+            Ok((revoked_at, session_revoked)) => Ok(SessionRevocations {
+                social_id: claims.sub.clone(),
+                revoked_at,
+                session_revoked,
+            }),
+            Err(err) => {
+                log::error!(
+                    "Couldn't check the revoked sessions of {}: {err}",
+                    claims.sub
+                );
+                Err(CommonError::ServiceUnavailable("".to_owned()))
+            }
+        }
+    }
+
+    /// Marks the refresh token as used until it expires, returns whether it wasn't already.
+    async fn use_refresh_token(&self, claims: &SessionClaims) -> Result<bool, CommonError> {
+        let key = used_refresh_token_key(&claims.jti);
+        let Some(redis) = &self.redis else {
+            let mut revocations = self.local_revocations();
+            let now = chrono::Utc::now().timestamp();
+            revocations.retain(|_, (_, expires_at)| *expires_at > now);
+            return Ok(revocations.insert(key, (1, claims.exp)).is_none());
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            log::error!("Couldn't use the refresh token, redis has no connection available");
+            return Err(CommonError::ServiceUnavailable("".to_owned()));
+        };
+
+        let seconds_left = claims.exp - chrono::Utc::now().timestamp();
+        // Only set when the token wasn't used before
+        let result: RedisResult<Option<String>> = cmd("SET")
+            .arg(key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(seconds_left.max(1))
+            .query_async(&mut connection)
+            .await;
+
+        result.map(|set| set.is_some()).map_err(|err| {
+            log::error!("Couldn't use the refresh token of {}: {err}", claims.sub);
+            CommonError::ServiceUnavailable("".to_owned())
+        })
+    }
+
+    /// The revocations made by this instance apply right away, not only once the cache expires.
+    fn forget_cached_revocations(&self, social_id: &str) {
+        let social_id = social_id.to_lowercase();
+        self.revocations_cache()
+            .remove_matching(|revocations| revocations.social_id == social_id);
+    }
+
+    fn revocations_cache(&self) -> MutexGuard<'_, LruCache<SessionRevocations>> {
+        self.revocations_cache
+            .lock()
+            .expect("the session revocations cache not to be poisoned")
+    }
+
+    /// Keeps the revocation as long as the refresh tokens it rejects, dropping the expired ones.
+    fn revoke_locally(&self, key: String, value: i64) {
+        let now = chrono::Utc::now().timestamp();
+        let mut revocations = self.local_revocations();
+        revocations.retain(|_, (_, expires_at)| *expires_at > now);
+        revocations.insert(key, (value, now + self.refresh_ttl_seconds.max(1) as i64));
+    }
+
+    fn local_revocation(&self, key: &str) -> Option<i64> {
+        let now = chrono::Utc::now().timestamp();
+        self.local_revocations()
+            .get(key)
+            .filter(|(_, expires_at)| *expires_at > now)
+            .map(|(value, _)| *value)
+    }
+
+    fn local_revocations(&self) -> MutexGuard<'_, HashMap<String, (i64, i64)>> {
+        self.local_revocations
+            .lock()
+            .expect("the local session revocations not to be poisoned")
+    }
+
+    fn sign(
+        &self,
+        user_id: &UserId,
+        device_id: &str,
+        token_type: SessionTokenType,
+        ttl_seconds: u64,
+    ) -> String {
+        let key = &self.keys[0];
+        let now = chrono::Utc::now().timestamp();
+
+        let mut header = Header::new(ALGORITHM);
+        header.kid = Some(key.id.clone());
+        let claims = SessionClaims {
+            sub: user_id.social_id.to_lowercase(),
+            synapse_id: user_id.synapse_id.clone(),
+            sid: device_id.to_string(),
+            token_type,
+            jti: generate_uuid_v4(),
+            iat: now,
+            exp: now + ttl_seconds as i64,
+        };
+
+        jsonwebtoken::encode(&header, &claims, &key.encoding_key())
+            .expect("the session claims to be serializable")
+    }
+
+    fn verify(
+        &self,
+        token: &str,
+        token_type: SessionTokenType,
+    ) -> Result<SessionClaims, CommonError> {
+        let unauthorized = || CommonError::Unauthorized("".to_owned());
+
+        let header = jsonwebtoken::decode_header(token).map_err(|_| unauthorized())?;
+        if header.alg != ALGORITHM {
+            return Err(unauthorized());
+        }
+
+        // Tokens signed with a key that was rotated out are no longer valid
+        let key = self
+            .keys
+            .iter()
+            .find(|key| header.kid.as_deref() == Some(key.id.as_str()))
+            .ok_or_else(unauthorized)?;
+
+        let mut validation = Validation::new(ALGORITHM);
+        validation.leeway = 0;
+        let claims = jsonwebtoken::decode::<SessionClaims>(token, &key.decoding_key(), &validation)
+            .map_err(|_| unauthorized())?
+            .claims;
+        // The expiration is checked again as the validation still accepts a token on its last second
+        if claims.token_type != token_type || claims.exp <= chrono::Utc::now().timestamp() {
+            return Err(unauthorized());
+        }
+
+        Ok(claims)
+    }
+}
+
+/// Whether the token has the shape of a session token, the ones issued by Synapse have no dots.
+pub fn is_session_token(token: &str) -> bool {
+    token.split('.').count() == 3
+}
+
+/// Retrieve the user id associated with the given token, either a session token or a Synapse one.
+///
+/// Session tokens are verified locally when they are enabled, along with their cached revocations,
+/// any other token is resolved through the users cache and Synapse with `get_user_id_from_token`.
+pub async fn get_user_id_from_any_token(
+    synapse: SynapseComponent,
+    users_cache: Arc<UsersCacheComponent>,
+    session_tokens: Option<&SessionTokensComponent>,
+    token: &String,
+) -> Result<UserId, CommonError> {
+    match session_tokens {
+        Some(session_tokens) if is_session_token(token) => {
+            session_tokens.verify_access_token(token).await
+        }
+        _ => get_user_id_from_token(synapse, users_cache, token).await,
+    }
+}
+
+fn sessions_revoked_at_key(social_id: &str) -> String {
+    format!(
+        "{SESSIONS_REVOKED_AT_KEY_PREFIX}:{}",
+        social_id.to_lowercase()
+    )
+}
+
+fn revoked_session_key(social_id: &str, device_id: &str) -> String {
+    format!(
+        "{REVOKED_SESSION_KEY_PREFIX}:{}:{device_id}",
+        social_id.to_lowercase()
+    )
+}
+
+fn used_refresh_token_key(jti: &str) -> String {
+    format!("{USED_REFRESH_TOKEN_KEY_PREFIX}:{jti}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_signing_keys() {
+        let keys = parse_signing_keys("new:a-secret, old:an-old-secret").unwrap();
+        assert_eq!(
+            keys.iter().map(|key| key.id.as_str()).collect::<Vec<_>>(),
+            vec!["new", "old"]
+        );
+
+        assert!(parse_signing_keys("").unwrap().is_empty());
+        assert!(parse_signing_keys("missing-secret").is_err());
+        assert!(parse_signing_keys("empty-secret:").is_err());
+    }
+
+    #[test]
+    fn test_is_session_token() {
+        assert!(is_session_token("a.b.c"));
+        assert!(!is_session_token("syt_a_synapse_token"));
+    }
+}
@@ -1,7 +1,13 @@
+use async_trait::async_trait;
+use prometheus::{IntCounterVec, IntGauge, Opts};
 use urlencoding::encode;
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::{collections::HashMap, time::SystemTime};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
 
 use crate::{
     api::routes::synapse::room_events::{
@@ -10,6 +16,12 @@ use crate::{
     domain::{error::CommonError, friendship_event::FriendshipEvent},
 };
 
+use super::{
+    circuit_breaker::{CircuitBreaker, CircuitState},
+    configuration::Synapse as SynapseConfig,
+    health::Healthy,
+};
+
 #[derive(Deserialize, Serialize)]
 pub struct AccountDataContentResponse {
     #[serde(flatten)]
@@ -24,14 +36,122 @@ pub struct RoomIdResponse {
     servers: Vec<String>,
 }
 
+/// Settings of the HTTP client used to reach Synapse.
 #[derive(Debug, Clone)]
+pub struct SynapseClientOptions {
+    pub connect_timeout: Duration,
+    pub request_timeout: Duration,
+    /// Times an idempotent request is retried after a connection or server error
+    pub max_retries: u32,
+    /// Delay before the first retry, it doubles on each attempt and gets a random jitter
+    pub retry_base_delay: Duration,
+    /// Consecutive failures that open the circuit
+    pub circuit_breaker_failure_threshold: u32,
+    /// Time the circuit stays open before letting a request probe Synapse again
+    pub circuit_breaker_open_duration: Duration,
+}
+
+impl Default for SynapseClientOptions {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(2),
+            request_timeout: Duration::from_secs(10),
+            max_retries: 2,
+            retry_base_delay: Duration::from_millis(100),
+            circuit_breaker_failure_threshold: 5,
+            circuit_breaker_open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+impl From<&SynapseConfig> for SynapseClientOptions {
+    fn from(config: &SynapseConfig) -> Self {
+        Self {
+            connect_timeout: Duration::from_millis(config.connect_timeout_ms),
+            request_timeout: Duration::from_millis(config.request_timeout_ms),
+            max_retries: config.max_retries,
+            retry_base_delay: Duration::from_millis(config.retry_base_delay_ms),
+            circuit_breaker_failure_threshold: config.circuit_breaker_failure_threshold,
+            circuit_breaker_open_duration: Duration::from_secs(config.circuit_breaker_open_seconds),
+        }
+    }
+}
+
+const SYNAPSE_CIRCUIT_STATE: (&str, &str) = (
+    "dcl_social_service_synapse_circuit_breaker_state",
+    "State of the Synapse circuit breaker: 0 closed, 1 half-open, 2 open",
+);
+const SYNAPSE_CALLS: (&str, &str) = (
+    "dcl_social_service_synapse_calls_total",
+    "Calls made to Synapse by outcome",
+);
+
+const CALL_SUCCEEDED: &str = "success";
+const CALL_FAILED: &str = "failure";
+const CALL_REJECTED: &str = "rejected";
+
+const IDEMPOTENT: bool = true;
+const NOT_IDEMPOTENT: bool = false;
+
+#[derive(Clone)]
+struct SynapseMetrics {
+    circuit_state: IntGauge,
+    calls_total: IntCounterVec,
+}
+
+impl SynapseMetrics {
+    fn new() -> Self {
+        let circuit_state = IntGauge::with_opts(Opts::new(
+            SYNAPSE_CIRCUIT_STATE.0,
+            SYNAPSE_CIRCUIT_STATE.1,
+        ))
+        .expect("Metrics definition is correct, so the synapse circuit breaker state metric should be created successfully");
+        let calls_total =
+            IntCounterVec::new(Opts::new(SYNAPSE_CALLS.0, SYNAPSE_CALLS.1), &["outcome"])
+            .expect("Metrics definition is correct, so the synapse calls metric should be created successfully");
+
+        Self {
+            circuit_state,
+            calls_total,
+        }
+    }
+
+    fn record_call(&self, outcome: &str) {
+        self.calls_total.with_label_values(&[outcome]).inc();
+    }
+
+    fn update_circuit_state(&self, circuit_breaker: &CircuitBreaker) {
+        self.circuit_state
+            .set(circuit_breaker.state().as_gauge_value());
+    }
+}
+
+/// Synapse client. Clones share the connection pool and the circuit breaker.
+#[derive(Clone)]
 pub struct SynapseComponent {
     pub synapse_url: String,
+    client: reqwest::Client,
+    options: SynapseClientOptions,
+    circuit_breaker: Arc<CircuitBreaker>,
+    metrics: SynapseMetrics,
+    /// Account invited to every room created, so the sync listener gets the events of the friendships
+    service_user_id: Option<String>,
+}
+
+impl std::fmt::Debug for SynapseComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SynapseComponent")
+            .field("synapse_url", &self.synapse_url)
+            .field("circuit_state", &self.circuit_breaker.state())
+            .finish()
+    }
 }
 
 pub const VERSION_URI: &str = "/_matrix/client/versions";
 pub const WHO_AM_I_URI: &str = "/_matrix/client/v3/account/whoami";
 pub const LOGIN_URI: &str = "/_matrix/client/r0/login";
+pub const LOGOUT_URI: &str = "/_matrix/client/r0/logout";
+pub const SYNC_URI: &str = "/_matrix/client/v3/sync";
 
 #[derive(Deserialize, Serialize)]
 pub struct VersionResponse {
@@ -43,6 +163,8 @@ pub struct VersionResponse {
 pub struct WhoAmIResponse {
     pub user_id: String,
     pub social_user_id: Option<String>, // social_user_id is not present in synapse
+    /// Device of the token, missing for the tokens of application services
+    pub device_id: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -85,6 +207,12 @@ pub struct SynapseLoginResponse {
     pub well_known: HashMap<String, HashMap<String, String>>,
 }
 
+/// https://element-hq.github.io/synapse/latest/admin_api/user_admin_api.html#login-as-a-user
+#[derive(Deserialize, Serialize)]
+pub struct LoginAsUserResponse {
+    pub access_token: String,
+}
+
 #[derive(Deserialize, Serialize, Debug)]
 pub struct RoomMember {
     pub state_key: String,
@@ -92,6 +220,25 @@ pub struct RoomMember {
     pub user_id: String,
     pub room_id: String,
     pub r#type: String,
+    #[serde(default)]
+    pub content: Option<RoomMemberContent>,
+}
+
+impl RoomMember {
+    /// Whether the member joined the room or was invited to it, as opposed to having left it or being banned.
+    pub fn is_joined_or_invited(&self) -> bool {
+        matches!(
+            self.content
+                .as_ref()
+                .map(|content| content.membership.as_str()),
+            Some("join") | Some("invite")
+        )
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct RoomMemberContent {
+    pub membership: String,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -123,26 +270,119 @@ pub struct CreateRoomResponse {
     pub room_id: String,
 }
 
+#[derive(Deserialize, Serialize)]
+pub struct KickUserRequest {
+    pub user_id: String,
+    pub reason: String,
+}
+
+/// Synapse answers `{}` to the calls that only change a state
+#[derive(Deserialize, Serialize)]
+pub struct EmptyResponse {}
+
+/// https://spec.matrix.org/v1.3/client-server-api/#get_matrixclientv3sync
+/// Only the joined rooms are read, the rest of the response is ignored.
+#[derive(Deserialize, Serialize, Debug)]
+pub struct SyncResponse {
+    pub next_batch: String,
+    #[serde(default)]
+    pub rooms: SyncRooms,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct SyncRooms {
+    #[serde(default)]
+    pub join: HashMap<String, JoinedRoomSync>,
+    /// Rooms the account was invited to, by id
+    #[serde(default)]
+    pub invite: HashMap<String, serde_json::Value>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct JoinedRoomSync {
+    /// State before the timeline starts, only filled when events were missed
+    #[serde(default)]
+    pub state: SyncEvents,
+    #[serde(default)]
+    pub timeline: SyncEvents,
+}
+
+#[derive(Deserialize, Serialize, Debug, Default)]
+pub struct SyncEvents {
+    #[serde(default)]
+    pub events: Vec<SyncRoomEvent>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct SyncRoomEvent {
+    pub event_id: String,
+    pub r#type: String,
+    pub sender: String,
+    /// Milliseconds since the epoch
+    pub origin_server_ts: i64,
+    pub content: serde_json::Value,
+}
+
 impl SynapseComponent {
     pub fn new(url: String) -> Self {
+        Self::with_options(url, SynapseClientOptions::default())
+    }
+
+    pub fn from_config(config: &SynapseConfig) -> Self {
+        Self::with_options(config.url.clone(), SynapseClientOptions::from(config))
+    }
+
+    pub fn with_options(url: String, options: SynapseClientOptions) -> Self {
         if url.is_empty() {
             panic!("missing synapse URL")
         }
 
-        Self { synapse_url: url }
+        let client = reqwest::Client::builder()
+            .connect_timeout(options.connect_timeout)
+            .timeout(options.request_timeout)
+            .build()
+            .expect("to build the synapse http client");
+        let circuit_breaker = Arc::new(CircuitBreaker::new(
+            options.circuit_breaker_failure_threshold,
+            options.circuit_breaker_open_duration,
+        ));
+
+        Self {
+            synapse_url: url,
+            client,
+            options,
+            circuit_breaker,
+            metrics: SynapseMetrics::new(),
+            service_user_id: None,
+        }
+    }
+
+    /// Invites the Synapse account to every room created from now on.
+    pub fn with_service_user(mut self, synapse_user_id: String) -> Self {
+        self.service_user_id = Some(synapse_user_id);
+        self
+    }
+
+    pub fn circuit_state(&self) -> CircuitState {
+        self.circuit_breaker.state()
+    }
+
+    /// Collectors to expose the state of the client along with the rest of the metrics
+    pub fn metrics_collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![
+            Box::new(self.metrics.circuit_state.clone()),
+            Box::new(self.metrics.calls_total.clone()),
+        ]
     }
 
     pub async fn get_version(&self) -> Result<VersionResponse, CommonError> {
-        Self::get_request::<VersionResponse>(VERSION_URI, &self.synapse_url).await
+        self.get_request::<VersionResponse>(VERSION_URI).await
     }
 
     pub async fn who_am_i(&self, token: &str) -> Result<WhoAmIResponse, CommonError> {
-        let result = Self::authenticated_get_request::<WhoAmIResponse>(
-            WHO_AM_I_URI,
-            token,
-            self.synapse_url.as_str(),
-        )
-        .await;
+        let result = self
+            .authenticated_get_request::<WhoAmIResponse>(WHO_AM_I_URI, token)
+            .await;
 
         result.map(|mut res| {
             res.social_user_id = Some(clean_synapse_user_id(&res.user_id));
@@ -156,14 +396,14 @@ impl SynapseComponent {
         request: SynapseLoginRequest,
     ) -> Result<SynapseLoginResponse, CommonError> {
         let login_url = format!("{}{}", self.synapse_url, LOGIN_URI);
-        let client = reqwest::Client::new();
-        let result = client
+        let request = self
+            .client
             .post(login_url)
-            .json::<SynapseLoginRequest>(&request)
-            .send()
-            .await;
+            .json::<SynapseLoginRequest>(&request);
 
-        let response = Self::process_synapse_response::<SynapseLoginResponse>(result).await;
+        let response = self
+            .send::<SynapseLoginResponse>(request, NOT_IDEMPOTENT)
+            .await;
 
         response.map(|mut res| {
             res.social_user_id = Some(clean_synapse_user_id(&res.user_id));
@@ -171,12 +411,40 @@ impl SynapseComponent {
         })
     }
 
+    /// https://spec.matrix.org/v1.3/client-server-api/#post_matrixclientv3logout
+    #[tracing::instrument(name = "logout > Synapse components", skip(token))]
+    pub async fn logout(&self, token: &str) -> Result<(), CommonError> {
+        self.authenticated_post_request::<EmptyResponse, _>(LOGOUT_URI, token, EmptyResponse {})
+            .await
+            .map(|_| ())
+    }
+
+    /// Gets a token to act on behalf of the user, `admin_token` must belong to a Synapse admin.
+    /// The token should be logged out once it's no longer needed.
+    #[tracing::instrument(name = "login as user > Synapse components", skip(admin_token))]
+    pub async fn login_as_user(
+        &self,
+        admin_token: &str,
+        synapse_user_id: &str,
+    ) -> Result<String, CommonError> {
+        let encoded_user_id = encode(synapse_user_id).to_string();
+        let path = format!("/_synapse/admin/v1/users/{encoded_user_id}/login");
+
+        self.authenticated_post_request::<LoginAsUserResponse, _>(
+            &path,
+            admin_token,
+            EmptyResponse {},
+        )
+        .await
+        .map(|res| res.access_token)
+    }
+
     /// https://spec.matrix.org/v1.3/client-server-api/#get_matrixclientv3joined_rooms
     #[tracing::instrument(name = "get joined rooms > Synapse components", skip(token))]
     pub async fn get_joined_rooms(&self, token: &str) -> Result<JoinedRoomsResponse, CommonError> {
         let path = "/_matrix/client/r0/joined_rooms".to_string();
 
-        Self::authenticated_get_request(&path, token, &self.synapse_url).await
+        self.authenticated_get_request(&path, token).await
     }
 
     /// https://spec.matrix.org/v1.3/client-server-api/#joining-rooms
@@ -189,7 +457,7 @@ impl SynapseComponent {
         let encoded_room_id = encode(room_id).to_string();
         let path = format!("/_matrix/client/r0/rooms/{encoded_room_id}/join");
 
-        Self::authenticated_post_request(&path, token, &self.synapse_url, ()).await
+        self.authenticated_post_request(&path, token, ()).await
     }
 
     #[tracing::instrument(name = "put room event > Synapse components", skip(token))]
@@ -204,10 +472,9 @@ impl SynapseComponent {
         let path =
             format!("/_matrix/client/r0/rooms/{encoded_room_id}/state/org.decentraland.friendship");
 
-        Self::authenticated_put_request(
+        self.authenticated_put_request(
             &path,
             token,
-            &self.synapse_url,
             &RoomEventRequestBody {
                 r#type: room_event,
                 message: room_message_body.map(|s| s.to_string()),
@@ -242,10 +509,9 @@ impl SynapseComponent {
         let path =
             format!("/_matrix/client/r0/rooms/{encoded_room_id}/send/m.room.message/{txn_id}");
 
-        Self::authenticated_put_request(
+        self.authenticated_put_request(
             &path,
             token,
-            &self.synapse_url,
             &MessageRequestEventBody {
                 msgtype: "m.text".to_string(),
                 body: room_message_body.to_string(),
@@ -254,6 +520,24 @@ impl SynapseComponent {
         .await
     }
 
+    /// Returns the content of the friendship state event of the room, `None` if it has none.
+    #[tracing::instrument(name = "get friendship room event > Synapse components", skip(token))]
+    pub async fn get_friendship_room_event(
+        &self,
+        token: &str,
+        room_id: &str,
+    ) -> Result<Option<RoomEventRequestBody>, CommonError> {
+        let encoded_room_id = encode(room_id).to_string();
+        let path =
+            format!("/_matrix/client/r0/rooms/{encoded_room_id}/state/org.decentraland.friendship");
+
+        match self.authenticated_get_request(&path, token).await {
+            Ok(event) => Ok(Some(event)),
+            Err(CommonError::NotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
     #[tracing::instrument(name = "get_room_members > Synapse components", skip(token))]
     pub async fn get_room_members(
         &self,
@@ -262,14 +546,15 @@ impl SynapseComponent {
     ) -> Result<RoomMembersResponse, CommonError> {
         let encoded_room_id = encode(room_id).to_string();
         let path = format!("/_matrix/client/r0/rooms/{encoded_room_id}/members");
-        let response = Self::authenticated_get_request::<RoomMembersResponse>(
-            &path,
-            token,
-            self.synapse_url.as_str(),
-        )
-        .await;
+        let response = self
+            .authenticated_get_request::<RoomMembersResponse>(&path, token)
+            .await;
 
         response.map(|mut res| {
+            // The service account isn't one of the users of the friendship
+            res.chunk.retain(|room_member| {
+                Some(&room_member.state_key) != self.service_user_id.as_ref()
+            });
             res.chunk
                 .iter_mut()
                 .filter(|room_member| room_member.state_key.starts_with('@'))
@@ -295,12 +580,12 @@ impl SynapseComponent {
         let invite = synapse_user_ids
             .iter()
             .map(|id| id.to_string().to_lowercase())
+            .chain(self.service_user_id.clone())
             .collect();
 
-        Self::authenticated_post_request(
+        self.authenticated_post_request(
             &path,
             token,
-            &self.synapse_url,
             &CreateRoomOpts {
                 room_alias_name: room_alias_name.to_string(),
                 preset: "trusted_private_chat".to_string(),
@@ -323,11 +608,10 @@ impl SynapseComponent {
         let path: String =
             format!("/_matrix/client/r0/user/{encoded_synapse_user_id}/account_data/m.direct");
 
-        let result: Result<HashMap<String, Vec<String>>, _> =
-            Self::authenticated_put_request::<HashMap<String, Vec<String>>, _>(
+        let result: Result<HashMap<String, Vec<String>>, _> = self
+            .authenticated_put_request::<HashMap<String, Vec<String>>, _>(
                 &path,
                 token,
-                &self.synapse_url,
                 direct_room_map,
             )
             .await;
@@ -349,7 +633,7 @@ impl SynapseComponent {
         let path: String =
             format!("/_matrix/client/r0/user/{encoded_synapse_user_id}/account_data/m.direct");
 
-        Self::authenticated_get_request(&path, token, &self.synapse_url).await
+        self.authenticated_get_request(&path, token).await
     }
 
     pub async fn get_room_id_for_alias(
@@ -361,71 +645,219 @@ impl SynapseComponent {
         let encoded_alias = full_encoded_alias(alias, synapse);
         let path = format!("/_matrix/client/r0/directory/room/{encoded_alias}");
 
-        Self::authenticated_get_request(&path, token, &self.synapse_url).await
+        self.authenticated_get_request(&path, token).await
     }
 
-    async fn get_request<T: DeserializeOwned>(
-        path: &str,
-        synapse_url: &str,
-    ) -> Result<T, CommonError> {
-        let url = format!("{synapse_url}{path}");
-        let client = reqwest::Client::new();
-        let response = client.get(url).send().await;
+    /// https://spec.matrix.org/v1.3/client-server-api/#leaving-rooms
+    #[tracing::instrument(name = "leave room > Synapse components", skip(token))]
+    pub async fn leave_room(&self, token: &str, room_id: &str) -> Result<(), CommonError> {
+        let encoded_room_id = encode(room_id).to_string();
+        let path = format!("/_matrix/client/r0/rooms/{encoded_room_id}/leave");
+
+        self.authenticated_post_request::<EmptyResponse, _>(&path, token, EmptyResponse {})
+            .await
+            .map(|_| ())
+    }
+
+    /// Stops the room from showing up for the user, it has to be left first.
+    #[tracing::instrument(name = "forget room > Synapse components", skip(token))]
+    pub async fn forget_room(&self, token: &str, room_id: &str) -> Result<(), CommonError> {
+        let encoded_room_id = encode(room_id).to_string();
+        let path = format!("/_matrix/client/r0/rooms/{encoded_room_id}/forget");
+
+        self.authenticated_post_request::<EmptyResponse, _>(&path, token, EmptyResponse {})
+            .await
+            .map(|_| ())
+    }
+
+    /// https://spec.matrix.org/v1.3/client-server-api/#post_matrixclientv3roomsroomidkick
+    #[tracing::instrument(name = "kick user > Synapse components", skip(token))]
+    pub async fn kick_user(
+        &self,
+        token: &str,
+        room_id: &str,
+        synapse_user_id: &str,
+        reason: &str,
+    ) -> Result<(), CommonError> {
+        let encoded_room_id = encode(room_id).to_string();
+        let path = format!("/_matrix/client/r0/rooms/{encoded_room_id}/kick");
+
+        self.authenticated_post_request::<EmptyResponse, _>(
+            &path,
+            token,
+            KickUserRequest {
+                user_id: synapse_user_id.to_string(),
+                reason: reason.to_string(),
+            },
+        )
+        .await
+        .map(|_| ())
+    }
+
+    /// Removes the alias, so it can be given to a new room
+    pub async fn delete_room_alias(&self, token: &str, alias: &str) -> Result<(), CommonError> {
+        let encoded_alias = full_encoded_alias(alias, self);
+        let path = format!("/_matrix/client/r0/directory/room/{encoded_alias}");
+
+        self.authenticated_delete_request::<EmptyResponse>(&path, token)
+            .await
+            .map(|_| ())
+    }
+
+    /// Long polls Synapse for the events after `since`, waiting up to `timeout` for new ones.
+    /// `filter` is the JSON of a Matrix filter.
+    #[tracing::instrument(name = "sync > Synapse components", skip(token))]
+    pub async fn sync(
+        &self,
+        token: &str,
+        since: Option<&str>,
+        timeout: Duration,
+        filter: &str,
+    ) -> Result<SyncResponse, CommonError> {
+        let timeout_ms = timeout.as_millis().to_string();
+        let mut query = vec![("timeout", timeout_ms.as_str()), ("filter", filter)];
+        if let Some(since) = since {
+            query.push(("since", since));
+        }
 
-        Self::process_synapse_response(response).await
+        let url = format!("{}{SYNC_URI}", self.synapse_url);
+        // Synapse holds the request until there are new events, so the usual timeout is not enough
+        let request = self
+            .client
+            .get(url)
+            .query(&query)
+            .timeout(timeout + self.options.request_timeout)
+            .header("Authorization", format!("Bearer {token}"));
+
+        self.send::<SyncResponse>(request, IDEMPOTENT).await
+    }
+
+    async fn get_request<T: DeserializeOwned>(&self, path: &str) -> Result<T, CommonError> {
+        let url = format!("{}{path}", self.synapse_url);
+        let request = self.client.get(url);
+
+        self.send::<T>(request, IDEMPOTENT).await
     }
 
     async fn authenticated_put_request<T: DeserializeOwned, S: Serialize>(
+        &self,
         path: &str,
         token: &str,
-        synapse_url: &str,
         body: S,
     ) -> Result<T, CommonError> {
-        let url = format!("{synapse_url}{path}");
-        let client = reqwest::Client::new();
-        let response = client
+        let url = format!("{}{path}", self.synapse_url);
+        let request = self
+            .client
             .put(url)
             .json(&body)
-            .header("Authorization", format!("Bearer {token}"))
-            .send()
-            .await;
+            .header("Authorization", format!("Bearer {token}"));
 
-        Self::process_synapse_response::<T>(response).await
+        // PUTs in the Matrix API replace the resource or carry a transaction id, so they can be repeated
+        self.send::<T>(request, IDEMPOTENT).await
     }
 
     async fn authenticated_post_request<T: DeserializeOwned, S: Serialize>(
+        &self,
         path: &str,
         token: &str,
-        synapse_url: &str,
         body: S,
     ) -> Result<T, CommonError> {
-        let url = format!("{synapse_url}{path}");
-        let client = reqwest::Client::new();
-        let response = client
+        let url = format!("{}{path}", self.synapse_url);
+        let request = self
+            .client
             .post(url)
             .json(&body)
-            .header("Authorization", format!("Bearer {token}"))
-            .send()
-            .await;
+            .header("Authorization", format!("Bearer {token}"));
 
-        Self::process_synapse_response::<T>(response).await
+        self.send::<T>(request, NOT_IDEMPOTENT).await
+    }
+
+    async fn authenticated_delete_request<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        token: &str,
+    ) -> Result<T, CommonError> {
+        let url = format!("{}{path}", self.synapse_url);
+        let request = self
+            .client
+            .delete(url)
+            .header("Authorization", format!("Bearer {token}"));
+
+        self.send::<T>(request, IDEMPOTENT).await
     }
 
     async fn authenticated_get_request<T: DeserializeOwned>(
+        &self,
         path: &str,
         token: &str,
-        synapse_url: &str,
     ) -> Result<T, CommonError> {
-        let url = format!("{synapse_url}{path}");
-        let client = reqwest::Client::new();
-        let response = client
+        let url = format!("{}{path}", self.synapse_url);
+        let request = self
+            .client
             .get(url)
-            .header("Authorization", format!("Bearer {token}"))
-            .send()
-            .await;
+            .header("Authorization", format!("Bearer {token}"));
+
+        self.send::<T>(request, IDEMPOTENT).await
+    }
+
+    /// Sends the request through the circuit breaker, retrying idempotent requests
+    /// when Synapse can't be reached or answers with a server error.
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+        idempotent: bool,
+    ) -> Result<T, CommonError> {
+        let max_attempts = if idempotent {
+            self.options.max_retries + 1
+        } else {
+            1
+        };
+        let mut attempt = 1;
+
+        loop {
+            if !self.circuit_breaker.try_acquire() {
+                self.metrics.record_call(CALL_REJECTED);
+                self.metrics.update_circuit_state(&self.circuit_breaker);
+                log::warn!("[Synapse] circuit breaker is open, failing fast");
+                return Err(CommonError::ServiceUnavailable(
+                    "Synapse is unavailable".to_owned(),
+                ));
+            }
 
-        Self::process_synapse_response::<T>(response).await
+            let response = request
+                .try_clone()
+                .expect("synapse requests to have a buffered body")
+                .send()
+                .await;
+
+            let failed = match &response {
+                Ok(response) => response.status().is_server_error(),
+                Err(_) => true,
+            };
+            if failed {
+                self.circuit_breaker.record_failure();
+                self.metrics.record_call(CALL_FAILED);
+            } else {
+                self.circuit_breaker.record_success();
+                self.metrics.record_call(CALL_SUCCEEDED);
+            }
+            self.metrics.update_circuit_state(&self.circuit_breaker);
+
+            if !failed || attempt >= max_attempts {
+                return Self::process_synapse_response::<T>(response).await;
+            }
+
+            let delay = retry_delay(self.options.retry_base_delay, attempt);
+            log::debug!(
+                "[Synapse] retrying request in {:?}, attempt {}",
+                delay,
+                attempt
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
     }
+
     async fn process_synapse_response<T: DeserializeOwned>(
         response: Result<reqwest::Response, reqwest::Error>,
     ) -> Result<T, CommonError> {
@@ -460,6 +892,7 @@ impl SynapseComponent {
                 "M_UNKNOWN_TOKEN" => CommonError::Unauthorized("".to_owned()),
                 "M_MISSING_TOKEN" => CommonError::Unauthorized("".to_owned()),
                 "M_LIMIT_EXCEEDED" => CommonError::TooManyRequests("".to_owned()),
+                "M_NOT_FOUND" => CommonError::NotFound(error.error.unwrap_or_default()),
                 _ => CommonError::Unknown("".to_owned()),
             },
             Err(err) => {
@@ -470,6 +903,25 @@ impl SynapseComponent {
     }
 }
 
+#[async_trait]
+impl Healthy for SynapseComponent {
+    async fn is_healthy(&self) -> bool {
+        self.circuit_breaker.state() != CircuitState::Open
+    }
+}
+
+/// Exponential backoff with jitter, so clients that failed together don't retry together.
+fn retry_delay(base_delay: Duration, attempt: u32) -> Duration {
+    let backoff = base_delay.saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)));
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.subsec_nanos())
+        .unwrap_or_default();
+
+    // Half of the backoff is kept and the other half is random
+    backoff / 2 + backoff.mul_f64(f64::from(nanos % 1000) / 2000.0)
+}
+
 /// This function is used when getting the room by alias (full alias: like '#wombat:example.com')
 /// and as it's part of the query parameter it must be encoded
 ///
@@ -537,7 +989,9 @@ pub fn user_id_as_synapse_user_id(user_id: &str, synapse_url: &str) -> String {
 
 #[cfg(test)]
 mod tests {
-    use super::{clean_synapse_user_id, user_id_as_synapse_user_id};
+    use std::time::Duration;
+
+    use super::{clean_synapse_user_id, retry_delay, user_id_as_synapse_user_id};
 
     #[test]
     fn clear_should_obtain_expected_string_for_synapse_user() {
@@ -559,4 +1013,15 @@ mod tests {
 
         assert_eq!(res, "@0x1111ada11111:decentraland.zone");
     }
+
+    #[test]
+    fn retry_delay_should_double_on_each_attempt_with_jitter() {
+        let base_delay = Duration::from_millis(100);
+
+        for (attempt, backoff) in [(1, 100), (2, 200), (3, 400)] {
+            let delay = retry_delay(base_delay, attempt);
+            assert!(delay >= Duration::from_millis(backoff / 2));
+            assert!(delay <= Duration::from_millis(backoff));
+        }
+    }
 }
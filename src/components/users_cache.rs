@@ -1,36 +1,155 @@
-use deadpool_redis::redis::{cmd, RedisResult};
+use deadpool_redis::redis::{cmd, pipe, RedisResult};
 
-use super::redis::Redis;
+use super::{lru_cache::LruCache, redis::Redis};
 
 use hex::encode;
 use hmac::{Hmac, Mac};
+use prometheus::{IntCounterVec, Opts};
 use sha2::Sha256;
 
 // Create alias for HMAC-SHA256
 type HmacSha256 = Hmac<Sha256>;
 
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
 
 use crate::{components::synapse::SynapseComponent, domain::error::CommonError};
 use serde::{Deserialize, Serialize};
-use tokio::sync::Mutex;
+use tokio::sync::OnceCell;
 
 const DEFAULT_EXPIRATION_TIME_SECONDS: i32 = 1800;
 
-#[derive(Debug)]
+const DEFAULT_LOCAL_CAPACITY: usize = 10_000;
+const DEFAULT_LOCAL_TTL: Duration = Duration::from_secs(60);
+const DEFAULT_NEGATIVE_TTL: Duration = Duration::from_secs(30);
+
+/// Set of the hashed tokens cached for a social id, so they can be revoked together
+const USER_TOKENS_KEY_PREFIX: &str = "user_tokens";
+/// Hashed tokens Synapse rejected recently
+const REJECTED_TOKEN_KEY_PREFIX: &str = "rejected_token";
+
+const USERS_CACHE_LOOKUPS: (&str, &str) = (
+    "dcl_social_service_users_cache_lookups_total",
+    "Social Service Users Cache Lookups by Tier",
+);
+const USERS_CACHE_SYNAPSE_LOOKUPS: (&str, &str) = (
+    "dcl_social_service_users_cache_synapse_lookups_total",
+    "Social Service Users Cache Lookups Resolved by Synapse",
+);
+const LOCAL_TIER: &str = "local";
+const REDIS_TIER: &str = "redis";
+const REJECTED_TIER: &str = "rejected";
+
+type PendingLookup = Arc<OnceCell<Result<UserId, CommonError>>>;
+
+#[derive(Clone)]
+struct UsersCacheMetrics {
+    lookups_total: IntCounterVec,
+    synapse_lookups_total: IntCounterVec,
+}
+
+impl UsersCacheMetrics {
+    fn new() -> Self {
+        let lookups_total = IntCounterVec::new(
+            Opts::new(USERS_CACHE_LOOKUPS.0, USERS_CACHE_LOOKUPS.1),
+            &["tier", "result"],
+        )
+        .expect("Metrics definition is correct, so the users cache lookups metric should be created successfully");
+        let synapse_lookups_total = IntCounterVec::new(
+            Opts::new(USERS_CACHE_SYNAPSE_LOOKUPS.0, USERS_CACHE_SYNAPSE_LOOKUPS.1),
+            &["outcome"],
+        )
+        .expect("Metrics definition is correct, so the users cache synapse lookups metric should be created successfully");
+
+        Self {
+            lookups_total,
+            synapse_lookups_total,
+        }
+    }
+
+    fn record_lookup(&self, tier: &str, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.lookups_total.with_label_values(&[tier, result]).inc();
+    }
+
+    fn record_synapse_lookup(&self, succeeded: bool) {
+        let outcome = if succeeded { "success" } else { "failure" };
+        self.synapse_lookups_total
+            .with_label_values(&[outcome])
+            .inc();
+    }
+}
+
+/// Cache of the users behind the tokens, in two tiers: a bounded in-process LRU in front of Redis.
+/// Without Redis only the in-process tier is kept, along with the tokens Synapse rejected.
+///
+/// The component is shared without a lock, the in-process tier is only locked to read or write an
+/// entry and never across a call to Redis or Synapse.
 pub struct UsersCacheComponent {
-    redis_component: Redis,
+    redis_component: Option<Redis>,
     hashing_key: String,
+    local: Mutex<LruCache<UserId>>,
+    /// Tokens Synapse rejected recently, used when there's no Redis
+    local_rejected: Mutex<LruCache<()>>,
+    local_ttl: Duration,
+    negative_ttl: Duration,
+    /// Lookups of the tokens missing in both tiers, so concurrent misses wait for the same one
+    pending: Mutex<HashMap<String, PendingLookup>>,
+    metrics: UsersCacheMetrics,
+}
+
+impl std::fmt::Debug for UsersCacheComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UsersCacheComponent")
+            .field("redis_component", &self.redis_component)
+            .field("local_entries", &self.local().len())
+            .field("local_ttl", &self.local_ttl)
+            .field("negative_ttl", &self.negative_ttl)
+            .finish()
+    }
 }
 
 impl UsersCacheComponent {
-    pub fn new(redis: Redis, hashing_key: String) -> Self {
+    pub fn new(redis: Option<Redis>, hashing_key: String) -> Self {
         Self {
             redis_component: redis,
             hashing_key,
+            local: Mutex::new(LruCache::new(DEFAULT_LOCAL_CAPACITY)),
+            local_rejected: Mutex::new(LruCache::new(DEFAULT_LOCAL_CAPACITY)),
+            local_ttl: DEFAULT_LOCAL_TTL,
+            negative_ttl: DEFAULT_NEGATIVE_TTL,
+            pending: Mutex::new(HashMap::new()),
+            metrics: UsersCacheMetrics::new(),
         }
     }
 
+    /// Sizes the in-process tier, a capacity of 0 disables it. Its TTL should be short since a
+    /// token invalidated by another instance is only dropped from Redis.
+    pub fn with_local_cache(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.local = Mutex::new(LruCache::new(capacity));
+        self.local_rejected = Mutex::new(LruCache::new(capacity));
+        self.local_ttl = ttl;
+        self
+    }
+
+    /// How long a token rejected by Synapse is rejected without asking it again, a TTL of 0
+    /// disables it.
+    pub fn with_negative_cache(mut self, ttl: Duration) -> Self {
+        self.negative_ttl = ttl;
+        self
+    }
+
+    /// Collectors to expose the lookups of each tier along with the rest of the metrics
+    pub fn metrics_collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![
+            Box::new(self.metrics.lookups_total.clone()),
+            Box::new(self.metrics.synapse_lookups_total.clone()),
+        ]
+    }
+
     #[tracing::instrument(
         name = "Storing user in cache",
         skip(token, custom_exipry_time),
@@ -39,13 +158,25 @@ impl UsersCacheComponent {
         )
     )]
     pub async fn add_user(
-        &mut self,
+        &self,
         token: &str,
         social_id: &str,
         synapse_id: &str,
         custom_exipry_time: Option<i32>,
     ) -> Result<(), String> {
-        let con = self.redis_component.get_async_connection().await;
+        let key = hash_with_key(token, &self.hashing_key);
+        let user_id = UserId {
+            social_id: social_id.to_string(),
+            synapse_id: synapse_id.to_string(),
+        };
+        let expiry_time = custom_exipry_time.unwrap_or(DEFAULT_EXPIRATION_TIME_SECONDS);
+        let local_ttl = Duration::from_secs(expiry_time.max(0) as u64).min(self.local_ttl);
+
+        let Some(redis) = &self.redis_component else {
+            self.local().insert(key, user_id, local_ttl);
+            return Ok(());
+        };
+        let con = redis.get_async_connection().await;
 
         if con.is_none() {
             let error =
@@ -54,28 +185,32 @@ impl UsersCacheComponent {
             return Err(error);
         }
 
-        let key = hash_with_key(token, &self.hashing_key);
-
         let mut connection = con.unwrap();
 
-        let set_res = cmd("SET")
-            .arg(&[
-                key.clone(),
-                serde_json::to_string(&UserId {
-                    social_id: social_id.to_string(),
-                    synapse_id: synapse_id.to_string(),
-                })
-                .unwrap(),
-            ])
-            .arg(&[
-                "EX".to_string(),
-                (custom_exipry_time.unwrap_or(DEFAULT_EXPIRATION_TIME_SECONDS)).to_string(),
-            ])
+        // The index lives as long as its newest token, the expired ones are skipped when revoking
+        let tokens_key = user_tokens_key(social_id);
+        let set_res = pipe()
+            .atomic()
+            .cmd("SET")
+            .arg(&[key.clone(), serde_json::to_string(&user_id).unwrap()])
+            .arg(&["EX".to_string(), expiry_time.to_string()])
+            .ignore()
+            .cmd("SADD")
+            .arg(&tokens_key)
+            .arg(&key)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&tokens_key)
+            .arg(expiry_time.max(DEFAULT_EXPIRATION_TIME_SECONDS))
+            .ignore()
             .query_async::<_, ()>(&mut connection)
             .await;
 
         match set_res {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                self.local().insert(key, user_id, local_ttl);
+                Ok(())
+            }
             Err(err) => {
                 let error = format!("Couldn't cache user {err}");
                 log::error!("{}", error);
@@ -84,22 +219,38 @@ impl UsersCacheComponent {
         }
     }
 
-    pub async fn get_user(&mut self, token: &str) -> Result<UserId, String> {
-        let con = self.redis_component.get_async_connection().await;
+    pub async fn get_user(&self, token: &str) -> Result<UserId, String> {
+        let key = hash_with_key(token, &self.hashing_key);
+
+        let local_user = self.local().get(&key);
+        self.metrics.record_lookup(LOCAL_TIER, local_user.is_some());
+        if let Some(user_id) = local_user {
+            return Ok(user_id);
+        }
+
+        let Some(redis) = &self.redis_component else {
+            return Err("User not found in the in-process cache".to_string());
+        };
+        let con = redis.get_async_connection().await;
 
         if con.is_none() {
             log::error!("Couldn't obtain user redis has no connection available");
             return Err("Couldn't obtain user redis has no connection available".to_string());
         }
 
-        let key = hash_with_key(token, &self.hashing_key);
-
         let mut connection = con.unwrap();
-        let res: RedisResult<String> = cmd("GET").arg(&[key]).query_async(&mut connection).await;
+        let res: RedisResult<String> = cmd("GET")
+            .arg(&[key.clone()])
+            .query_async(&mut connection)
+            .await;
 
+        self.metrics.record_lookup(REDIS_TIER, res.is_ok());
         match res {
             Ok(user_id) => match serde_json::from_str::<UserId>(&user_id) {
-                Ok(user_id) => Ok(user_id),
+                Ok(user_id) => {
+                    self.local().insert(key, user_id.clone(), self.local_ttl);
+                    Ok(user_id)
+                }
                 Err(err) => Err(err.to_string()),
             },
             Err(err) => {
@@ -108,6 +259,235 @@ impl UsersCacheComponent {
             }
         }
     }
+
+    /// Drops the token from both tiers, so the next request with it is authenticated by Synapse.
+    ///
+    /// Other instances keep it in their in-process tier until it expires.
+    pub async fn invalidate_token(&self, token: &str) -> Result<(), String> {
+        let key = hash_with_key(token, &self.hashing_key);
+        self.local().remove(&key);
+
+        let Some(redis) = &self.redis_component else {
+            return Ok(());
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            let error = "Couldn't invalidate token, redis has no connection available".to_string();
+            log::error!("{}", error);
+            return Err(error);
+        };
+
+        cmd("DEL")
+            .arg(&[key])
+            .query_async::<_, ()>(&mut connection)
+            .await
+            .map_err(|err| {
+                let error = format!("Couldn't invalidate token {err}");
+                log::error!("{}", error);
+                error
+            })
+    }
+
+    /// Drops every cached token of the user from Redis and from this instance, returns how many
+    /// of them were still cached.
+    ///
+    /// Other instances keep them in their in-process tier until it expires.
+    pub async fn revoke_user_tokens(&self, social_id: &str) -> Result<usize, String> {
+        let Some(redis) = &self.redis_component else {
+            let revoked = self
+                .local()
+                .remove_matching(|user_id| user_id.social_id.eq_ignore_ascii_case(social_id));
+            log::info!("Revoked {revoked} cached tokens of {social_id}");
+            return Ok(revoked);
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            let error = "Couldn't revoke tokens, redis has no connection available".to_string();
+            log::error!("{}", error);
+            return Err(error);
+        };
+
+        let tokens_key = user_tokens_key(social_id);
+        let keys: Vec<String> = cmd("SMEMBERS")
+            .arg(&tokens_key)
+            .query_async(&mut connection)
+            .await
+            .map_err(|err| {
+                let error = format!("Couldn't get the tokens of {social_id} {err}");
+                log::error!("{}", error);
+                error
+            })?;
+
+        if keys.is_empty() {
+            return Ok(0);
+        }
+
+        {
+            let mut local = self.local();
+            for key in &keys {
+                local.remove(key);
+            }
+        }
+
+        // Only the read keys are removed from the index, a token cached in the meantime stays in it
+        let (revoked,): (usize,) = pipe()
+            .atomic()
+            .cmd("DEL")
+            .arg(&keys)
+            .cmd("SREM")
+            .arg(&tokens_key)
+            .arg(&keys)
+            .ignore()
+            .query_async(&mut connection)
+            .await
+            .map_err(|err| {
+                let error = format!("Couldn't revoke the tokens of {social_id} {err}");
+                log::error!("{}", error);
+                error
+            })?;
+
+        log::info!("Revoked {revoked} cached tokens of {social_id}");
+        Ok(revoked)
+    }
+
+    /// Whether Synapse rejected the token recently, any error on Redis is taken as it didn't.
+    async fn is_rejected(&self, key: &str) -> bool {
+        if self.negative_ttl.is_zero() {
+            return false;
+        }
+
+        let Some(redis) = &self.redis_component else {
+            let rejected = self.local_rejected().get(key).is_some();
+            self.metrics.record_lookup(REJECTED_TIER, rejected);
+            return rejected;
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            return false;
+        };
+
+        let rejected: RedisResult<bool> = cmd("EXISTS")
+            .arg(rejected_token_key(key))
+            .query_async(&mut connection)
+            .await;
+        let rejected = rejected.unwrap_or(false);
+        self.metrics.record_lookup(REJECTED_TIER, rejected);
+
+        rejected
+    }
+
+    async fn reject(&self, key: &str) {
+        if self.negative_ttl.is_zero() {
+            return;
+        }
+
+        let Some(redis) = &self.redis_component else {
+            self.local_rejected()
+                .insert(key.to_string(), (), self.negative_ttl);
+            return;
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            return;
+        };
+
+        let result: RedisResult<()> = cmd("SET")
+            .arg(rejected_token_key(key))
+            .arg(1)
+            .arg("EX")
+            .arg(self.negative_ttl.as_secs().max(1))
+            .query_async(&mut connection)
+            .await;
+        if let Err(err) = result {
+            log::warn!("Couldn't remember the rejected token {err}");
+        }
+    }
+
+    /// Resolves a token missing in the cache through Synapse. Concurrent calls for the same token
+    /// share a single lookup.
+    async fn resolve_token(
+        &self,
+        synapse: &SynapseComponent,
+        token: &String,
+    ) -> Result<UserId, CommonError> {
+        let key = hash_with_key(token, &self.hashing_key);
+        let lookup = self.pending().entry(key.clone()).or_default().clone();
+
+        let result = lookup
+            .get_or_init(|| async {
+                // A concurrent lookup may have cached it in the meantime
+                if let Ok(user_id) = self.get_user(token).await {
+                    return Ok(user_id);
+                }
+
+                if self.is_rejected(&key).await {
+                    return Err(CommonError::Unauthorized("".to_owned()));
+                }
+
+                let response = synapse.who_am_i(token).await;
+                self.metrics.record_synapse_lookup(response.is_ok());
+                let response = match response {
+                    Ok(response) => response,
+                    Err(err) => {
+                        if matches!(err, CommonError::Unauthorized(_)) {
+                            self.reject(&key).await;
+                        }
+                        return Err(err);
+                    }
+                };
+                let user_id = UserId {
+                    social_id: response.social_user_id.unwrap(),
+                    synapse_id: response.user_id,
+                };
+
+                if let Err(err) = self
+                    .add_user(token, &user_id.social_id, &user_id.synapse_id, None)
+                    .await
+                {
+                    log::error!(
+                        "Get user id from token > check_auth.rs > Error on storing token into Redis: {:?}",
+                        err
+                    )
+                }
+
+                Ok(user_id)
+            })
+            .await
+            .clone();
+
+        // The next miss starts a new lookup, unless a newer one is already in place
+        let mut pending = self.pending();
+        if pending
+            .get(&key)
+            .map_or(false, |current| Arc::ptr_eq(current, &lookup))
+        {
+            pending.remove(&key);
+        }
+
+        result
+    }
+
+    fn local(&self) -> MutexGuard<'_, LruCache<UserId>> {
+        self.local
+            .lock()
+            .expect("the local users cache not to be poisoned")
+    }
+
+    fn local_rejected(&self) -> MutexGuard<'_, LruCache<()>> {
+        self.local_rejected
+            .lock()
+            .expect("the rejected tokens cache not to be poisoned")
+    }
+
+    fn pending(&self) -> MutexGuard<'_, HashMap<String, PendingLookup>> {
+        self.pending
+            .lock()
+            .expect("the pending user lookups not to be poisoned")
+    }
+}
+
+fn user_tokens_key(social_id: &str) -> String {
+    format!("{USER_TOKENS_KEY_PREFIX}:{}", social_id.to_lowercase())
+}
+
+fn rejected_token_key(hashed_token: &str) -> String {
+    format!("{REJECTED_TOKEN_KEY_PREFIX}:{hashed_token}")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -117,43 +497,19 @@ pub struct UserId {
 }
 /// Retrieve the user id associated with the given token.
 ///
-/// It first checks the user cache for the user id associated with the token.
+/// It first checks the in-process and Redis tiers of the users cache for the user id associated with the token.
 /// If the user id is not found in the cache, it calls `who_am_i` on the `SynapseComponent` to get the user id,
 /// then adds the token and user id to the cache before returning the user id.
 pub async fn get_user_id_from_token(
     synapse: SynapseComponent,
-    users_cache: Arc<Mutex<UsersCacheComponent>>,
+    users_cache: Arc<UsersCacheComponent>,
     token: &String,
 ) -> Result<UserId, CommonError> {
-    // Drop mutex lock at the end of scope.
-    {
-        let mut user_cache = users_cache.lock().await;
-        match user_cache.get_user(token).await {
-            Ok(user_id) => Ok(user_id),
-            Err(e) => {
-                log::info!("trying to get user {token} but {e}");
-                match synapse.who_am_i(token).await {
-                    Ok(response) => {
-                        let user_id = UserId {
-                            social_id: response.social_user_id.unwrap(),
-                            synapse_id: response.user_id,
-                        };
-
-                        if let Err(err) = user_cache
-                            .add_user(token, &user_id.social_id, &user_id.synapse_id, None)
-                            .await
-                        {
-                            log::error!(
-                                "Get user id from token > check_auth.rs > Error on storing token into Redis: {:?}",
-                                err
-                            )
-                        }
-
-                        Ok(user_id)
-                    }
-                    Err(err) => Err(err),
-                }
-            }
+    match users_cache.get_user(token).await {
+        Ok(user_id) => Ok(user_id),
+        Err(e) => {
+            log::info!("trying to get user {token} but {e}");
+            users_cache.resolve_token(&synapse, token).await
         }
     }
 }
@@ -188,6 +544,14 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_user_tokens_key_is_case_insensitive() {
+        assert_eq!(
+            user_tokens_key("0xAbC"),
+            format!("{USER_TOKENS_KEY_PREFIX}:0xabc")
+        );
+    }
+
     #[test]
     fn test_hash_with_different_string() {
         // Should be deterministic for the same string twice
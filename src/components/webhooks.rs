@@ -0,0 +1,492 @@
+use std::{sync::Arc, time::Duration};
+
+use deadpool_redis::redis::{cmd, RedisResult};
+use futures_util::future::join_all;
+use prometheus::{IntCounterVec, Opts};
+use reqwest::{header::CONTENT_TYPE, StatusCode};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Uuid;
+
+use crate::{
+    domain::{error::CommonError, friendship_event::FriendshipEvent},
+    entities::webhook_deliveries::{
+        DeliveryStatus, NewWebhookDelivery, WebhookDeliveriesRepositoryImplementation,
+        WebhookDelivery,
+    },
+    friendships::friendship_event_response::Body,
+    notifications::Event,
+};
+
+use super::{
+    notifications::{ChannelSubscriber, EVENT_UPDATES_CHANNEL_NAME},
+    redis::Redis,
+    users_cache::hash_with_key,
+};
+
+/// HMAC-SHA256 of the body with the signing key, hex encoded
+pub const SIGNATURE_HEADER: &str = "X-Social-Signature";
+/// Id of the delivery, the same on every attempt and replay
+pub const DELIVERY_HEADER: &str = "X-Social-Delivery";
+
+/// Marks an event as taken by an instance, so only one of them delivers it
+const WEBHOOK_EVENT_KEY_PREFIX: &str = "webhook_event";
+const WEBHOOK_EVENT_CLAIM_SECONDS: u64 = 10;
+
+const REQUEST_TIMEOUT_SECONDS: u64 = 10;
+/// Pending deliveries resumed at once by the sweep
+const SWEEP_BATCH_SIZE: i64 = 100;
+
+const WEBHOOK_ATTEMPTS: (&str, &str) = (
+    "dcl_social_service_webhook_attempts_total",
+    "Social Service Webhook Delivery Attempts",
+);
+const ATTEMPT_DELIVERED: &str = "delivered";
+const ATTEMPT_FAILED: &str = "failed";
+
+#[derive(Clone)]
+struct WebhooksMetrics {
+    attempts_total: IntCounterVec,
+}
+
+impl WebhooksMetrics {
+    fn new() -> Self {
+        let attempts_total = IntCounterVec::new(
+            Opts::new(WEBHOOK_ATTEMPTS.0, WEBHOOK_ATTEMPTS.1),
+            &["result"],
+        )
+        .expect("Metrics definition is correct, so the webhook attempts metric should be created successfully");
+
+        Self { attempts_total }
+    }
+}
+
+/// Body posted to the subscribers.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: FriendshipEvent,
+    /// User who triggered the event
+    pub from: String,
+    pub to: String,
+    /// When the event was dispatched, in seconds
+    pub timestamp: i64,
+}
+
+/// Parses a comma separated list of event names, e.g. `accept,delete`.
+pub fn parse_webhook_events(events: &str) -> Result<Vec<FriendshipEvent>, String> {
+    events
+        .split(',')
+        .map(str::trim)
+        .filter(|event| !event.is_empty())
+        .map(|event| {
+            serde_json::from_value(serde_json::Value::String(event.to_lowercase()))
+                .map_err(|_| format!("Unknown friendship event {event}"))
+        })
+        .collect()
+}
+
+/// Posts the friendship events published on the events channel to the configured subscribers.
+///
+/// Every delivery is persisted along with the outcome of its last attempt, so the failed ones can
+/// be inspected and replayed through the admin API, and the pending ones left behind by a stopped
+/// instance are resumed by the sweep.
+#[derive(Clone)]
+pub struct WebhooksComponent {
+    urls: Vec<String>,
+    signing_key: String,
+    events: Vec<FriendshipEvent>,
+    max_attempts: u32,
+    retry_backoff: Duration,
+    client: reqwest::Client,
+    deliveries: Arc<dyn WebhookDeliveriesRepositoryImplementation>,
+    /// `None` when there's no Redis, the deliveries table lets only one instance deliver an event
+    redis: Option<Redis>,
+    metrics: WebhooksMetrics,
+}
+
+impl WebhooksComponent {
+    pub fn new(
+        urls: Vec<String>,
+        signing_key: String,
+        events: Vec<FriendshipEvent>,
+        deliveries: Arc<dyn WebhookDeliveriesRepositoryImplementation>,
+        redis: Option<Redis>,
+    ) -> Self {
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECONDS))
+            .build()
+            .expect("to build the webhooks http client");
+
+        Self {
+            urls,
+            signing_key,
+            events,
+            max_attempts: 5,
+            retry_backoff: Duration::from_secs(1),
+            client,
+            deliveries,
+            redis,
+            metrics: WebhooksMetrics::new(),
+        }
+    }
+
+    /// * `max_attempts` - Attempts of each delivery before it's marked as failed.
+    /// * `retry_backoff` - Wait before the second attempt, doubled on each of the following ones.
+    pub fn with_retries(mut self, max_attempts: u32, retry_backoff: Duration) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self.retry_backoff = retry_backoff;
+        self
+    }
+
+    /// Collectors to expose the delivery attempts along with the rest of the metrics
+    pub fn metrics_collectors(&self) -> Vec<Box<dyn prometheus::core::Collector>> {
+        vec![Box::new(self.metrics.attempts_total.clone())]
+    }
+
+    /// Delivers the events published on the events channel from now on.
+    pub fn subscribe(&self, subscriber: &impl ChannelSubscriber) {
+        let webhooks = self.clone();
+        subscriber.subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event: Event| {
+            let webhooks = webhooks.clone();
+            async move {
+                // The retries shouldn't hold the following events back
+                tokio::spawn(async move { webhooks.dispatch(&event).await });
+            }
+        });
+    }
+
+    /// Resumes the stale pending deliveries now and then every `interval`, 0 to not resume them.
+    pub fn spawn_pending_sweep(&self, interval: Duration) {
+        if interval.is_zero() {
+            return;
+        }
+
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            loop {
+                webhooks.resume_pending(webhooks.stale_after()).await;
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// Attempts the pending deliveries without attempts for `stale_after` again, until they're
+    /// delivered or out of attempts.
+    pub async fn resume_pending(&self, stale_after: Duration) {
+        loop {
+            let deliveries = match self
+                .deliveries
+                .take_stale_pending(stale_after, SWEEP_BATCH_SIZE)
+                .await
+            {
+                Ok(deliveries) => deliveries,
+                Err(err) => {
+                    log::error!("[Webhooks] Couldn't read the pending deliveries: {err}");
+                    return;
+                }
+            };
+            if deliveries.is_empty() {
+                return;
+            }
+
+            log::info!(
+                "[Webhooks] Resuming {} pending deliveries",
+                deliveries.len()
+            );
+            join_all(deliveries.iter().map(|delivery| async move {
+                let signature = self.signature_of(delivery);
+                self.attempt_until_done(
+                    delivery.id,
+                    &delivery.url,
+                    &delivery.payload,
+                    &signature,
+                    delivery.attempts.max(0) as u32,
+                )
+                .await
+            }))
+            .await;
+        }
+    }
+
+    /// Delivers the event to every subscriber, if it's one of the configured events and no other
+    /// instance took it.
+    ///
+    /// The deliveries are saved before the event is claimed, so they're resumed by the sweep if
+    /// the instance that claimed it stops before delivering them.
+    pub async fn dispatch(&self, event: &Event) {
+        let Some(friendship_event) = friendship_event_of(event) else {
+            return;
+        };
+        if !self.events.contains(&friendship_event) {
+            return;
+        }
+        if event.id.is_empty() {
+            // Every instance would deliver it, as there's nothing to tell them apart
+            log::warn!("[Webhooks] Skipping an event without id, published by an older version");
+            return;
+        }
+
+        let payload = WebhookPayload {
+            event: friendship_event,
+            from: event.from.clone(),
+            to: event.to.clone(),
+            timestamp: chrono::Utc::now().timestamp(),
+        };
+        let payload = serde_json::to_string(&payload).expect("the payload to be serializable");
+        let signature = hash_with_key(&payload, &self.signing_key);
+
+        let saved = join_all(self.urls.iter().map(|url| {
+            let delivery = NewWebhookDelivery {
+                event_id: &event.id,
+                url,
+                event: friendship_event.as_str(),
+                from_address: &event.from,
+                to_address: &event.to,
+                payload: &payload,
+                signature: &signature,
+            };
+            async move { (url, self.save(delivery).await) }
+        }))
+        .await;
+        let saved: Vec<(&String, Uuid)> = saved
+            .into_iter()
+            .filter_map(|(url, id)| id.map(|id| (url, id)))
+            .collect();
+        if saved.is_empty() || !self.claim(&event.id).await {
+            return;
+        }
+
+        join_all(
+            saved
+                .iter()
+                .map(|(url, id)| self.attempt_until_done(*id, url, &payload, &signature, 0)),
+        )
+        .await;
+    }
+
+    /// Attempts the delivery once more, regardless of its status.
+    ///
+    /// Returns the delivery after the attempt, `None` if it doesn't exist.
+    pub async fn replay(&self, id: Uuid) -> Result<Option<WebhookDelivery>, CommonError> {
+        let Some(delivery) = self.deliveries.get(id).await.map_err(|err| {
+            log::error!("[Webhooks] Couldn't read delivery {id}: {err}");
+            CommonError::Unknown("".to_owned())
+        })?
+        else {
+            return Ok(None);
+        };
+
+        self.attempt(
+            id,
+            &delivery.url,
+            &delivery.payload,
+            &self.signature_of(&delivery),
+            true,
+        )
+        .await;
+
+        self.deliveries.get(id).await.map_err(|err| {
+            log::error!("[Webhooks] Couldn't read delivery {id}: {err}");
+            CommonError::Unknown("".to_owned())
+        })
+    }
+
+    /// Saves the pending delivery, returns its id unless another instance already saved it.
+    async fn save(&self, delivery: NewWebhookDelivery<'_>) -> Option<Uuid> {
+        let url = delivery.url;
+        match self.deliveries.create(delivery).await {
+            Ok(id) => id,
+            Err(err) => {
+                log::error!("[Webhooks] Couldn't save the delivery to {url}: {err}");
+                None
+            }
+        }
+    }
+
+    /// The signature the delivery was first sent with, the ones saved before it was stored are
+    /// signed again with the current key.
+    fn signature_of(&self, delivery: &WebhookDelivery) -> String {
+        delivery
+            .signature
+            .clone()
+            .unwrap_or_else(|| hash_with_key(&delivery.payload, &self.signing_key))
+    }
+
+    /// Attempts the delivery until it's delivered or out of attempts, `attempts` were already made.
+    async fn attempt_until_done(
+        &self,
+        id: Uuid,
+        url: &str,
+        payload: &str,
+        signature: &str,
+        attempts: u32,
+    ) {
+        let mut backoff = self.retry_backoff;
+        // A resumed delivery out of attempts gets a last one
+        for attempt in (attempts + 1).min(self.max_attempts)..=self.max_attempts {
+            let is_last = attempt == self.max_attempts;
+            match self.attempt(id, url, payload, signature, is_last).await {
+                DeliveryStatus::Pending => {
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                DeliveryStatus::Delivered | DeliveryStatus::Failed => return,
+            }
+        }
+    }
+
+    /// How long a delivery being attempted can go without an update, the longest backoff and
+    /// request timeout, so the sweep only resumes the ones nobody is attempting anymore.
+    fn stale_after(&self) -> Duration {
+        self.retry_backoff
+            .saturating_mul(1 << self.max_attempts.min(16))
+            .saturating_add(Duration::from_secs(REQUEST_TIMEOUT_SECONDS))
+    }
+
+    /// Posts the payload and records the outcome. The delivery is left pending when the attempt
+    /// failed and can be retried.
+    async fn attempt(
+        &self,
+        id: Uuid,
+        url: &str,
+        payload: &str,
+        signature: &str,
+        is_last: bool,
+    ) -> DeliveryStatus {
+        let response = self
+            .client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, signature)
+            .header(DELIVERY_HEADER, id.to_string())
+            .body(payload.to_string())
+            .send()
+            .await;
+
+        let (status, status_code, error) = match response {
+            Ok(response) if response.status().is_success() => {
+                (DeliveryStatus::Delivered, Some(response.status()), None)
+            }
+            Ok(response) => {
+                let status_code = response.status();
+                let error = format!("Unexpected status code {status_code}");
+                let status = if is_last || !is_retryable(status_code) {
+                    DeliveryStatus::Failed
+                } else {
+                    DeliveryStatus::Pending
+                };
+                (status, Some(status_code), Some(error))
+            }
+            Err(err) => {
+                let status = if is_last {
+                    DeliveryStatus::Failed
+                } else {
+                    DeliveryStatus::Pending
+                };
+                (status, None, Some(err.to_string()))
+            }
+        };
+
+        let result = if error.is_none() {
+            ATTEMPT_DELIVERED
+        } else {
+            log::warn!("[Webhooks] Couldn't deliver {id} to {url}: {error:?}");
+            ATTEMPT_FAILED
+        };
+        self.metrics
+            .attempts_total
+            .with_label_values(&[result])
+            .inc();
+
+        if let Err(err) = self
+            .deliveries
+            .record_attempt(
+                id,
+                status,
+                status_code.map(|status_code| status_code.as_u16()),
+                error.as_deref(),
+            )
+            .await
+        {
+            log::error!("[Webhooks] Couldn't record the attempt of {id}: {err}");
+        }
+
+        status
+    }
+
+    /// Whether this instance should deliver the event it saved deliveries of. Every instance
+    /// receives the events, and the uniqueness of the event id and URL of the deliveries lets only
+    /// one save each of them, so the first one to mark its id in Redis delivers them and the
+    /// deliveries saved by another instance are left to the sweep. Without Redis, or when it's
+    /// unavailable, every instance delivers the ones it saved.
+    async fn claim(&self, event_id: &str) -> bool {
+        let Some(redis) = &self.redis else {
+            return true;
+        };
+        let Some(mut connection) = redis.get_async_connection().await else {
+            return true;
+        };
+
+        let claimed: RedisResult<Option<String>> = cmd("SET")
+            .arg(format!("{WEBHOOK_EVENT_KEY_PREFIX}:{event_id}"))
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(WEBHOOK_EVENT_CLAIM_SECONDS)
+            .query_async(&mut connection)
+            .await;
+
+        match claimed {
+            Ok(claimed) => claimed.is_some(),
+            Err(err) => {
+                log::warn!("[Webhooks] Couldn't claim the event: {err}");
+                true
+            }
+        }
+    }
+}
+
+/// Client errors won't succeed by retrying, except timeouts and rate limits.
+fn is_retryable(status_code: StatusCode) -> bool {
+    !status_code.is_client_error()
+        || status_code == StatusCode::REQUEST_TIMEOUT
+        || status_code == StatusCode::TOO_MANY_REQUESTS
+}
+
+fn friendship_event_of(event: &Event) -> Option<FriendshipEvent> {
+    let body = event.friendship_event.as_ref()?.body.as_ref()?;
+
+    Some(match body {
+        Body::Request(_) => FriendshipEvent::REQUEST,
+        Body::Accept(_) => FriendshipEvent::ACCEPT,
+        Body::Reject(_) => FriendshipEvent::REJECT,
+        Body::Cancel(_) => FriendshipEvent::CANCEL,
+        Body::Delete(_) => FriendshipEvent::DELETE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use super::{is_retryable, parse_webhook_events};
+    use crate::domain::friendship_event::FriendshipEvent;
+
+    #[test]
+    fn test_parse_webhook_events() {
+        assert_eq!(
+            parse_webhook_events("accept, DELETE").unwrap(),
+            vec![FriendshipEvent::ACCEPT, FriendshipEvent::DELETE]
+        );
+        assert!(parse_webhook_events("").unwrap().is_empty());
+        assert!(parse_webhook_events("accept,befriend").is_err());
+    }
+
+    #[test]
+    fn test_only_transient_errors_are_retried() {
+        assert!(is_retryable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(is_retryable(StatusCode::REQUEST_TIMEOUT));
+        assert!(!is_retryable(StatusCode::BAD_REQUEST));
+        assert!(!is_retryable(StatusCode::NOT_FOUND));
+    }
+}
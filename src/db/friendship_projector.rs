@@ -0,0 +1,167 @@
+// Rebuilds the `friendships` table, a projection of `friendship_history`, by replaying the history of each friendship.
+// It can run while the service is up: the friendships are updated one by one, and the ones that get a new event
+// while being replayed are left as they are.
+use std::collections::HashMap;
+
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    components::database::DBRepositories,
+    domain::{
+        error::CommonError,
+        friendship_projection::{FriendshipProjection, InvalidHistoryEvent},
+    },
+    entities::{friendship_history::UserFriendshipHistory, friendships::Friendship},
+};
+
+#[derive(Debug, Clone)]
+pub struct ProjectionOptions {
+    /// Friendships replayed at once
+    pub batch_size: i64,
+    /// Whether to overwrite the friendships that drifted, otherwise it's a dry run
+    pub apply: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FriendshipProjectionReport {
+    pub friendship_id: Uuid,
+    /// Whether `is_active` doesn't match the history
+    pub drifted: bool,
+    pub rebuilt: bool,
+    pub invalid_events: Vec<InvalidHistoryEvent>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ProjectionReport {
+    pub scanned: u64,
+    pub drifted: u64,
+    pub rebuilt: u64,
+    /// Only the friendships that drifted or have invalid events
+    pub friendships: Vec<FriendshipProjectionReport>,
+}
+
+/// Replays the history of every friendship in batches, reports the invalid sequences
+/// and, in apply mode, rewrites the friendships that don't match their history.
+///
+/// The friendships with invalid events are never rewritten, their history has to be fixed first.
+pub async fn rebuild_friendships_projection(
+    repos: &DBRepositories,
+    options: &ProjectionOptions,
+) -> Result<ProjectionReport, CommonError> {
+    let mut report = ProjectionReport::default();
+    let mut after = None;
+
+    loop {
+        let (page, _) = repos
+            .friendships
+            .get_friendships_page(after, options.batch_size, None)
+            .await;
+        let page = page.map_err(|err| {
+            log::error!("[Projector] Couldn't fetch friendships: {err}");
+            CommonError::Unknown("".to_owned())
+        })?;
+
+        let Some(last) = page.last() else {
+            break;
+        };
+        after = Some(last.id);
+
+        let ids: Vec<Uuid> = page.iter().map(|friendship| friendship.id).collect();
+        let history = repos
+            .friendship_history
+            .get_friendships_history(&ids)
+            .await
+            .map_err(|err| {
+                log::error!("[Projector] Couldn't fetch friendships history: {err}");
+                CommonError::Unknown("".to_owned())
+            })?;
+        let mut history_by_friendship = group_by_friendship(history);
+
+        for friendship in &page {
+            report.scanned += 1;
+
+            let history = history_by_friendship
+                .remove(&friendship.id)
+                .unwrap_or_default();
+            let projection = FriendshipProjection::replay(&history);
+
+            let drifted = friendship.is_active != projection.is_active();
+            let trusted = projection.invalid_events.is_empty();
+            if drifted && options.apply && !trusted {
+                log::warn!(
+                    "[Projector] Friendship {} has invalid events, it's left as it is",
+                    friendship.id
+                );
+            }
+            let rebuilt = drifted
+                && options.apply
+                && trusted
+                && rebuild(repos, friendship, &projection).await;
+
+            report.drifted += drifted as u64;
+            report.rebuilt += rebuilt as u64;
+            if drifted || !projection.invalid_events.is_empty() {
+                report.friendships.push(FriendshipProjectionReport {
+                    friendship_id: friendship.id,
+                    drifted,
+                    rebuilt,
+                    invalid_events: projection.invalid_events,
+                });
+            }
+        }
+
+        log::info!("[Projector] {} friendships replayed", report.scanned);
+    }
+
+    Ok(report)
+}
+
+/// Overwrites the friendship unless it got a new event after it was read.
+async fn rebuild(
+    repos: &DBRepositories,
+    friendship: &Friendship,
+    projection: &FriendshipProjection,
+) -> bool {
+    let (result, _) = repos
+        .friendships
+        .update_friendship_projection(
+            &friendship.id,
+            projection.is_active(),
+            projection
+                .last_interaction_at
+                .unwrap_or(friendship.updated_at),
+            projection.friends_since,
+            friendship.updated_at,
+            None,
+        )
+        .await;
+
+    match result {
+        Ok(true) => true,
+        Ok(false) => {
+            log::info!(
+                "[Projector] Friendship {} changed while being replayed, it's left as it is",
+                friendship.id
+            );
+            false
+        }
+        Err(err) => {
+            log::error!(
+                "[Projector] Couldn't rebuild friendship {}: {err}",
+                friendship.id
+            );
+            false
+        }
+    }
+}
+
+fn group_by_friendship(
+    history: Vec<UserFriendshipHistory>,
+) -> HashMap<Uuid, Vec<UserFriendshipHistory>> {
+    let mut grouped: HashMap<Uuid, Vec<UserFriendshipHistory>> = HashMap::new();
+    for entry in history {
+        grouped.entry(entry.friendship_id).or_default().push(entry);
+    }
+    grouped
+}
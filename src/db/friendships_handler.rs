@@ -1,28 +1,38 @@
 // Responsible for managing friendship relationships between two users,
 // The errors of this file are coupled with the `ws` scope.
-use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
+    components::database::DatabaseTransaction,
     db::types::FriendshipDbRepositories,
-    domain::{error::CommonError, friendship_status::FriendshipStatus, room::RoomInfo},
+    domain::{
+        address::Address,
+        error::CommonError,
+        friendship_event::{FriendshipEvent, ADMIN_ACTING_USER},
+        friendship_limits::{FriendshipLimits, LimitReached},
+        friendship_status::FriendshipStatus,
+        room::RoomInfo,
+    },
     entities::{
-        friendship_history::{FriendshipHistory, FriendshipHistoryRepository, FriendshipMetadata},
-        friendships::{Friendship, FriendshipRepositoryImplementation, FriendshipsRepository},
+        friendship_history::{
+            FriendshipHistory, FriendshipHistoryRepositoryImplementation, FriendshipMetadata,
+        },
+        friendships::{Friendship, FriendshipRepositoryImplementation},
+        user_features::UserFeaturesRepositoryImplementation,
     },
 };
 
 /// Retrieves a friendship relationship between two addresses
 ///
-/// * `friendships_repository` - A reference to the `FriendshipsRepository` instance.
+/// * `friendships_repository` - A reference to the friendships repository.
 /// * `address_1` - The address to look for in the friendship relationship.
 /// * `address_2` - The address to look for in the friendship relationship.
 ///
 /// Returns an `Option<Friendship>` if the friendship was found, or a `FriendshipServiceError` if an error occurs.
 pub async fn get_friendship(
-    friendships_repository: &FriendshipsRepository,
-    address_1: &str,
-    address_2: &str,
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
+    address_1: &Address,
+    address_2: &Address,
 ) -> Result<Option<Friendship>, CommonError> {
     let (friendship_result, _) = friendships_repository
         .get_friendship((address_1, address_2), None)
@@ -36,12 +46,12 @@ pub async fn get_friendship(
 
 /// Fetches the last friendship history for a given friendship.
 ///
-/// * `friendship_history_repository` - A reference to the `FriendshipHistoryRepository` instance.
+/// * `friendship_history_repository` - A reference to the friendship history repository.
 /// * `friendship` - An `Option<Friendship>` to fetch the last history for.
 ///
 /// Returns an `Option<FriendshipHistory>` if the last history was found, or a `FriendshipServiceError` if an error occurs.
 pub async fn get_last_history(
-    friendship_history_repository: &FriendshipHistoryRepository,
+    friendship_history_repository: &dyn FriendshipHistoryRepositoryImplementation,
     friendship: &Option<Friendship>,
 ) -> Result<Option<FriendshipHistory>, CommonError> {
     let friendship = {
@@ -61,22 +71,197 @@ pub async fn get_last_history(
     })
 }
 
+/// Fetches the limits of the user, the default ones unless the user has features overriding them.
+pub async fn get_friendship_limits(
+    user_features_repository: &dyn UserFeaturesRepositoryImplementation,
+    defaults: FriendshipLimits,
+    address: &Address,
+) -> Result<FriendshipLimits, CommonError> {
+    let features = user_features_repository
+        .get_all_user_features(address)
+        .await
+        .map_err(|err| {
+            log::error!("Database handler > Get friendship limits > Error {err}");
+            CommonError::Unknown("There was an error retrieving the user limits".to_owned())
+        })?;
+
+    Ok(match features {
+        Some(features) => defaults.with_overrides(features.features.iter().map(|feature| {
+            (
+                feature.feature_name.as_str(),
+                feature.feature_value.as_str(),
+            )
+        })),
+        None => defaults,
+    })
+}
+
+/// Fails with the limit reached if the new event takes one of the users over their limits.
+///
+/// The limits of each user are the default ones unless overridden by the user features.
+/// The friendships of both users are locked until the transaction ends, so concurrent updates
+/// can't go over the limits together. The transaction is rolled back on failure.
+pub async fn check_friendship_limits<'a>(
+    new_event: FriendshipEvent,
+    acting_user: &'a Address,
+    second_user: &'a Address,
+    default_limits: FriendshipLimits,
+    user_features_repository: &dyn UserFeaturesRepositoryImplementation,
+    friendship_ports: &FriendshipDbRepositories<'a>,
+    transaction: DatabaseTransaction,
+) -> Result<DatabaseTransaction, CommonError> {
+    if !matches!(
+        new_event,
+        FriendshipEvent::REQUEST | FriendshipEvent::ACCEPT
+    ) {
+        return Ok(transaction);
+    }
+
+    let limits = (
+        get_friendship_limits(user_features_repository, default_limits, acting_user).await,
+        get_friendship_limits(user_features_repository, default_limits, second_user).await,
+    );
+    let (acting_user_limits, second_user_limits) = match limits {
+        (Ok(acting_user_limits), Ok(second_user_limits)) => {
+            (acting_user_limits, second_user_limits)
+        }
+        (Err(err), _) | (_, Err(err)) => {
+            let _ = transaction.rollback().await;
+            return Err(err);
+        }
+    };
+
+    let (result, transaction) = friendship_ports
+        .friendships_repository
+        .lock_users_friendships((acting_user, second_user), transaction)
+        .await;
+    if result.is_err() {
+        let _ = transaction.rollback().await;
+        return Err(CommonError::Unknown(
+            "There was an error storing friendship update".to_owned(),
+        ));
+    }
+
+    let (result, transaction) = match new_event {
+        FriendshipEvent::REQUEST => {
+            let (requests, transaction) = friendship_ports
+                .friendship_history_repository
+                .count_outgoing_pending_requests(acting_user, Some(transaction))
+                .await;
+            let (friends, transaction) = count_active_friends(
+                friendship_ports.friendships_repository,
+                acting_user,
+                transaction.unwrap(),
+            )
+            .await;
+
+            let result = requests.and_then(|requests| {
+                Ok(LimitReached::check(
+                    requests,
+                    acting_user_limits.max_outgoing_requests,
+                    LimitReached::OutgoingRequests,
+                )
+                .and(LimitReached::check(
+                    friends?,
+                    acting_user_limits.max_friends,
+                    LimitReached::Friends,
+                )))
+            });
+            (result, transaction)
+        }
+        _ => {
+            let (acting_user_friends, transaction) = count_active_friends(
+                friendship_ports.friendships_repository,
+                acting_user,
+                transaction,
+            )
+            .await;
+            let (second_user_friends, transaction) = count_active_friends(
+                friendship_ports.friendships_repository,
+                second_user,
+                transaction,
+            )
+            .await;
+
+            let result = acting_user_friends.and_then(|acting_user_friends| {
+                Ok(LimitReached::check(
+                    acting_user_friends,
+                    acting_user_limits.max_friends,
+                    LimitReached::Friends,
+                )
+                .and(LimitReached::check(
+                    second_user_friends?,
+                    second_user_limits.max_friends,
+                    LimitReached::OtherUserFriends,
+                )))
+            });
+            (result, transaction)
+        }
+    };
+
+    match result {
+        Ok(Ok(())) => Ok(transaction),
+        Ok(Err(limit_reached)) => {
+            log::info!(
+                "Database handler > Check friendship limits > {acting_user} reached {limit_reached:?}"
+            );
+            let _ = transaction.rollback().await;
+            Err(limit_reached.into())
+        }
+        Err(err) => {
+            log::error!("Database handler > Check friendship limits > Error {err}");
+            let _ = transaction.rollback().await;
+            Err(CommonError::Unknown(
+                "There was an error storing friendship update".to_owned(),
+            ))
+        }
+    }
+}
+
+async fn count_active_friends(
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
+    address: &Address,
+    transaction: DatabaseTransaction,
+) -> (Result<u64, sqlx::Error>, DatabaseTransaction) {
+    let (count, transaction) = friendships_repository
+        .count_active_friends(address, Some(transaction))
+        .await;
+
+    (count, transaction.unwrap())
+}
+
 /// Stores updates to a friendship or creates a new friendship if it does not exist.
 async fn store_friendship_update(
-    friendships_repository: &FriendshipsRepository,
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
     friendship: &Option<Friendship>,
     is_active: bool,
-    address_1: &str,
-    address_2: &str,
+    address_1: &Address,
+    address_2: &Address,
     synapse_room_id: &str,
-    transaction: Transaction<'static, Postgres>,
-) -> (Result<Uuid, CommonError>, Transaction<'static, Postgres>) {
+    transaction: DatabaseTransaction,
+) -> (Result<Uuid, CommonError>, DatabaseTransaction) {
+    // Updates without a room, e.g. the ones forced by an admin, keep the one of the friendship
+    let new_room_id = Some(synapse_room_id).filter(|room_id| !room_id.is_empty());
+
     match friendship {
         Some(friendship) => {
             let (res, transaction) = friendships_repository
                 .update_friendship_status(&friendship.id, is_active, Some(transaction))
                 .await;
 
+            // The previous room was cleaned up when the friendship ended, so a new one was created
+            let (res, transaction) = match res {
+                Ok(_)
+                    if new_room_id.is_some()
+                        && friendship.synapse_room_id.as_deref() != new_room_id =>
+                {
+                    friendships_repository
+                        .update_synapse_room_id(&friendship.id, new_room_id, transaction)
+                        .await
+                }
+                res => (res, transaction),
+            };
+
             let res = match res {
                 Ok(_) => Ok(friendship.id),
                 Err(err) => {
@@ -112,13 +297,13 @@ async fn store_friendship_update(
 /// Updates the friendship status in the friendship table and stores an update in the friendship_history table.
 pub async fn update_friendship_status<'a>(
     friendship: &'a Option<Friendship>,
-    acting_user: &'a str,
-    second_user: &'a str,
+    acting_user: &'a Address,
+    second_user: &'a Address,
     new_status: FriendshipStatus,
     room_info: RoomInfo<'a>,
     friendship_ports: FriendshipDbRepositories<'a>,
-    transaction: Transaction<'static, Postgres>,
-) -> Result<Transaction<'static, Postgres>, CommonError> {
+    transaction: DatabaseTransaction,
+) -> Result<DatabaseTransaction, CommonError> {
     // Store friendship update
     let is_active = new_status == FriendshipStatus::Friends;
     let (friendship_id_result, transaction) = store_friendship_update(
@@ -158,8 +343,11 @@ pub async fn update_friendship_status<'a>(
 
     let metadata = sqlx::types::Json(FriendshipMetadata {
         message: room_info.room_message_body.map(|m| m.to_string()),
-        synapse_room_id: Some(room_info.room_id.to_string()),
+        synapse_room_id: Some(room_info.room_id)
+            .filter(|room_id| !room_id.is_empty())
+            .map(str::to_string),
         migrated_from_synapse: None,
+        forced_by_admin: (acting_user.as_str() == ADMIN_ACTING_USER).then_some(true),
     });
 
     // Store history
@@ -187,3 +375,19 @@ pub async fn update_friendship_status<'a>(
         }
     }
 }
+
+/// Unlinks the friendship from its Synapse room once the room was cleaned up,
+/// so the next request creates a new one.
+pub async fn clear_synapse_room_id(
+    friendships_repository: &dyn FriendshipRepositoryImplementation,
+    friendship_id: &Uuid,
+) -> Result<(), CommonError> {
+    let (result, _) = friendships_repository
+        .update_synapse_room_id(friendship_id, None, None)
+        .await;
+
+    result.map_err(|err| {
+        log::error!("Database handler > Clear synapse room id > Error {err}");
+        CommonError::Unknown("There was an error updating the friendship".to_owned())
+    })
+}
@@ -1,2 +1,3 @@
+pub mod friendship_projector;
 pub mod friendships_handler;
 pub mod types;
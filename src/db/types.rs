@@ -1,12 +1,13 @@
 use crate::{
-    components::database::DatabaseComponent,
+    components::database::DatabaseComponentImplementation,
     entities::{
-        friendship_history::FriendshipHistoryRepository, friendships::FriendshipsRepository,
+        friendship_history::FriendshipHistoryRepositoryImplementation,
+        friendships::FriendshipRepositoryImplementation,
     },
 };
 
 pub struct FriendshipDbRepositories<'a> {
-    pub db: &'a DatabaseComponent,
-    pub friendships_repository: &'a FriendshipsRepository,
-    pub friendship_history_repository: &'a FriendshipHistoryRepository,
+    pub db: &'a dyn DatabaseComponentImplementation,
+    pub friendships_repository: &'a dyn FriendshipRepositoryImplementation,
+    pub friendship_history_repository: &'a dyn FriendshipHistoryRepositoryImplementation,
 }
@@ -1,54 +1,168 @@
-use std::hash::{Hash, Hasher};
+use std::{fmt, str::FromStr};
 
-#[derive(Debug)]
-pub struct Address(pub String);
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-impl PartialEq for Address {
-    fn eq(&self, other: &Self) -> bool {
-        self.0.eq_ignore_ascii_case(&other.0)
-    }
+use super::error::CommonError;
+
+const ADDRESS_PREFIX: &str = "0x";
+const ADDRESS_HEX_LENGTH: usize = 40;
+
+/// An Ethereum address (`0x` followed by 40 hex chars), always stored in lowercase.
+///
+/// Addresses are normalized when parsed, so they can be compared, hashed and persisted
+/// without caring about the casing the client sent.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Address(String);
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum AddressError {
+    #[error("`{0}` is not a valid address")]
+    InvalidFormat(String),
 }
 
-impl Eq for Address {}
+impl Address {
+    pub fn parse(value: &str) -> Result<Self, AddressError> {
+        let hex = value
+            .strip_prefix(ADDRESS_PREFIX)
+            .or_else(|| value.strip_prefix("0X"))
+            .ok_or_else(|| AddressError::InvalidFormat(value.to_string()))?;
 
-impl Hash for Address {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        // Hashing each char to avoid copying the String
-        for c in self.0.as_bytes() {
-            c.to_ascii_lowercase().hash(state)
+        if hex.len() != ADDRESS_HEX_LENGTH || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(AddressError::InvalidFormat(value.to_string()));
         }
+
+        let normalized = hex.to_ascii_lowercase();
+
+        Ok(Self(format!("{ADDRESS_PREFIX}{normalized}")))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl FromStr for Address {
+    type Err = AddressError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::parse(value)
+    }
+}
+
+impl TryFrom<String> for Address {
+    type Error = AddressError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl TryFrom<&str> for Address {
+    type Error = AddressError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::parse(value)
+    }
+}
+
+impl From<Address> for String {
+    fn from(address: Address) -> Self {
+        address.0
+    }
+}
+
+impl AsRef<str> for Address {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<AddressError> for CommonError {
+    fn from(err: AddressError) -> Self {
+        CommonError::BadRequest(err.to_string())
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::address::Address;
+    use crate::domain::address::{Address, AddressError};
     use std::collections::HashMap;
 
+    const ALICE: &str = "0x1aB2c3D4e5F60718293a4B5c6D7e8F9012345678";
+    const BOB: &str = "0x9999999999999999999999999999999999999999";
+
     #[test]
     fn test_different_addresses() {
-        let first_address = Address("0xAlice".to_string());
-        let second_address = Address("0xBob".to_string());
+        let first_address = Address::parse(ALICE).unwrap();
+        let second_address = Address::parse(BOB).unwrap();
 
         assert_ne!(first_address, second_address);
     }
 
     #[test]
     fn test_same_address_lower() {
-        let first_address = Address("0xAlice".to_string());
-        let second_address = Address("0xaLICE".to_string());
+        let first_address = Address::parse(ALICE).unwrap();
+        let second_address = Address::parse(&ALICE.to_lowercase()).unwrap();
 
         assert_eq!(first_address, second_address);
+        assert_eq!(first_address.as_str(), ALICE.to_lowercase());
+    }
+
+    #[test]
+    fn test_upper_case_prefix_is_normalized() {
+        let address = Address::parse(&ALICE.replacen("0x", "0X", 1)).unwrap();
+
+        assert_eq!(address.as_str(), ALICE.to_lowercase());
     }
 
     #[test]
     fn test_hash_map() {
         let mut map = HashMap::new();
 
-        map.insert(Address("0xAlice".to_string()), "first_value");
+        map.insert(Address::parse(ALICE).unwrap(), "first_value");
+
+        assert!(map.contains_key(&Address::parse(ALICE).unwrap()));
+        let upper_alice = ALICE.to_uppercase().replacen("0X", "0x", 1);
+        assert!(map.contains_key(&Address::parse(&upper_alice).unwrap()));
+        assert!(!map.contains_key(&Address::parse(BOB).unwrap()));
+    }
+
+    #[test]
+    fn test_invalid_addresses() {
+        for invalid in [
+            "",
+            "0x",
+            "0xAlice",
+            "1aB2c3D4e5F60718293a4B5c6D7e8F9012345678",
+            "0x1aB2c3D4e5F60718293a4B5c6D7e8F901234567",
+            "0x1aB2c3D4e5F60718293a4B5c6D7e8F90123456789",
+            "0x1aB2c3D4e5F60718293a4B5c6D7e8F901234567g",
+            "@0x1ab2c3d4e5f60718293a4b5c6d7e8f9012345678:decentraland.org",
+        ] {
+            assert_eq!(
+                Address::parse(invalid),
+                Err(AddressError::InvalidFormat(invalid.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_serde_round_trip() {
+        let address: Address = serde_json::from_str(&format!("\"{ALICE}\"")).unwrap();
 
-        assert!(map.contains_key(&Address("0xAlice".to_string())));
-        assert!(map.contains_key(&Address("0xaLICE".to_string())));
-        assert!(!map.contains_key(&Address("0xBob".to_string())));
+        assert_eq!(address.as_str(), ALICE.to_lowercase());
+        assert_eq!(
+            serde_json::to_string(&address).unwrap(),
+            format!("\"{}\"", ALICE.to_lowercase())
+        );
+        assert!(serde_json::from_str::<Address>("\"0xAlice\"").is_err());
     }
 }
@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub code: u16,
     pub error: String,
@@ -24,6 +24,9 @@ pub enum CommonError {
     Unauthorized(String),
     #[error("Too many requests")]
     TooManyRequests(String),
+    /// A service we depend on is down, e.g. the Synapse circuit breaker is open
+    #[error("Service unavailable")]
+    ServiceUnavailable(String),
 }
 
 impl PartialEq for CommonError {
@@ -0,0 +1,77 @@
+use super::error::CommonError;
+
+const ADDRESS_PREFIX: &str = "0x";
+const MAX_SEARCH_LENGTH: usize = 42;
+
+/// A search term used to filter a user's friends.
+///
+/// It matches friends whose address starts with the term (with or without the `0x` prefix)
+/// and, when profile data is available, friends whose display name starts with it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendsSearch {
+    term: String,
+}
+
+impl FriendsSearch {
+    pub fn parse(value: &str) -> Result<Self, CommonError> {
+        let term = value.trim().to_lowercase();
+
+        if term.is_empty() {
+            return Err(CommonError::BadRequest(
+                "`search` must not be empty".to_owned(),
+            ));
+        }
+
+        if term.chars().count() > MAX_SEARCH_LENGTH {
+            return Err(CommonError::BadRequest(format!(
+                "`search` must have at most {MAX_SEARCH_LENGTH} characters"
+            )));
+        }
+
+        Ok(Self { term })
+    }
+
+    /// Returns the address prefix to look for, `None` when the term can't be part of an address.
+    pub fn address_prefix(&self) -> Option<String> {
+        let hex = self.term.strip_prefix(ADDRESS_PREFIX).unwrap_or(&self.term);
+
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        Some(format!("{ADDRESS_PREFIX}{hex}"))
+    }
+
+    pub fn matches_name(&self, name: &str) -> bool {
+        name.to_lowercase().starts_with(&self.term)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FriendsSearch;
+
+    #[test]
+    fn test_address_prefix_with_and_without_0x() {
+        let with_prefix = FriendsSearch::parse("0xAbC").unwrap();
+        let without_prefix = FriendsSearch::parse("abc").unwrap();
+
+        assert_eq!(with_prefix.address_prefix().as_deref(), Some("0xabc"));
+        assert_eq!(without_prefix.address_prefix().as_deref(), Some("0xabc"));
+    }
+
+    #[test]
+    fn test_non_hex_terms_are_not_address_prefixes() {
+        let search = FriendsSearch::parse("Pizarnik").unwrap();
+
+        assert_eq!(search.address_prefix(), None);
+        assert!(search.matches_name("pizarnik#1234"));
+        assert!(!search.matches_name("Martha"));
+    }
+
+    #[test]
+    fn test_invalid_terms() {
+        assert!(FriendsSearch::parse("  ").is_err());
+        assert!(FriendsSearch::parse(&"a".repeat(43)).is_err());
+    }
+}
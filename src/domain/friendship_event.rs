@@ -2,6 +2,9 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+/// Acting user recorded in the friendship history for the events forced by an admin.
+pub const ADMIN_ACTING_USER: &str = "0x0000000000000000000000000000000000000000";
+
 #[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy, Hash)]
 pub enum FriendshipEvent {
     #[serde(rename = "request")]
@@ -26,6 +29,11 @@ impl FriendshipEvent {
             FriendshipEvent::DELETE => "delete",
         }
     }
+
+    /// Whether the event rejects or ends a friendship, after which the room of the users isn't needed
+    pub fn ends_relationship(&self) -> bool {
+        matches!(self, FriendshipEvent::REJECT | FriendshipEvent::DELETE)
+    }
 }
 
 lazy_static::lazy_static! {
@@ -1,6 +1,9 @@
 use crate::{
-    domain::{error::CommonError, friendship_event::FriendshipEvent},
-    entities::friendship_history::FriendshipHistory,
+    domain::{
+        error::CommonError,
+        friendship_event::{FriendshipEvent, ADMIN_ACTING_USER},
+    },
+    entities::friendship_history::{is_forced_by_admin, FriendshipHistory},
 };
 
 /**
@@ -11,6 +14,12 @@ pub fn validate_new_event(
     last_recorded_history: &Option<FriendshipHistory>,
     new_event: FriendshipEvent,
 ) -> Result<(), CommonError> {
+    // The admin acting user is reserved for the events forced by an admin
+    if acting_user == ADMIN_ACTING_USER {
+        return Err(CommonError::Forbidden(
+            "The acting user is reserved for admins".to_owned(),
+        ));
+    }
     validate_transition(last_recorded_history, new_event)?;
     validate_auth_new_event(acting_user, last_recorded_history, new_event)?;
     Ok(())
@@ -46,8 +55,13 @@ fn validate_auth_new_event(
     last_recorded_history: &Option<FriendshipHistory>,
     new_event: FriendshipEvent,
 ) -> Result<(), CommonError> {
+    // No user acted last when an admin forced the event, so any of them can go on
+    let last_recorded_history = last_recorded_history
+        .as_ref()
+        .filter(|history| !is_forced_by_admin(&history.acting_user, &history.metadata));
+
     if let Some(last_history) = last_recorded_history {
-        if last_history.acting_user.eq_ignore_ascii_case(acting_user) {
+        if last_history.acting_user == acting_user {
             match new_event {
                 FriendshipEvent::ACCEPT => {
                     log::error!(
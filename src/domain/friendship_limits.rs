@@ -0,0 +1,143 @@
+use crate::domain::error::CommonError;
+
+/// Feature of the user overriding the maximum number of friends, e.g. for partners and creators
+pub const MAX_FRIENDS_FEATURE: &str = "max_friends";
+/// Feature of the user overriding the maximum number of requests sent and not answered yet
+pub const MAX_OUTGOING_REQUESTS_FEATURE: &str = "max_outgoing_requests";
+
+/// Reasons of the `ForbiddenError` returned when an update would go over a limit, so the client
+/// can explain it. The message starts with the reason, followed by the limit.
+pub const FRIENDS_LIMIT_REACHED: &str = "FRIENDS_LIMIT_REACHED";
+pub const OTHER_USER_FRIENDS_LIMIT_REACHED: &str = "OTHER_USER_FRIENDS_LIMIT_REACHED";
+pub const OUTGOING_REQUESTS_LIMIT_REACHED: &str = "OUTGOING_REQUESTS_LIMIT_REACHED";
+
+/// Caps on the friendships of a user, 0 means there's no cap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FriendshipLimits {
+    pub max_friends: u64,
+    pub max_outgoing_requests: u64,
+}
+
+impl FriendshipLimits {
+    /// Replaces the limits with the ones set as features of the user. Invalid values are ignored.
+    pub fn with_overrides<'a>(
+        mut self,
+        features: impl IntoIterator<Item = (&'a str, &'a str)>,
+    ) -> Self {
+        for (name, value) in features {
+            let limit = match name {
+                MAX_FRIENDS_FEATURE => &mut self.max_friends,
+                MAX_OUTGOING_REQUESTS_FEATURE => &mut self.max_outgoing_requests,
+                _ => continue,
+            };
+
+            match value.trim().parse() {
+                Ok(value) => *limit = value,
+                Err(_) => log::warn!("Friendship limits > Invalid value for {name}: {value}"),
+            }
+        }
+
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitReached {
+    /// The acting user has as many friends as allowed
+    Friends(u64),
+    /// The user whose request is accepted has as many friends as allowed
+    OtherUserFriends(u64),
+    /// The acting user has as many requests waiting for an answer as allowed
+    OutgoingRequests(u64),
+}
+
+impl LimitReached {
+    /// Returns the limit reached by the count, if any.
+    pub fn check(count: u64, limit: u64, reached: fn(u64) -> Self) -> Result<(), Self> {
+        if limit != 0 && count >= limit {
+            Err(reached(limit))
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn reason(&self) -> &'static str {
+        match self {
+            LimitReached::Friends(_) => FRIENDS_LIMIT_REACHED,
+            LimitReached::OtherUserFriends(_) => OTHER_USER_FRIENDS_LIMIT_REACHED,
+            LimitReached::OutgoingRequests(_) => OUTGOING_REQUESTS_LIMIT_REACHED,
+        }
+    }
+}
+
+impl From<LimitReached> for CommonError {
+    fn from(value: LimitReached) -> Self {
+        let message = match value {
+            LimitReached::Friends(limit) => format!("You can't have more than {limit} friends"),
+            LimitReached::OtherUserFriends(limit) => {
+                format!("The other user can't have more than {limit} friends")
+            }
+            LimitReached::OutgoingRequests(limit) => {
+                format!(
+                    "You can't have more than {limit} friendship requests waiting for an answer"
+                )
+            }
+        };
+
+        CommonError::Forbidden(format!("{}: {message}", value.reason()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FriendshipLimits, LimitReached, MAX_FRIENDS_FEATURE, MAX_OUTGOING_REQUESTS_FEATURE,
+    };
+    use crate::domain::error::CommonError;
+
+    const DEFAULT_LIMITS: FriendshipLimits = FriendshipLimits {
+        max_friends: 1000,
+        max_outgoing_requests: 100,
+    };
+
+    #[test]
+    fn test_limits_are_overridden_by_the_user_features() {
+        let limits = DEFAULT_LIMITS.with_overrides([
+            (MAX_FRIENDS_FEATURE, "5000"),
+            ("exceeds_max_friends", "true"),
+        ]);
+        assert_eq!(limits.max_friends, 5000);
+        assert_eq!(limits.max_outgoing_requests, 100);
+
+        let limits = DEFAULT_LIMITS.with_overrides([(MAX_OUTGOING_REQUESTS_FEATURE, "0")]);
+        assert_eq!(limits.max_outgoing_requests, 0);
+    }
+
+    #[test]
+    fn test_invalid_overrides_are_ignored() {
+        let limits = DEFAULT_LIMITS.with_overrides([
+            (MAX_FRIENDS_FEATURE, "many"),
+            (MAX_OUTGOING_REQUESTS_FEATURE, "-1"),
+        ]);
+        assert_eq!(limits, DEFAULT_LIMITS);
+    }
+
+    #[test]
+    fn test_limit_is_reached_once_the_count_gets_to_it() {
+        assert!(LimitReached::check(99, 100, LimitReached::Friends).is_ok());
+        assert_eq!(
+            LimitReached::check(100, 100, LimitReached::Friends),
+            Err(LimitReached::Friends(100))
+        );
+        // No limit
+        assert!(LimitReached::check(100_000, 0, LimitReached::Friends).is_ok());
+    }
+
+    #[test]
+    fn test_limit_reached_is_forbidden_with_its_reason() {
+        let CommonError::Forbidden(message) = LimitReached::OutgoingRequests(100).into() else {
+            panic!("the limit to be forbidden");
+        };
+        assert!(message.starts_with("OUTGOING_REQUESTS_LIMIT_REACHED: "));
+    }
+}
@@ -0,0 +1,242 @@
+use chrono::NaiveDateTime;
+use serde::Serialize;
+
+use crate::entities::friendship_history::{is_forced_by_admin, UserFriendshipHistory};
+
+use super::{
+    friendship_event::FriendshipEvent, friendship_status::FriendshipStatus,
+    friendship_status_calculator::get_new_friendship_status,
+};
+
+/// An entry of the history that couldn't be applied to the state the friendship had at that time.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InvalidHistoryEvent {
+    /// Raw event as stored
+    pub event: String,
+    pub acting_user: String,
+    pub timestamp: NaiveDateTime,
+    pub reason: String,
+}
+
+/// State of a friendship computed by replaying its history, what the `friendships` table should hold.
+/// Values derived from the history, like counters, are meant to be added here.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendshipProjection {
+    pub status: FriendshipStatus,
+    pub last_event: Option<FriendshipEvent>,
+    /// When the last valid event happened
+    pub last_interaction_at: Option<NaiveDateTime>,
+    /// When the last ACCEPT happened, if the users are still friends
+    pub friends_since: Option<NaiveDateTime>,
+    /// Entries skipped while replaying, in the order they happened
+    pub invalid_events: Vec<InvalidHistoryEvent>,
+}
+
+impl Default for FriendshipProjection {
+    fn default() -> Self {
+        Self {
+            status: FriendshipStatus::NotFriends,
+            last_event: None,
+            last_interaction_at: None,
+            friends_since: None,
+            invalid_events: vec![],
+        }
+    }
+}
+
+impl FriendshipProjection {
+    /// Replays the history of a friendship, which has to be in the order the events happened.
+    /// Events that aren't valid for the state at that time are skipped and reported, as the service would have rejected them.
+    pub fn replay<'a>(history: impl IntoIterator<Item = &'a UserFriendshipHistory>) -> Self {
+        let mut projection = Self::default();
+
+        for entry in history {
+            if let Err(reason) = projection.apply(entry) {
+                projection.invalid_events.push(InvalidHistoryEvent {
+                    event: entry.event.clone(),
+                    acting_user: entry.acting_user.clone(),
+                    timestamp: entry.timestamp,
+                    reason,
+                });
+            }
+        }
+
+        projection
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.status == FriendshipStatus::Friends
+    }
+
+    fn apply(&mut self, entry: &UserFriendshipHistory) -> Result<(), String> {
+        let event = parse_stored_event(&entry.event)
+            .ok_or_else(|| format!("Unknown event {}", entry.event))?;
+
+        // Admins remove relationships regardless of the transitions users go through
+        let acting_user = entry.acting_user.as_str();
+        if !is_forced_by_admin(acting_user, &entry.metadata) {
+            self.validate(acting_user, event)?;
+        }
+
+        self.status = get_new_friendship_status(acting_user, event);
+        self.last_event = Some(event);
+        self.last_interaction_at = Some(entry.timestamp);
+        self.friends_since = match event {
+            FriendshipEvent::ACCEPT => Some(entry.timestamp),
+            _ if self.is_active() => self.friends_since,
+            _ => None,
+        };
+
+        Ok(())
+    }
+
+    /// Whether the event is a valid transition that the acting user is allowed to make.
+    fn validate(&self, acting_user: &str, event: FriendshipEvent) -> Result<(), String> {
+        if !FriendshipEvent::validate_new_event_is_valid(&self.last_event, event) {
+            return Err(format!(
+                "Invalid transition from {:?} to {event:?}",
+                self.last_event
+            ));
+        }
+
+        // Only the user that didn't send the request can answer it, and only the one who did can cancel it
+        let is_requester = matches!(
+            &self.status,
+            FriendshipStatus::Requested(requester) if requester == acting_user
+        );
+        let is_authorized = match event {
+            FriendshipEvent::ACCEPT | FriendshipEvent::REJECT => !is_requester,
+            FriendshipEvent::CANCEL => is_requester,
+            FriendshipEvent::REQUEST | FriendshipEvent::DELETE => true,
+        };
+        if !is_authorized {
+            return Err(format!("{acting_user} can't {}", event.as_str()));
+        }
+
+        Ok(())
+    }
+}
+
+/// The events are stored as JSON strings (e.g. `"request"`), except the migrated ones which aren't quoted.
+fn parse_stored_event(event: &str) -> Option<FriendshipEvent> {
+    serde_json::from_str(&format!("\"{}\"", event.trim_matches('"'))).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use uuid::Uuid;
+
+    use super::FriendshipProjection;
+    use crate::{
+        domain::{
+            friendship_event::{FriendshipEvent, ADMIN_ACTING_USER},
+            friendship_status::FriendshipStatus,
+        },
+        entities::friendship_history::UserFriendshipHistory,
+    };
+
+    const PIZARNIK: &str = "0x0000000000000000000000000000000000000001";
+    const MARTHA: &str = "0x0000000000000000000000000000000000000002";
+
+    fn entry(event: &str, acting_user: &str, timestamp: i64) -> UserFriendshipHistory {
+        UserFriendshipHistory {
+            friendship_id: Uuid::nil(),
+            address_1: PIZARNIK.to_string(),
+            address_2: MARTHA.to_string(),
+            event: event.to_string(),
+            acting_user: acting_user.to_string(),
+            timestamp: NaiveDateTime::from_timestamp_opt(timestamp, 0).unwrap(),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_replay_of_a_valid_history() {
+        let history = vec![
+            entry("\"request\"", PIZARNIK, 1),
+            entry("\"accept\"", MARTHA, 2),
+            entry("\"delete\"", PIZARNIK, 3),
+            // Migrated entries aren't quoted
+            entry("request", MARTHA, 4),
+            entry("accept", PIZARNIK, 5),
+        ];
+
+        let projection = FriendshipProjection::replay(&history);
+
+        assert!(projection.is_active());
+        assert_eq!(projection.last_event, Some(FriendshipEvent::ACCEPT));
+        assert_eq!(projection.last_interaction_at, Some(history[4].timestamp));
+        assert_eq!(projection.friends_since, Some(history[4].timestamp));
+        assert!(projection.invalid_events.is_empty());
+    }
+
+    #[test]
+    fn test_replay_skips_invalid_transitions() {
+        let history = vec![
+            entry("\"request\"", PIZARNIK, 1),
+            entry("\"request\"", PIZARNIK, 2),
+            entry("\"delete\"", MARTHA, 3),
+        ];
+
+        let projection = FriendshipProjection::replay(&history);
+
+        assert_eq!(
+            projection.status,
+            FriendshipStatus::Requested(PIZARNIK.to_string())
+        );
+        assert_eq!(projection.last_interaction_at, Some(history[0].timestamp));
+        assert_eq!(projection.invalid_events.len(), 2);
+        assert_eq!(projection.invalid_events[1].event, "\"delete\"");
+    }
+
+    #[test]
+    fn test_replay_skips_events_of_unauthorized_users() {
+        let history = vec![
+            entry("\"request\"", PIZARNIK, 1),
+            entry("\"accept\"", PIZARNIK, 2),
+            entry("\"cancel\"", MARTHA, 3),
+        ];
+
+        let projection = FriendshipProjection::replay(&history);
+
+        assert!(!projection.is_active());
+        assert_eq!(projection.last_event, Some(FriendshipEvent::REQUEST));
+        assert_eq!(projection.invalid_events.len(), 2);
+    }
+
+    #[test]
+    fn test_replay_of_events_forced_by_an_admin() {
+        let history = vec![
+            entry("\"request\"", PIZARNIK, 1),
+            entry("\"cancel\"", ADMIN_ACTING_USER, 2),
+            entry("\"request\"", MARTHA, 3),
+            entry("\"accept\"", PIZARNIK, 4),
+        ];
+
+        let projection = FriendshipProjection::replay(&history);
+
+        assert!(projection.is_active());
+        assert_eq!(projection.friends_since, Some(history[3].timestamp));
+        assert!(projection.invalid_events.is_empty());
+    }
+
+    #[test]
+    fn test_replay_reports_unknown_events() {
+        let history = vec![entry("\"block\"", PIZARNIK, 1)];
+
+        let projection = FriendshipProjection::replay(&history);
+
+        assert_eq!(
+            projection,
+            FriendshipProjection {
+                invalid_events: projection.invalid_events.clone(),
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            projection.invalid_events[0].reason,
+            "Unknown event \"block\""
+        );
+    }
+}
@@ -2,8 +2,11 @@
 pub mod address;
 pub mod error;
 pub mod event;
+pub mod friends_search;
 pub mod friendship_event;
 pub mod friendship_event_validator;
+pub mod friendship_limits;
+pub mod friendship_projection;
 pub mod friendship_status;
 pub mod friendship_status_calculator;
 pub mod room;
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use async_trait::async_trait;
 use chrono::NaiveDateTime;
 use mockall::predicate::*;
 use serde::{Deserialize, Serialize};
@@ -7,13 +8,19 @@ use sqlx::{
     postgres::Postgres,
     query::Query,
     types::{Json, Uuid},
-    Error, FromRow, Row, Transaction,
+    Error, FromRow, Row,
 };
 
 use crate::{
-    components::database::{DBConnection, DatabaseComponent, Executor},
-    domain::friendship_event::FriendshipEvent,
-    entities::queries::USER_REQUESTS_QUERY,
+    components::database::{DBConnection, DatabaseComponent, DatabaseTransaction, Executor},
+    domain::{
+        address::Address,
+        friendship_event::{FriendshipEvent, ADMIN_ACTING_USER},
+    },
+    entities::queries::{
+        FRIENDSHIPS_HISTORY_QUERY, USER_HISTORY_QUERY, USER_OUTGOING_REQUESTS_COUNT_QUERY,
+        USER_REQUESTS_QUERY,
+    },
     entities::utils::get_transaction_result_from_executor,
     generate_uuid_v4,
 };
@@ -28,6 +35,18 @@ pub struct FriendshipMetadata {
     pub message: Option<String>,
     pub synapse_room_id: Option<String>,
     pub migrated_from_synapse: Option<bool>,
+    /// Set on the events forced by an admin, which don't follow the transitions users go through
+    pub forced_by_admin: Option<bool>,
+}
+
+/// Whether the event was forced by an admin. The ones recorded before the metadata flag existed
+/// are told apart by the admin acting user.
+pub fn is_forced_by_admin(acting_user: &str, metadata: &Option<Json<FriendshipMetadata>>) -> bool {
+    acting_user == ADMIN_ACTING_USER
+        || metadata
+            .as_ref()
+            .and_then(|metadata| metadata.forced_by_admin)
+            .unwrap_or(false)
 }
 
 pub struct FriendshipHistory {
@@ -47,6 +66,76 @@ pub struct FriendshipRequestEvent {
     pub metadata: Option<Json<FriendshipMetadata>>,
 }
 
+/// A history entry of one of the user's friendships, along with the users of the friendship.
+#[derive(FromRow)]
+pub struct UserFriendshipHistory {
+    pub friendship_id: Uuid,
+    pub address_1: String,
+    pub address_2: String,
+    /// Raw event as stored, it may be quoted (e.g. `"request"`) or not for migrated entries
+    pub event: String,
+    pub acting_user: String,
+    pub timestamp: NaiveDateTime,
+    pub metadata: Option<Json<FriendshipMetadata>>,
+}
+
+#[async_trait]
+pub trait FriendshipHistoryRepositoryImplementation: Send + Sync {
+    async fn create(
+        &self,
+        friendship_id: Uuid,
+        event: &str,
+        acting_user: &Address,
+        metadata: Option<Json<FriendshipMetadata>>,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>);
+
+    async fn get_last_history_for_friendship(
+        &self,
+        friendship_id: Uuid,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Option<FriendshipHistory>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    );
+
+    /// Fetches the pending request events of the given user.
+    async fn get_user_pending_request_events(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<FriendshipRequestEvent>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    );
+
+    /// Counts the requests sent by the given user that weren't answered yet.
+    async fn count_outgoing_pending_requests(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>);
+
+    /// Fetches the whole history of every friendship, past or current, of the given user.
+    async fn get_user_history(
+        &self,
+        address: &Address,
+    ) -> Result<Vec<UserFriendshipHistory>, sqlx::Error>;
+
+    /// Deletes the history of every friendship, past or current, of the given user.
+    async fn delete_user_history(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>);
+
+    /// Fetches the whole history of the given friendships, grouped by friendship and in the order the events happened.
+    async fn get_friendships_history(
+        &self,
+        friendship_ids: &[Uuid],
+    ) -> Result<Vec<UserFriendshipHistory>, sqlx::Error>;
+}
+
 impl FriendshipHistoryRepository {
     pub fn new(db: Arc<Option<DBConnection>>) -> Self {
         Self { db_connection: db }
@@ -56,7 +145,7 @@ impl FriendshipHistoryRepository {
         &self,
         friendship_id: Uuid,
         event: &'a str,
-        acting_user: &'a str,
+        acting_user: &'a Address,
         metadata: Option<Json<FriendshipMetadata>>,
     ) -> Query<'a, Postgres, sqlx::postgres::PgArguments> {
         sqlx::query(
@@ -65,22 +154,32 @@ impl FriendshipHistoryRepository {
         .bind(Uuid::parse_str(generate_uuid_v4().as_str()).unwrap())
         .bind(friendship_id)
         .bind(event)
-        .bind(acting_user)
+        .bind(acting_user.as_str())
         .bind(metadata)
     }
 
-    pub async fn create(
+    fn get_executor(
+        &self,
+        transaction: Option<DatabaseTransaction>,
+    ) -> Result<Executor<'static>, (sqlx::Error, DatabaseTransaction)> {
+        Executor::from_transaction(transaction, &self.db_connection)
+    }
+}
+
+#[async_trait]
+impl FriendshipHistoryRepositoryImplementation for FriendshipHistoryRepository {
+    async fn create(
         &self,
         friendship_id: Uuid,
         event: &str,
-        acting_user: &str,
+        acting_user: &Address,
         metadata: Option<Json<FriendshipMetadata>>,
-        transaction: Option<Transaction<'static, Postgres>>,
-    ) -> (
-        Result<(), sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
-    ) {
-        let executor = self.get_executor(transaction);
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>) {
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
 
         let query = self.create_query(friendship_id, event, acting_user, metadata);
 
@@ -97,15 +196,18 @@ impl FriendshipHistoryRepository {
         }
     }
 
-    pub async fn get_last_history_for_friendship(
+    async fn get_last_history_for_friendship(
         &self,
         friendship_id: Uuid,
-        transaction: Option<Transaction<'static, Postgres>>,
+        transaction: Option<DatabaseTransaction>,
     ) -> (
         Result<Option<FriendshipHistory>, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
+        Option<DatabaseTransaction>,
     ) {
-        let executor = self.get_executor(transaction);
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
         let query = sqlx::query("SELECT * FROM friendship_history where friendship_id = $1 ORDER BY timestamp DESC LIMIT 1")
             .bind(friendship_id);
 
@@ -143,18 +245,25 @@ impl FriendshipHistoryRepository {
         }
     }
 
-    /// Fetches the pending request events of the given user.
-    pub async fn get_user_pending_request_events(
+    async fn get_user_pending_request_events(
         &self,
-        address: &str,
-    ) -> Result<Vec<FriendshipRequestEvent>, sqlx::Error> {
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<FriendshipRequestEvent>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
         let query = USER_REQUESTS_QUERY.to_string();
 
-        let query = sqlx::query(&query).bind(address);
+        let query = sqlx::query(&query).bind(address.as_str());
 
-        let executor = self.get_executor(None);
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
 
-        let (res, _) = DatabaseComponent::fetch_all(query, executor).await;
+        let (res, resulting_executor) = DatabaseComponent::fetch_all(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
 
         match res {
             Ok(rows) => {
@@ -165,23 +274,118 @@ impl FriendshipHistoryRepository {
                             .expect("to be a friendship request event")
                     })
                     .collect::<Vec<FriendshipRequestEvent>>());
-                response
+                (response, transaction_to_return)
             }
-            Err(Error::RowNotFound) => Ok(vec![]),
+            Err(Error::RowNotFound) => (Ok(vec![]), transaction_to_return),
             Err(err) => {
                 log::error!("Couldn't fetch user {} requests, {}", address, err);
+                (Err(err), transaction_to_return)
+            }
+        }
+    }
+
+    async fn count_outgoing_pending_requests(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query(USER_OUTGOING_REQUESTS_COUNT_QUERY).bind(address.as_str());
+
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::fetch_one(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res.and_then(|row| row.try_get::<i64, _>(0)) {
+            Ok(count) => (Ok(count as u64), transaction_to_return),
+            Err(err) => {
+                log::error!("Couldn't count user {} outgoing requests, {}", address, err);
+                (Err(err), transaction_to_return)
+            }
+        }
+    }
+
+    async fn get_user_history(
+        &self,
+        address: &Address,
+    ) -> Result<Vec<UserFriendshipHistory>, sqlx::Error> {
+        let query = sqlx::query(USER_HISTORY_QUERY).bind(address.as_str());
+
+        let executor = Executor::from_pool(&self.db_connection);
+
+        let (res, _) = DatabaseComponent::fetch_all(query, executor).await;
+
+        match res {
+            Ok(rows) => Ok(rows
+                .iter()
+                .map(|row| {
+                    UserFriendshipHistory::from_row(row).expect("to be a friendship history entry")
+                })
+                .collect()),
+            Err(Error::RowNotFound) => Ok(vec![]),
+            Err(err) => {
+                log::error!("Couldn't fetch user {} history, {}", address, err);
                 Err(err)
             }
         }
     }
 
-    fn get_executor(
+    async fn delete_user_history(
         &self,
-        transaction: Option<Transaction<'static, Postgres>>,
-    ) -> Executor<'static> {
-        transaction.map_or_else(
-            || Executor::Pool(DatabaseComponent::get_connection(&self.db_connection).clone()), // choose to Clone because it's cheap and the pool use an Arc internally
-            Executor::Transaction,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query(
+            "DELETE FROM friendship_history WHERE friendship_id IN (SELECT id FROM friendships WHERE address_1 = $1 OR address_2 = $1)",
         )
+        .bind(address.as_str());
+
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res {
+            Ok(result) => (Ok(result.rows_affected()), transaction_to_return),
+            Err(err) => {
+                log::error!(
+                    "Couldn't delete user {} friendships history, {}",
+                    address,
+                    err
+                );
+                (Err(err), transaction_to_return)
+            }
+        }
+    }
+
+    async fn get_friendships_history(
+        &self,
+        friendship_ids: &[Uuid],
+    ) -> Result<Vec<UserFriendshipHistory>, sqlx::Error> {
+        let query = sqlx::query(FRIENDSHIPS_HISTORY_QUERY).bind(friendship_ids);
+
+        let executor = Executor::from_pool(&self.db_connection);
+
+        let (res, _) = DatabaseComponent::fetch_all(query, executor).await;
+
+        match res {
+            Ok(rows) => Ok(rows
+                .iter()
+                .map(|row| {
+                    UserFriendshipHistory::from_row(row).expect("to be a friendship history entry")
+                })
+                .collect()),
+            Err(Error::RowNotFound) => Ok(vec![]),
+            Err(err) => {
+                log::error!("Couldn't fetch friendships history, {}", err);
+                Err(err)
+            }
+        }
     }
 }
@@ -1,24 +1,36 @@
 use async_trait::async_trait;
+use chrono::NaiveDateTime;
 use futures_util::{Stream, StreamExt};
-use sqlx::{types::Uuid, Error, FromRow, Postgres, Row, Transaction};
+use sqlx::{types::Uuid, Error, FromRow, Row};
 use std::{fmt, pin::Pin, sync::Arc};
 
-use super::queries::MUTUALS_FRIENDS_QUERY;
+use super::queries::{
+    MUTUALS_FRIENDS_QUERY, USER_ACTIVE_FRIENDS_BY_PREFIX_QUERY, USER_ACTIVE_FRIENDS_QUERY,
+    USER_FRIENDS_BY_PREFIX_QUERY, USER_FRIENDS_QUERY,
+};
 
 use crate::{
-    components::database::{DBConnection, DatabaseComponent, Executor},
+    components::database::{DBConnection, DatabaseComponent, DatabaseTransaction, Executor},
+    domain::address::Address,
     generate_uuid_v4,
 };
 
 use super::utils::get_transaction_result_from_executor;
 
-#[derive(Default, FromRow)]
+#[derive(FromRow, Clone)]
 pub struct Friendship {
     pub id: Uuid,
     pub address_1: String,
     pub address_2: String,
     pub is_active: bool,
-    pub synapse_room_id: String,
+    /// `None` once the room was cleaned up after the friendship ended
+    pub synapse_room_id: Option<String>,
+    /// When the friendship was first requested
+    pub created_at: NaiveDateTime,
+    /// Last time the friendship status changed
+    pub updated_at: NaiveDateTime,
+    /// When the users became friends, `None` if they aren't friends anymore
+    pub friends_since: Option<NaiveDateTime>,
 }
 
 #[derive(FromRow)]
@@ -35,120 +47,167 @@ impl FriendshipsRepository {
     pub fn new(db: Arc<Option<DBConnection>>) -> Self {
         Self { db_connection: db }
     }
+
+    fn get_executor(
+        &self,
+        transaction: Option<DatabaseTransaction>,
+    ) -> Result<Executor<'static>, (sqlx::Error, DatabaseTransaction)> {
+        Executor::from_transaction(transaction, &self.db_connection)
+    }
 }
 
 impl fmt::Debug for FriendshipsRepository {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("FriendshipsRepository")
-            .field(
-                "db_connection has value",
-                &self.get_db_connection().is_some(),
-            )
+            .field("db_connection has value", &self.db_connection.is_some())
             .finish()
     }
 }
 
 #[async_trait]
-pub trait FriendshipRepositoryImplementation {
-    fn get_db_connection(&self) -> &Arc<Option<DBConnection>>;
-
+pub trait FriendshipRepositoryImplementation: Send + Sync {
     async fn create_new_friendships(
         &self,
-        addresses: (&str, &str),
+        addresses: (&Address, &Address),
         is_active: bool,
         synapse_room_id: &str,
-        transaction: Option<Transaction<'static, Postgres>>,
-    ) -> (
-        Result<Uuid, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
-    );
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<Uuid, sqlx::Error>, Option<DatabaseTransaction>);
 
     async fn get_friendship(
         &self,
-        addresses: (&str, &str),
-        transaction: Option<Transaction<'static, Postgres>>,
+        addresses: (&Address, &Address),
+        transaction: Option<DatabaseTransaction>,
     ) -> (
         Result<Option<Friendship>, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
+        Option<DatabaseTransaction>,
     );
 
     async fn get_user_friends(
         &self,
-        address: &str,
+        address: &Address,
         only_active: bool,
-        transaction: Option<Transaction<'static, Postgres>>,
+        address_prefix: Option<&str>,
+        transaction: Option<DatabaseTransaction>,
     ) -> (
         Result<Vec<Friendship>, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
+        Option<DatabaseTransaction>,
     );
 
     async fn get_user_friends_stream(
         &self,
-        address: &str,
+        address: &Address,
         only_active: bool,
+        address_prefix: Option<&str>,
     ) -> Result<Pin<Box<dyn Stream<Item = Friendship> + Send>>, sqlx::Error>;
 
     async fn get_mutual_friends_stream<'a>(
         &'a self,
-        address_1: String,
-        address_2: String,
+        address_1: Address,
+        address_2: Address,
     ) -> Result<Pin<Box<dyn Stream<Item = UserEntity> + Send>>, sqlx::Error>;
 
     async fn update_friendship_status(
         &self,
         friendship_id: &Uuid,
         is_active: bool,
-        transaction: Option<Transaction<'static, Postgres>>,
-    ) -> (
-        Result<(), sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
-    );
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>);
+
+    /// Points the friendship to another Synapse room, `None` once the room was cleaned up.
+    /// It doesn't change `updated_at`, as the status of the friendship is the same.
+    async fn update_synapse_room_id(
+        &self,
+        friendship_id: &Uuid,
+        synapse_room_id: Option<&str>,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>);
 
     async fn get_mutual_friends(
         &self,
-        address_1: &str,
-        address_2: &str,
-        transaction: Option<Transaction<'static, Postgres>>,
+        address_1: &Address,
+        address_2: &Address,
+        transaction: Option<DatabaseTransaction>,
     ) -> (
         Result<Vec<String>, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
+        Option<DatabaseTransaction>,
     );
 
-    fn get_executor<'a>(&self, transaction: Option<Transaction<'static, Postgres>>)
-        -> Executor<'a>;
+    async fn delete_user_friendships(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>);
+
+    /// Overwrites the status of the friendship with the one computed from its history.
+    /// Nothing is changed if the friendship was updated after `read_updated_at`, so a concurrent
+    /// update isn't overwritten. Returns whether it was changed.
+    async fn update_friendship_projection(
+        &self,
+        friendship_id: &Uuid,
+        is_active: bool,
+        updated_at: NaiveDateTime,
+        friends_since: Option<NaiveDateTime>,
+        read_updated_at: NaiveDateTime,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<bool, sqlx::Error>, Option<DatabaseTransaction>);
+
+    /// Counts the current friends of the given user.
+    async fn count_active_friends(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>);
+
+    /// Locks the friendships of both users until the transaction ends, so the concurrent updates
+    /// of their friendships are applied one after the other.
+    async fn lock_users_friendships(
+        &self,
+        addresses: (&Address, &Address),
+        transaction: DatabaseTransaction,
+    ) -> (Result<(), sqlx::Error>, DatabaseTransaction);
+
+    /// Fetches up to `limit` friendships ordered by id, after the given one,
+    /// so all of them can be gone through in batches.
+    async fn get_friendships_page(
+        &self,
+        after: Option<Uuid>,
+        limit: i64,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<Friendship>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    );
 }
 
 #[async_trait]
 impl FriendshipRepositoryImplementation for FriendshipsRepository {
-    fn get_db_connection(&self) -> &Arc<Option<DBConnection>> {
-        &self.db_connection
-    }
-
     async fn create_new_friendships(
         &self,
-        addresses: (&str, &str),
+        addresses: (&Address, &Address),
         is_active: bool,
         synapse_room_id: &str,
-        transaction: Option<Transaction<'static, Postgres>>,
-    ) -> (
-        Result<Uuid, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
-    ) {
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<Uuid, sqlx::Error>, Option<DatabaseTransaction>) {
         // The addresses are lexicographicly sorted to ensure that the friendship tuple is unique
         let (address1, address2) = sort_addresses(addresses);
 
         let id = Uuid::parse_str(generate_uuid_v4().as_str()).unwrap();
 
         let query = sqlx::query(
-            "INSERT INTO friendships(id, address_1, address_2, is_active, synapse_room_id) VALUES($1, $2, $3, $4, $5);",
+            "INSERT INTO friendships(id, address_1, address_2, is_active, synapse_room_id, created_at, updated_at, friends_since)
+              VALUES($1, $2, $3, $4, $5, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, CASE WHEN $4 THEN CURRENT_TIMESTAMP END);",
         )
         .bind(id)
-        .bind(address1)
-        .bind(address2)
+        .bind(address1.as_str())
+        .bind(address2.as_str())
         .bind(is_active)
         .bind(synapse_room_id);
 
-        let executor = self.get_executor(transaction);
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
 
         let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
 
@@ -162,21 +221,24 @@ impl FriendshipRepositoryImplementation for FriendshipsRepository {
 
     async fn get_friendship(
         &self,
-        addresses: (&str, &str),
-        transaction: Option<Transaction<'static, Postgres>>,
+        addresses: (&Address, &Address),
+        transaction: Option<DatabaseTransaction>,
     ) -> (
         Result<Option<Friendship>, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
+        Option<DatabaseTransaction>,
     ) {
         let (address1, address2) = addresses;
 
         let query = sqlx::query(
-            "SELECT * FROM friendships WHERE (LOWER(address_1) = LOWER($1) AND LOWER(address_2) = LOWER($2)) OR (LOWER(address_1) = LOWER($2) AND LOWER(address_2) = LOWER($1))"
+            "SELECT * FROM friendships WHERE (address_1 = $1 AND address_2 = $2) OR (address_1 = $2 AND address_2 = $1)"
         )
-        .bind(address1)
-        .bind(address2);
+        .bind(address1.as_str())
+        .bind(address2.as_str());
 
-        let executor = self.get_executor(transaction);
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
 
         let (result, resulting_executor) = DatabaseComponent::fetch_one(query, executor).await;
 
@@ -197,29 +259,29 @@ impl FriendshipRepositoryImplementation for FriendshipsRepository {
     /// Fetches the friendships of a given user.
     /// If `only_active` is set to true, only the current friends will be returned.
     /// If set to false, all past and current friendships will be returned.
+    /// If `address_prefix` is set, only the friends whose address starts with it will be returned.
     #[tracing::instrument(name = "Get user friends from DB")]
     async fn get_user_friends(
         &self,
-        address: &str,
+        address: &Address,
         only_active: bool,
-        transaction: Option<Transaction<'static, Postgres>>,
+        address_prefix: Option<&str>,
+        transaction: Option<DatabaseTransaction>,
     ) -> (
         Result<Vec<Friendship>, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
+        Option<DatabaseTransaction>,
     ) {
-        let active_only_clause = " AND is_active";
-
-        let mut query =
-            "SELECT * FROM friendships WHERE (LOWER(address_1) = LOWER($1) OR LOWER(address_2) = LOWER($1))"
-                .to_owned();
+        let query = user_friends_query(only_active, address_prefix.is_some());
 
-        if only_active {
-            query.push_str(active_only_clause);
+        let mut query = sqlx::query(query).bind(address.as_str());
+        if let Some(address_prefix) = address_prefix {
+            query = query.bind(format!("{address_prefix}%"));
         }
 
-        let query = sqlx::query(&query).bind(address);
-
-        let executor = self.get_executor(transaction);
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
 
         let (res, resulting_executor) = DatabaseComponent::fetch_all(query, executor).await;
 
@@ -247,19 +309,20 @@ impl FriendshipRepositoryImplementation for FriendshipsRepository {
 
     /// If `only_active` is set to true, only the current friends will be returned.
     /// If set to false, all past and current friendships will be returned.
+    /// If `address_prefix` is set, only the friends whose address starts with it will be returned.
     #[tracing::instrument(name = "Get user friends from DB stream")]
     async fn get_user_friends_stream(
         &self,
-        address: &str,
+        address: &Address,
         only_active: bool,
+        address_prefix: Option<&str>,
     ) -> Result<Pin<Box<dyn Stream<Item = Friendship> + Send>>, sqlx::Error> {
-        let active = "SELECT * FROM friendships WHERE (LOWER(address_1) = LOWER($1) OR LOWER(address_2) = LOWER($1)) AND is_active;";
-        let inactive =
-            "SELECT * FROM friendships WHERE (LOWER(address_1) = LOWER($1) OR LOWER(address_2) = LOWER($1));";
-
-        let query = if only_active { active } else { inactive };
+        let query = user_friends_query(only_active, address_prefix.is_some());
 
-        let query = sqlx::query(query).bind(address.to_string());
+        let mut query = sqlx::query(query).bind(address.to_string());
+        if let Some(address_prefix) = address_prefix {
+            query = query.bind(format!("{address_prefix}%"));
+        }
 
         let pool = DatabaseComponent::get_connection(&self.db_connection).clone();
 
@@ -282,12 +345,14 @@ impl FriendshipRepositoryImplementation for FriendshipsRepository {
     #[tracing::instrument(name = "Get mutual friends from DB stream")]
     async fn get_mutual_friends_stream<'a>(
         &'a self,
-        address_1: String,
-        address_2: String,
+        address_1: Address,
+        address_2: Address,
     ) -> Result<Pin<Box<dyn Stream<Item = UserEntity> + Send>>, sqlx::Error> {
         let query: &str = MUTUALS_FRIENDS_QUERY;
 
-        let query = sqlx::query(query).bind(address_1).bind(address_2);
+        let query = sqlx::query(query)
+            .bind(String::from(address_1))
+            .bind(String::from(address_2));
 
         let pool = DatabaseComponent::get_connection(&self.db_connection).clone();
 
@@ -310,18 +375,23 @@ impl FriendshipRepositoryImplementation for FriendshipsRepository {
     #[tracing::instrument(name = "Get mutual user friends from DB")]
     async fn get_mutual_friends(
         &self,
-        address_1: &str,
-        address_2: &str,
-        transaction: Option<Transaction<'static, Postgres>>,
+        address_1: &Address,
+        address_2: &Address,
+        transaction: Option<DatabaseTransaction>,
     ) -> (
         Result<Vec<String>, sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
+        Option<DatabaseTransaction>,
     ) {
         let query = MUTUALS_FRIENDS_QUERY.to_string();
 
-        let query = sqlx::query(&query).bind(address_1).bind(address_2);
+        let query = sqlx::query(&query)
+            .bind(address_1.as_str())
+            .bind(address_2.as_str());
 
-        let executor = self.get_executor(transaction);
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
 
         let (res, resulting_executor) = DatabaseComponent::fetch_all(query, executor).await;
 
@@ -354,16 +424,44 @@ impl FriendshipRepositoryImplementation for FriendshipsRepository {
         &self,
         friendship_id: &Uuid,
         is_active: bool,
-        transaction: Option<Transaction<'static, Postgres>>,
-    ) -> (
-        Result<(), sqlx::Error>,
-        Option<Transaction<'static, Postgres>>,
-    ) {
-        let query = sqlx::query("UPDATE friendships SET is_active = $1 WHERE id = $2")
-            .bind(is_active)
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query(
+            "UPDATE friendships
+              SET is_active = $1, updated_at = CURRENT_TIMESTAMP, friends_since = CASE WHEN $1 THEN CURRENT_TIMESTAMP END
+              WHERE id = $2",
+        )
+        .bind(is_active)
+        .bind(friendship_id);
+
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res {
+            Ok(_) => (Ok(()), transaction_to_return),
+            Err(err) => (Err(err), transaction_to_return),
+        }
+    }
+
+    async fn update_synapse_room_id(
+        &self,
+        friendship_id: &Uuid,
+        synapse_room_id: Option<&str>,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query("UPDATE friendships SET synapse_room_id = $1 WHERE id = $2")
+            .bind(synapse_room_id)
             .bind(friendship_id);
 
-        let executor = self.get_executor(transaction);
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
 
         let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
         let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
@@ -374,22 +472,165 @@ impl FriendshipRepositoryImplementation for FriendshipsRepository {
         }
     }
 
-    fn get_executor<'a>(
+    /// Deletes every friendship, past or current, of the given user.
+    /// The history of those friendships has to be deleted first.
+    async fn delete_user_friendships(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query("DELETE FROM friendships WHERE address_1 = $1 OR address_2 = $1")
+            .bind(address.as_str());
+
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res {
+            Ok(result) => (Ok(result.rows_affected()), transaction_to_return),
+            Err(err) => {
+                log::error!("Couldn't delete user {} friendships, {}", address, err);
+                (Err(err), transaction_to_return)
+            }
+        }
+    }
+
+    async fn update_friendship_projection(
+        &self,
+        friendship_id: &Uuid,
+        is_active: bool,
+        updated_at: NaiveDateTime,
+        friends_since: Option<NaiveDateTime>,
+        read_updated_at: NaiveDateTime,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<bool, sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query(
+            "UPDATE friendships SET is_active = $1, updated_at = $2, friends_since = $3 WHERE id = $4 AND updated_at = $5",
+        )
+        .bind(is_active)
+        .bind(updated_at)
+        .bind(friends_since)
+        .bind(friendship_id)
+        .bind(read_updated_at);
+
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res {
+            Ok(result) => (Ok(result.rows_affected() > 0), transaction_to_return),
+            Err(err) => (Err(err), transaction_to_return),
+        }
+    }
+
+    async fn count_active_friends(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query(
+            "SELECT COUNT(*) FROM friendships WHERE (address_1 = $1 OR address_2 = $1) AND is_active",
+        )
+        .bind(address.as_str());
+
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::fetch_one(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res.and_then(|row| row.try_get::<i64, _>(0)) {
+            Ok(count) => (Ok(count as u64), transaction_to_return),
+            Err(err) => {
+                log::error!("Couldn't count user {} friends, {}", address, err);
+                (Err(err), transaction_to_return)
+            }
+        }
+    }
+
+    async fn lock_users_friendships(
         &self,
-        transaction: Option<Transaction<'static, Postgres>>,
-    ) -> Executor<'a> {
-        match transaction {
-            Some(transaction) => Executor::Transaction(transaction),
-            None => {
-                // choose to Clone because it's cheap and the pool use an Arc internally
-                let conn = DatabaseComponent::get_connection(&self.db_connection).clone();
-                Executor::Pool(conn)
+        addresses: (&Address, &Address),
+        transaction: DatabaseTransaction,
+    ) -> (Result<(), sqlx::Error>, DatabaseTransaction) {
+        // Always locked in the same order, so two updates of the same users can't deadlock
+        let (address1, address2) = sort_addresses(addresses);
+
+        let mut transaction = transaction;
+        for address in [address1, address2] {
+            let query =
+                sqlx::query("SELECT pg_advisory_xact_lock(hashtext($1))").bind(address.as_str());
+
+            let executor = match self.get_executor(Some(transaction)) {
+                Ok(executor) => executor,
+                Err((err, transaction)) => return (Err(err), transaction),
+            };
+
+            let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
+            transaction = get_transaction_result_from_executor(resulting_executor)
+                .expect("the transaction to be returned");
+
+            if let Err(err) = res {
+                log::error!("Couldn't lock user {} friendships, {}", address, err);
+                return (Err(err), transaction);
+            }
+        }
+
+        (Ok(()), transaction)
+    }
+
+    async fn get_friendships_page(
+        &self,
+        after: Option<Uuid>,
+        limit: i64,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<Friendship>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
+        let query = sqlx::query(
+            "SELECT * FROM friendships WHERE ($1::uuid IS NULL OR id > $1) ORDER BY id LIMIT $2",
+        )
+        .bind(after)
+        .bind(limit);
+
+        let executor = match self.get_executor(transaction) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::fetch_all(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res {
+            Ok(rows) => {
+                let friendships = rows
+                    .iter()
+                    .map(|row| Friendship::from_row(row).expect("to be a friendship"))
+                    .collect();
+                (Ok(friendships), transaction_to_return)
+            }
+            Err(err) => {
+                log::error!("Couldn't fetch friendships page, {}", err);
+                (Err(err), transaction_to_return)
             }
         }
     }
 }
 
-fn sort_addresses<'a>(addresses: (&'a str, &'a str)) -> (&'a str, &'a str) {
+pub(crate) fn sort_addresses<'a>(
+    addresses: (&'a Address, &'a Address),
+) -> (&'a Address, &'a Address) {
     let (address1, address2) = addresses;
 
     if address1 < address2 {
@@ -398,3 +639,12 @@ fn sort_addresses<'a>(addresses: (&'a str, &'a str)) -> (&'a str, &'a str) {
         (address2, address1)
     }
 }
+
+fn user_friends_query(only_active: bool, by_address_prefix: bool) -> &'static str {
+    match (only_active, by_address_prefix) {
+        (true, true) => USER_ACTIVE_FRIENDS_BY_PREFIX_QUERY,
+        (true, false) => USER_ACTIVE_FRIENDS_QUERY,
+        (false, true) => USER_FRIENDS_BY_PREFIX_QUERY,
+        (false, false) => USER_FRIENDS_QUERY,
+    }
+}
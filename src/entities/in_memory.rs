@@ -0,0 +1,1215 @@
+// In-memory implementations of the repositories, so the friendship flow can run without Postgres.
+// They mimic the constraints of the database schema, and the changes made within a transaction are
+// staged until it's committed.
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use futures_util::{stream, Stream};
+use sqlx::types::{Json, Uuid};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::{
+    components::database::DatabaseTransaction,
+    domain::{address::Address, friendship_event::FriendshipEvent},
+};
+
+use super::{
+    friendship_history::{
+        FriendshipHistory, FriendshipHistoryRepositoryImplementation, FriendshipMetadata,
+        FriendshipRequestEvent, UserFriendshipHistory,
+    },
+    friendships::{sort_addresses, Friendship, FriendshipRepositoryImplementation, UserEntity},
+    sync_state::SyncStateRepositoryImplementation,
+    user_features::{UserFeature, UserFeatures, UserFeaturesRepositoryImplementation},
+    webhook_deliveries::{
+        DeliveryStatus, NewWebhookDelivery, WebhookDeliveriesRepositoryImplementation,
+        WebhookDelivery,
+    },
+};
+
+#[derive(Clone)]
+struct HistoryEntry {
+    friendship_id: Uuid,
+    /// Stored as the Postgres repository does, serialized as JSON
+    event: String,
+    acting_user: String,
+    timestamp: NaiveDateTime,
+    metadata: Option<Json<FriendshipMetadata>>,
+}
+
+#[derive(Clone)]
+struct FeatureEntry {
+    user: String,
+    feature: UserFeature,
+}
+
+#[derive(Clone, Default)]
+struct InMemoryData {
+    friendships: Vec<Friendship>,
+    /// Kept in insertion order, so the last entry of a friendship is its last event
+    history: Vec<HistoryEntry>,
+    features: Vec<FeatureEntry>,
+    /// Sync tokens by listener name
+    sync_tokens: HashMap<String, String>,
+    webhook_deliveries: Vec<WebhookDelivery>,
+}
+
+impl InMemoryData {
+    fn user_friendships<'a>(&'a self, address: &'a str) -> impl Iterator<Item = &'a Friendship> {
+        self.friendships
+            .iter()
+            .filter(move |friendship| is_part_of(friendship, address))
+    }
+
+    fn active_friends(&self, address: &str) -> HashSet<String> {
+        self.user_friendships(address)
+            .filter(|friendship| friendship.is_active)
+            .map(|friendship| other_user(friendship, address).to_string())
+            .collect()
+    }
+
+    fn user_friendship_ids(&self, address: &str) -> HashSet<Uuid> {
+        self.user_friendships(address)
+            .map(|friendship| friendship.id)
+            .collect()
+    }
+
+    fn friendship(&self, id: Uuid) -> Option<&Friendship> {
+        self.friendships
+            .iter()
+            .find(|friendship| friendship.id == id)
+    }
+}
+
+/// Data shared by the in-memory repositories, so they behave as tables of the same database.
+#[derive(Clone, Default)]
+pub struct InMemoryStore {
+    data: Arc<Mutex<InMemoryData>>,
+    /// Taken by the transactions updating the friendship of a pair of users, by their sorted addresses
+    pair_locks: Arc<Mutex<HashMap<(String, String), Arc<AsyncMutex<()>>>>>,
+}
+
+impl InMemoryStore {
+    pub fn friendships(&self) -> InMemoryFriendshipsRepository {
+        InMemoryFriendshipsRepository {
+            store: self.clone(),
+        }
+    }
+
+    pub fn friendship_history(&self) -> InMemoryFriendshipHistoryRepository {
+        InMemoryFriendshipHistoryRepository {
+            store: self.clone(),
+        }
+    }
+
+    pub fn user_features(&self) -> InMemoryUserFeaturesRepository {
+        InMemoryUserFeaturesRepository {
+            store: self.clone(),
+        }
+    }
+
+    pub fn sync_state(&self) -> InMemorySyncStateRepository {
+        InMemorySyncStateRepository {
+            store: self.clone(),
+        }
+    }
+
+    pub fn webhook_deliveries(&self) -> InMemoryWebhookDeliveriesRepository {
+        InMemoryWebhookDeliveriesRepository {
+            store: self.clone(),
+        }
+    }
+
+    /// Starts a transaction on a snapshot of the data, its changes are staged until it's committed.
+    pub fn begin(&self) -> InMemoryTransaction {
+        InMemoryTransaction {
+            store: self.clone(),
+            staged: self.lock().clone(),
+            writes: vec![],
+            locks: HashMap::new(),
+        }
+    }
+
+    fn owns(&self, transaction: &InMemoryTransaction) -> bool {
+        Arc::ptr_eq(&self.data, &transaction.store.data)
+    }
+
+    fn pair_lock(&self, pair: &(String, String)) -> Arc<AsyncMutex<()>> {
+        self.pair_locks
+            .lock()
+            .expect("the in-memory locks not to be poisoned")
+            .entry(pair.clone())
+            .or_default()
+            .clone()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, InMemoryData> {
+        self.data
+            .lock()
+            .expect("the in-memory store not to be poisoned")
+    }
+
+    /// Reads the data as the transaction sees it, or the committed data if there is none.
+    fn read<T>(
+        &self,
+        transaction: &Option<DatabaseTransaction>,
+        read: impl FnOnce(&InMemoryData) -> T,
+    ) -> Result<T, sqlx::Error> {
+        match transaction {
+            Some(DatabaseTransaction::InMemory(transaction)) if self.owns(transaction) => {
+                Ok(read(&transaction.staged))
+            }
+            Some(_) => Err(foreign_transaction()),
+            None => Ok(read(&self.lock())),
+        }
+    }
+
+    /// Stages the change in the transaction, or applies it right away if there is none.
+    ///
+    /// The staged change is run again on commit, so it must check the constraints it relies on.
+    fn write<T>(
+        &self,
+        transaction: &mut Option<DatabaseTransaction>,
+        write: impl Fn(&mut InMemoryData) -> Result<T, sqlx::Error> + Send + 'static,
+    ) -> Result<T, sqlx::Error> {
+        match transaction {
+            Some(DatabaseTransaction::InMemory(transaction)) if self.owns(transaction) => {
+                let result = write(&mut transaction.staged)?;
+                transaction
+                    .writes
+                    .push(Box::new(move |data| write(data).map(|_| ())));
+                Ok(result)
+            }
+            Some(_) => Err(foreign_transaction()),
+            None => write(&mut self.lock()),
+        }
+    }
+}
+
+type StagedWrite = Box<dyn Fn(&mut InMemoryData) -> Result<(), sqlx::Error> + Send>;
+
+/// The changes made within a transaction, applied to the store once it's committed.
+pub struct InMemoryTransaction {
+    store: InMemoryStore,
+    /// The data as seen from the transaction, with its changes applied
+    staged: InMemoryData,
+    writes: Vec<StagedWrite>,
+    /// The pairs of users locked by the transaction, released once it ends
+    locks: HashMap<(String, String), OwnedMutexGuard<()>>,
+}
+
+impl InMemoryTransaction {
+    /// Applies the staged changes on top of the ones committed since the transaction started.
+    ///
+    /// Nothing is applied if any of them fails, e.g. a friendship created by another transaction.
+    pub fn commit(self) -> Result<(), sqlx::Error> {
+        let mut data = self.store.lock();
+        *data = self.replay(&data)?;
+
+        Ok(())
+    }
+
+    /// Takes the lock of the pair of users, unless the transaction already holds it.
+    async fn lock_pair(&mut self, pair: (String, String)) -> Result<(), sqlx::Error> {
+        if self.locks.contains_key(&pair) {
+            return Ok(());
+        }
+
+        let guard = self.store.pair_lock(&pair).lock_owned().await;
+        self.locks.insert(pair, guard);
+
+        // As in Postgres, the changes committed while waiting for the lock are seen from now on
+        let staged = self.replay(&self.store.lock())?;
+        self.staged = staged;
+
+        Ok(())
+    }
+
+    fn replay(&self, committed: &InMemoryData) -> Result<InMemoryData, sqlx::Error> {
+        let mut data = committed.clone();
+        for write in &self.writes {
+            write(&mut data)?;
+        }
+
+        Ok(data)
+    }
+}
+
+pub struct InMemoryFriendshipsRepository {
+    store: InMemoryStore,
+}
+
+#[async_trait]
+impl FriendshipRepositoryImplementation for InMemoryFriendshipsRepository {
+    async fn create_new_friendships(
+        &self,
+        addresses: (&Address, &Address),
+        is_active: bool,
+        synapse_room_id: &str,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<Uuid, sqlx::Error>, Option<DatabaseTransaction>) {
+        let (address1, address2) = sort_addresses(addresses);
+        let now = now();
+
+        let friendship = Friendship {
+            id: Uuid::new_v4(),
+            address_1: address1.to_string(),
+            address_2: address2.to_string(),
+            is_active,
+            synapse_room_id: Some(synapse_room_id.to_string()),
+            created_at: now,
+            updated_at: now,
+            friends_since: is_active.then_some(now),
+        };
+        let result = self.store.write(&mut transaction, move |data| {
+            let exists = data.friendships.iter().any(|existing| {
+                existing.address_1 == friendship.address_1
+                    && existing.address_2 == friendship.address_2
+            });
+            if exists {
+                return Err(sqlx::Error::Protocol(format!(
+                    "friendship between {} and {} already exists",
+                    friendship.address_1, friendship.address_2
+                )));
+            }
+
+            data.friendships.push(friendship.clone());
+            Ok(friendship.id)
+        });
+
+        (result, transaction)
+    }
+
+    async fn get_friendship(
+        &self,
+        addresses: (&Address, &Address),
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Option<Friendship>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
+        let (address1, address2) = sort_addresses(addresses);
+
+        let friendship = self.store.read(&transaction, |data| {
+            data.friendships
+                .iter()
+                .find(|friendship| {
+                    friendship.address_1 == address1.as_str()
+                        && friendship.address_2 == address2.as_str()
+                })
+                .cloned()
+        });
+
+        (friendship, transaction)
+    }
+
+    async fn get_user_friends(
+        &self,
+        address: &Address,
+        only_active: bool,
+        address_prefix: Option<&str>,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<Friendship>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
+        let friends = self.store.read(&transaction, |data| {
+            get_friends(data, address, only_active, address_prefix)
+        });
+
+        (friends, transaction)
+    }
+
+    async fn get_user_friends_stream(
+        &self,
+        address: &Address,
+        only_active: bool,
+        address_prefix: Option<&str>,
+    ) -> Result<Pin<Box<dyn Stream<Item = Friendship> + Send>>, sqlx::Error> {
+        let friends = get_friends(&self.store.lock(), address, only_active, address_prefix);
+
+        Ok(Box::pin(stream::iter(friends)))
+    }
+
+    async fn get_mutual_friends_stream<'a>(
+        &'a self,
+        address_1: Address,
+        address_2: Address,
+    ) -> Result<Pin<Box<dyn Stream<Item = UserEntity> + Send>>, sqlx::Error> {
+        let mutuals = get_mutuals(&self.store.lock(), &address_1, &address_2)
+            .into_iter()
+            .map(|address| UserEntity { address });
+
+        Ok(Box::pin(stream::iter(mutuals.collect::<Vec<_>>())))
+    }
+
+    async fn update_friendship_status(
+        &self,
+        friendship_id: &Uuid,
+        is_active: bool,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>) {
+        let (friendship_id, now) = (*friendship_id, now());
+
+        let result = self.store.write(&mut transaction, move |data| {
+            if let Some(friendship) = data
+                .friendships
+                .iter_mut()
+                .find(|friendship| friendship.id == friendship_id)
+            {
+                friendship.is_active = is_active;
+                friendship.updated_at = now;
+                friendship.friends_since = is_active.then_some(now);
+            }
+            Ok(())
+        });
+
+        (result, transaction)
+    }
+
+    async fn update_synapse_room_id(
+        &self,
+        friendship_id: &Uuid,
+        synapse_room_id: Option<&str>,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>) {
+        let (friendship_id, synapse_room_id) =
+            (*friendship_id, synapse_room_id.map(str::to_string));
+
+        let result = self.store.write(&mut transaction, move |data| {
+            if let Some(friendship) = data
+                .friendships
+                .iter_mut()
+                .find(|friendship| friendship.id == friendship_id)
+            {
+                friendship.synapse_room_id = synapse_room_id.clone();
+            }
+            Ok(())
+        });
+
+        (result, transaction)
+    }
+
+    async fn get_mutual_friends(
+        &self,
+        address_1: &Address,
+        address_2: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<String>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
+        let mutuals = self
+            .store
+            .read(&transaction, |data| get_mutuals(data, address_1, address_2));
+
+        (mutuals, transaction)
+    }
+
+    async fn delete_user_friendships(
+        &self,
+        address: &Address,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let address = address.to_string();
+
+        let deleted = self.store.write(&mut transaction, move |data| {
+            let before = data.friendships.len();
+            data.friendships
+                .retain(|friendship| !is_part_of(friendship, &address));
+            Ok((before - data.friendships.len()) as u64)
+        });
+
+        (deleted, transaction)
+    }
+
+    async fn update_friendship_projection(
+        &self,
+        friendship_id: &Uuid,
+        is_active: bool,
+        updated_at: NaiveDateTime,
+        friends_since: Option<NaiveDateTime>,
+        read_updated_at: NaiveDateTime,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<bool, sqlx::Error>, Option<DatabaseTransaction>) {
+        let friendship_id = *friendship_id;
+
+        let updated = self.store.write(&mut transaction, move |data| {
+            let friendship = data.friendships.iter_mut().find(|friendship| {
+                friendship.id == friendship_id && friendship.updated_at == read_updated_at
+            });
+
+            match friendship {
+                Some(friendship) => {
+                    friendship.is_active = is_active;
+                    friendship.updated_at = updated_at;
+                    friendship.friends_since = friends_since;
+                    Ok(true)
+                }
+                None => Ok(false),
+            }
+        });
+
+        (updated, transaction)
+    }
+
+    async fn count_active_friends(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let count = self.store.read(&transaction, |data| {
+            data.active_friends(address.as_str()).len() as u64
+        });
+
+        (count, transaction)
+    }
+
+    async fn lock_users_friendships(
+        &self,
+        addresses: (&Address, &Address),
+        transaction: DatabaseTransaction,
+    ) -> (Result<(), sqlx::Error>, DatabaseTransaction) {
+        let mut transaction = match transaction {
+            DatabaseTransaction::InMemory(transaction) if self.store.owns(&transaction) => {
+                transaction
+            }
+            transaction => return (Err(foreign_transaction()), transaction),
+        };
+
+        let (address1, address2) = sort_addresses(addresses);
+        let result = transaction
+            .lock_pair((address1.to_string(), address2.to_string()))
+            .await;
+
+        (result, DatabaseTransaction::InMemory(transaction))
+    }
+
+    async fn get_friendships_page(
+        &self,
+        after: Option<Uuid>,
+        limit: i64,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<Friendship>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
+        let friendships = self.store.read(&transaction, |data| {
+            let mut friendships: Vec<Friendship> = data
+                .friendships
+                .iter()
+                .filter(|friendship| after.map_or(true, |after| friendship.id > after))
+                .cloned()
+                .collect();
+            friendships.sort_by_key(|friendship| friendship.id);
+            friendships.truncate(limit.max(0) as usize);
+            friendships
+        });
+
+        (friendships, transaction)
+    }
+}
+
+fn get_friends(
+    data: &InMemoryData,
+    address: &Address,
+    only_active: bool,
+    address_prefix: Option<&str>,
+) -> Vec<Friendship> {
+    data.user_friendships(address.as_str())
+        .filter(|friendship| !only_active || friendship.is_active)
+        .filter(|friendship| {
+            address_prefix.map_or(true, |prefix| {
+                other_user(friendship, address.as_str()).starts_with(prefix)
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+fn get_mutuals(data: &InMemoryData, address_1: &Address, address_2: &Address) -> Vec<String> {
+    let friends_1 = data.active_friends(address_1.as_str());
+    let friends_2 = data.active_friends(address_2.as_str());
+
+    let mut mutuals: Vec<String> = friends_1.intersection(&friends_2).cloned().collect();
+    mutuals.sort();
+    mutuals
+}
+
+pub struct InMemoryFriendshipHistoryRepository {
+    store: InMemoryStore,
+}
+
+#[async_trait]
+impl FriendshipHistoryRepositoryImplementation for InMemoryFriendshipHistoryRepository {
+    async fn create(
+        &self,
+        friendship_id: Uuid,
+        event: &str,
+        acting_user: &Address,
+        metadata: Option<Json<FriendshipMetadata>>,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<(), sqlx::Error>, Option<DatabaseTransaction>) {
+        let entry = HistoryEntry {
+            friendship_id,
+            event: event.to_string(),
+            acting_user: acting_user.to_string(),
+            timestamp: now(),
+            metadata,
+        };
+
+        let result = self.store.write(&mut transaction, move |data| {
+            data.history.push(entry.clone());
+            Ok(())
+        });
+
+        (result, transaction)
+    }
+
+    async fn get_last_history_for_friendship(
+        &self,
+        friendship_id: Uuid,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Option<FriendshipHistory>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
+        let entry = self.store.read(&transaction, |data| {
+            data.history
+                .iter()
+                .rev()
+                .find(|entry| entry.friendship_id == friendship_id)
+                .cloned()
+        });
+        let entry = match entry {
+            Ok(Some(entry)) => entry,
+            Ok(None) => return (Ok(None), transaction),
+            Err(err) => return (Err(err), transaction),
+        };
+
+        match serde_json::from_str::<FriendshipEvent>(&entry.event) {
+            Ok(event) => (
+                Ok(Some(FriendshipHistory {
+                    friendship_id,
+                    event,
+                    acting_user: entry.acting_user,
+                    timestamp: entry.timestamp,
+                    metadata: entry.metadata,
+                })),
+                transaction,
+            ),
+            Err(err) => {
+                log::error!("Row for {friendship_id} has an invalid event {}", err);
+                (Err(sqlx::Error::Decode(Box::new(err))), transaction)
+            }
+        }
+    }
+
+    async fn get_user_pending_request_events(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (
+        Result<Vec<FriendshipRequestEvent>, sqlx::Error>,
+        Option<DatabaseTransaction>,
+    ) {
+        let request = serialized_event(FriendshipEvent::REQUEST);
+
+        let requests = self.store.read(&transaction, |data| {
+            data.user_friendships(address.as_str())
+                .filter(|friendship| !friendship.is_active)
+                .filter_map(|friendship| {
+                    let last = data
+                        .history
+                        .iter()
+                        .rev()
+                        .find(|entry| entry.friendship_id == friendship.id)?;
+
+                    (last.event == request).then(|| FriendshipRequestEvent {
+                        address_1: friendship.address_1.clone(),
+                        address_2: friendship.address_2.clone(),
+                        acting_user: last.acting_user.clone(),
+                        timestamp: last.timestamp,
+                        metadata: last.metadata.clone(),
+                    })
+                })
+                .collect()
+        });
+
+        (requests, transaction)
+    }
+
+    async fn count_outgoing_pending_requests(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let request = serialized_event(FriendshipEvent::REQUEST);
+
+        let count = self.store.read(&transaction, |data| {
+            data.user_friendships(address.as_str())
+                .filter(|friendship| !friendship.is_active)
+                .filter(|friendship| {
+                    data.history
+                        .iter()
+                        .rev()
+                        .find(|entry| entry.friendship_id == friendship.id)
+                        .map_or(false, |last| {
+                            last.event == request && last.acting_user == address.as_str()
+                        })
+                })
+                .count()
+        });
+
+        (count.map(|count| count as u64), transaction)
+    }
+
+    async fn get_user_history(
+        &self,
+        address: &Address,
+    ) -> Result<Vec<UserFriendshipHistory>, sqlx::Error> {
+        let data = self.store.lock();
+        let friendship_ids = data.user_friendship_ids(address.as_str());
+
+        let history = data
+            .history
+            .iter()
+            .filter(|entry| friendship_ids.contains(&entry.friendship_id))
+            .filter_map(|entry| {
+                let friendship = data.friendship(entry.friendship_id)?;
+
+                Some(UserFriendshipHistory {
+                    friendship_id: entry.friendship_id,
+                    address_1: friendship.address_1.clone(),
+                    address_2: friendship.address_2.clone(),
+                    event: entry.event.clone(),
+                    acting_user: entry.acting_user.clone(),
+                    timestamp: entry.timestamp,
+                    metadata: entry.metadata.clone(),
+                })
+            })
+            .collect();
+
+        Ok(history)
+    }
+
+    async fn delete_user_history(
+        &self,
+        address: &Address,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let address = address.to_string();
+
+        let deleted = self.store.write(&mut transaction, move |data| {
+            let friendship_ids = data.user_friendship_ids(&address);
+
+            let before = data.history.len();
+            data.history
+                .retain(|entry| !friendship_ids.contains(&entry.friendship_id));
+            Ok((before - data.history.len()) as u64)
+        });
+
+        (deleted, transaction)
+    }
+
+    async fn get_friendships_history(
+        &self,
+        friendship_ids: &[Uuid],
+    ) -> Result<Vec<UserFriendshipHistory>, sqlx::Error> {
+        let data = self.store.lock();
+
+        let mut history: Vec<UserFriendshipHistory> = data
+            .history
+            .iter()
+            .filter(|entry| friendship_ids.contains(&entry.friendship_id))
+            .filter_map(|entry| {
+                let friendship = data.friendship(entry.friendship_id)?;
+
+                Some(UserFriendshipHistory {
+                    friendship_id: entry.friendship_id,
+                    address_1: friendship.address_1.clone(),
+                    address_2: friendship.address_2.clone(),
+                    event: entry.event.clone(),
+                    acting_user: entry.acting_user.clone(),
+                    timestamp: entry.timestamp,
+                    metadata: entry.metadata.clone(),
+                })
+            })
+            .collect();
+        // The sort is stable, so the entries of a friendship keep their insertion order
+        history.sort_by_key(|entry| entry.friendship_id);
+
+        Ok(history)
+    }
+}
+
+pub struct InMemoryUserFeaturesRepository {
+    store: InMemoryStore,
+}
+
+#[async_trait]
+impl UserFeaturesRepositoryImplementation for InMemoryUserFeaturesRepository {
+    async fn create(
+        &self,
+        user: &Address,
+        feature_name: &str,
+        feature_value: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut data = self.store.lock();
+
+        let exists = data
+            .features
+            .iter()
+            .any(|entry| entry.user == user.as_str() && entry.feature.feature_name == feature_name);
+        if exists {
+            return Err(sqlx::Error::Protocol(format!(
+                "feature {feature_name} of {user} already exists"
+            )));
+        }
+
+        data.features.push(FeatureEntry {
+            user: user.to_string(),
+            feature: UserFeature {
+                feature_name: feature_name.to_string(),
+                feature_value: feature_value.to_string(),
+            },
+        });
+
+        Ok(())
+    }
+
+    async fn get_all_user_features(
+        &self,
+        user: &Address,
+    ) -> Result<Option<UserFeatures>, sqlx::Error> {
+        let data = self.store.lock();
+
+        let features: Vec<UserFeature> = data
+            .features
+            .iter()
+            .filter(|entry| entry.user == user.as_str())
+            .map(|entry| entry.feature.clone())
+            .collect();
+
+        if features.is_empty() {
+            return Ok(None);
+        }
+
+        Ok(Some(UserFeatures {
+            user: user.to_string(),
+            features,
+        }))
+    }
+
+    async fn get_user_feature(
+        &self,
+        user: &Address,
+        feature_name: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        Ok(self
+            .store
+            .lock()
+            .features
+            .iter()
+            .find(|entry| entry.user == user.as_str() && entry.feature.feature_name == feature_name)
+            .map(|entry| entry.feature.feature_value.clone()))
+    }
+
+    async fn set_user_feature(
+        &self,
+        user: &Address,
+        feature_name: &str,
+        feature_value: &str,
+    ) -> Result<(), sqlx::Error> {
+        let mut data = self.store.lock();
+
+        match data
+            .features
+            .iter_mut()
+            .find(|entry| entry.user == user.as_str() && entry.feature.feature_name == feature_name)
+        {
+            Some(entry) => entry.feature.feature_value = feature_value.to_string(),
+            None => data.features.push(FeatureEntry {
+                user: user.to_string(),
+                feature: UserFeature {
+                    feature_name: feature_name.to_string(),
+                    feature_value: feature_value.to_string(),
+                },
+            }),
+        }
+
+        Ok(())
+    }
+
+    async fn delete_all_user_features(
+        &self,
+        user: &Address,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let user = user.to_string();
+
+        let deleted = self.store.write(&mut transaction, move |data| {
+            let before = data.features.len();
+            data.features.retain(|entry| entry.user != user);
+            Ok((before - data.features.len()) as u64)
+        });
+
+        (deleted, transaction)
+    }
+}
+
+pub struct InMemorySyncStateRepository {
+    store: InMemoryStore,
+}
+
+#[async_trait]
+impl SyncStateRepositoryImplementation for InMemorySyncStateRepository {
+    async fn get_next_batch(&self, name: &str) -> Result<Option<String>, sqlx::Error> {
+        Ok(self.store.lock().sync_tokens.get(name).cloned())
+    }
+
+    async fn save_next_batch(&self, name: &str, next_batch: &str) -> Result<(), sqlx::Error> {
+        self.store
+            .lock()
+            .sync_tokens
+            .insert(name.to_string(), next_batch.to_string());
+
+        Ok(())
+    }
+}
+
+pub struct InMemoryWebhookDeliveriesRepository {
+    store: InMemoryStore,
+}
+
+#[async_trait]
+impl WebhookDeliveriesRepositoryImplementation for InMemoryWebhookDeliveriesRepository {
+    async fn create(&self, delivery: NewWebhookDelivery<'_>) -> Result<Option<Uuid>, sqlx::Error> {
+        let mut data = self.store.lock();
+        if data.webhook_deliveries.iter().any(|existing| {
+            existing.event_id.as_deref() == Some(delivery.event_id) && existing.url == delivery.url
+        }) {
+            return Ok(None);
+        }
+
+        let id = Uuid::new_v4();
+        data.webhook_deliveries.push(WebhookDelivery {
+            id,
+            event_id: Some(delivery.event_id.to_string()),
+            url: delivery.url.to_string(),
+            event: delivery.event.to_string(),
+            from_address: Some(delivery.from_address.to_string()),
+            to_address: Some(delivery.to_address.to_string()),
+            payload: delivery.payload.to_string(),
+            signature: Some(delivery.signature.to_string()),
+            status: DeliveryStatus::Pending,
+            attempts: 0,
+            last_status_code: None,
+            last_error: None,
+            created_at: now(),
+            updated_at: now(),
+        });
+
+        Ok(Some(id))
+    }
+
+    async fn record_attempt(
+        &self,
+        id: Uuid,
+        status: DeliveryStatus,
+        status_code: Option<u16>,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let mut data = self.store.lock();
+        if let Some(delivery) = data
+            .webhook_deliveries
+            .iter_mut()
+            .find(|delivery| delivery.id == id)
+        {
+            delivery.status = status;
+            delivery.attempts += 1;
+            delivery.last_status_code = status_code.map(i32::from);
+            delivery.last_error = error.map(str::to_string);
+            delivery.updated_at = now();
+        }
+
+        Ok(())
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+        Ok(self
+            .store
+            .lock()
+            .webhook_deliveries
+            .iter()
+            .find(|delivery| delivery.id == id)
+            .cloned())
+    }
+
+    async fn take_stale_pending(
+        &self,
+        stale_after: Duration,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let stale_before = now()
+            - chrono::Duration::from_std(stale_after).unwrap_or_else(|_| chrono::Duration::zero());
+        let mut data = self.store.lock();
+
+        Ok(data
+            .webhook_deliveries
+            .iter_mut()
+            .filter(|delivery| {
+                delivery.status == DeliveryStatus::Pending && delivery.updated_at <= stale_before
+            })
+            .take(limit.max(0) as usize)
+            .map(|delivery| {
+                delivery.updated_at = now();
+                delivery.clone()
+            })
+            .collect())
+    }
+
+    async fn get_by_status(
+        &self,
+        status: DeliveryStatus,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        Ok(self
+            .store
+            .lock()
+            .webhook_deliveries
+            .iter()
+            .rev()
+            .filter(|delivery| delivery.status == status)
+            .take(limit.max(0) as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn delete_user_deliveries(
+        &self,
+        address: &Address,
+        mut transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let address = address.to_string();
+
+        let deleted = self.store.write(&mut transaction, move |data| {
+            let before = data.webhook_deliveries.len();
+            data.webhook_deliveries.retain(|delivery| {
+                delivery.from_address.as_deref() != Some(address.as_str())
+                    && delivery.to_address.as_deref() != Some(address.as_str())
+            });
+            Ok((before - data.webhook_deliveries.len()) as u64)
+        });
+
+        (deleted, transaction)
+    }
+}
+
+fn foreign_transaction() -> sqlx::Error {
+    sqlx::Error::Protocol("the transaction wasn't started on this in-memory store".to_string())
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn is_part_of(friendship: &Friendship, address: &str) -> bool {
+    friendship.address_1 == address || friendship.address_2 == address
+}
+
+fn other_user<'a>(friendship: &'a Friendship, address: &str) -> &'a str {
+    if friendship.address_1 == address {
+        &friendship.address_2
+    } else {
+        &friendship.address_1
+    }
+}
+
+fn serialized_event(event: FriendshipEvent) -> String {
+    serde_json::to_string(&event).expect("a friendship event to be serializable")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::InMemoryStore;
+    use crate::{
+        components::database::DatabaseTransaction,
+        domain::address::Address,
+        entities::{
+            friendship_history::FriendshipHistoryRepositoryImplementation,
+            friendships::FriendshipRepositoryImplementation,
+        },
+    };
+
+    const PIZARNIK: &str = "0x0000000000000000000000000000000000000001";
+    const MARTHA: &str = "0x0000000000000000000000000000000000000002";
+
+    #[tokio::test]
+    async fn test_pending_request_is_only_the_last_request_event() {
+        let store = InMemoryStore::default();
+        let (friendships, history) = (store.friendships(), store.friendship_history());
+        let (pizarnik, martha) = (
+            Address::parse(PIZARNIK).unwrap(),
+            Address::parse(MARTHA).unwrap(),
+        );
+
+        let (id, _) = friendships
+            .create_new_friendships((&martha, &pizarnik), false, "room", None)
+            .await;
+        let id = id.unwrap();
+        history
+            .create(id, "\"request\"", &martha, None, None)
+            .await
+            .0
+            .unwrap();
+
+        let (requests, _) = history
+            .get_user_pending_request_events(&pizarnik, None)
+            .await;
+        assert_eq!(requests.unwrap().len(), 1);
+
+        history
+            .create(id, "\"cancel\"", &martha, None, None)
+            .await
+            .0
+            .unwrap();
+
+        let (requests, _) = history
+            .get_user_pending_request_events(&pizarnik, None)
+            .await;
+        assert!(requests.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_changes_are_only_applied_on_commit() {
+        let store = InMemoryStore::default();
+        let friendships = store.friendships();
+        let (pizarnik, martha) = (
+            Address::parse(PIZARNIK).unwrap(),
+            Address::parse(MARTHA).unwrap(),
+        );
+
+        let transaction = Some(DatabaseTransaction::InMemory(store.begin()));
+        let (id, transaction) = friendships
+            .create_new_friendships((&martha, &pizarnik), true, "room", transaction)
+            .await;
+        id.unwrap();
+        let (friendship, transaction) = friendships
+            .get_friendship((&martha, &pizarnik), transaction)
+            .await;
+        assert!(friendship.unwrap().is_some());
+        let (friendship, _) = friendships.get_friendship((&martha, &pizarnik), None).await;
+        assert!(friendship.unwrap().is_none());
+
+        transaction.unwrap().rollback().await.unwrap();
+        let (friendship, _) = friendships.get_friendship((&martha, &pizarnik), None).await;
+        assert!(friendship.unwrap().is_none());
+
+        let transaction = Some(DatabaseTransaction::InMemory(store.begin()));
+        let (_, transaction) = friendships
+            .create_new_friendships((&martha, &pizarnik), true, "room", transaction)
+            .await;
+        transaction.unwrap().commit().await.unwrap();
+        let (friendship, _) = friendships.get_friendship((&martha, &pizarnik), None).await;
+        assert!(friendship.unwrap().unwrap().is_active);
+    }
+
+    #[tokio::test]
+    async fn test_commit_fails_when_a_staged_change_is_no_longer_valid() {
+        let store = InMemoryStore::default();
+        let friendships = store.friendships();
+        let (pizarnik, martha) = (
+            Address::parse(PIZARNIK).unwrap(),
+            Address::parse(MARTHA).unwrap(),
+        );
+
+        let first = Some(DatabaseTransaction::InMemory(store.begin()));
+        let second = Some(DatabaseTransaction::InMemory(store.begin()));
+        let (id, first) = friendships
+            .create_new_friendships((&martha, &pizarnik), true, "room", first)
+            .await;
+        id.unwrap();
+        let (id, second) = friendships
+            .create_new_friendships((&martha, &pizarnik), false, "room", second)
+            .await;
+        id.unwrap();
+
+        first.unwrap().commit().await.unwrap();
+        assert!(second.unwrap().commit().await.is_err());
+
+        let (friendship, _) = friendships.get_friendship((&martha, &pizarnik), None).await;
+        assert!(friendship.unwrap().unwrap().is_active);
+    }
+
+    #[tokio::test]
+    async fn test_users_lock_is_held_until_the_transaction_ends() {
+        let store = InMemoryStore::default();
+        let friendships = store.friendships();
+        let (pizarnik, martha) = (
+            Address::parse(PIZARNIK).unwrap(),
+            Address::parse(MARTHA).unwrap(),
+        );
+
+        let transaction = DatabaseTransaction::InMemory(store.begin());
+        let (locked, transaction) = friendships
+            .lock_users_friendships((&martha, &pizarnik), transaction)
+            .await;
+        locked.unwrap();
+        // The transaction holding the lock can take it again
+        let (locked, transaction) = friendships
+            .lock_users_friendships((&pizarnik, &martha), transaction)
+            .await;
+        locked.unwrap();
+
+        let mut waiting = friendships.lock_users_friendships(
+            (&martha, &pizarnik),
+            DatabaseTransaction::InMemory(store.begin()),
+        );
+        let timeout = tokio::time::timeout(Duration::from_millis(50), &mut waiting).await;
+        assert!(timeout.is_err());
+
+        let (id, transaction) = friendships
+            .create_new_friendships((&martha, &pizarnik), true, "room", Some(transaction))
+            .await;
+        id.unwrap();
+        transaction.unwrap().commit().await.unwrap();
+
+        // Once the lock is taken, the changes committed while waiting are seen
+        let (locked, transaction) = waiting.await;
+        locked.unwrap();
+        let (friendship, _) = friendships
+            .get_friendship((&martha, &pizarnik), Some(transaction))
+            .await;
+        assert!(friendship.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_transaction_of_another_store_is_rejected() {
+        let (store, other_store) = (InMemoryStore::default(), InMemoryStore::default());
+        let friendships = store.friendships();
+        let (pizarnik, martha) = (
+            Address::parse(PIZARNIK).unwrap(),
+            Address::parse(MARTHA).unwrap(),
+        );
+
+        let transaction = Some(DatabaseTransaction::InMemory(other_store.begin()));
+        let (id, transaction) = friendships
+            .create_new_friendships((&martha, &pizarnik), true, "room", transaction)
+            .await;
+        assert!(id.is_err());
+
+        let (locked, _) = friendships
+            .lock_users_friendships((&martha, &pizarnik), transaction.unwrap())
+            .await;
+        assert!(locked.is_err());
+    }
+}
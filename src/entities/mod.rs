@@ -1,5 +1,8 @@
 pub mod friendship_history;
 pub mod friendships;
+pub mod in_memory;
 mod queries;
+pub mod sync_state;
 pub mod user_features;
 mod utils;
+pub mod webhook_deliveries;
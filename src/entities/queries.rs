@@ -1,8 +1,23 @@
+pub const USER_FRIENDS_QUERY: &str =
+    "SELECT * FROM friendships WHERE (address_1 = $1 OR address_2 = $1);";
+
+pub const USER_ACTIVE_FRIENDS_QUERY: &str =
+    "SELECT * FROM friendships WHERE (address_1 = $1 OR address_2 = $1) AND is_active;";
+
+/// The prefix is matched against the other user's address, so the user itself is never a match.
+/// Backed by the `text_pattern_ops` indexes on both address columns.
+pub const USER_FRIENDS_BY_PREFIX_QUERY: &str = "SELECT * FROM friendships
+      WHERE ((address_1 = $1 AND address_2 LIKE $2) OR (address_2 = $1 AND address_1 LIKE $2));";
+
+pub const USER_ACTIVE_FRIENDS_BY_PREFIX_QUERY: &str = "SELECT * FROM friendships
+      WHERE ((address_1 = $1 AND address_2 LIKE $2) OR (address_2 = $1 AND address_1 LIKE $2))
+      AND is_active;";
+
 // This query retrieves the intersecition of friends between two users
 pub const MUTUALS_FRIENDS_QUERY: &str = "WITH friendsA as (
   SELECT
     CASE
-      WHEN LOWER(address_1) = LOWER($1) then address_2
+      WHEN address_1 = $1 then address_2
       else address_1
     end as address
   FROM
@@ -13,8 +28,8 @@ pub const MUTUALS_FRIENDS_QUERY: &str = "WITH friendsA as (
         friendships f_a
       where
         (
-          LOWER(f_a.address_1) = LOWER($1)
-          or LOWER(f_a.address_2) = LOWER($1)
+          f_a.address_1 = $1
+          or f_a.address_2 = $1
         ) and f_a.is_active = true
     ) as friends_a
 )
@@ -26,7 +41,7 @@ WHERE
   address IN (
     SELECT
       CASE
-        WHEN LOWER(address_1) = LOWER($2) then address_2
+        WHEN address_1 = $2 then address_2
         else address_1
       end as address_a
     FROM
@@ -37,8 +52,8 @@ WHERE
           friendships f_b
         where
           (
-            LOWER(f_b.address_1) = LOWER($2)
-            or LOWER(f_b.address_2) = LOWER($2)
+            f_b.address_1 = $2
+            or f_b.address_2 = $2
           ) and f_b.is_active = true
       ) as friends_b
   );";
@@ -50,7 +65,21 @@ pub const USER_REQUESTS_QUERY: &str =
     "SELECT f.address_1, f.address_2, fh.acting_user, fh.timestamp, fh.metadata
       FROM friendships f
       INNER JOIN friendship_history fh ON f.id = fh.friendship_id
-      WHERE (LOWER(f.address_1) = LOWER($1) OR LOWER(f.address_2) = LOWER($1))
+      WHERE (f.address_1 = $1 OR f.address_2 = $1)
+      AND fh.event = '\"request\"'
+      AND f.is_active IS FALSE
+      AND fh.timestamp = (
+        SELECT MAX(fh2.timestamp)
+        FROM friendship_history fh2
+        WHERE fh2.friendship_id = fh.friendship_id
+      );";
+
+/// Counts the friendships where the lastest event is a REQUEST sent by the given user.
+pub const USER_OUTGOING_REQUESTS_COUNT_QUERY: &str = "SELECT COUNT(*)
+      FROM friendships f
+      INNER JOIN friendship_history fh ON f.id = fh.friendship_id
+      WHERE (f.address_1 = $1 OR f.address_2 = $1)
+      AND fh.acting_user = $1
       AND fh.event = '\"request\"'
       AND f.is_active IS FALSE
       AND fh.timestamp = (
@@ -58,3 +87,17 @@ pub const USER_REQUESTS_QUERY: &str =
         FROM friendship_history fh2
         WHERE fh2.friendship_id = fh.friendship_id
       );";
+
+pub const USER_HISTORY_QUERY: &str =
+    "SELECT fh.friendship_id, f.address_1, f.address_2, fh.event, fh.acting_user, fh.timestamp, fh.metadata
+      FROM friendships f
+      INNER JOIN friendship_history fh ON f.id = fh.friendship_id
+      WHERE (f.address_1 = $1 OR f.address_2 = $1)
+      ORDER BY fh.timestamp ASC;";
+
+pub const FRIENDSHIPS_HISTORY_QUERY: &str =
+    "SELECT fh.friendship_id, f.address_1, f.address_2, fh.event, fh.acting_user, fh.timestamp, fh.metadata
+      FROM friendships f
+      INNER JOIN friendship_history fh ON f.id = fh.friendship_id
+      WHERE f.id = ANY($1)
+      ORDER BY fh.friendship_id, fh.timestamp ASC, fh.id;";
@@ -0,0 +1,60 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use sqlx::{Error, Row};
+
+use crate::components::database::{DBConnection, DatabaseComponent};
+
+/// Stores the token returned by the Synapse `/sync` API, so a listener resumes where it left off.
+#[derive(Clone)]
+pub struct SyncStateRepository {
+    db_connection: Arc<Option<DBConnection>>,
+}
+
+#[async_trait]
+pub trait SyncStateRepositoryImplementation: Send + Sync {
+    /// Returns the `next_batch` token saved by the listener with the given name, if any.
+    async fn get_next_batch(&self, name: &str) -> Result<Option<String>, sqlx::Error>;
+
+    /// Saves the `next_batch` token of the listener with the given name, replacing the previous one.
+    async fn save_next_batch(&self, name: &str, next_batch: &str) -> Result<(), sqlx::Error>;
+}
+
+impl SyncStateRepository {
+    pub fn new(db: Arc<Option<DBConnection>>) -> Self {
+        Self { db_connection: db }
+    }
+}
+
+#[async_trait]
+impl SyncStateRepositoryImplementation for SyncStateRepository {
+    async fn get_next_batch(&self, name: &str) -> Result<Option<String>, sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        match sqlx::query("SELECT next_batch FROM synapse_sync_state WHERE name = $1")
+            .bind(name)
+            .fetch_one(db_conn)
+            .await
+        {
+            Ok(row) => Ok(Some(row.try_get("next_batch")?)),
+            Err(err) => match err {
+                Error::RowNotFound => Ok(None),
+                _ => Err(err),
+            },
+        }
+    }
+
+    async fn save_next_batch(&self, name: &str, next_batch: &str) -> Result<(), sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        sqlx::query(
+            "INSERT INTO synapse_sync_state (name, next_batch) VALUES ($1, $2)
+            ON CONFLICT (name) DO UPDATE SET next_batch = EXCLUDED.next_batch, updated_at = CURRENT_TIMESTAMP",
+        )
+        .bind(name)
+        .bind(next_batch)
+        .execute(db_conn)
+        .await
+        .map(|_| ())
+    }
+}
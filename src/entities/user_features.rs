@@ -1,14 +1,20 @@
 use std::sync::Arc;
 
+use async_trait::async_trait;
 use sqlx::{Error, Row};
 
-use crate::components::database::{DBConnection, DatabaseComponent};
+use crate::{
+    components::database::{DBConnection, DatabaseComponent, DatabaseTransaction, Executor},
+    domain::address::Address,
+    entities::utils::get_transaction_result_from_executor,
+};
 
 #[derive(Clone)]
 pub struct UserFeaturesRepository {
     db_connection: Arc<Option<DBConnection>>,
 }
 
+#[derive(Clone)]
 pub struct UserFeature {
     pub feature_name: String,
     pub feature_value: String,
@@ -19,21 +25,61 @@ pub struct UserFeatures {
     pub features: Vec<UserFeature>,
 }
 
+#[async_trait]
+pub trait UserFeaturesRepositoryImplementation: Send + Sync {
+    async fn create(
+        &self,
+        user: &Address,
+        feature_name: &str,
+        feature_value: &str,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn get_all_user_features(
+        &self,
+        user: &Address,
+    ) -> Result<Option<UserFeatures>, sqlx::Error>;
+
+    /// Returns the value of the given feature of the user, if it's set.
+    async fn get_user_feature(
+        &self,
+        user: &Address,
+        feature_name: &str,
+    ) -> Result<Option<String>, sqlx::Error>;
+
+    /// Sets the value of the given feature of the user, replacing the previous one.
+    async fn set_user_feature(
+        &self,
+        user: &Address,
+        feature_name: &str,
+        feature_value: &str,
+    ) -> Result<(), sqlx::Error>;
+
+    /// Deletes all the features of the given user.
+    async fn delete_all_user_features(
+        &self,
+        user: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>);
+}
+
 impl UserFeaturesRepository {
     pub fn new(db: Arc<Option<DBConnection>>) -> Self {
         Self { db_connection: db }
     }
+}
 
-    pub async fn create(
+#[async_trait]
+impl UserFeaturesRepositoryImplementation for UserFeaturesRepository {
+    async fn create(
         &self,
-        user: &str,
+        user: &Address,
         feature_name: &str,
         feature_value: &str,
     ) -> Result<(), sqlx::Error> {
         let db_conn = DatabaseComponent::get_connection(&self.db_connection);
 
         match sqlx::query("INSERT INTO user_features VALUES ($1,$2,$3)")
-            .bind(user)
+            .bind(user.as_str())
             .bind(feature_name)
             .bind(feature_value)
             .execute(db_conn)
@@ -44,13 +90,13 @@ impl UserFeaturesRepository {
         }
     }
 
-    pub async fn get_all_user_features(
+    async fn get_all_user_features(
         &self,
-        user: &str,
+        user: &Address,
     ) -> Result<Option<UserFeatures>, sqlx::Error> {
         let db_conn = DatabaseComponent::get_connection(&self.db_connection);
         match sqlx::query("SELECT * FROM user_features WHERE \"user\" = $1")
-            .bind(user)
+            .bind(user.as_str())
             .fetch_all(db_conn)
             .await
         {
@@ -78,4 +124,70 @@ impl UserFeaturesRepository {
             },
         }
     }
+
+    async fn get_user_feature(
+        &self,
+        user: &Address,
+        feature_name: &str,
+    ) -> Result<Option<String>, sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        match sqlx::query(
+            "SELECT feature_value FROM user_features WHERE \"user\" = $1 AND feature_name = $2",
+        )
+        .bind(user.as_str())
+        .bind(feature_name)
+        .fetch_one(db_conn)
+        .await
+        {
+            Ok(row) => Ok(Some(row.try_get("feature_value")?)),
+            Err(Error::RowNotFound) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn set_user_feature(
+        &self,
+        user: &Address,
+        feature_name: &str,
+        feature_value: &str,
+    ) -> Result<(), sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        sqlx::query(
+            "INSERT INTO user_features (\"user\", feature_name, feature_value) VALUES ($1, $2, $3)
+            ON CONFLICT (\"user\", feature_name) DO UPDATE SET feature_value = EXCLUDED.feature_value",
+        )
+        .bind(user.as_str())
+        .bind(feature_name)
+        .bind(feature_value)
+        .execute(db_conn)
+        .await
+        .map(|_| ())
+    }
+
+    async fn delete_all_user_features(
+        &self,
+        user: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let query =
+            sqlx::query("DELETE FROM user_features WHERE \"user\" = $1").bind(user.as_str());
+
+        let executor = match Executor::from_transaction(transaction, &self.db_connection) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        match res {
+            Ok(result) => (Ok(result.rows_affected()), transaction_to_return),
+            Err(err) => {
+                log::error!("Couldn't delete user {} features, {}", user, err);
+                (Err(err), transaction_to_return)
+            }
+        }
+    }
 }
@@ -1,14 +1,12 @@
-use sqlx::{Postgres, Transaction};
-
-use crate::components::database::Executor;
+use crate::components::database::{DatabaseTransaction, Executor};
 
 pub fn get_transaction_result_from_executor(
-    executor_result: Option<Executor>,
-) -> Option<Transaction<'_, Postgres>> {
+    executor_result: Option<Executor<'static>>,
+) -> Option<DatabaseTransaction> {
     executor_result.map_or_else(
         || None,
         |executor| match executor {
-            Executor::Transaction(transaction) => Some(transaction),
+            Executor::Transaction(transaction) => Some(DatabaseTransaction::Postgres(transaction)),
             Executor::Pool(_) => None,
         },
     )
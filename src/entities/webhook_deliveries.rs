@@ -0,0 +1,296 @@
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgRow, types::Uuid, Error, Row};
+
+use crate::{
+    components::database::{DBConnection, DatabaseComponent, DatabaseTransaction, Executor},
+    domain::address::Address,
+    entities::utils::get_transaction_result_from_executor,
+    generate_uuid_v4,
+};
+
+#[derive(Deserialize, Serialize, PartialEq, Eq, Debug, Clone, Copy)]
+pub enum DeliveryStatus {
+    /// Still being attempted
+    #[serde(rename = "pending")]
+    Pending,
+    #[serde(rename = "delivered")]
+    Delivered,
+    /// Every attempt failed, it can be replayed
+    #[serde(rename = "failed")]
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            DeliveryStatus::Pending => "pending",
+            DeliveryStatus::Delivered => "delivered",
+            DeliveryStatus::Failed => "failed",
+        }
+    }
+
+    pub fn parse(status: &str) -> Option<Self> {
+        match status {
+            "pending" => Some(DeliveryStatus::Pending),
+            "delivered" => Some(DeliveryStatus::Delivered),
+            "failed" => Some(DeliveryStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// A friendship event sent to a webhook subscriber, along with the outcome of its last attempt.
+#[derive(Clone, Debug)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    /// Id of the delivered event, `None` for the deliveries saved before events had one
+    pub event_id: Option<String>,
+    pub url: String,
+    /// Name of the friendship event, e.g. `accept`
+    pub event: String,
+    /// Users of the event, `None` for the deliveries saved before they were stored apart
+    pub from_address: Option<String>,
+    pub to_address: Option<String>,
+    /// Body sent to the subscriber, kept as is so replays carry the same signature
+    pub payload: String,
+    /// Signature sent on every attempt and replay, `None` for the deliveries saved before it was
+    /// stored
+    pub signature: Option<String>,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub last_status_code: Option<i32>,
+    pub last_error: Option<String>,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl WebhookDelivery {
+    fn from_row(row: &PgRow) -> Result<Self, Error> {
+        let status: String = row.try_get("status")?;
+
+        Ok(Self {
+            id: row.try_get("id")?,
+            event_id: row.try_get("event_id")?,
+            url: row.try_get("url")?,
+            event: row.try_get("event")?,
+            from_address: row.try_get("from_address")?,
+            to_address: row.try_get("to_address")?,
+            payload: row.try_get("payload")?,
+            signature: row.try_get("signature")?,
+            status: DeliveryStatus::parse(&status).ok_or_else(|| Error::ColumnDecode {
+                index: "status".to_string(),
+                source: format!("Unknown delivery status {status}").into(),
+            })?,
+            attempts: row.try_get("attempts")?,
+            last_status_code: row.try_get("last_status_code")?,
+            last_error: row.try_get("last_error")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+}
+
+/// A delivery to save, pending and without attempts.
+pub struct NewWebhookDelivery<'a> {
+    pub event_id: &'a str,
+    pub url: &'a str,
+    /// Name of the friendship event, e.g. `accept`
+    pub event: &'a str,
+    pub from_address: &'a str,
+    pub to_address: &'a str,
+    pub payload: &'a str,
+    pub signature: &'a str,
+}
+
+/// Stores the deliveries of the friendship events to the webhook subscribers, so the failed ones
+/// can be inspected and replayed.
+#[derive(Clone)]
+pub struct WebhookDeliveriesRepository {
+    db_connection: Arc<Option<DBConnection>>,
+}
+
+#[async_trait]
+pub trait WebhookDeliveriesRepositoryImplementation: Send + Sync {
+    /// Saves a new pending delivery without attempts and returns its id, `None` if the event was
+    /// already saved for the URL, e.g. by another instance.
+    async fn create(&self, delivery: NewWebhookDelivery<'_>) -> Result<Option<Uuid>, sqlx::Error>;
+
+    /// Counts an attempt of the delivery and records its outcome.
+    ///
+    /// * `status_code` - The status code answered by the subscriber, `None` if it couldn't be reached.
+    async fn record_attempt(
+        &self,
+        id: Uuid,
+        status: DeliveryStatus,
+        status_code: Option<u16>,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error>;
+
+    async fn get(&self, id: Uuid) -> Result<Option<WebhookDelivery>, sqlx::Error>;
+
+    /// Takes the oldest pending deliveries without attempts for `stale_after`, e.g. the ones of an
+    /// instance that stopped while delivering them. They're marked as updated, so concurrent calls
+    /// don't take the same deliveries.
+    async fn take_stale_pending(
+        &self,
+        stale_after: Duration,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error>;
+
+    /// Returns the most recent deliveries with the given status, newest first.
+    async fn get_by_status(
+        &self,
+        status: DeliveryStatus,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error>;
+
+    /// Deletes the deliveries of the events sent or received by the given user.
+    async fn delete_user_deliveries(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>);
+}
+
+impl WebhookDeliveriesRepository {
+    pub fn new(db: Arc<Option<DBConnection>>) -> Self {
+        Self { db_connection: db }
+    }
+}
+
+#[async_trait]
+impl WebhookDeliveriesRepositoryImplementation for WebhookDeliveriesRepository {
+    async fn create(&self, delivery: NewWebhookDelivery<'_>) -> Result<Option<Uuid>, sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+        let id = Uuid::parse_str(generate_uuid_v4().as_str()).unwrap();
+
+        sqlx::query(
+            "INSERT INTO webhook_deliveries (id, event_id, url, event, from_address, to_address, payload, signature, status)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (event_id, url) DO NOTHING",
+        )
+        .bind(id)
+        .bind(delivery.event_id)
+        .bind(delivery.url)
+        .bind(delivery.event)
+        .bind(delivery.from_address)
+        .bind(delivery.to_address)
+        .bind(delivery.payload)
+        .bind(delivery.signature)
+        .bind(DeliveryStatus::Pending.as_str())
+        .execute(db_conn)
+        .await
+        .map(|result| (result.rows_affected() > 0).then_some(id))
+    }
+
+    async fn record_attempt(
+        &self,
+        id: Uuid,
+        status: DeliveryStatus,
+        status_code: Option<u16>,
+        error: Option<&str>,
+    ) -> Result<(), sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        sqlx::query(
+            "UPDATE webhook_deliveries
+            SET status = $2, attempts = attempts + 1, last_status_code = $3, last_error = $4, updated_at = CURRENT_TIMESTAMP
+            WHERE id = $1",
+        )
+        .bind(id)
+        .bind(status.as_str())
+        .bind(status_code.map(i32::from))
+        .bind(error)
+        .execute(db_conn)
+        .await
+        .map(|_| ())
+    }
+
+    async fn get(&self, id: Uuid) -> Result<Option<WebhookDelivery>, sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        match sqlx::query("SELECT * FROM webhook_deliveries WHERE id = $1")
+            .bind(id)
+            .fetch_one(db_conn)
+            .await
+        {
+            Ok(row) => WebhookDelivery::from_row(&row).map(Some),
+            Err(Error::RowNotFound) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn take_stale_pending(
+        &self,
+        stale_after: Duration,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        sqlx::query(
+            "UPDATE webhook_deliveries SET updated_at = CURRENT_TIMESTAMP
+            WHERE id IN (
+                SELECT id FROM webhook_deliveries
+                WHERE status = $1 AND updated_at < CURRENT_TIMESTAMP - make_interval(secs => $2)
+                ORDER BY created_at LIMIT $3
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING *",
+        )
+        .bind(DeliveryStatus::Pending.as_str())
+        .bind(stale_after.as_secs_f64())
+        .bind(limit)
+        .fetch_all(db_conn)
+        .await?
+        .iter()
+        .map(WebhookDelivery::from_row)
+        .collect()
+    }
+
+    async fn get_by_status(
+        &self,
+        status: DeliveryStatus,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+        let db_conn = DatabaseComponent::get_connection(&self.db_connection);
+
+        sqlx::query(
+            "SELECT * FROM webhook_deliveries WHERE status = $1 ORDER BY created_at DESC LIMIT $2",
+        )
+        .bind(status.as_str())
+        .bind(limit)
+        .fetch_all(db_conn)
+        .await?
+        .iter()
+        .map(WebhookDelivery::from_row)
+        .collect()
+    }
+
+    async fn delete_user_deliveries(
+        &self,
+        address: &Address,
+        transaction: Option<DatabaseTransaction>,
+    ) -> (Result<u64, sqlx::Error>, Option<DatabaseTransaction>) {
+        let query = sqlx::query(
+            "DELETE FROM webhook_deliveries WHERE from_address = $1 OR to_address = $1",
+        )
+        .bind(address.as_str());
+
+        let executor = match Executor::from_transaction(transaction, &self.db_connection) {
+            Ok(executor) => executor,
+            Err((err, transaction)) => return (Err(err), Some(transaction)),
+        };
+
+        let (res, resulting_executor) = DatabaseComponent::execute_query(query, executor).await;
+        let transaction_to_return = get_transaction_result_from_executor(resulting_executor);
+
+        (
+            res.map(|result| result.rows_affected()),
+            transaction_to_return,
+        )
+    }
+}
@@ -21,3 +21,12 @@ pub mod notifications {
 fn generate_uuid_v4() -> String {
     uuid::Uuid::new_v4().to_string()
 }
+
+lazy_static::lazy_static! {
+    static ref INSTANCE_ID: String = generate_uuid_v4();
+}
+
+/// Id of this instance of the service, it tells which one published an event.
+pub fn instance_id() -> &'static str {
+    &INSTANCE_ID
+}
@@ -1,37 +1,97 @@
-use std::{io, sync::Arc};
+use std::{io, time::Duration};
 
+use actix_web::web::Data;
+use clap::Parser;
 use social_service::{
     api::app::{get_app_data, run_service},
-    ws::app::{init_ws_components, run_ws_transport, ConfigRpcServer, SocialContext},
+    components::{
+        configuration::{Args, Command, Config},
+        database::{DBRepositories, DatabaseComponent, DatabaseComponentImplementation},
+        synapse::SynapseComponent,
+    },
+    db::friendship_projector::{rebuild_friendships_projection, ProjectionOptions},
+    synapse::{
+        consistency_checker::{ConsistencyCheckOptions, ConsistencyChecker},
+        sync_listener::SynapseSyncListener,
+    },
+    ws::app::{init_ws_components, run_ws_transport, SocialContext},
 };
 use tokio::join;
 
 #[actix_web::main]
 async fn main() -> io::Result<()> {
+    match Args::parse().command {
+        Some(Command::CheckConsistency {
+            batch_size,
+            apply,
+            synapse_access_token,
+        }) => {
+            let options = ConsistencyCheckOptions { batch_size, apply };
+            return check_consistency(options, synapse_access_token).await;
+        }
+        Some(Command::RebuildProjection { batch_size, apply }) => {
+            let options = ProjectionOptions { batch_size, apply };
+            return rebuild_projection(options).await;
+        }
+        None => {}
+    }
+
     // Get AppComponents
     let app_data = get_app_data(None).await;
+    // Get components WS specific
+    let ws_components = init_ws_components(app_data.config.clone(), &app_data.db).await;
+    ws_components
+        .metrics
+        .register_synapse_collectors(&app_data.synapse);
+    ws_components
+        .metrics
+        .register_users_cache_collectors(&app_data.users_cache);
+    if let Some(friends_cache) = &app_data.friends_cache {
+        ws_components
+            .metrics
+            .register_friends_cache_collectors(friends_cache);
+    }
+    if let Some(service_keys) = &app_data.service_keys {
+        ws_components
+            .metrics
+            .register_service_keys_collectors(service_keys);
+    }
+    if let Some(push_notifications) = &app_data.push_notifications {
+        ws_components
+            .metrics
+            .register_push_notifications_collectors(push_notifications);
+    }
+    if let Some(webhooks) = &app_data.webhooks {
+        ws_components.metrics.register_webhooks_collectors(webhooks);
+        webhooks.subscribe(ws_components.events_subscriber.as_ref());
+        webhooks.spawn_pending_sweep(Duration::from_secs(
+            app_data.config.webhooks_sweep_interval_seconds,
+        ));
+    }
+
+    // Ingest the friendship events created directly in Synapse, only when a service account is set
+    if !app_data.config.synapse_sync_access_token.is_empty() {
+        let mut sync_listener = SynapseSyncListener::new(
+            app_data.synapse.clone(),
+            app_data.db.clone(),
+            app_data.config.synapse_sync_access_token.clone(),
+            Duration::from_millis(app_data.config.synapse_sync_timeout_ms),
+        )
+        .with_events_publisher(
+            ws_components.events_publisher.clone(),
+            ws_components.metrics.clone(),
+        );
+        if let Some(friends_cache) = &app_data.friends_cache {
+            sync_listener = sync_listener.with_friends_cache(friends_cache.clone());
+        }
+        tokio::spawn(sync_listener.run());
+    }
+
+    // Create Context to run RPC WebSocket transport, the HTTP server shares it for the updates
+    let ctx = SocialContext::new(&app_data, ws_components);
     // Run HTTP Server
-    let server = run_service(app_data.clone()).unwrap();
+    let server = run_service(app_data.clone(), Data::new(ctx.clone())).unwrap();
 
-    // Get components WS specific
-    let ws_components = init_ws_components(app_data.config.clone()).await;
-
-    // Create Context to run RPC WebSocket transport
-    let ctx = SocialContext {
-        synapse: app_data.synapse.clone(),
-        db: app_data.db.clone(),
-        users_cache: Arc::clone(&app_data.users_cache),
-        config: ConfigRpcServer {
-            rpc_server: app_data.config.rpc_server.clone(),
-            wkc_metrics_bearer_token: app_data.config.wkc_metrics_bearer_token.clone(),
-        },
-        redis_publisher: ws_components.redis_publisher.clone(),
-        redis_subscriber: ws_components.redis_subscriber.clone(),
-        friendships_events_generators: ws_components.friendships_events_generators.clone(),
-        transport_context: ws_components.transport_context.clone(),
-        friends_stream_page_size: app_data.config.friends_stream_page_size,
-        metrics: ws_components.metrics,
-    };
     // Run RPC Websocket Transport
     let (rpc_server_handle, http_server_handle) = run_ws_transport(ctx).await;
 
@@ -41,3 +101,63 @@ async fn main() -> io::Result<()> {
 
     Ok(())
 }
+
+/// Checks the friendships against their Synapse rooms and prints the report.
+async fn check_consistency(
+    options: ConsistencyCheckOptions,
+    synapse_access_token: Option<String>,
+) -> io::Result<()> {
+    let _ = env_logger::try_init();
+    let config = Config::new().expect("Couldn't read the configuration");
+
+    let access_token = synapse_access_token.unwrap_or(config.synapse_sync_access_token);
+    if access_token.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "a Synapse access token is needed to read the rooms",
+        ));
+    }
+
+    let db_repos = run_db(&config).await?;
+
+    let checker = ConsistencyChecker::new(
+        SynapseComponent::from_config(&config.synapse),
+        db_repos,
+        access_token,
+        options,
+    );
+    let report = checker
+        .run()
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("{err:?}")))?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+/// Replays the friendships history and prints the report.
+async fn rebuild_projection(options: ProjectionOptions) -> io::Result<()> {
+    let _ = env_logger::try_init();
+    let config = Config::new().expect("Couldn't read the configuration");
+
+    let db_repos = run_db(&config).await?;
+
+    let report = rebuild_friendships_projection(&db_repos, &options)
+        .await
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, format!("{err:?}")))?;
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+async fn run_db(config: &Config) -> io::Result<DBRepositories> {
+    let mut db = DatabaseComponent::new(&config.db);
+    if let Err(err) = db.run().await {
+        return Err(io::Error::new(io::ErrorKind::Other, err));
+    }
+
+    Ok(db
+        .db_repos
+        .clone()
+        .expect("the repositories once the DB runs"))
+}
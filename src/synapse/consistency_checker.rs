@@ -0,0 +1,281 @@
+// Compares the friendships stored in Postgres with their Synapse rooms, to find the drift left by
+// the migrations and by updates that failed halfway. Only the database side can be repaired, the
+// Synapse side would need the tokens of the users.
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    components::{
+        database::DBRepositories,
+        synapse::{RoomMembersResponse, SynapseComponent},
+    },
+    db::friendships_handler::{clear_synapse_room_id, get_last_history},
+    domain::{address::Address, error::CommonError, friendship_event::FriendshipEvent},
+    entities::friendships::Friendship,
+};
+
+#[derive(Debug, Clone)]
+pub struct ConsistencyCheckOptions {
+    /// Friendships fetched from the database at once
+    pub batch_size: i64,
+    /// Whether to repair the mismatches that can be fixed, otherwise it's a dry run
+    pub apply: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Mismatch {
+    /// The friendship has no history, so its status is unknown
+    MissingHistory,
+    /// `is_active` doesn't match the last event of the history
+    IsActive { expected: bool },
+    /// The friendship is active or pending but it's not linked to a room
+    MissingRoom,
+    /// The room doesn't exist, or the service account can't read it
+    RoomNotFound,
+    /// One of the users of an active or pending friendship is not in the room
+    MissingMember { address: String },
+    /// The friendship event of the room is not the last one of the history
+    RoomEvent {
+        database: FriendshipEvent,
+        synapse: Option<FriendshipEvent>,
+    },
+}
+
+#[derive(Debug, Serialize)]
+pub struct FriendshipReport {
+    pub friendship_id: Uuid,
+    pub address_1: String,
+    pub address_2: String,
+    pub synapse_room_id: Option<String>,
+    pub mismatches: Vec<Mismatch>,
+    /// Mismatches fixed in apply mode
+    pub repaired: Vec<Mismatch>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ConsistencyReport {
+    pub scanned: u64,
+    /// Friendships that couldn't be checked, the errors are logged
+    pub failed: u64,
+    /// Only the friendships with mismatches
+    pub friendships: Vec<FriendshipReport>,
+}
+
+pub struct ConsistencyChecker {
+    synapse: SynapseComponent,
+    db_repos: DBRepositories,
+    /// Token of a service account that is a member of the friendship rooms
+    access_token: String,
+    options: ConsistencyCheckOptions,
+}
+
+impl ConsistencyChecker {
+    pub fn new(
+        synapse: SynapseComponent,
+        db_repos: DBRepositories,
+        access_token: String,
+        options: ConsistencyCheckOptions,
+    ) -> Self {
+        Self {
+            synapse,
+            db_repos,
+            access_token,
+            options,
+        }
+    }
+
+    /// Goes through all the friendships in batches and reports the ones that don't match their room.
+    pub async fn run(&self) -> Result<ConsistencyReport, CommonError> {
+        let mut report = ConsistencyReport::default();
+        let mut after = None;
+
+        loop {
+            let (page, _) = self
+                .db_repos
+                .friendships
+                .get_friendships_page(after, self.options.batch_size, None)
+                .await;
+            let page = page.map_err(|err| {
+                log::error!("[Consistency] Couldn't fetch friendships: {err}");
+                CommonError::Unknown("".to_owned())
+            })?;
+
+            let Some(last) = page.last() else {
+                break;
+            };
+            after = Some(last.id);
+
+            for friendship in &page {
+                report.scanned += 1;
+                match self.check_friendship(friendship).await {
+                    Ok(friendship_report) if !friendship_report.mismatches.is_empty() => {
+                        report.friendships.push(friendship_report)
+                    }
+                    Ok(_) => {}
+                    Err(err) => {
+                        log::warn!(
+                            "[Consistency] Couldn't check friendship {}: {err:?}",
+                            friendship.id
+                        );
+                        report.failed += 1;
+                    }
+                }
+            }
+
+            log::info!("[Consistency] {} friendships checked", report.scanned);
+        }
+
+        Ok(report)
+    }
+
+    async fn check_friendship(
+        &self,
+        friendship: &Friendship,
+    ) -> Result<FriendshipReport, CommonError> {
+        let last_history =
+            get_last_history(&self.db_repos.friendship_history, &Some(friendship.clone())).await?;
+        let last_event = last_history.map(|history| history.event);
+
+        let mismatches = self.find_mismatches(friendship, last_event).await?;
+        let repaired = if self.options.apply {
+            self.repair(friendship, last_event, &mismatches).await
+        } else {
+            vec![]
+        };
+
+        Ok(FriendshipReport {
+            friendship_id: friendship.id,
+            address_1: friendship.address_1.clone(),
+            address_2: friendship.address_2.clone(),
+            synapse_room_id: friendship.synapse_room_id.clone(),
+            mismatches,
+            repaired,
+        })
+    }
+
+    async fn find_mismatches(
+        &self,
+        friendship: &Friendship,
+        last_event: Option<FriendshipEvent>,
+    ) -> Result<Vec<Mismatch>, CommonError> {
+        let mut mismatches = vec![];
+
+        let Some(last_event) = last_event else {
+            mismatches.push(Mismatch::MissingHistory);
+            return Ok(mismatches);
+        };
+
+        let expected_active = last_event == FriendshipEvent::ACCEPT;
+        if friendship.is_active != expected_active {
+            mismatches.push(Mismatch::IsActive {
+                expected: expected_active,
+            });
+        }
+
+        let is_ongoing = is_ongoing(last_event);
+        let Some(room_id) = &friendship.synapse_room_id else {
+            if is_ongoing {
+                mismatches.push(Mismatch::MissingRoom);
+            }
+            return Ok(mismatches);
+        };
+
+        let members = match self
+            .synapse
+            .get_room_members(&self.access_token, room_id)
+            .await
+        {
+            Ok(members) => members,
+            Err(CommonError::NotFound(_) | CommonError::Forbidden(_)) => {
+                mismatches.push(Mismatch::RoomNotFound);
+                return Ok(mismatches);
+            }
+            Err(err) => return Err(err),
+        };
+
+        if is_ongoing {
+            for address in [&friendship.address_1, &friendship.address_2] {
+                if !is_member(&members, address) {
+                    mismatches.push(Mismatch::MissingMember {
+                        address: address.clone(),
+                    });
+                }
+            }
+        }
+
+        let room_event = self
+            .synapse
+            .get_friendship_room_event(&self.access_token, room_id)
+            .await?
+            .map(|event| event.r#type);
+        if room_event != Some(last_event) {
+            mismatches.push(Mismatch::RoomEvent {
+                database: last_event,
+                synapse: room_event,
+            });
+        }
+
+        Ok(mismatches)
+    }
+
+    /// Fixes the database side of the mismatches, the history is taken as the source of truth.
+    async fn repair(
+        &self,
+        friendship: &Friendship,
+        last_event: Option<FriendshipEvent>,
+        mismatches: &[Mismatch],
+    ) -> Vec<Mismatch> {
+        let mut repaired = vec![];
+
+        for mismatch in mismatches {
+            let result = match mismatch {
+                Mismatch::IsActive { expected } => {
+                    let (result, _) = self
+                        .db_repos
+                        .friendships
+                        .update_friendship_status(&friendship.id, *expected, None)
+                        .await;
+                    result.map_err(|err| {
+                        log::error!("[Consistency] Couldn't update friendship status: {err}");
+                        CommonError::Unknown("".to_owned())
+                    })
+                }
+                // Unlinking the room of an active or pending friendship would break its next update
+                Mismatch::RoomNotFound if last_event.map_or(false, |event| !is_ongoing(event)) => {
+                    clear_synapse_room_id(self.db_repos.friendships.as_ref(), &friendship.id).await
+                }
+                _ => continue,
+            };
+
+            match result {
+                Ok(()) => repaired.push(mismatch.clone()),
+                Err(err) => log::warn!(
+                    "[Consistency] Couldn't repair {mismatch:?} of friendship {}: {err:?}",
+                    friendship.id
+                ),
+            }
+        }
+
+        repaired
+    }
+}
+
+/// Whether the users are friends or one of them has a pending request, so they need a room.
+fn is_ongoing(last_event: FriendshipEvent) -> bool {
+    matches!(
+        last_event,
+        FriendshipEvent::REQUEST | FriendshipEvent::ACCEPT
+    )
+}
+
+fn is_member(members: &RoomMembersResponse, address: &str) -> bool {
+    members.chunk.iter().any(|member| {
+        member.is_joined_or_invited()
+            && member
+                .social_user_id
+                .as_deref()
+                .and_then(|user| Address::parse(user).ok())
+                .map_or(false, |user| user.as_str() == address)
+    })
+}
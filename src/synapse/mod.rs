@@ -1 +1,3 @@
+pub mod consistency_checker;
 pub mod synapse_handler;
+pub mod sync_listener;
@@ -1,10 +1,14 @@
 // Responsible for managing Synapse rooms and storing events in these rooms.
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
-    components::synapse::{user_id_as_synapse_user_id, CreateRoomResponse, SynapseComponent},
-    domain::{error::CommonError, friendship_event::FriendshipEvent},
-    entities::friendships::Friendship,
+    components::{
+        configuration::RoomCleanupPolicy,
+        synapse::{user_id_as_synapse_user_id, CreateRoomResponse, SynapseComponent},
+    },
+    db::friendships_handler::clear_synapse_room_id,
+    domain::{address::Address, error::CommonError, friendship_event::FriendshipEvent},
+    entities::friendships::{Friendship, FriendshipRepositoryImplementation},
 };
 
 /// Function used when creating a room and setting an alias,
@@ -156,7 +160,8 @@ async fn get_room_id_for_alias_in_synapse(
 
 /// Creates a new Synapse room or returns the existing room id, depending on the `Friendship` and `FriendshipEvent`.
 ///
-/// If the `Friendship` exists, returns the `synapse_room_id` in the `Friendship` struct.
+/// If the `Friendship` exists, returns the `synapse_room_id` in the `Friendship` struct,
+/// unless the room was cleaned up after the friendship ended, in which case it's handled as a new friendship.
 ///
 /// If the `Friendship` does not exist and the `FriendshipEvent` is `REQUEST`, checks if a room with the alias exists in Synapse.
 /// If the room exists, returns its id.
@@ -172,8 +177,11 @@ pub async fn get_or_create_synapse_room_id(
     synapse: &SynapseComponent,
 ) -> Result<String, CommonError> {
     match friendship {
-        Some(friendship) => Ok(friendship.synapse_room_id.clone()),
-        None => {
+        Some(Friendship {
+            synapse_room_id: Some(room_id),
+            ..
+        }) => Ok(room_id.clone()),
+        _ => {
             if new_event == &FriendshipEvent::REQUEST {
                 let room_alias_name: String = build_room_local_alias(acting_user, second_user);
 
@@ -260,9 +268,231 @@ pub async fn set_account_data(
     Ok(())
 }
 
+/// A member of the room being cleaned up, with their token if the service can act on their behalf.
+#[derive(Debug, Clone, Copy)]
+pub struct RoomUser<'a> {
+    pub address: &'a str,
+    pub token: Option<&'a str>,
+}
+
+/// Removes the room of a friendship that was rejected or deleted, so users don't pile up dead direct chats.
+///
+/// Each user with a token removes the room from their `m.direct` account data, the users without one
+/// are kicked, the room alias is deleted so a future request gets a new room, and finally the users
+/// with a token leave and forget the room.
+/// Steps that were already done are skipped, so it's safe to run it more than once for the same room.
+///
+/// Returns an error if no user has a token or one of them couldn't leave the room, the rest of the
+/// steps are best effort.
+pub async fn clean_up_synapse_room(
+    room_id: &str,
+    users: [RoomUser<'_>; 2],
+    synapse: &SynapseComponent,
+) -> Result<(), CommonError> {
+    let Some(token) = users.iter().find_map(|user| user.token) else {
+        return Err(CommonError::Unknown(
+            "There is no token to clean up the room with".to_owned(),
+        ));
+    };
+
+    for user in users {
+        if let Some(user_token) = user.token {
+            remove_direct_room(user_token, user.address, room_id, synapse).await;
+        }
+    }
+
+    for user in users.iter().filter(|user| user.token.is_none()) {
+        let user_as_synapse_id = user_id_as_synapse_user_id(user.address, &synapse.synapse_url);
+        if let Err(err) = synapse
+            .kick_user(token, room_id, &user_as_synapse_id, "Friendship ended")
+            .await
+        {
+            log_unless_already_done("kick user", &err);
+        }
+    }
+
+    let room_alias_name = build_room_local_alias(users[0].address, users[1].address);
+    if let Err(err) = synapse.delete_room_alias(token, &room_alias_name).await {
+        log_unless_already_done("delete room alias", &err);
+    }
+
+    let mut left = Ok(());
+    for user in users {
+        let Some(user_token) = user.token else {
+            continue;
+        };
+
+        if let Err(err) = synapse.leave_room(user_token, room_id).await {
+            if !is_already_done(&err) {
+                log::error!(
+                    "[RPC] Clean up synapse room > Couldn't leave room {room_id} > Error {err}"
+                );
+                left = Err(err);
+                continue;
+            }
+        }
+
+        if let Err(err) = synapse.forget_room(user_token, room_id).await {
+            log_unless_already_done("forget room", &err);
+        }
+    }
+
+    left
+}
+
+/// Cleans up the Synapse room of a friendship that ended in the background, as configured by `policy`.
+///
+/// The acting user cleans up with their own token, if any. The token of the other user is got through
+/// `admin_token` when it isn't empty, otherwise they're only kicked out of the room. The update was
+/// already stored, so a failure is only logged and the room is kept linked to the friendship.
+pub fn spawn_room_cleanup(
+    policy: RoomCleanupPolicy,
+    admin_token: &str,
+    event: FriendshipEvent,
+    friendship: Option<&Friendship>,
+    acting_user: Option<(&Address, &str)>,
+    synapse: &SynapseComponent,
+    friendships_repository: Arc<dyn FriendshipRepositoryImplementation>,
+) {
+    if policy != RoomCleanupPolicy::LeaveAndForget || !event.ends_relationship() {
+        return;
+    }
+
+    let Some(friendship) = friendship else {
+        return;
+    };
+    let Some(room_id) = friendship.synapse_room_id.clone() else {
+        return;
+    };
+
+    let friendship_id = friendship.id;
+    let addresses = [friendship.address_1.clone(), friendship.address_2.clone()];
+    let acting_user =
+        acting_user.map(|(address, token)| (address.as_str().to_owned(), token.to_owned()));
+    let admin_token = admin_token.to_owned();
+    let synapse = synapse.clone();
+
+    tokio::spawn(async move {
+        let mut tokens = Vec::with_capacity(addresses.len());
+        let mut logged_in_tokens = vec![];
+        for address in &addresses {
+            let token = match &acting_user {
+                Some((acting_address, token)) if acting_address == address => Some(token.clone()),
+                _ => {
+                    let token = login_as_user(&admin_token, address, &synapse).await;
+                    logged_in_tokens.extend(token.clone());
+                    token
+                }
+            };
+            tokens.push(token);
+        }
+
+        let users = [
+            RoomUser {
+                address: &addresses[0],
+                token: tokens[0].as_deref(),
+            },
+            RoomUser {
+                address: &addresses[1],
+                token: tokens[1].as_deref(),
+            },
+        ];
+        let cleaned_up = clean_up_synapse_room(&room_id, users, &synapse).await;
+
+        for token in logged_in_tokens {
+            if let Err(err) = synapse.logout(&token).await {
+                log::warn!("[RPC] Clean up synapse room > Couldn't log out > Error {err}");
+            }
+        }
+
+        match cleaned_up {
+            Ok(()) => {
+                let _ =
+                    clear_synapse_room_id(friendships_repository.as_ref(), &friendship_id).await;
+            }
+            Err(err) => {
+                log::warn!("[RPC] Clean up synapse room > Room {room_id} was kept > Error {err}");
+            }
+        }
+    });
+}
+
+/// Gets a token of the user through the admin token, `None` if there is no admin token or it failed.
+async fn login_as_user(
+    admin_token: &str,
+    user: &str,
+    synapse: &SynapseComponent,
+) -> Option<String> {
+    if admin_token.is_empty() {
+        return None;
+    }
+
+    let user_as_synapse_id = user_id_as_synapse_user_id(user, &synapse.synapse_url);
+    synapse
+        .login_as_user(admin_token, &user_as_synapse_id)
+        .await
+        .map_err(|err| {
+            log::warn!("[RPC] Clean up synapse room > Couldn't log in as {user} > Error {err}");
+        })
+        .ok()
+}
+
+/// Removes the room from the direct chats in the `m.direct` account data of the user.
+async fn remove_direct_room(token: &str, user: &str, room_id: &str, synapse: &SynapseComponent) {
+    let user_as_synapse_id = user_id_as_synapse_user_id(user, &synapse.synapse_url);
+    let mut direct_room_map = match synapse.get_account_data(token, &user_as_synapse_id).await {
+        Ok(m_direct_event) => m_direct_event.direct,
+        Err(err) => {
+            log_unless_already_done("get account data", &err);
+            return;
+        }
+    };
+
+    if !prune_direct_room(&mut direct_room_map, room_id) {
+        return;
+    }
+
+    if let Err(err) = synapse
+        .set_account_data(token, &user_as_synapse_id, direct_room_map)
+        .await
+    {
+        log::warn!("[RPC] Clean up synapse room > Couldn't set account data > Error {err}");
+    }
+}
+
+/// Removes the room from every user in the map, and the users left without rooms.
+///
+/// Returns whether the map changed.
+fn prune_direct_room(direct_room_map: &mut HashMap<String, Vec<String>>, room_id: &str) -> bool {
+    let mut changed = false;
+
+    direct_room_map.retain(|_, room_ids| {
+        let rooms_before = room_ids.len();
+        room_ids.retain(|id| id != room_id);
+        changed |= room_ids.len() != rooms_before;
+
+        !room_ids.is_empty()
+    });
+
+    changed
+}
+
+/// Synapse answers with these errors when the user is no longer in the room, or it doesn't exist anymore
+fn is_already_done(err: &CommonError) -> bool {
+    matches!(err, CommonError::NotFound(_) | CommonError::Forbidden(_))
+}
+
+fn log_unless_already_done(step: &str, err: &CommonError) {
+    if !is_already_done(err) {
+        log::warn!("[RPC] Clean up synapse room > Couldn't {step} > Error {err}");
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use super::build_room_local_alias;
+    use std::collections::HashMap;
+
+    use super::{build_room_local_alias, prune_direct_room};
 
     #[test]
     fn build_room_alias_name_for_users() {
@@ -270,4 +500,29 @@ mod tests {
 
         assert_eq!(res, "0x1111ada11111+0x1111ada11112");
     }
+
+    #[test]
+    fn prune_direct_room_should_remove_the_room_and_empty_entries() {
+        let mut direct_room_map = HashMap::from([
+            ("@a".to_string(), vec!["!room".to_string()]),
+            (
+                "@b".to_string(),
+                vec!["!room".to_string(), "!other".to_string()],
+            ),
+        ]);
+
+        assert!(prune_direct_room(&mut direct_room_map, "!room"));
+        assert_eq!(
+            direct_room_map,
+            HashMap::from([("@b".to_string(), vec!["!other".to_string()])])
+        );
+    }
+
+    #[test]
+    fn prune_direct_room_should_do_nothing_when_the_room_is_not_there() {
+        let mut direct_room_map = HashMap::from([("@b".to_string(), vec!["!other".to_string()])]);
+
+        assert!(!prune_direct_room(&mut direct_room_map, "!room"));
+        assert_eq!(direct_room_map.len(), 1);
+    }
 }
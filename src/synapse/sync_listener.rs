@@ -0,0 +1,422 @@
+// Ingests the friendship events created directly in Synapse, by clients that don't go through this service.
+// The events are applied with the same validation and persistence as the RPC updates, but nothing is
+// written back to Synapse since the event is already there.
+use std::{collections::HashSet, sync::Arc, time::Duration};
+
+use tokio::sync::OnceCell;
+
+use crate::{
+    api::routes::synapse::room_events::RoomEventRequestBody,
+    components::{
+        database::{DBRepositories, DatabaseComponentImplementation},
+        friends_cache::FriendsCacheComponent,
+        notifications::EventsChannelPublisher,
+        synapse::{clean_synapse_user_id, SynapseComponent, SyncRoomEvent},
+    },
+    db::{
+        friendships_handler::{get_friendship, get_last_history, update_friendship_status},
+        types::FriendshipDbRepositories,
+    },
+    domain::{
+        address::Address, error::CommonError, event::EventPayload,
+        friendship_event::FriendshipEvent, friendship_event_validator::validate_new_event,
+        friendship_status_calculator::get_new_friendship_status, room::RoomInfo,
+    },
+    entities::friendship_history::FriendshipHistory,
+    ws::{
+        metrics::Metrics,
+        service::{
+            friendship_event_updates::publish_friendship_update,
+            mapper::event::event_payload_as_friendship_payload,
+        },
+    },
+};
+
+/// Name under which the sync token is stored
+pub const SYNC_LISTENER_NAME: &str = "friendship_events";
+
+const FRIENDSHIP_EVENT_TYPE: &str = "org.decentraland.friendship";
+
+/// Only the friendship events of the joined rooms are needed
+const FRIENDSHIP_EVENTS_FILTER: &str = r#"{"presence":{"types":[]},"account_data":{"types":[]},"room":{"account_data":{"types":[]},"ephemeral":{"types":[]},"state":{"types":["org.decentraland.friendship"]},"timeline":{"types":["org.decentraland.friendship"]}}}"#;
+
+/// Time to wait before syncing again after a failure
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+pub struct SynapseSyncListener {
+    synapse: SynapseComponent,
+    db: Arc<dyn DatabaseComponentImplementation>,
+    /// Token of the service account, it has to be a member of the friendship rooms to get their events
+    access_token: String,
+    timeout: Duration,
+    /// Address of the service account when it has one, so it's not taken as one of the users of a room
+    service_user: OnceCell<Option<Address>>,
+    friends_cache: Option<FriendsCacheComponent>,
+    events_publisher: Option<(Arc<EventsChannelPublisher>, Arc<Metrics>)>,
+}
+
+impl SynapseSyncListener {
+    pub fn new(
+        synapse: SynapseComponent,
+        db: Arc<dyn DatabaseComponentImplementation>,
+        access_token: String,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            synapse,
+            db,
+            access_token,
+            timeout,
+            service_user: OnceCell::new(),
+            friends_cache: None,
+            events_publisher: None,
+        }
+    }
+
+    /// Drops the cached friends of the users whose friendship is updated.
+    pub fn with_friends_cache(mut self, friends_cache: FriendsCacheComponent) -> Self {
+        self.friends_cache = Some(friends_cache);
+        self
+    }
+
+    /// Publishes the applied updates, so the users get notified as with the RPC updates.
+    pub fn with_events_publisher(
+        mut self,
+        events_publisher: Arc<EventsChannelPublisher>,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        self.events_publisher = Some((events_publisher, metrics));
+        self
+    }
+
+    /// Syncs until the process stops.
+    pub async fn run(self) {
+        log::info!("[Synapse Sync] Listening to friendship events created in Synapse");
+        loop {
+            if let Err(err) = self.sync_once().await {
+                log::warn!("[Synapse Sync] Couldn't sync friendship events: {err:?}");
+                tokio::time::sleep(RETRY_DELAY).await;
+            }
+        }
+    }
+
+    /// Applies the friendship events after the stored sync token and stores the new one.
+    ///
+    /// The first sync only stores the token, so the history of the rooms from before the listener
+    /// was enabled isn't applied. Use the `check-consistency` subcommand to repair it.
+    ///
+    /// The rooms the service account is invited to are joined, so their events come in the next syncs.
+    /// The token is only stored once every event was applied or failed for good, e.g. an invalid
+    /// transition. Otherwise the sync is retried from the same token, skipping the events already applied.
+    pub async fn sync_once(&self) -> Result<(), CommonError> {
+        let db_repos = self.db_repos()?;
+
+        let since = db_repos
+            .sync_state
+            .get_next_batch(SYNC_LISTENER_NAME)
+            .await
+            .map_err(|err| {
+                log::error!("[Synapse Sync] Couldn't get the sync token: {err}");
+                CommonError::Unknown("".to_owned())
+            })?;
+
+        let response = self
+            .synapse
+            .sync(
+                &self.access_token,
+                since.as_deref(),
+                self.timeout,
+                FRIENDSHIP_EVENTS_FILTER,
+            )
+            .await?;
+
+        for room_id in response.rooms.invite.keys() {
+            self.synapse
+                .join_room(&self.access_token, room_id)
+                .await
+                .map_err(|err| {
+                    log::warn!("[Synapse Sync] Couldn't join room {room_id}: {err}");
+                    err
+                })?;
+        }
+
+        if since.is_some() {
+            for (room_id, room) in &response.rooms.join {
+                let mut seen = HashSet::new();
+                let events = room
+                    .state
+                    .events
+                    .iter()
+                    .chain(room.timeline.events.iter())
+                    .filter(|event| event.r#type == FRIENDSHIP_EVENT_TYPE)
+                    .filter(|event| seen.insert(event.event_id.as_str()));
+
+                for event in events {
+                    match self.apply_event(&db_repos, room_id, event).await {
+                        Ok(()) => {}
+                        Err(err) if is_permanent_failure(&err) => {
+                            log::warn!(
+                                "[Synapse Sync] Skipping event {} of room {room_id}: {err:?}",
+                                event.event_id
+                            );
+                        }
+                        Err(err) => return Err(err),
+                    }
+                }
+            }
+        }
+
+        db_repos
+            .sync_state
+            .save_next_batch(SYNC_LISTENER_NAME, &response.next_batch)
+            .await
+            .map_err(|err| {
+                log::error!("[Synapse Sync] Couldn't save the sync token: {err}");
+                CommonError::Unknown("".to_owned())
+            })
+    }
+
+    async fn apply_event(
+        &self,
+        db_repos: &DBRepositories,
+        room_id: &str,
+        event: &SyncRoomEvent,
+    ) -> Result<(), CommonError> {
+        let body = serde_json::from_value::<RoomEventRequestBody>(event.content.clone())
+            .map_err(|_| CommonError::BadRequest("Invalid friendship event".to_owned()))?;
+        let new_event = body.r#type;
+
+        let acting_user = Address::parse(&clean_synapse_user_id(&event.sender))?;
+        let second_user = self.second_user(room_id, &acting_user).await?;
+
+        let friendship = get_friendship(&db_repos.friendships, &acting_user, &second_user).await?;
+        let last_recorded_history =
+            get_last_history(&db_repos.friendship_history, &friendship).await?;
+
+        if is_already_applied(
+            &last_recorded_history,
+            acting_user.as_str(),
+            new_event,
+            event.origin_server_ts,
+        ) {
+            log::debug!(
+                "[Synapse Sync] Event {} was already applied",
+                event.event_id
+            );
+            return Ok(());
+        }
+
+        validate_new_event(acting_user.as_str(), &last_recorded_history, new_event)?;
+
+        let new_status = get_new_friendship_status(acting_user.as_str(), new_event);
+
+        let friendship_ports = FriendshipDbRepositories {
+            db: &self.db,
+            friendships_repository: &db_repos.friendships,
+            friendship_history_repository: &db_repos.friendship_history,
+        };
+        let transaction = friendship_ports
+            .db
+            .start_transaction()
+            .await
+            .map_err(|err| {
+                log::error!(
+                    "[Synapse Sync] Couldn't start transaction to store friendship update {err}"
+                );
+                CommonError::Unknown("".to_owned())
+            })?;
+
+        let room_info = RoomInfo {
+            room_event: new_event,
+            room_message_body: body.message.as_deref(),
+            room_id,
+        };
+        let transaction = update_friendship_status(
+            &friendship,
+            &acting_user,
+            &second_user,
+            new_status,
+            room_info,
+            friendship_ports,
+            transaction,
+        )
+        .await?;
+
+        transaction.commit().await.map_err(|err| {
+            log::error!("[Synapse Sync] Couldn't end transaction to store friendship update {err}");
+            CommonError::Unknown("".to_owned())
+        })?;
+
+        match &self.events_publisher {
+            Some((events_publisher, metrics)) => {
+                let payload = event_payload_as_friendship_payload(EventPayload {
+                    friendship_event: new_event,
+                    second_user: second_user.to_string(),
+                    request_event_message_body: body.message,
+                });
+                publish_friendship_update(
+                    events_publisher,
+                    self.friends_cache.as_ref(),
+                    payload,
+                    &acting_user,
+                    event.origin_server_ts / 1000,
+                    metrics,
+                )
+                .await;
+            }
+            None => {
+                if let Some(friends_cache) = &self.friends_cache {
+                    friends_cache
+                        .invalidate(&[&acting_user, &second_user])
+                        .await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The other user of the room, taken from its members.
+    async fn second_user(
+        &self,
+        room_id: &str,
+        acting_user: &Address,
+    ) -> Result<Address, CommonError> {
+        let service_user = self
+            .service_user
+            .get_or_try_init(|| async {
+                let who_am_i = self.synapse.who_am_i(&self.access_token).await?;
+                Ok::<_, CommonError>(Address::parse(&clean_synapse_user_id(&who_am_i.user_id)).ok())
+            })
+            .await?;
+
+        let members = self
+            .synapse
+            .get_room_members(&self.access_token, room_id)
+            .await?;
+
+        let users: HashSet<Address> = members
+            .chunk
+            .iter()
+            .filter_map(|member| member.social_user_id.as_deref())
+            .filter_map(|user| Address::parse(user).ok())
+            .filter(|user| user != acting_user && Some(user) != service_user.as_ref())
+            .collect();
+
+        match users.len() {
+            1 => Ok(users.into_iter().next().expect("a user in the set")),
+            _ => Err(CommonError::BadRequest(format!(
+                "Room {room_id} is not a friendship room"
+            ))),
+        }
+    }
+
+    fn db_repos(&self) -> Result<DBRepositories, CommonError> {
+        self.db.get_repos().clone().ok_or_else(|| {
+            log::error!("[Synapse Sync] Db repositories > `repos` is None.");
+            CommonError::Unknown("".to_owned())
+        })
+    }
+}
+
+/// Events that would fail again if retried, e.g. invalid transitions or rooms that aren't friendships.
+fn is_permanent_failure(err: &CommonError) -> bool {
+    matches!(
+        err,
+        CommonError::BadRequest(_)
+            | CommonError::Forbidden(_)
+            | CommonError::NotFound(_)
+            | CommonError::UserNotFound(_)
+    )
+}
+
+/// The RPC and REST updates are stored in the database before they're sent to Synapse,
+/// so their events come back through the sync after the update was stored.
+fn is_already_applied(
+    last_recorded_history: &Option<FriendshipHistory>,
+    acting_user: &str,
+    new_event: FriendshipEvent,
+    origin_server_ts: i64,
+) -> bool {
+    let Some(last_history) = last_recorded_history else {
+        return false;
+    };
+
+    let is_last_event = last_history.acting_user == acting_user && last_history.event == new_event;
+
+    is_last_event || last_history.timestamp.timestamp_millis() >= origin_server_ts
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveDateTime;
+    use uuid::Uuid;
+
+    use super::is_already_applied;
+    use crate::{
+        domain::friendship_event::FriendshipEvent, entities::friendship_history::FriendshipHistory,
+    };
+
+    const ACTING_USER: &str = "0x0000000000000000000000000000000000000001";
+    const OTHER_USER: &str = "0x0000000000000000000000000000000000000002";
+
+    fn history(
+        event: FriendshipEvent,
+        acting_user: &str,
+        timestamp_millis: i64,
+    ) -> FriendshipHistory {
+        FriendshipHistory {
+            friendship_id: Uuid::new_v4(),
+            event,
+            acting_user: acting_user.to_string(),
+            timestamp: NaiveDateTime::from_timestamp_millis(timestamp_millis).unwrap(),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_first_event_of_a_friendship_is_new() {
+        assert!(!is_already_applied(
+            &None,
+            ACTING_USER,
+            FriendshipEvent::REQUEST,
+            1000
+        ));
+    }
+
+    #[test]
+    fn test_event_stored_by_the_service_is_already_applied() {
+        let last = Some(history(FriendshipEvent::REQUEST, ACTING_USER, 1000));
+
+        assert!(is_already_applied(
+            &last,
+            ACTING_USER,
+            FriendshipEvent::REQUEST,
+            2000
+        ));
+    }
+
+    #[test]
+    fn test_event_older_than_the_last_history_is_already_applied() {
+        let last = Some(history(FriendshipEvent::CANCEL, ACTING_USER, 2000));
+
+        assert!(is_already_applied(
+            &last,
+            ACTING_USER,
+            FriendshipEvent::REQUEST,
+            1000
+        ));
+    }
+
+    #[test]
+    fn test_event_after_the_last_history_is_new() {
+        let last = Some(history(FriendshipEvent::REQUEST, OTHER_USER, 1000));
+
+        assert!(!is_already_applied(
+            &last,
+            ACTING_USER,
+            FriendshipEvent::ACCEPT,
+            2000
+        ));
+    }
+}
@@ -1,4 +1,8 @@
-use std::{collections::HashMap, sync::Arc, time::Instant};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use dcl_rpc::{
     server::RpcServer,
@@ -6,7 +10,7 @@ use dcl_rpc::{
     transports::web_sockets::{warp::WarpWebSocket, Message, WebSocket, WebSocketTransport},
 };
 
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::RwLock;
 
 use warp::{http::header::HeaderValue, Filter};
 
@@ -14,88 +18,174 @@ use prost::Message as ProstMessage;
 
 use crate::{
     components::notifications::{
-        init_events_channel_publisher, init_events_channel_subscriber, RedisChannelPublisher,
-        RedisChannelSubscriber,
+        init_configured_events_channel_publisher, init_configured_events_channel_subscriber,
+        EventsChannelPublisher, EventsChannelSubscriber,
     },
     components::{
-        configuration::{Config, RpcServerConfig},
-        database::DatabaseComponent,
+        app::AppComponents,
+        configuration::{Config, EventsChannelBackend, RoomCleanupPolicy, RpcServerConfig},
+        database::DatabaseComponentImplementation,
+        friends_cache::FriendsCacheComponent,
         notifications::{ChannelSubscriber, EVENT_UPDATES_CHANNEL_NAME},
+        profiles::ProfilesComponent,
+        push_notifications::PushNotificationsComponent,
         redis::Redis,
+        session_tokens::SessionTokensComponent,
         synapse::SynapseComponent,
         users_cache::UsersCacheComponent,
     },
-    domain::address::Address,
+    domain::{address::Address, friendship_limits::FriendshipLimits},
     friendships::{
-        subscribe_friendship_events_updates_response, FriendshipEventResponses,
-        FriendshipsServiceRegistration, SubscribeFriendshipEventsUpdatesResponse,
+        friendship_event_response, subscribe_friendship_events_updates_response, CancelResponse,
+        DeleteResponse, FriendshipEventResponse, FriendshipEventResponses,
+        FriendshipsServiceRegistration, RejectResponse, SubscribeFriendshipEventsUpdatesResponse,
+        User,
     },
     notifications::Event,
 };
 
 use super::{
+    auth::{
+        authenticate_handshake, watch_transport_auth, HandshakeAuth, TransportAuthenticator,
+        HANDSHAKE_TOKEN_HEADER,
+    },
     metrics::{metrics_handler, validate_bearer_token, Metrics, Procedure},
     service::friendships_service,
 };
 
+#[derive(Clone)]
 pub struct ConfigRpcServer {
     pub rpc_server: RpcServerConfig,
     pub wkc_metrics_bearer_token: String,
+    pub room_cleanup: RoomCleanupPolicy,
+    /// Token of a Synapse admin to clean up the rooms for the other user, empty to not use one
+    pub synapse_admin_access_token: String,
+    /// Limits of the users without features overriding them
+    pub friendship_limits: FriendshipLimits,
 }
 
 pub struct SocialTransportContext {
-    pub address: Address,
+    /// The address of the user attached to the transport, `None` until the user authenticates.
+    pub address: Option<Address>,
+    /// The token the user authenticated with, checked again on each call.
+    pub token: Option<String>,
     pub connection_ts: Instant,
 }
 
-type TransportId = u32;
+pub type TransportId = u32;
+
+/// Subscription of each user to their friendship events, along with the transport it was made
+/// through, so only that transport removes it once it closes.
+pub type FriendshipsEventsGenerators = RwLock<
+    HashMap<
+        Address,
+        (
+            TransportId,
+            GeneratorYielder<SubscribeFriendshipEventsUpdatesResponse>,
+        ),
+    >,
+>;
+
+/// The context of the transport is the user authenticated on the handshake
+type SocialTransport = WebSocketTransport<WarpWebSocket, Option<HandshakeAuth>>;
 
+#[derive(Clone)]
 pub struct SocialContext {
     pub synapse: SynapseComponent,
-    pub db: DatabaseComponent,
-    pub users_cache: Arc<Mutex<UsersCacheComponent>>,
+    pub db: Arc<dyn DatabaseComponentImplementation>,
+    pub users_cache: Arc<UsersCacheComponent>,
+    /// `None` when the friends cache is disabled
+    pub friends_cache: Option<FriendsCacheComponent>,
+    /// `None` when no session signing keys are configured
+    pub session_tokens: Option<SessionTokensComponent>,
+    /// `None` when no push notifications provider is configured
+    pub push_notifications: Option<PushNotificationsComponent>,
+    /// `None` when no profiles URL is configured
+    pub profiles: Option<ProfilesComponent>,
     pub config: ConfigRpcServer,
-    pub redis_publisher: Arc<RedisChannelPublisher>,
-    pub redis_subscriber: Arc<RedisChannelSubscriber>,
-    pub friendships_events_generators:
-        Arc<RwLock<HashMap<Address, GeneratorYielder<SubscribeFriendshipEventsUpdatesResponse>>>>,
+    pub events_publisher: Arc<EventsChannelPublisher>,
+    pub events_subscriber: Arc<EventsChannelSubscriber>,
+    pub friendships_events_generators: Arc<FriendshipsEventsGenerators>,
     pub transport_context: Arc<RwLock<HashMap<TransportId, SocialTransportContext>>>,
     pub friends_stream_page_size: u16,
     pub metrics: Arc<Metrics>,
 }
 
+impl SocialContext {
+    pub fn new(app_data: &AppComponents, ws_components: WsComponents) -> Self {
+        Self {
+            synapse: app_data.synapse.clone(),
+            db: app_data.db.clone(),
+            users_cache: Arc::clone(&app_data.users_cache),
+            friends_cache: app_data.friends_cache.clone(),
+            session_tokens: app_data.session_tokens.clone(),
+            push_notifications: app_data.push_notifications.clone(),
+            profiles: app_data.profiles.clone(),
+            config: ConfigRpcServer {
+                rpc_server: app_data.config.rpc_server.clone(),
+                wkc_metrics_bearer_token: app_data.config.wkc_metrics_bearer_token.clone(),
+                room_cleanup: app_data.config.synapse.room_cleanup,
+                synapse_admin_access_token: app_data.config.synapse_admin_access_token.clone(),
+                friendship_limits: FriendshipLimits {
+                    max_friends: app_data.config.friendships_max_friends,
+                    max_outgoing_requests: app_data.config.friendships_max_outgoing_requests,
+                },
+            },
+            events_publisher: ws_components.events_publisher,
+            events_subscriber: ws_components.events_subscriber,
+            friendships_events_generators: ws_components.friendships_events_generators,
+            transport_context: ws_components.transport_context,
+            friends_stream_page_size: app_data.config.friends_stream_page_size,
+            metrics: ws_components.metrics,
+        }
+    }
+}
+
 pub struct WsComponents {
-    pub redis_publisher: Arc<RedisChannelPublisher>,
-    pub redis_subscriber: Arc<RedisChannelSubscriber>,
-    pub friendships_events_generators:
-        Arc<RwLock<HashMap<Address, GeneratorYielder<SubscribeFriendshipEventsUpdatesResponse>>>>,
+    pub events_publisher: Arc<EventsChannelPublisher>,
+    pub events_subscriber: Arc<EventsChannelSubscriber>,
+    pub friendships_events_generators: Arc<FriendshipsEventsGenerators>,
     pub transport_context: Arc<RwLock<HashMap<TransportId, SocialTransportContext>>>,
     pub metrics: Arc<Metrics>,
 }
 
-pub async fn init_ws_components(config: Config) -> WsComponents {
-    let redis = Redis::new_and_run(&config.redis).await;
+pub async fn init_ws_components(
+    config: Config,
+    db: &Arc<dyn DatabaseComponentImplementation>,
+) -> WsComponents {
+    // Without a Redis host the events channel fails to start if it's the configured backend
+    let redis = match config.events_channel {
+        EventsChannelBackend::Redis if !config.redis.host.is_empty() => {
+            match Redis::new_and_run(&config.redis).await {
+                Ok(redis) => Some(Arc::new(redis)),
+                Err(err) => {
+                    panic!("There was an error initializing Redis for Pub/Sub: {err}");
+                }
+            }
+        }
+        EventsChannelBackend::Redis
+        | EventsChannelBackend::Postgres
+        | EventsChannelBackend::InProcess => None,
+    };
 
     let metrics = Arc::new(Metrics::new());
 
-    match redis {
-        Ok(redis) => {
-            let redis = Arc::new(redis);
-            let redis_publisher = Arc::new(init_events_channel_publisher(redis.clone()).await);
-            let redis_subscriber = Arc::new(init_events_channel_subscriber(redis));
-            let friendships_events_generators = Arc::new(RwLock::new(HashMap::new()));
-            let transport_context = Arc::new(RwLock::new(HashMap::new()));
-            WsComponents {
-                redis_publisher,
-                redis_subscriber,
-                friendships_events_generators,
-                transport_context,
-                metrics,
-            }
-        }
-        Err(err) => {
-            panic!("There was an error initializing Redis for Pub/Sub: {err}");
-        }
+    let events_publisher = Arc::new(
+        init_configured_events_channel_publisher(config.events_channel, redis.clone(), db).await,
+    );
+    let events_subscriber = Arc::new(init_configured_events_channel_subscriber(
+        config.events_channel,
+        redis,
+        db,
+    ));
+    let friendships_events_generators = Arc::new(RwLock::new(HashMap::new()));
+    let transport_context = Arc::new(RwLock::new(HashMap::new()));
+    WsComponents {
+        events_publisher,
+        events_subscriber,
+        friendships_events_generators,
+        transport_context,
+        metrics,
     }
 }
 
@@ -107,20 +197,24 @@ pub async fn run_ws_transport(
     }
 
     let port = ctx.config.rpc_server.port;
-    let subs = ctx.redis_subscriber.clone();
+    let subs = ctx.events_subscriber.clone();
     let generators = ctx.friendships_events_generators.clone();
     let wkc_metrics_bearer_token = ctx.config.wkc_metrics_bearer_token.clone();
     let generators_clone = ctx.friendships_events_generators.clone();
     let transport_contexts = ctx.transport_context.clone();
     let metrics = ctx.metrics.clone();
     let rpc_config = ctx.config.rpc_server.clone();
+    let synapse = ctx.synapse.clone();
+    let users_cache = ctx.users_cache.clone();
+    let session_tokens = ctx.session_tokens.clone();
+    let push_notifications = ctx.push_notifications.clone();
 
     let metrics_clone = Arc::clone(&metrics);
     tokio::spawn(async move {
-        subscribe_to_event_updates(subs, generators.clone(), metrics_clone);
+        subscribe_to_event_updates(subs, generators.clone(), push_notifications, metrics_clone);
     });
 
-    let mut rpc_server: RpcServer<SocialContext, WebSocketTransport<WarpWebSocket, ()>> =
+    let mut rpc_server: RpcServer<SocialContext, SocialTransport> =
         dcl_rpc::server::RpcServer::create(ctx);
     rpc_server.set_module_registrator_handler(|port| {
         FriendshipsServiceRegistration::register_service(
@@ -131,17 +225,44 @@ pub async fn run_ws_transport(
 
     let metrics_clone = Arc::clone(&metrics);
     let transport_contexts_clone = Arc::clone(&transport_contexts);
-    rpc_server.set_on_transport_connected_handler(move |_transport, transport_id| {
+    let auth_grace_period = Duration::from_secs(rpc_config.auth_grace_period_seconds);
+    let auth_recheck_interval = Duration::from_secs(rpc_config.auth_recheck_interval_seconds);
+    let authenticator = TransportAuthenticator {
+        synapse: synapse.clone(),
+        users_cache: users_cache.clone(),
+        session_tokens: session_tokens.clone(),
+    };
+    rpc_server.set_on_transport_connected_handler(move |transport, transport_id| {
         metrics_clone.increment_connected_clients();
         let transport_contexts_clone = Arc::clone(&transport_contexts_clone);
+        let authenticator = authenticator.clone();
+        // User authenticated on the handshake, if any
+        let handshake_auth = transport.context.clone();
         tokio::spawn(async move {
-            transport_contexts_clone.write().await.insert(
+            // A first call may have bound the transport already, it must not be overwritten
+            transport_contexts_clone
+                .write()
+                .await
+                .entry(transport_id)
+                .or_insert_with(|| {
+                    let (address, token) = match handshake_auth {
+                        Some(HandshakeAuth { address, token }) => (Some(address), Some(token)),
+                        None => (None, None),
+                    };
+                    SocialTransportContext {
+                        address,
+                        token,
+                        connection_ts: Instant::now(),
+                    }
+                });
+            watch_transport_auth(
+                transport,
                 transport_id,
-                SocialTransportContext {
-                    address: Address("".to_string()),
-                    connection_ts: Instant::now(),
-                },
-            )
+                transport_contexts_clone,
+                authenticator,
+                auth_grace_period,
+                auth_recheck_interval,
+            );
         });
     });
 
@@ -174,15 +295,28 @@ pub async fn run_ws_transport(
     let rpc_route = warp::path::end()
         // Check if the connection wants to be upgraded to have a WebSocket Connection.
         .and(warp::ws())
+        // The token to authenticate the connection is optional, it can be sent on the first call instead
+        .and(warp::header::optional::<String>(HANDSHAKE_TOKEN_HEADER))
         // Get the connection and set a callback to send the WebSocket Transport to the RpcServer once the connection is finally upgraded.
-        .map(move |ws: warp::ws::Ws| {
+        .map(move |ws: warp::ws::Ws, authorization: Option<String>| {
             let rpc_config = rpc_config.clone();
             let server_events_sender = server_events_sender.clone();
+            let synapse = synapse.clone();
+            let users_cache = users_cache.clone();
+            let session_tokens = session_tokens.clone();
             ws.on_upgrade(|ws| async move {
+                let handshake_auth = authenticate_handshake(
+                    &synapse,
+                    users_cache,
+                    session_tokens.as_ref(),
+                    authorization.as_deref(),
+                )
+                .await;
                 let websocket = WarpWebSocket::new(ws);
                 let websocket = Arc::new(websocket);
                 ping_every_s(rpc_config, websocket.clone());
-                let transport = Arc::new(WebSocketTransport::new(websocket));
+                let transport =
+                    Arc::new(WebSocketTransport::with_context(websocket, handshake_auth));
 
                 server_events_sender
                     .send_attach_transport(transport)
@@ -233,44 +367,83 @@ async fn observe_connection_duration(
 async fn remove_transport_id_from_context(
     transport_id: TransportId,
     transport_contexts: Arc<RwLock<HashMap<TransportId, SocialTransportContext>>>,
-    generators: Arc<
-        RwLock<HashMap<Address, GeneratorYielder<SubscribeFriendshipEventsUpdatesResponse>>>,
-    >,
+    generators: Arc<FriendshipsEventsGenerators>,
 ) {
     if let Some(transport_ctx) = transport_contexts.read().await.get(&transport_id) {
-        // First remove the generators of the corresponding address
-        generators.write().await.remove(&transport_ctx.address);
+        // First remove the generator of the corresponding address, unless the user subscribed
+        // again through another transport which owns it now
+        if let Some(address) = &transport_ctx.address {
+            let mut generators = generators.write().await;
+            if matches!(generators.get(address), Some((owner, _)) if *owner == transport_id) {
+                generators.remove(address);
+            }
+        }
     };
     transport_contexts.write().await.remove(&transport_id);
 }
 
-// Subscribe to Redis Pub/Sub to listen on friendship events updates, so then can notify the affected users on their corresponding generators
+// Subscribe to the events channel to listen on friendship events updates, so then can notify the affected users on their corresponding generators,
+// or through a push notification when they aren't subscribed
 fn subscribe_to_event_updates(
-    event_subscriptions: Arc<RedisChannelSubscriber>,
-    client_generators: Arc<
-        RwLock<HashMap<Address, GeneratorYielder<SubscribeFriendshipEventsUpdatesResponse>>>,
-    >,
+    event_subscriptions: Arc<EventsChannelSubscriber>,
+    client_generators: Arc<FriendshipsEventsGenerators>,
+    push_notifications: Option<PushNotificationsComponent>,
     metrics: Arc<Metrics>,
 ) {
     event_subscriptions.subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event_update: Event| {
         log::debug!("[RPC] User Update received > event_update: {event_update:?}");
         let generators = client_generators.clone();
+        let push_notifications = push_notifications.clone();
         let metrics_clone = Arc::clone(&metrics);
         async move {
-            send_update_to_corresponding_generator(generators, event_update, metrics_clone).await;
+            if let Some(mirrored_update) = mirrored_event(&event_update) {
+                send_update_to_corresponding_generator(
+                    generators.clone(),
+                    mirrored_update,
+                    metrics_clone.clone(),
+                )
+                .await;
+            }
+
+            let delivered = send_update_to_corresponding_generator(
+                generators,
+                event_update.clone(),
+                metrics_clone,
+            )
+            .await;
+
+            let Some(push_notifications) =
+                push_notifications.filter(|_| PushNotificationsComponent::is_pushed(&event_update))
+            else {
+                return;
+            };
+
+            if delivered {
+                push_notifications.mark_delivered_live(&event_update).await;
+            } else {
+                // It waits for the other instances, so it shouldn't hold the following events back
+                tokio::spawn(
+                    async move { push_notifications.notify_if_offline(&event_update).await },
+                );
+            }
         }
     });
 }
 
+/// Returns whether the update was sent to a subscription of the recipient.
 async fn send_update_to_corresponding_generator(
-    generators: Arc<
-        RwLock<HashMap<Address, GeneratorYielder<SubscribeFriendshipEventsUpdatesResponse>>>,
-    >,
+    generators: Arc<FriendshipsEventsGenerators>,
     event_update: Event,
     metrics: Arc<Metrics>,
-) {
+) -> bool {
     if let Some(response) = event_as_friendship_update_response(event_update.clone()) {
-        let corresponding_user_id = Address(event_update.to.to_lowercase());
+        let Ok(corresponding_user_id) = Address::parse(&event_update.to) else {
+            log::error!(
+                "[RPC] Event Update received > Invalid recipient address: {}",
+                event_update.to
+            );
+            return false;
+        };
 
         metrics.record_out_procedure_call_size(
             None,
@@ -280,12 +453,49 @@ async fn send_update_to_corresponding_generator(
 
         let generators_lock = generators.read().await;
 
-        if let Some(generator) = generators_lock.get(&corresponding_user_id) {
+        if let Some((_, generator)) = generators_lock.get(&corresponding_user_id) {
             if generator.r#yield(response.clone()).await.is_err() {
                 log::error!("[RPC] Event Update received > Couldn't send update to subscriptors. Update: {:?}, Subscriptor: {:?}", response, &corresponding_user_id);
+                return false;
             }
+            return true;
         }
     }
+
+    false
+}
+
+/// The event as seen by `from`, for the events that notify both users: a DELETE from the other
+/// user, and the REJECT of a cancelled request or the CANCEL of a rejected one.
+fn mirrored_event(event_update: &Event) -> Option<Event> {
+    if !event_update.notify_from {
+        return None;
+    }
+
+    let user = Some(User {
+        address: event_update.to.clone(),
+        ..Default::default()
+    });
+    let body = match event_update.friendship_event.as_ref()?.body.as_ref()? {
+        friendship_event_response::Body::Delete(_) => {
+            friendship_event_response::Body::Delete(DeleteResponse { user })
+        }
+        friendship_event_response::Body::Cancel(_) => {
+            friendship_event_response::Body::Reject(RejectResponse { user })
+        }
+        friendship_event_response::Body::Reject(_) => {
+            friendship_event_response::Body::Cancel(CancelResponse { user })
+        }
+        _ => return None,
+    };
+
+    Some(Event {
+        friendship_event: Some(FriendshipEventResponse { body: Some(body) }),
+        from: event_update.to.clone(),
+        to: event_update.from.clone(),
+        notify_from: false,
+        ..event_update.clone()
+    })
 }
 
 fn event_as_friendship_update_response(
@@ -318,3 +528,42 @@ fn ping_every_s(config: RpcServerConfig, websocket: Arc<WarpWebSocket>) {
         }
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc, time::Instant};
+
+    use dcl_rpc::stream_protocol::Generator;
+    use tokio::sync::RwLock;
+
+    use super::{remove_transport_id_from_context, SocialTransportContext};
+    use crate::{domain::address::Address, friendships::SubscribeFriendshipEventsUpdatesResponse};
+
+    const PIZARNIK: &str = "0x0000000000000000000000000000000000000001";
+
+    #[tokio::test]
+    async fn test_generator_is_only_removed_by_the_transport_owning_it() {
+        let address = Address::parse(PIZARNIK).unwrap();
+        let transport_context = || SocialTransportContext {
+            address: Some(address.clone()),
+            token: Some("a_token".to_string()),
+            connection_ts: Instant::now(),
+        };
+        let transport_contexts = Arc::new(RwLock::new(HashMap::from([
+            (1, transport_context()),
+            (2, transport_context()),
+        ])));
+        // The user subscribed again through the second transport
+        let (_generator, yielder) = Generator::<SubscribeFriendshipEventsUpdatesResponse>::create();
+        let generators = Arc::new(RwLock::new(HashMap::from([(
+            address.clone(),
+            (2, yielder),
+        )])));
+
+        remove_transport_id_from_context(1, transport_contexts.clone(), generators.clone()).await;
+        assert!(generators.read().await.contains_key(&address));
+
+        remove_transport_id_from_context(2, transport_contexts, generators.clone()).await;
+        assert!(generators.read().await.is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+// Connection level authentication of the RPC transports. The address of the user is bound to the
+// transport once, on the WebSocket handshake or on its first call with a token, and the calls are
+// resolved from that binding. The token is kept along with it and checked again periodically, so a
+// transport is closed once its token is revoked, logged out or expired, and a call can't act as
+// another user.
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use dcl_rpc::transports::Transport;
+use tokio::sync::RwLock;
+
+use crate::{
+    components::{
+        session_tokens::{get_user_id_from_any_token, SessionTokensComponent},
+        synapse::SynapseComponent,
+        users_cache::UsersCacheComponent,
+    },
+    domain::{address::Address, error::CommonError},
+};
+
+use super::app::{SocialTransportContext, TransportId};
+
+/// Header of the WebSocket upgrade request that carries the token of the user, as `Bearer <token>`.
+/// Tokens aren't taken from the query string, as URLs end up in the logs of proxies.
+pub const HANDSHAKE_TOKEN_HEADER: &str = "authorization";
+
+pub type TransportContexts = RwLock<HashMap<TransportId, SocialTransportContext>>;
+
+/// User authenticated on the WebSocket handshake, along with the token used.
+#[derive(Debug, Clone)]
+pub struct HandshakeAuth {
+    pub address: Address,
+    pub token: String,
+}
+
+/// Resolves the address of the user the token, a session or a Synapse one, belongs to.
+pub async fn authenticate_token(
+    synapse: &SynapseComponent,
+    users_cache: Arc<UsersCacheComponent>,
+    session_tokens: Option<&SessionTokensComponent>,
+    token: &String,
+) -> Result<Address, CommonError> {
+    let user_id =
+        get_user_id_from_any_token(synapse.clone(), users_cache, session_tokens, token).await?;
+    Address::parse(&user_id.social_id).map_err(CommonError::from)
+}
+
+/// Authenticates the bearer token given on the handshake, if any. An invalid token is only logged,
+/// the transport can still authenticate on its first call within the grace period.
+pub async fn authenticate_handshake(
+    synapse: &SynapseComponent,
+    users_cache: Arc<UsersCacheComponent>,
+    session_tokens: Option<&SessionTokensComponent>,
+    authorization: Option<&str>,
+) -> Option<HandshakeAuth> {
+    let token = authorization?.strip_prefix("Bearer ")?.trim().to_string();
+    match authenticate_token(synapse, users_cache, session_tokens, &token).await {
+        Ok(address) => Some(HandshakeAuth { address, token }),
+        Err(err) => {
+            log::warn!("[RPC] Handshake > Couldn't authenticate the transport: {err:?}");
+            None
+        }
+    }
+}
+
+/// Address bound to the transport, `None` while it's not authenticated.
+pub async fn get_transport_address(
+    transport_contexts: &TransportContexts,
+    transport_id: TransportId,
+) -> Option<Address> {
+    transport_contexts
+        .read()
+        .await
+        .get(&transport_id)
+        .and_then(|transport_context| transport_context.address.clone())
+}
+
+/// Token the transport authenticated with, `None` while it's not authenticated.
+pub async fn get_transport_token(
+    transport_contexts: &TransportContexts,
+    transport_id: TransportId,
+) -> Option<String> {
+    transport_contexts
+        .read()
+        .await
+        .get(&transport_id)
+        .and_then(|transport_context| transport_context.token.clone())
+}
+
+/// Binds the address of the authenticated user to the transport, along with the token, unless the
+/// transport is already bound to another user.
+pub async fn bind_transport_address(
+    transport_contexts: &TransportContexts,
+    transport_id: TransportId,
+    address: &Address,
+    token: &str,
+) -> Result<(), CommonError> {
+    let mut transport_contexts = transport_contexts.write().await;
+    let transport_context =
+        transport_contexts
+            .entry(transport_id)
+            .or_insert_with(|| SocialTransportContext {
+                address: None,
+                token: None,
+                connection_ts: Instant::now(),
+            });
+
+    match &transport_context.address {
+        Some(bound_address) if bound_address != address => {
+            log::warn!("[RPC] Transport {transport_id} is bound to another user than the token");
+            Err(CommonError::Unauthorized(
+                "The token belongs to another user".to_owned(),
+            ))
+        }
+        _ => {
+            transport_context.address = Some(address.clone());
+            // The latest token is kept, e.g. a refreshed session one
+            transport_context.token = Some(token.to_string());
+            Ok(())
+        }
+    }
+}
+
+/// What the transports are authenticated with, to check their tokens again in the background.
+#[derive(Clone)]
+pub struct TransportAuthenticator {
+    pub synapse: SynapseComponent,
+    pub users_cache: Arc<UsersCacheComponent>,
+    pub session_tokens: Option<SessionTokensComponent>,
+}
+
+/// Closes the transport if it's still not authenticated once the grace period is over, and later
+/// once its token is no longer valid, checked every `recheck_interval`. The calls are resolved from
+/// the bound address, so this is what stops a transport whose token was revoked.
+pub fn watch_transport_auth<T: Transport + Send + Sync + 'static>(
+    transport: Arc<T>,
+    transport_id: TransportId,
+    transport_contexts: Arc<TransportContexts>,
+    authenticator: TransportAuthenticator,
+    grace_period: Duration,
+    recheck_interval: Duration,
+) {
+    tokio::spawn(async move {
+        tokio::time::sleep(grace_period).await;
+
+        // A transport that is already closed is no longer in the contexts
+        let is_unauthenticated = matches!(
+            transport_contexts.read().await.get(&transport_id),
+            Some(transport_context) if transport_context.address.is_none()
+        );
+        if is_unauthenticated {
+            log::info!("[RPC] Closing transport {transport_id}, it didn't authenticate in time");
+            transport.close().await;
+            return;
+        }
+
+        if recheck_interval.is_zero() {
+            return;
+        }
+
+        loop {
+            tokio::time::sleep(recheck_interval).await;
+
+            let Some(token) = get_transport_token(&transport_contexts, transport_id).await else {
+                return;
+            };
+            let result = authenticate_token(
+                &authenticator.synapse,
+                authenticator.users_cache.clone(),
+                authenticator.session_tokens.as_ref(),
+                &token,
+            )
+            .await;
+
+            // Synapse or Redis being unavailable doesn't close the transport
+            if let Err(CommonError::Unauthorized(_)) = result {
+                log::info!("[RPC] Closing transport {transport_id}, its token is no longer valid");
+                transport.close().await;
+                return;
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, time::Instant};
+
+    use tokio::sync::RwLock;
+
+    use super::{
+        bind_transport_address, get_transport_address, get_transport_token, TransportContexts,
+    };
+    use crate::{domain::address::Address, ws::app::SocialTransportContext};
+
+    const PIZARNIK: &str = "0x0000000000000000000000000000000000000001";
+    const MARTHA: &str = "0x0000000000000000000000000000000000000002";
+
+    #[tokio::test]
+    async fn test_bind_transport_address() {
+        let transport_contexts: TransportContexts = RwLock::new(HashMap::from([(
+            1,
+            SocialTransportContext {
+                address: None,
+                token: None,
+                connection_ts: Instant::now(),
+            },
+        )]));
+        let address = Address::parse(PIZARNIK).unwrap();
+
+        assert_eq!(get_transport_address(&transport_contexts, 1).await, None);
+
+        bind_transport_address(&transport_contexts, 1, &address, "a_token")
+            .await
+            .unwrap();
+
+        assert_eq!(
+            get_transport_address(&transport_contexts, 1).await,
+            Some(address)
+        );
+        assert_eq!(
+            get_transport_token(&transport_contexts, 1).await.as_deref(),
+            Some("a_token")
+        );
+        assert_eq!(get_transport_address(&transport_contexts, 2).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_bind_transport_address_of_another_user() {
+        let transport_contexts: TransportContexts = RwLock::new(HashMap::new());
+        let address = Address::parse(PIZARNIK).unwrap();
+        bind_transport_address(&transport_contexts, 1, &address, "a_token")
+            .await
+            .unwrap();
+
+        let result = bind_transport_address(
+            &transport_contexts,
+            1,
+            &Address::parse(MARTHA).unwrap(),
+            "another_token",
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(
+            get_transport_address(&transport_contexts, 1).await,
+            Some(address)
+        );
+        assert_eq!(
+            get_transport_token(&transport_contexts, 1).await.as_deref(),
+            Some("a_token")
+        );
+    }
+}
@@ -6,7 +6,14 @@ use prometheus::{
     self, Encoder, Histogram, HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry,
 };
 
-use crate::domain::friendship_event::FriendshipEvent;
+use crate::{
+    components::{
+        friends_cache::FriendsCacheComponent, push_notifications::PushNotificationsComponent,
+        service_keys::ServiceKeysComponent, synapse::SynapseComponent,
+        users_cache::UsersCacheComponent, webhooks::WebhooksComponent,
+    },
+    domain::friendship_event::FriendshipEvent,
+};
 
 use super::service::mapper::error::WsServiceError;
 
@@ -133,6 +140,59 @@ impl Metrics {
         }
     }
 
+    /// Exposes the state of the Synapse client, e.g. whether its circuit breaker is open.
+    pub fn register_synapse_collectors(&self, synapse: &SynapseComponent) {
+        for collector in synapse.metrics_collectors() {
+            self.registry.register(collector).expect(
+                "Synapse metrics should be correct, so they can be registered successfully",
+            );
+        }
+    }
+
+    /// Exposes the hits and misses of the friends cache.
+    pub fn register_friends_cache_collectors(&self, friends_cache: &FriendsCacheComponent) {
+        for collector in friends_cache.metrics_collectors() {
+            self.registry.register(collector).expect(
+                "Friends cache metrics should be correct, so they can be registered successfully",
+            );
+        }
+    }
+
+    pub fn register_users_cache_collectors(&self, users_cache: &UsersCacheComponent) {
+        for collector in users_cache.metrics_collectors() {
+            self.registry.register(collector).expect(
+                "Users cache metrics should be correct, so they can be registered successfully",
+            );
+        }
+    }
+
+    pub fn register_service_keys_collectors(&self, service_keys: &ServiceKeysComponent) {
+        for collector in service_keys.metrics_collectors() {
+            self.registry.register(collector).expect(
+                "Service keys metrics should be correct, so they can be registered successfully",
+            );
+        }
+    }
+
+    pub fn register_push_notifications_collectors(
+        &self,
+        push_notifications: &PushNotificationsComponent,
+    ) {
+        for collector in push_notifications.metrics_collectors() {
+            self.registry.register(collector).expect(
+                "Push notifications metrics should be correct, so they can be registered successfully",
+            );
+        }
+    }
+
+    pub fn register_webhooks_collectors(&self, webhooks: &WebhooksComponent) {
+        for collector in webhooks.metrics_collectors() {
+            self.registry.register(collector).expect(
+                "Webhooks metrics should be correct, so they can be registered successfully",
+            );
+        }
+    }
+
     fn create_int_counter_vec(
         metric: (&str, &str),
         labels: &[&str],
@@ -1,4 +1,5 @@
 pub mod app;
+pub mod auth;
 pub mod metrics;
 pub mod service;
 pub mod transport;
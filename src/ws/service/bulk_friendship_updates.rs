@@ -0,0 +1,220 @@
+// Applies the same transition to many pending requests of a user, e.g. accepting all the requests
+// received while they were away. It's only exposed through `POST /v1/friendships/bulk`, the pinned
+// friendships protocol has no bulk procedures so the RPC service doesn't offer them.
+use std::{collections::HashSet, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    domain::{
+        address::Address, error::CommonError, event::EventPayload,
+        friendship_event::FriendshipEvent,
+    },
+    friendships::{
+        friendship_event_payload, AcceptPayload, CancelPayload, FriendshipEventPayload,
+        RejectPayload, User,
+    },
+    ws::app::SocialContext,
+};
+
+use super::friendship_event_updates::{handle_friendship_update, publish_friendship_update};
+
+/// Most users a bulk update goes through, so a single call can't hold the connection for too long
+pub const MAX_BULK_UPDATE_USERS: usize = 100;
+
+/// Transitions that can be applied to many pending requests at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkFriendshipAction {
+    /// Accepts the requests received by the user
+    AcceptAll,
+    /// Rejects the requests received by the user
+    RejectAll,
+    /// Cancels the requests sent by the user
+    CancelAllOutgoing,
+}
+
+impl BulkFriendshipAction {
+    pub fn event(&self) -> FriendshipEvent {
+        match self {
+            BulkFriendshipAction::AcceptAll => FriendshipEvent::ACCEPT,
+            BulkFriendshipAction::RejectAll => FriendshipEvent::REJECT,
+            BulkFriendshipAction::CancelAllOutgoing => FriendshipEvent::CANCEL,
+        }
+    }
+
+    /// Whether the action answers the requests sent to the user, rather than the ones they sent
+    fn answers_incoming_requests(&self) -> bool {
+        !matches!(self, BulkFriendshipAction::CancelAllOutgoing)
+    }
+
+    /// Payload published to the other user, as if the update was sent on its own.
+    fn payload(&self, address: &str) -> FriendshipEventPayload {
+        let user = Some(User {
+            address: address.to_string(),
+            ..Default::default()
+        });
+        let body = match self {
+            BulkFriendshipAction::AcceptAll => {
+                friendship_event_payload::Body::Accept(AcceptPayload { user })
+            }
+            BulkFriendshipAction::RejectAll => {
+                friendship_event_payload::Body::Reject(RejectPayload { user })
+            }
+            BulkFriendshipAction::CancelAllOutgoing => {
+                friendship_event_payload::Body::Cancel(CancelPayload { user })
+            }
+        };
+
+        FriendshipEventPayload { body: Some(body) }
+    }
+}
+
+/// Users whose requests the bulk update goes through.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BulkTarget {
+    /// Every pending request in the direction of the action
+    All,
+    Users(Vec<String>),
+}
+
+/// Outcome of the update with one of the users.
+#[derive(Debug)]
+pub struct BulkItemResult {
+    pub user: String,
+    pub result: Result<(), CommonError>,
+}
+
+/// Applies the transition of the action to the requests of each target user.
+///
+/// Each update is stored in its own transaction, so a failure doesn't roll back the rest, and it's
+/// published to the other user once stored. Returns the outcome of each of them, in the order of
+/// the target users.
+pub async fn handle_bulk_friendship_update(
+    synapse_token: String,
+    action: BulkFriendshipAction,
+    target: BulkTarget,
+    context: Arc<SocialContext>,
+    acting_user: Address,
+) -> Result<Vec<BulkItemResult>, CommonError> {
+    let users = match target {
+        BulkTarget::All => pending_requests_users(action, &context, &acting_user).await?,
+        BulkTarget::Users(users) if users.len() > MAX_BULK_UPDATE_USERS => {
+            return Err(CommonError::BadRequest(format!(
+                "Up to {MAX_BULK_UPDATE_USERS} users can be updated at once"
+            )));
+        }
+        BulkTarget::Users(users) => users,
+    };
+
+    let mut results = Vec::with_capacity(users.len());
+    for user in parse_users(users) {
+        let user = match user {
+            Ok(user) => user,
+            Err(invalid_user) => {
+                results.push(invalid_user);
+                continue;
+            }
+        };
+        let event_payload = EventPayload {
+            friendship_event: action.event(),
+            second_user: user.to_string(),
+            request_event_message_body: None,
+        };
+
+        let result = handle_friendship_update(
+            synapse_token.clone(),
+            event_payload,
+            context.clone(),
+            acting_user.clone(),
+        )
+        .await;
+
+        match result {
+            Ok(response) => {
+                publish_friendship_update(
+                    &context.events_publisher,
+                    context.friends_cache.as_ref(),
+                    action.payload(&response.user_id),
+                    &acting_user,
+                    chrono::Utc::now().timestamp(),
+                    &context.metrics,
+                )
+                .await;
+
+                results.push(BulkItemResult {
+                    user: response.user_id,
+                    result: Ok(()),
+                });
+            }
+            Err(err) => {
+                log::warn!(
+                    "[RPC] Bulk friendship update > {:?} of {acting_user} with {user} failed: {err}",
+                    action.event()
+                );
+                results.push(BulkItemResult {
+                    user: user.to_string(),
+                    result: Err(err),
+                });
+            }
+        }
+    }
+
+    Ok(results)
+}
+
+/// The other user of each pending request in the direction of the action, oldest first.
+async fn pending_requests_users(
+    action: BulkFriendshipAction,
+    context: &SocialContext,
+    acting_user: &Address,
+) -> Result<Vec<String>, CommonError> {
+    let Some(repos) = context.db.get_repos().as_ref() else {
+        log::error!("[RPC] Bulk friendship update > Db repositories > `repos` is None.");
+        return Err(CommonError::Unknown("".to_owned()));
+    };
+
+    let (requests, _) = repos
+        .friendship_history
+        .get_user_pending_request_events(acting_user, None)
+        .await;
+    let mut requests = requests.map_err(|err| {
+        log::error!(
+            "[RPC] Bulk friendship update > Get user pending request events > Error: {err}."
+        );
+        CommonError::Unknown("".to_owned())
+    })?;
+    requests.sort_by_key(|request| request.timestamp);
+
+    Ok(requests
+        .into_iter()
+        .filter(|request| {
+            let is_incoming = request.acting_user != acting_user.as_str();
+            is_incoming == action.answers_incoming_requests()
+        })
+        .map(|request| {
+            if request.address_1 == acting_user.as_str() {
+                request.address_2
+            } else {
+                request.address_1
+            }
+        })
+        .take(MAX_BULK_UPDATE_USERS)
+        .collect())
+}
+
+/// Parses the addresses of the users, dropping the repeated ones and keeping the order of their
+/// first appearance. The invalid ones are returned as failed items.
+fn parse_users(users: Vec<String>) -> Vec<Result<Address, BulkItemResult>> {
+    let mut seen = HashSet::new();
+    users
+        .into_iter()
+        .filter_map(|user| match Address::parse(&user) {
+            Ok(address) => seen.insert(address.clone()).then_some(Ok(address)),
+            Err(err) => Some(Err(BulkItemResult {
+                user,
+                result: Err(err.into()),
+            })),
+        })
+        .collect()
+}
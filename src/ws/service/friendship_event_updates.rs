@@ -1,23 +1,36 @@
 use std::sync::Arc;
 
 use crate::{
-    components::database::DatabaseComponentImplementation,
+    components::{
+        friends_cache::FriendsCacheComponent,
+        notifications::{ChannelPublisher, EventsChannelPublisher},
+    },
     db::{
-        friendships_handler::{get_friendship, get_last_history, update_friendship_status},
+        friendships_handler::{
+            check_friendship_limits, get_friendship, get_last_history, update_friendship_status,
+        },
         types::FriendshipDbRepositories,
     },
     domain::room::RoomInfo,
     domain::{
+        address::Address,
         error::CommonError,
         event::{EventPayload, EventResponse},
         friendship_event_validator::validate_new_event,
         friendship_status_calculator::get_new_friendship_status,
     },
+    friendships::FriendshipEventPayload,
     synapse::synapse_handler::{
         accept_room_invitation, get_or_create_synapse_room_id, set_account_data,
-        store_message_in_synapse_room, store_room_event_in_synapse_room,
+        spawn_room_cleanup, store_message_in_synapse_room, store_room_event_in_synapse_room,
+    },
+    ws::{
+        app::SocialContext,
+        metrics::Metrics,
+        service::mapper::event::{
+            parse_event_payload_to_friendship_event, update_friendship_payload_as_event,
+        },
     },
-    ws::app::SocialContext,
 };
 
 /// Processes a friendship event update by validating it and updating the Database and Synapse.
@@ -25,12 +38,12 @@ pub async fn handle_friendship_update(
     synapse_token: String,
     event_payload: EventPayload,
     context: Arc<SocialContext>,
-    acting_user: String,
+    acting_user: Address,
 ) -> Result<EventResponse, CommonError> {
     let new_event = event_payload.friendship_event;
-    let second_user = event_payload.second_user;
+    let second_user = Address::parse(&event_payload.second_user)?;
 
-    let db_repos = context.db.clone().db_repos.ok_or_else(|| {
+    let db_repos = context.db.get_repos().clone().ok_or_else(|| {
         log::error!("[RPC] Handle friendship update > Db repositories > `repos` is None.");
         CommonError::Unknown("".to_owned())
     })?;
@@ -41,8 +54,8 @@ pub async fn handle_friendship_update(
     let synapse_room_id = get_or_create_synapse_room_id(
         friendship.as_ref(),
         &new_event,
-        &acting_user,
-        &second_user,
+        acting_user.as_str(),
+        second_user.as_str(),
         &synapse_token,
         &context.synapse.clone(),
     )
@@ -55,8 +68,8 @@ pub async fn handle_friendship_update(
 
     set_account_data(
         &synapse_token,
-        &acting_user,
-        &second_user,
+        acting_user.as_str(),
+        second_user.as_str(),
         &synapse_room_id,
         &context.synapse,
     )
@@ -66,10 +79,10 @@ pub async fn handle_friendship_update(
     let last_recorded_history = get_last_history(&db_repos.friendship_history, &friendship).await?;
 
     // Validate the transition is valid and acting user has permission to perform it
-    validate_new_event(&acting_user, &last_recorded_history, new_event)?;
+    validate_new_event(acting_user.as_str(), &last_recorded_history, new_event)?;
 
     // Get new friendship status
-    let new_status = get_new_friendship_status(&acting_user, new_event);
+    let new_status = get_new_friendship_status(acting_user.as_str(), new_event);
 
     // Start a database transaction.
     let friendship_ports = FriendshipDbRepositories {
@@ -85,6 +98,18 @@ pub async fn handle_friendship_update(
         }
     };
 
+    // Check the limits within the transaction, so concurrent updates can't go over them
+    let transaction = check_friendship_limits(
+        new_event,
+        &acting_user,
+        &second_user,
+        context.config.friendship_limits,
+        db_repos.user_features.as_ref(),
+        &friendship_ports,
+        transaction,
+    )
+    .await?;
+
     // Update the friendship accordingly in the database. This means creating an entry in the friendships table or updating the is_active column.
     let room_info = RoomInfo {
         room_event: new_event,
@@ -128,10 +153,50 @@ pub async fn handle_friendship_update(
         log::error!(
             "[RPC] Handle friendship update > Couldn't end transaction to store friendship update {err}"
         );
-        Err(CommonError::Unknown("".to_owned()))
-    } else {
-        Ok(EventResponse {
-            user_id: second_user.to_string(),
-        })
+        return Err(CommonError::Unknown("".to_owned()));
+    }
+
+    spawn_room_cleanup(
+        context.config.room_cleanup,
+        &context.config.synapse_admin_access_token,
+        new_event,
+        friendship.as_ref(),
+        Some((&acting_user, &synapse_token)),
+        &context.synapse,
+        db_repos.friendships.clone(),
+    );
+
+    Ok(EventResponse {
+        user_id: second_user.into(),
+    })
+}
+
+/// Publishes a friendship update through the events channel, so the other user gets notified
+/// through their subscription.
+///
+/// The cached friends of both users are dropped first, so the notified user doesn't read them stale.
+pub async fn publish_friendship_update(
+    publisher: &EventsChannelPublisher,
+    friends_cache: Option<&FriendsCacheComponent>,
+    event: FriendshipEventPayload,
+    acting_user: &Address,
+    created_at: i64,
+    metrics: &Metrics,
+) {
+    let Ok(update) =
+        update_friendship_payload_as_event(event.clone(), acting_user.as_str(), created_at)
+    else {
+        log::error!("[RPC] There was an error parsing from friendship payload to event");
+        return;
+    };
+
+    if let Some(friends_cache) = friends_cache {
+        friends_cache.invalidate_event(&update).await;
+    }
+
+    publisher.publish(update).await;
+
+    if let Some(event) = parse_event_payload_to_friendship_event(event) {
+        metrics.record_friendship_event_updates_sent(event);
     }
 }
@@ -1,4 +1,5 @@
 use std::{
+    pin::Pin,
     sync::Arc,
     time::{Instant, SystemTime, UNIX_EPOCH},
 };
@@ -7,18 +8,13 @@ use dcl_rpc::{
     rpc_protocol::RemoteErrorResponse,
     {service_module_definition::ProcedureContext, stream_protocol::Generator},
 };
-use futures_util::StreamExt;
+use futures_util::{stream, Stream, StreamExt};
 use prost::Message;
-use tokio::sync::Mutex;
 
 use crate::{
-    components::{
-        notifications::ChannelPublisher,
-        synapse::SynapseComponent,
-        users_cache::{get_user_id_from_token, UserId, UsersCacheComponent},
-    },
+    components::{database::DBRepositories, profiles::ProfilesComponent},
     domain::{address::Address, error::CommonError},
-    entities::friendships::{Friendship, FriendshipRepositoryImplementation},
+    entities::friendships::Friendship,
     friendships::{
         request_events_response, update_friendship_response, users_response, BadRequestError,
         FriendshipsServiceServer, InternalServerError, MutualFriendsPayload, Payload,
@@ -27,17 +23,19 @@ use crate::{
         UsersResponse,
     },
     ws::{
-        app::{SocialContext, SocialTransportContext},
+        app::SocialContext,
+        auth::{
+            authenticate_token, bind_transport_address, get_transport_address, get_transport_token,
+        },
         metrics::Procedure,
     },
 };
 
 use super::{
-    friendship_event_updates::handle_friendship_update,
+    friendship_event_updates::{handle_friendship_update, publish_friendship_update},
     mapper::{
         event::{
             event_response_as_update_response, friendship_requests_as_request_events_response,
-            parse_event_payload_to_friendship_event, update_friendship_payload_as_event,
             update_request_as_event_payload,
         },
         payload::get_synapse_token,
@@ -76,16 +74,11 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
             .clone()
             .record_in_procedure_call_size(Procedure::GetFriends, &request);
 
-        let request_user_id = get_user_id_from_request(
-            &request,
-            context.server_context.synapse.clone(),
-            context.server_context.users_cache.clone(),
-        )
-        .await;
+        let request_address = get_address_from_request(Some(&request), &context).await;
 
         let (friendships_generator, friendships_yielder) = Generator::create();
 
-        let Some(repos) = context.server_context.db.db_repos.clone() else {
+        let Some(repos) = context.server_context.db.get_repos().clone() else {
             log::error!("[RPC] Get friends > Db repositories > `repos` is None.");
             let error = InternalServerError {
                 message: "An error occurred while getting the friendships".to_owned(),
@@ -108,7 +101,7 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
             return Ok(friendships_generator);
         };
 
-        match request_user_id {
+        match request_address {
             Err(err) => {
                 let error_response: UsersResponse = err.clone().into();
                 metrics.record_procedure_call_and_duration_and_out_size(
@@ -125,14 +118,11 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                     );
                 };
             }
-            Ok(user_id) => {
-                let social_id = user_id.social_id.clone();
-                log::info!("[RPC] Getting all friends for user: {}", social_id);
+            Ok(address) => {
+                log::info!("[RPC] Getting all friends for user: {}", address);
 
-                let Ok(mut friendship) = repos
-                    .friendships
-                    .get_user_friends_stream(&user_id.social_id, true)
-                    .await
+                let Ok(mut friends) =
+                    get_friends_stream(&context.server_context, &repos, &address).await
                 else {
                     log::error!(
                             "[RPC] Get friends > Get user friends stream > Error: There was an error accessing to the friendships repository."
@@ -165,9 +155,11 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                     let friends_stream_page_size =
                         context.server_context.friends_stream_page_size as usize;
 
-                    while let Some(friendship) = friendship.next().await {
-                        users.users.push(build_user(friendship, user_id.clone()));
+                    while let Some(user) = friends.next().await {
+                        users.users.push(user);
                         if users.users.len() == friends_stream_page_size {
+                            enrich_users_page(context.server_context.profiles.as_ref(), &mut users)
+                                .await;
                             let response = UsersResponse::from_response(
                                 users_response::Response::Users(users.clone()),
                             );
@@ -185,6 +177,8 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                         }
                     }
                     if !users.users.is_empty() {
+                        enrich_users_page(context.server_context.profiles.as_ref(), &mut users)
+                            .await;
                         let response =
                             UsersResponse::from_response(users_response::Response::Users(users));
                         metrics_clone.record_out_procedure_call_size(
@@ -203,7 +197,7 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
 
                 log::info!(
                     "[RPC] Returning generator for all friends for user {}",
-                    social_id
+                    address
                 );
             }
         }
@@ -249,36 +243,10 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
             return Ok(friendships_generator);
         };
 
-        let Some(auth_token) = request.clone().auth_token.take() else {
-            let error = UnauthorizedError {
-                message: "`auth_token` was not provided".to_owned(),
-            };
-            metrics.record_procedure_call_and_duration_and_out_size(
-                Some(error.clone().into()),
-                Procedure::GetMutualFriends,
-                start_time,
-                error.encoded_len(),
-            );
+        // The token is only needed when the transport is not authenticated yet
+        let request_address = get_address_from_request(request.auth_token.as_ref(), &context).await;
 
-            let result = friendships_yielder
-                .r#yield(UsersResponse::from_response(
-                    users_response::Response::UnauthorizedError(error),
-                ))
-                .await;
-            if let Err(err) = result {
-                log::error!("[RPC] There was an error yielding the error to the mutual friendships generator: {:?}", err);
-            };
-            return Ok(friendships_generator);
-        };
-
-        let request_user_id = get_user_id_from_request(
-            &auth_token,
-            context.server_context.synapse.clone(),
-            context.server_context.users_cache.clone(),
-        )
-        .await;
-
-        let Some(repos) = context.server_context.db.db_repos.clone() else {
+        let Some(repos) = context.server_context.db.get_repos().clone() else {
             log::error!("[RPC] Get mutual friends > Db repositories > `repos` is None.");
             let error = InternalServerError {
                 message: "An error occurred while getting the mutual friendships".to_owned(),
@@ -301,7 +269,7 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
             return Ok(friendships_generator);
         };
 
-        match request_user_id {
+        match request_address {
             Err(err) => {
                 let error_response: UsersResponse = err.clone().into();
                 metrics.record_procedure_call_and_duration_and_out_size(
@@ -318,23 +286,44 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                     );
                 };
             }
-            Ok(user_id) => {
-                let social_id = user_id.social_id.clone();
-                let other_id = other_user.address.clone();
+            Ok(address) => {
+                let other_address = match Address::parse(&other_user.address) {
+                    Ok(other_address) => other_address,
+                    Err(err) => {
+                        let error = BadRequestError {
+                            message: err.to_string(),
+                        };
+                        metrics.record_procedure_call_and_duration_and_out_size(
+                            Some(error.clone().into()),
+                            Procedure::GetMutualFriends,
+                            start_time,
+                            error.encoded_len(),
+                        );
+
+                        let result = friendships_yielder
+                            .r#yield(UsersResponse::from_response(
+                                users_response::Response::BadRequestError(error),
+                            ))
+                            .await;
+                        if let Err(err) = result {
+                            log::error!("[RPC] There was an error yielding the error to the mutual friendships generator: {:?}", err);
+                        };
+                        return Ok(friendships_generator);
+                    }
+                };
                 log::info!(
                     "[RPC] Getting all mutual friends for user: {} and {}",
-                    social_id,
-                    other_id
+                    address,
+                    other_address
                 );
 
-                let Ok(mut friendship) = repos
-                    .friendships
-                    .clone()
-                    .get_mutual_friends_stream(
-                        user_id.social_id.clone().to_string(),
-                        other_user.address.clone().to_string(),
-                    )
-                    .await
+                let Ok(mut friendship) = get_mutual_friends_stream(
+                    &context.server_context,
+                    &repos,
+                    &address,
+                    &other_address,
+                )
+                .await
                 else {
                     log::error!(
                             "[RPC] Get mutual friends > Get user friends stream > Error: There was an error accessing to the friendships repository."
@@ -367,11 +356,8 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                     let friends_stream_page_size =
                         context.server_context.friends_stream_page_size as usize;
 
-                    while let Some(user_id) = friendship.next().await {
-                        let current_user = User {
-                            address: user_id.address,
-                        };
-                        users.users.push(current_user);
+                    while let Some(user) = friendship.next().await {
+                        users.users.push(user);
                         if users.users.len() == friends_stream_page_size {
                             let response = UsersResponse::from_response(
                                 users_response::Response::Users(users.clone()),
@@ -413,8 +399,8 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
 
                 log::info!(
                     "[RPC] Returning generator for mutual friends for user {} and {}",
-                    social_id,
-                    other_id
+                    address,
+                    other_address
                 );
             }
         }
@@ -431,14 +417,9 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
         let metrics = context.server_context.metrics.clone();
         metrics.record_in_procedure_call_size(Procedure::GetRequestEvents, &request);
 
-        let request_user_id = get_user_id_from_request(
-            &request,
-            context.server_context.synapse.clone(),
-            context.server_context.users_cache.clone(),
-        )
-        .await;
+        let request_address = get_address_from_request(Some(&request), &context).await;
 
-        match request_user_id {
+        match request_address {
             Err(err) => {
                 let error_response: RequestEventsResponse = err.clone().into();
                 metrics.record_procedure_call_and_duration_and_out_size(
@@ -449,11 +430,10 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                 );
                 return Ok(error_response);
             }
-            Ok(user_id) => {
-                let social_id = user_id.social_id.clone();
-                log::info!("[RPC] Getting requests events for user: {}", social_id);
+            Ok(address) => {
+                log::info!("[RPC] Getting requests events for user: {}", address);
 
-                let Some(repos) = context.server_context.db.db_repos.clone() else {
+                let Some(repos) = context.server_context.db.get_repos().clone() else {
                     log::error!("[RPC] Get request events > Db repositories > `repos` is None.");
                     let error = InternalServerError {
                         message: "".to_owned(),
@@ -470,9 +450,9 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                     ));
                 };
 
-                let requests = repos
+                let (requests, _) = repos
                     .friendship_history
-                    .get_user_pending_request_events(&user_id.social_id)
+                    .get_user_pending_request_events(&address, None)
                     .await;
 
                 match requests {
@@ -494,11 +474,9 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                         ))
                     }
                     Ok(requests) => {
-                        log::info!("Returning requests events for user {}", social_id);
-                        let response = friendship_requests_as_request_events_response(
-                            requests,
-                            user_id.social_id,
-                        );
+                        log::info!("Returning requests events for user {}", address);
+                        let response =
+                            friendship_requests_as_request_events_response(requests, &address);
                         metrics.record_procedure_call_and_duration_and_out_size(
                             None,
                             Procedure::GetRequestEvents,
@@ -538,14 +516,9 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
             ));
         };
 
-        let request_user_id = get_user_id_from_request(
-            &auth_token,
-            context.server_context.synapse.clone(),
-            context.server_context.users_cache.clone(),
-        )
-        .await;
+        let request_address = get_address_from_request(Some(&auth_token), &context).await;
 
-        match request_user_id {
+        match request_address {
             Err(err) => {
                 let error_response: UpdateFriendshipResponse = err.clone().into();
                 metrics.record_procedure_call_and_duration_and_out_size(
@@ -556,7 +529,7 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                 );
                 return Ok(error_response);
             }
-            Ok(user_id) => {
+            Ok(address) => {
                 let event_payload = update_request_as_event_payload(request.clone());
 
                 match event_payload {
@@ -590,7 +563,7 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                                     token,
                                     event_payload,
                                     context.server_context.clone(),
-                                    user_id.clone().social_id,
+                                    address.clone(),
                                 )
                                 .await;
 
@@ -633,32 +606,20 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                                             }
                                             Ok(update_response) => {
                                                 let publisher =
-                                                    context.server_context.redis_publisher.clone();
+                                                    context.server_context.events_publisher.clone();
+                                                let friends_cache =
+                                                    context.server_context.friends_cache.clone();
                                                 if let Some(event) = request.clone().event {
                                                     tokio::spawn(async move {
-                                                        if let Ok(
-                                                            update_friendship_payload_as_event,
-                                                        ) = update_friendship_payload_as_event(
-                                                            event.clone(),
-                                                            user_id.social_id.as_str(),
+                                                        publish_friendship_update(
+                                                            &publisher,
+                                                            friends_cache.as_ref(),
+                                                            event,
+                                                            &address,
                                                             created_at,
-                                                        ) {
-                                                            publisher
-                                                                .publish(update_friendship_payload_as_event)
-                                                                .await;
-
-                                                            if let Some(event) =
-                                                                parse_event_payload_to_friendship_event(
-                                                                    event,
-                                                                )
-                                                            {
-                                                              metrics_clone.record_friendship_event_updates_sent(
-                                                                    event,
-                                                                );
-                                                            }
-                                                        } else {
-                                                            log::error!("[RPC] There was an error parsing from friendship payload to event")
-                                                        }
+                                                            &metrics_clone,
+                                                        )
+                                                        .await;
                                                     });
                                                 };
                                                 metrics.record_procedure_call_and_duration_and_out_size(
@@ -698,16 +659,11 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
         metrics
             .record_in_procedure_call_size(Procedure::SubscribeFriendshipEventsUpdates, &request);
 
-        let request_user_id = get_user_id_from_request(
-            &request,
-            context.server_context.synapse.clone(),
-            context.server_context.users_cache.clone(),
-        )
-        .await;
+        let request_address = get_address_from_request(Some(&request), &context).await;
 
         let (friendships_generator, friendships_yielder) = Generator::create();
 
-        match request_user_id {
+        match request_address {
             Err(err) => {
                 let error_response: SubscribeFriendshipEventsUpdatesResponse = err.clone().into();
                 metrics.record_procedure_call_and_duration_and_out_size(
@@ -721,34 +677,21 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
                     log::error!("[RPC] There was an error yielding the error to the subscribe friendships generator: {:?}", err);
                 };
             }
-            Ok(user_id) => {
+            Ok(address) => {
                 metrics.record_procedure_call_and_duration(
                     None,
                     Procedure::SubscribeFriendshipEventsUpdates,
                     start_time,
                 );
 
-                // Attach social_id to the context by transport_id
-                let mut transport_context = context.server_context.transport_context.write().await;
-                transport_context
-                    .entry(context.transport_id)
-                    .and_modify(|e| e.address = Address(user_id.social_id.to_string()))
-                    .or_insert_with(|| {
-                        log::warn!("This code should be unreachable");
-                        // This should never happen
-                        SocialTransportContext {
-                            address: Address(user_id.social_id.to_string()),
-                            connection_ts: Instant::now(),
-                        }
-                    });
-
-                // Attach generator to the context by user_id
+                // Attach generator to the context by address, along with the transport the address is
+                // bound to, so the generator is removed when that transport closes
                 context
                     .server_context
                     .friendships_events_generators
                     .write()
                     .await
-                    .insert(Address(user_id.social_id), friendships_yielder.clone());
+                    .insert(address, (context.transport_id, friendships_yielder.clone()));
             }
         }
 
@@ -756,44 +699,150 @@ impl FriendshipsServiceServer<SocialContext, RPCFriendshipsServiceError> for MyF
     }
 }
 
-/// Retrieves the User Id associated with the given Authentication Token.
+/// Retrieves the address of the user of the transport the request came through.
 ///
-/// If an authentication token was provided in the request, gets the
-/// user id from the token and returns it as a `Result<UserId>`. If no
-/// authentication token was provided, returns a `Err(CommonError::Unauthorized)`
-/// error.
-async fn get_user_id_from_request(
-    request: &Payload,
-    synapse: SynapseComponent,
-    users_cache: Arc<Mutex<UsersCacheComponent>>,
-) -> Result<UserId, CommonError> {
-    match request.synapse_token.clone() {
-        // If an authentication token was provided, get the user id from the token
-        Some(token) => get_user_id_from_token(synapse.clone(), users_cache.clone(), &token)
-            .await
-            .map_err(|err| {
-                log::error!("[RPC] Get user id from request > Error {err}");
-                err
-            }),
-        // If no authentication token was provided, return an Unauthorized error.
-        None => {
-            log::error!("[RPC] Get user id from request > `synapse_token` is None.");
-            Err(CommonError::Unauthorized(
-                "`synapse_token` was not provided".to_owned(),
-            ))
+/// The user is bound to the transport on the WebSocket handshake or by the first call that
+/// provides an authentication token, later calls are resolved from that binding. A token is only
+/// authenticated when it isn't the bound one, e.g. a refreshed session token, and a token of
+/// another user than the bound one is rejected. The bound token being revoked, logged out or
+/// expired is left to the periodic recheck of the transport. If there is no token at all, returns
+/// a `Err(CommonError::Unauthorized)` error.
+async fn get_address_from_request(
+    request: Option<&Payload>,
+    context: &ProcedureContext<SocialContext>,
+) -> Result<Address, CommonError> {
+    let server_context = &context.server_context;
+    let transport_contexts = &server_context.transport_context;
+    let request_token = request.and_then(|request| request.synapse_token.clone());
+
+    if let Some(address) = get_transport_address(transport_contexts, context.transport_id).await {
+        let bound_token = get_transport_token(transport_contexts, context.transport_id).await;
+        if request_token.is_none() || request_token == bound_token {
+            return Ok(address);
         }
     }
+
+    // If no authentication token was provided, return an Unauthorized error.
+    let Some(token) = request_token else {
+        log::error!("[RPC] Get address from request > `synapse_token` is None.");
+        return Err(CommonError::Unauthorized(
+            "`synapse_token` was not provided".to_owned(),
+        ));
+    };
+
+    let address = authenticate_token(
+        &server_context.synapse,
+        server_context.users_cache.clone(),
+        server_context.session_tokens.as_ref(),
+        &token,
+    )
+    .await
+    .map_err(|err| {
+        log::error!("[RPC] Get address from request > Error {err}");
+        err
+    })?;
+    bind_transport_address(transport_contexts, context.transport_id, &address, &token).await?;
+    Ok(address)
+}
+
+/// Friends of the user, read from the friends cache when it's enabled.
+async fn get_friends_stream(
+    context: &SocialContext,
+    repos: &DBRepositories,
+    address: &Address,
+) -> Result<Pin<Box<dyn Stream<Item = User> + Send>>, CommonError> {
+    if let Some(friends_cache) = &context.friends_cache {
+        let friends = friends_cache.get_friends(address, repos).await?;
+        return Ok(Box::pin(stream::iter(friends.into_iter().map(|address| {
+            User {
+                address,
+                ..Default::default()
+            }
+        }))));
+    }
+
+    let friendships = repos
+        .friendships
+        .get_user_friends_stream(address, true, None)
+        .await
+        .map_err(|_| CommonError::Unknown("".to_owned()))?;
+    let address = address.clone();
+    Ok(Box::pin(
+        friendships.map(move |friendship| build_user(friendship, &address)),
+    ))
+}
+
+/// Fills the name and face image of the users of a page when the profiles component is enabled,
+/// fetching the profiles of the whole page in a single batch.
+async fn enrich_users_page(profiles_component: Option<&ProfilesComponent>, users: &mut Users) {
+    let Some(profiles_component) = profiles_component else {
+        return;
+    };
+
+    let addresses: Vec<Address> = users
+        .users
+        .iter()
+        .filter_map(|user| Address::parse(&user.address).ok())
+        .collect();
+    let profiles = profiles_component.get_profiles(&addresses).await;
+
+    for user in users.users.iter_mut() {
+        let Some(profile) = Address::parse(&user.address)
+            .ok()
+            .and_then(|address| profiles.get(&address))
+        else {
+            continue;
+        };
+        user.name = profile.name.clone();
+        user.face_image = profile.face_image.clone();
+    }
 }
 
-/// Filters out the friend of the authenticated user based on the provided `user_id`.
+/// Mutual friends of the users, intersected in the friends cache when it's enabled.
+async fn get_mutual_friends_stream(
+    context: &SocialContext,
+    repos: &DBRepositories,
+    address: &Address,
+    other_address: &Address,
+) -> Result<Pin<Box<dyn Stream<Item = User> + Send>>, CommonError> {
+    if let Some(friends_cache) = &context.friends_cache {
+        let mutuals = friends_cache
+            .get_mutual_friends(address, other_address, repos)
+            .await?;
+        return Ok(Box::pin(stream::iter(mutuals.into_iter().map(|address| {
+            User {
+                address,
+                ..Default::default()
+            }
+        }))));
+    }
+
+    let mutuals = repos
+        .friendships
+        .get_mutual_friends_stream(address.clone(), other_address.clone())
+        .await
+        .map_err(|_| CommonError::Unknown("".to_owned()))?;
+    Ok(Box::pin(mutuals.map(|user| User {
+        address: user.address,
+        ..Default::default()
+    })))
+}
+
+/// Filters out the friend of the authenticated user based on the provided `address`.
 ///
 /// * `friendship` - A `Friendship` struct representing the friendship between the two users.
-/// * `user_id` - The id of the authenticated user.
-fn build_user(friendship: Friendship, user_id: UserId) -> User {
+/// * `address` - The address of the authenticated user.
+fn build_user(friendship: Friendship, address: &Address) -> User {
     let address1: String = friendship.address_1;
     let address2: String = friendship.address_2;
-    match address1.eq_ignore_ascii_case(&user_id.social_id) {
-        true => User { address: address2 },
-        false => User { address: address1 },
+    match address1 == address.as_str() {
+        true => User {
+            address: address2,
+            ..Default::default()
+        },
+        false => User {
+            address: address1,
+            ..Default::default()
+        },
     }
 }
@@ -58,7 +58,8 @@ impl From<CommonError> for WsServiceError {
             CommonError::Forbidden(message) => {
                 WsServiceError::Forbidden(ForbiddenError { message })
             }
-            CommonError::Unknown(message) => {
+            // The protocol has no dedicated error for an unavailable dependency
+            CommonError::Unknown(message) | CommonError::ServiceUnavailable(message) => {
                 WsServiceError::InternalServer(InternalServerError { message })
             }
             CommonError::Unauthorized(message) => {
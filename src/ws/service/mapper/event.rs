@@ -1,5 +1,6 @@
 use crate::{
     domain::{
+        address::Address,
         error::CommonError,
         event::{EventPayload, EventResponse},
         friendship_event::FriendshipEvent,
@@ -7,11 +8,12 @@ use crate::{
     entities::friendship_history::FriendshipRequestEvent,
     friendships::{
         friendship_event_payload, friendship_event_response, request_events_response,
-        update_friendship_response, AcceptResponse, CancelResponse, DeleteResponse,
-        FriendshipEventPayload, FriendshipEventResponse, RejectResponse, RequestEvents,
-        RequestEventsResponse, RequestResponse, Requests, UpdateFriendshipPayload,
-        UpdateFriendshipResponse, User,
+        update_friendship_response, AcceptPayload, AcceptResponse, CancelPayload, CancelResponse,
+        DeletePayload, DeleteResponse, FriendshipEventPayload, FriendshipEventResponse,
+        RejectPayload, RejectResponse, RequestEvents, RequestEventsResponse, RequestPayload,
+        RequestResponse, Requests, UpdateFriendshipPayload, UpdateFriendshipResponse, User,
     },
+    generate_uuid_v4, instance_id,
     notifications::Event,
 };
 
@@ -20,10 +22,10 @@ use super::response::payload_event_as_response;
 /// Maps a list of `FriendshipRequestEvents` to a `RequestEvents` struct.
 ///
 /// * `requests` - A vector of `FriendshipRequestEvents` to map to `RequestResponse` struct.
-/// * `user_id` - The id of the auth user.
+/// * `address` - The address of the auth user.
 pub fn friendship_requests_as_request_events_response(
     requests: Vec<FriendshipRequestEvent>,
-    user_id: String,
+    address: &Address,
 ) -> RequestEventsResponse {
     let mut outgoing_requests: Vec<RequestResponse> = Vec::new();
     let mut incoming_requests: Vec<RequestResponse> = Vec::new();
@@ -34,7 +36,7 @@ pub fn friendship_requests_as_request_events_response(
         let acting_user_id = request.acting_user.clone();
 
         // Determine the address of the other user involved in the request event
-        let address = if request.address_1.eq_ignore_ascii_case(&user_id) {
+        let other_address = if request.address_1 == address.as_str() {
             request.address_2.clone()
         } else {
             request.address_1.clone()
@@ -47,12 +49,15 @@ pub fn friendship_requests_as_request_events_response(
             .and_then(|metadata| metadata.message.clone());
 
         let request_response = RequestResponse {
-            user: Some(User { address }),
+            user: Some(User {
+                address: other_address,
+                ..Default::default()
+            }),
             created_at: request.timestamp.timestamp(),
             message,
         };
 
-        if acting_user_id.eq_ignore_ascii_case(&user_id) {
+        if acting_user_id == address.as_str() {
             // If the acting user is the same as the user id, then the request is outgoing
             outgoing_requests.push(request_response);
         } else {
@@ -156,6 +161,7 @@ pub fn event_response_as_update_response(
                 let request_response = RequestResponse {
                     user: Some(User {
                         address: result.user_id,
+                        ..Default::default()
                     }),
                     created_at,
                     message: payload.message,
@@ -172,6 +178,7 @@ pub fn event_response_as_update_response(
                 let accept_response = AcceptResponse {
                     user: Some(User {
                         address: result.user_id,
+                        ..Default::default()
                     }),
                 };
 
@@ -186,6 +193,7 @@ pub fn event_response_as_update_response(
                 let reject_response = RejectResponse {
                     user: Some(User {
                         address: result.user_id,
+                        ..Default::default()
                     }),
                 };
 
@@ -200,6 +208,7 @@ pub fn event_response_as_update_response(
                 let cancel_response = CancelResponse {
                     user: Some(User {
                         address: result.user_id,
+                        ..Default::default()
                     }),
                 };
 
@@ -214,6 +223,7 @@ pub fn event_response_as_update_response(
                 let delete_response = DeleteResponse {
                     user: Some(User {
                         address: result.user_id,
+                        ..Default::default()
                     }),
                 };
 
@@ -244,12 +254,36 @@ pub fn update_friendship_payload_as_event(
             friendship_event: Some(friendship_event),
             from: from.to_string(),
             to,
+            id: generate_uuid_v4(),
+            origin: instance_id().to_string(),
+            notify_from: false,
         })
     } else {
         Err(CommonError::Unknown("".to_owned()))
     }
 }
 
+/// Maps an `EventPayload` back to the `FriendshipEventPayload` of the update,
+/// e.g. for the updates that didn't come through the RPC server.
+pub fn event_payload_as_friendship_payload(payload: EventPayload) -> FriendshipEventPayload {
+    let user = Some(User {
+        address: payload.second_user,
+        ..Default::default()
+    });
+    let body = match payload.friendship_event {
+        FriendshipEvent::REQUEST => friendship_event_payload::Body::Request(RequestPayload {
+            user,
+            message: payload.request_event_message_body,
+        }),
+        FriendshipEvent::ACCEPT => friendship_event_payload::Body::Accept(AcceptPayload { user }),
+        FriendshipEvent::REJECT => friendship_event_payload::Body::Reject(RejectPayload { user }),
+        FriendshipEvent::CANCEL => friendship_event_payload::Body::Cancel(CancelPayload { user }),
+        FriendshipEvent::DELETE => friendship_event_payload::Body::Delete(DeletePayload { user }),
+    };
+
+    FriendshipEventPayload { body: Some(body) }
+}
+
 /// Maps a `FriendshipEventPayload` to an `FriendshipEvent` struct.
 pub fn parse_event_payload_to_friendship_event(
     payload: FriendshipEventPayload,
@@ -1,5 +1,10 @@
-use crate::{domain::error::CommonError, friendships::UpdateFriendshipPayload};
+use crate::{
+    components::session_tokens::is_session_token, domain::error::CommonError,
+    friendships::UpdateFriendshipPayload,
+};
 
+/// Synapse token of the request, the update is done in Synapse on behalf of the user so a session
+/// token issued by the service is rejected.
 pub fn get_synapse_token(request: UpdateFriendshipPayload) -> Result<String, CommonError> {
     let Some(auth_token) = request.auth_token.as_ref() else {
         log::error!("[RPC] Handle friendship update > `auth_token` is missing.");
@@ -14,5 +19,14 @@ pub fn get_synapse_token(request: UpdateFriendshipPayload) -> Result<String, Com
             "`synapse_token` is missing".to_owned(),
         ));
     };
+
+    if is_session_token(synapse_token) {
+        log::error!("[RPC] Handle friendship update > `synapse_token` is a session token.");
+        return Err(CommonError::BadRequest(
+            "`synapse_token` must be a Synapse token, session tokens can't update Synapse"
+                .to_owned(),
+        ));
+    }
+
     Ok(synapse_token.to_string())
 }
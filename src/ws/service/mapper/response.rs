@@ -66,6 +66,7 @@ pub fn payload_event_as_response(
 fn user(from: &str) -> Option<User> {
     Some(User {
         address: from.to_string(),
+        ..Default::default()
     })
 }
 
@@ -149,6 +150,7 @@ fn test_request_as_response() {
             crate::friendships::RequestPayload {
                 user: Some(User {
                     address: "0xBob".to_owned(),
+                    ..Default::default()
                 }),
                 message: Some("Hi Bob, let's be friends!".to_owned()),
             },
@@ -163,6 +165,7 @@ fn test_request_as_response() {
                     body: Some(friendship_event_response::Body::Request(RequestResponse {
                         user: Some(User {
                             address: "0xAlice".to_owned(),
+                            ..Default::default()
                         }),
                         created_at: 1234567890,
                         message: Some("Hi Bob, let's be friends!".to_owned()),
@@ -184,6 +187,7 @@ fn test_accept_as_response() {
             crate::friendships::AcceptPayload {
                 user: Some(User {
                     address: "0xBob".to_owned(),
+                    ..Default::default()
                 }),
             },
         )),
@@ -197,6 +201,7 @@ fn test_accept_as_response() {
                     body: Some(friendship_event_response::Body::Accept(AcceptResponse {
                         user: Some(User {
                             address: "0xAlice".to_owned(),
+                            ..Default::default()
                         })
                     })),
                 }
@@ -216,6 +221,7 @@ fn test_reject_as_response() {
             crate::friendships::RejectPayload {
                 user: Some(User {
                     address: "0xBob".to_owned(),
+                    ..Default::default()
                 }),
             },
         )),
@@ -229,6 +235,7 @@ fn test_reject_as_response() {
                     body: Some(friendship_event_response::Body::Reject(RejectResponse {
                         user: Some(User {
                             address: "0xAlice".to_owned(),
+                            ..Default::default()
                         })
                     })),
                 }
@@ -248,6 +255,7 @@ fn test_cancel_as_response() {
             crate::friendships::CancelPayload {
                 user: Some(User {
                     address: "0xBob".to_owned(),
+                    ..Default::default()
                 }),
             },
         )),
@@ -261,6 +269,7 @@ fn test_cancel_as_response() {
                     body: Some(friendship_event_response::Body::Cancel(CancelResponse {
                         user: Some(User {
                             address: "0xAlice".to_owned(),
+                            ..Default::default()
                         })
                     })),
                 }
@@ -280,6 +289,7 @@ fn test_delete_as_response() {
             crate::friendships::DeletePayload {
                 user: Some(User {
                     address: "0xBob".to_owned(),
+                    ..Default::default()
                 }),
             },
         )),
@@ -293,6 +303,7 @@ fn test_delete_as_response() {
                     body: Some(friendship_event_response::Body::Delete(DeleteResponse {
                         user: Some(User {
                             address: "0xAlice".to_owned(),
+                            ..Default::default()
                         })
                     })),
                 }
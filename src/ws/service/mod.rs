@@ -1,3 +1,4 @@
+pub mod bulk_friendship_updates;
 pub mod friendship_event_updates;
 pub mod friendships_service;
 pub mod mapper;
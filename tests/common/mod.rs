@@ -8,8 +8,10 @@ use social_service::{
         app::AppComponents,
         configuration::{Config, Database},
         database::{DatabaseComponent, DatabaseComponentImplementation},
+        profiles::PROFILES_URI,
         synapse::{WhoAmIResponse, WHO_AM_I_URI},
     },
+    domain::address::Address,
 };
 use sqlx::{Connection, Executor, PgConnection, PgPool};
 use wiremock::{
@@ -101,6 +103,7 @@ pub async fn who_am_i_synapse_mock_server(token_to_user_id: HashMap<String, Stri
         let response = WhoAmIResponse {
             user_id: user_id.to_string(),
             social_user_id: None,
+            device_id: None,
         };
 
         Mock::given(method("GET"))
@@ -130,3 +133,34 @@ pub async fn create_db_component(config: Option<&Config>) -> DatabaseComponent {
     assert!(db.is_connected());
     db
 }
+
+/// Builds a valid address repeating the given hex character, e.g. `address('a')` is `0xaaaa...aaaa`.
+pub fn address(hex_char: char) -> Address {
+    Address::parse(&format!("0x{}", hex_char.to_string().repeat(40))).expect("a valid address")
+}
+
+/// Creates a lambdas mocked server which responds the given `(address, name, face image)` profiles.
+pub async fn profiles_mock_server(profiles: Vec<(&str, &str, &str)>) -> MockServer {
+    let server = MockServer::start().await;
+
+    let response: Vec<serde_json::Value> = profiles
+        .iter()
+        .map(|(address, name, face_image)| {
+            serde_json::json!({
+                "avatars": [{
+                    "ethAddress": address,
+                    "name": name,
+                    "avatar": { "snapshots": { "face256": face_image } }
+                }]
+            })
+        })
+        .collect();
+
+    Mock::given(method("POST"))
+        .and(path(PROFILES_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(response))
+        .mount(&server)
+        .await;
+
+    server
+}
@@ -4,11 +4,10 @@ pub use common::*;
 
 use social_service::{
     components::database::{DBRepositories, DatabaseComponentImplementation},
-    domain::friendship_event::FriendshipEvent,
-    entities::{
-        friendship_history::FriendshipMetadata, friendships::FriendshipRepositoryImplementation,
-    },
+    domain::{address::Address, friendship_event::FriendshipEvent},
+    entities::{friendship_history::FriendshipMetadata, webhook_deliveries::NewWebhookDelivery},
 };
+use std::time::Duration;
 use uuid::Uuid;
 
 #[actix_web::test]
@@ -17,20 +16,159 @@ async fn should_create_and_get_a_friendship() {
     let db = create_db_component(None).await;
     let dbrepos = db.db_repos.as_ref().unwrap();
 
-    create_friendship(dbrepos, "B", "A", false).await;
+    let (address_a, address_b) = (address('a'), address('b'));
+    create_friendship(dbrepos, &address_b, &address_a, false).await;
 
     let friendship = dbrepos
         .friendships
-        .get_friendship(("a", "b"), None)
+        .get_friendship((&address_a, &address_b), None)
         .await
         .0
         .unwrap();
 
     assert!(friendship.is_some());
 
-    assert_eq!(friendship.as_ref().unwrap().address_1, "A");
-    assert_eq!(friendship.as_ref().unwrap().address_2, "B");
-    assert_eq!(friendship.as_ref().unwrap().synapse_room_id, "room_id_B_A");
+    assert_eq!(friendship.as_ref().unwrap().address_1, address_a.as_str());
+    assert_eq!(friendship.as_ref().unwrap().address_2, address_b.as_str());
+    assert_eq!(
+        friendship.as_ref().unwrap().synapse_room_id,
+        Some(format!("room_id_{address_b}_{address_a}"))
+    );
+}
+
+#[actix_web::test]
+#[serial_test::serial]
+async fn should_get_a_friendship_regardless_of_the_address_casing() {
+    let db = create_db_component(None).await;
+    let dbrepos = db.db_repos.as_ref().unwrap();
+
+    let upper_case_address = Address::parse(&format!("0x{}", "A".repeat(40))).unwrap();
+    create_friendship(dbrepos, &upper_case_address, &address('b'), false).await;
+
+    let friendship = dbrepos
+        .friendships
+        .get_friendship((&address('b'), &address('a')), None)
+        .await
+        .0
+        .unwrap();
+
+    assert!(friendship.is_some());
+    assert_eq!(friendship.unwrap().address_1, address('a').as_str());
+}
+
+#[actix_web::test]
+#[serial_test::serial]
+async fn should_maintain_friendship_timestamps() {
+    let db = create_db_component(None).await;
+    let dbrepos = db.db_repos.as_ref().unwrap();
+
+    let (address_a, address_b) = (address('a'), address('b'));
+    let friendship_id = create_friendship(dbrepos, &address_a, &address_b, false).await;
+
+    let friendship = dbrepos
+        .friendships
+        .get_friendship((&address_a, &address_b), None)
+        .await
+        .0
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(friendship.created_at, friendship.updated_at);
+    assert!(friendship.friends_since.is_none());
+
+    dbrepos
+        .friendships
+        .update_friendship_status(&friendship_id, true, None)
+        .await
+        .0
+        .unwrap();
+
+    let updated_friendship = dbrepos
+        .friendships
+        .get_friendship((&address_a, &address_b), None)
+        .await
+        .0
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(updated_friendship.created_at, friendship.created_at);
+    assert!(updated_friendship.updated_at >= friendship.updated_at);
+    assert_eq!(
+        updated_friendship.friends_since,
+        Some(updated_friendship.updated_at)
+    );
+}
+
+#[actix_web::test]
+#[serial_test::serial]
+async fn should_clear_the_synapse_room_id_without_changing_the_status_timestamp() {
+    let db = create_db_component(None).await;
+    let dbrepos = db.db_repos.as_ref().unwrap();
+
+    let (address_a, address_b) = (address('a'), address('b'));
+    let friendship_id = create_friendship(dbrepos, &address_a, &address_b, false).await;
+    let get_friendship = || async {
+        dbrepos
+            .friendships
+            .get_friendship((&address_a, &address_b), None)
+            .await
+            .0
+            .unwrap()
+            .unwrap()
+    };
+    let friendship = get_friendship().await;
+
+    dbrepos
+        .friendships
+        .update_synapse_room_id(&friendship_id, None, None)
+        .await
+        .0
+        .unwrap();
+
+    let cleared_friendship = get_friendship().await;
+    assert_eq!(cleared_friendship.synapse_room_id, None);
+    assert_eq!(cleared_friendship.updated_at, friendship.updated_at);
+}
+
+#[actix_web::test]
+#[serial_test::serial]
+async fn should_get_user_friends_by_address_prefix() {
+    let db = create_db_component(None).await;
+    let dbrepos = db.db_repos.as_ref().unwrap();
+
+    let address_a = address('a');
+    create_friendship(dbrepos, &address_a, &address('b'), true).await;
+    create_friendship(dbrepos, &address_a, &address('c'), true).await;
+    create_friendship(dbrepos, &address_a, &address('d'), false).await;
+
+    let friendships = dbrepos
+        .friendships
+        .get_user_friends(&address_a, true, Some("0xbb"), None)
+        .await
+        .0
+        .unwrap();
+
+    assert_eq!(friendships.len(), 1);
+    assert_eq!(friendships[0].address_2, address('b').as_str());
+
+    // The user's own address never matches
+    let friendships = dbrepos
+        .friendships
+        .get_user_friends(&address_a, true, Some("0xaa"), None)
+        .await
+        .0
+        .unwrap();
+
+    assert!(friendships.is_empty());
+
+    let friendships = dbrepos
+        .friendships
+        .get_user_friends(&address_a, false, Some("0xdd"), None)
+        .await
+        .0
+        .unwrap();
+
+    assert_eq!(friendships.len(), 1);
 }
 
 #[actix_web::test]
@@ -39,24 +177,26 @@ async fn should_create_a_friendship_request_event() {
     let db = create_db_component(None).await;
     let dbrepos = db.db_repos.as_ref().unwrap();
 
-    create_friendship(dbrepos, "C", "D", false).await;
+    let (address_c, address_d) = (address('c'), address('d'));
+    create_friendship(dbrepos, &address_c, &address_d, false).await;
 
     let friendship = dbrepos
         .friendships
-        .get_friendship(("C", "D"), None)
+        .get_friendship((&address_c, &address_d), None)
         .await
         .0
         .unwrap()
         .unwrap();
 
-    let synapse_room_id = "room_id_C_D".to_string();
+    let synapse_room_id = format!("room_id_{address_c}_{address_d}");
     let metadata = Some(sqlx::types::Json(FriendshipMetadata {
         message: None,
         synapse_room_id: Some(synapse_room_id),
         migrated_from_synapse: None,
+        forced_by_admin: None,
     }));
 
-    create_friendship_event(dbrepos, friendship.id, "\"request\"", "C", metadata).await;
+    create_friendship_event(dbrepos, friendship.id, "\"request\"", &address_c, metadata).await;
 
     let friendship_history = dbrepos
         .friendship_history
@@ -70,7 +210,7 @@ async fn should_create_a_friendship_request_event() {
 
     assert_eq!(friendship_history.friendship_id, friendship.id);
     assert_eq!(friendship_history.event, FriendshipEvent::REQUEST);
-    assert_eq!(friendship_history.acting_user, "C");
+    assert_eq!(friendship_history.acting_user, address_c.as_str());
     assert!(friendship_history.metadata.is_some());
     assert!(friendship_history
         .metadata
@@ -92,12 +232,12 @@ async fn should_create_a_user_feature() {
     let dbrepos = db.db_repos.as_ref().unwrap();
     dbrepos
         .user_features
-        .create("A", "exposure_level", "anyone")
+        .create(&address('a'), "exposure_level", "anyone")
         .await
         .unwrap();
     let user_features = dbrepos
         .user_features
-        .get_all_user_features("A")
+        .get_all_user_features(&address('a'))
         .await
         .unwrap();
 
@@ -122,28 +262,30 @@ async fn should_get_pending_request_events() {
     let db = create_db_component(None).await;
     let dbrepos = db.db_repos.as_ref().unwrap();
 
+    let (address_a, address_b, address_c) = (address('a'), address('b'), address('c'));
+
     // create friendships between two users
-    let friendship_id_1 = create_friendship(dbrepos, "A", "B", false).await;
-    let friendship_id_2 = create_friendship(dbrepos, "A", "C", false).await;
+    let friendship_id_1 = create_friendship(dbrepos, &address_a, &address_b, false).await;
+    let friendship_id_2 = create_friendship(dbrepos, &address_a, &address_c, false).await;
 
     // create friendship history entries to represent friendship events
-    create_friendship_event(dbrepos, friendship_id_1, "\"request\"", "A", None).await;
-    create_friendship_event(dbrepos, friendship_id_2, "\"request\"", "A", None).await;
-    create_friendship_event(dbrepos, friendship_id_2, "\"accept\"", "C", None).await;
+    create_friendship_event(dbrepos, friendship_id_1, "\"request\"", &address_a, None).await;
+    create_friendship_event(dbrepos, friendship_id_2, "\"request\"", &address_a, None).await;
+    create_friendship_event(dbrepos, friendship_id_2, "\"accept\"", &address_c, None).await;
 
     // retrieve the pending request events for the auth user
-    let requests = dbrepos
+    let (requests, _) = dbrepos
         .friendship_history
-        .get_user_pending_request_events("A")
-        .await
-        .unwrap();
+        .get_user_pending_request_events(&address_a, None)
+        .await;
+    let requests = requests.unwrap();
 
     // check that the retrieved events have the expected properties
     assert!(requests.len() == 1);
     let first_request = &requests[0];
-    assert_eq!(first_request.address_1, "A");
-    assert_eq!(first_request.address_2, "B");
-    assert_eq!(first_request.acting_user, "A");
+    assert_eq!(first_request.address_1, address_a.as_str());
+    assert_eq!(first_request.address_2, address_b.as_str());
+    assert_eq!(first_request.acting_user, address_a.as_str());
     assert!(first_request.metadata.is_none());
 }
 
@@ -154,28 +296,31 @@ async fn should_get_pending_request_events_other_acting_user() {
     let db = create_db_component(None).await;
     let dbrepos = db.db_repos.as_ref().unwrap();
 
+    let (address_a, address_b, address_c) = (address('a'), address('b'), address('c'));
+
     // create friendships between two users
-    let friendship_id_1 = create_friendship(dbrepos, "A", "B", false).await;
-    let friendship_id_2 = create_friendship(dbrepos, "A", "C", false).await;
+    let friendship_id_1 = create_friendship(dbrepos, &address_a, &address_b, false).await;
+    let friendship_id_2 = create_friendship(dbrepos, &address_a, &address_c, false).await;
 
     // create friendship history entries to represent friendship events
-    create_friendship_event(dbrepos, friendship_id_1, "\"request\"", "B", None).await;
-    create_friendship_event(dbrepos, friendship_id_2, "\"request\"", "C", None).await;
-    create_friendship_event(dbrepos, friendship_id_2, "\"accept\"", "A", None).await;
+    create_friendship_event(dbrepos, friendship_id_1, "\"request\"", &address_b, None).await;
+    create_friendship_event(dbrepos, friendship_id_2, "\"request\"", &address_c, None).await;
+    create_friendship_event(dbrepos, friendship_id_2, "\"accept\"", &address_a, None).await;
 
     // retrieve the pending request events for the auth user
     let requests = dbrepos
         .friendship_history
-        .get_user_pending_request_events("A")
+        .get_user_pending_request_events(&address_a, None)
         .await
+        .0
         .unwrap();
 
     // check that the retrieved events have the expected properties
     assert!(requests.len() == 1);
     let first_request = &requests[0];
-    assert_eq!(first_request.address_1, "A");
-    assert_eq!(first_request.address_2, "B");
-    assert_eq!(first_request.acting_user, "B");
+    assert_eq!(first_request.address_1, address_a.as_str());
+    assert_eq!(first_request.address_2, address_b.as_str());
+    assert_eq!(first_request.acting_user, address_b.as_str());
     assert!(first_request.metadata.is_none());
 }
 
@@ -185,8 +330,9 @@ async fn should_run_transaction_succesfully() {
     // create the database component
     let db = create_db_component(None).await;
     let dbrepos = db.db_repos.as_ref().unwrap();
-    let addresses = ("1", "2");
-    let addresses_2 = ("2", "3");
+    let (address_1, address_2, address_3) = (address('1'), address('2'), address('3'));
+    let addresses = (&address_1, &address_2);
+    let addresses_2 = (&address_2, &address_3);
 
     let trans = db.start_transaction().await.unwrap();
 
@@ -231,11 +377,94 @@ async fn should_run_transaction_succesfully() {
     }
 }
 
+#[actix_web::test]
+#[serial_test::serial]
+async fn should_save_a_webhook_delivery_once_per_event_and_url() {
+    let db = create_db_component(None).await;
+    let dbrepos = db.db_repos.as_ref().unwrap();
+    let event_id = Uuid::new_v4().to_string();
+
+    let deliveries = &dbrepos.webhook_deliveries;
+    let first = deliveries
+        .create(new_webhook_delivery(&event_id, "http://a.url"))
+        .await
+        .unwrap();
+    let duplicated = deliveries
+        .create(new_webhook_delivery(&event_id, "http://a.url"))
+        .await
+        .unwrap();
+    let other_url = deliveries
+        .create(new_webhook_delivery(&event_id, "http://another.url"))
+        .await
+        .unwrap();
+
+    assert!(first.is_some());
+    assert!(duplicated.is_none());
+    assert!(other_url.is_some());
+    assert_eq!(
+        deliveries
+            .get(first.unwrap())
+            .await
+            .unwrap()
+            .unwrap()
+            .event_id,
+        Some(event_id)
+    );
+}
+
+#[actix_web::test]
+#[serial_test::serial]
+async fn should_take_each_stale_pending_webhook_delivery_once() {
+    let db = create_db_component(None).await;
+    let dbrepos = db.db_repos.as_ref().unwrap();
+    let deliveries = &dbrepos.webhook_deliveries;
+
+    let event_id = Uuid::new_v4().to_string();
+    let id = deliveries
+        .create(new_webhook_delivery(&event_id, "http://a.url"))
+        .await
+        .unwrap()
+        .unwrap();
+
+    let not_stale = deliveries
+        .take_stale_pending(Duration::from_secs(3600), 1000)
+        .await
+        .unwrap();
+    assert!(!not_stale.iter().any(|delivery| delivery.id == id));
+
+    tokio::time::sleep(Duration::from_millis(10)).await;
+    let taken = deliveries
+        .take_stale_pending(Duration::ZERO, 1000)
+        .await
+        .unwrap();
+    assert!(taken.iter().any(|delivery| delivery.id == id));
+
+    // Taking them marks them as updated, so they aren't stale anymore
+    let taken_again = deliveries
+        .take_stale_pending(Duration::from_secs(3600), 1000)
+        .await
+        .unwrap();
+    assert!(!taken_again.iter().any(|delivery| delivery.id == id));
+}
+
+/// A pending accept delivery of the given event to the given url.
+fn new_webhook_delivery<'a>(event_id: &'a str, url: &'a str) -> NewWebhookDelivery<'a> {
+    NewWebhookDelivery {
+        event_id,
+        url,
+        event: "accept",
+        from_address: "0xa",
+        to_address: "0xb",
+        payload: "{}",
+        signature: "a-signature",
+    }
+}
+
 /// Creates a new friendship between two users and returns the friendship_id.
 async fn create_friendship(
     dbrepos: &DBRepositories,
-    address_1: &str,
-    address_2: &str,
+    address_1: &Address,
+    address_2: &Address,
     is_active: bool,
 ) -> Uuid {
     let synapse_room_id = format!("room_id_{address_1}_{address_2}");
@@ -252,7 +481,7 @@ async fn create_friendship_event(
     dbrepos: &DBRepositories,
     friendship_id: Uuid,
     event: &str,
-    acting_user: &str,
+    acting_user: &Address,
     metadata: Option<sqlx::types::Json<FriendshipMetadata>>,
 ) {
     dbrepos
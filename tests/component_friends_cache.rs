@@ -0,0 +1,91 @@
+use social_service::{
+    components::{
+        configuration::RedisConfig, database::DBRepositories, friends_cache::FriendsCacheComponent,
+        redis::Redis,
+    },
+    domain::address::Address,
+};
+
+async fn create_friends_cache() -> FriendsCacheComponent {
+    let redis = Redis::new_and_run(&RedisConfig {
+        host: "0.0.0.0:6379".to_string(),
+    })
+    .await
+    .expect("There was an error initializing Redis");
+
+    FriendsCacheComponent::new(redis, 60)
+}
+
+/// Random address so cached friends from previous runs don't interfere.
+fn random_address() -> Address {
+    let hex = uuid::Uuid::new_v4().simple().to_string();
+    Address::parse(&format!("0x{hex}00000000")).expect("a valid address")
+}
+
+async fn create_friendship(repos: &DBRepositories, user_1: &Address, user_2: &Address) {
+    let (id, _) = repos
+        .friendships
+        .create_new_friendships((user_1, user_2), true, "a_room_id", None)
+        .await;
+    id.unwrap();
+}
+
+#[actix_web::test]
+async fn test_should_serve_the_friends_from_the_cache_until_invalidated() {
+    let repos = DBRepositories::in_memory();
+    let cache = create_friends_cache().await;
+    let (user, friend, new_friend) = (random_address(), random_address(), random_address());
+
+    create_friendship(&repos, &user, &friend).await;
+    let friends = cache.get_friends(&user, &repos).await.unwrap();
+    assert_eq!(friends, vec![friend.to_string()]);
+
+    create_friendship(&repos, &new_friend, &user).await;
+    let friends = cache.get_friends(&user, &repos).await.unwrap();
+    assert_eq!(friends, vec![friend.to_string()]);
+
+    cache.invalidate(&[&user]).await;
+    let mut friends = cache.get_friends(&user, &repos).await.unwrap();
+    friends.sort();
+    let mut expected = vec![friend.to_string(), new_friend.to_string()];
+    expected.sort();
+    assert_eq!(friends, expected);
+}
+
+#[actix_web::test]
+async fn test_should_cache_users_without_friends() {
+    let repos = DBRepositories::in_memory();
+    let cache = create_friends_cache().await;
+    let (user, friend) = (random_address(), random_address());
+
+    assert!(cache.get_friends(&user, &repos).await.unwrap().is_empty());
+
+    create_friendship(&repos, &user, &friend).await;
+    assert!(cache.get_friends(&user, &repos).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_should_intersect_the_cached_friends() {
+    let repos = DBRepositories::in_memory();
+    let cache = create_friends_cache().await;
+    let (user_a, user_b, mutual, only_a) = (
+        random_address(),
+        random_address(),
+        random_address(),
+        random_address(),
+    );
+
+    create_friendship(&repos, &user_a, &mutual).await;
+    create_friendship(&repos, &mutual, &user_b).await;
+    create_friendship(&repos, &user_a, &only_a).await;
+    create_friendship(&repos, &user_a, &user_b).await;
+
+    // One of the users is cached already
+    cache.get_friends(&user_a, &repos).await.unwrap();
+
+    let mutuals = cache
+        .get_mutual_friends(&user_a, &user_b, &repos)
+        .await
+        .unwrap();
+    assert_eq!(mutuals, vec![mutual.to_string()]);
+}
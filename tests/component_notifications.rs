@@ -0,0 +1,136 @@
+mod common;
+
+pub use common::*;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use social_service::{
+    components::{
+        configuration::EventsChannelBackend,
+        database::DatabaseComponentImplementation,
+        notifications::{
+            init_configured_events_channel_publisher, init_configured_events_channel_subscriber,
+            ChannelPublisher, ChannelSubscriber, InProcessChannel, EVENT_UPDATES_CHANNEL_NAME,
+        },
+        redis::Redis,
+    },
+    friendships::{friendship_event_response, FriendshipEventResponse, RequestResponse, User},
+    notifications::Event,
+};
+
+fn request_event(from: &str, to: &str) -> Event {
+    Event {
+        friendship_event: Some(FriendshipEventResponse {
+            body: Some(friendship_event_response::Body::Request(RequestResponse {
+                user: Some(User {
+                    address: from.to_string(),
+                    ..Default::default()
+                }),
+                created_at: 0,
+                message: Some("Hi!".to_string()),
+            })),
+        }),
+        from: from.to_string(),
+        to: to.to_string(),
+        id: uuid::Uuid::new_v4().to_string(),
+        origin: social_service::instance_id().to_string(),
+        notify_from: false,
+    }
+}
+
+async fn should_publish_and_receive_events(backend: EventsChannelBackend, to: &str) {
+    let config = get_configuration().await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(create_db_component(Some(&config)).await);
+    let redis = match backend {
+        EventsChannelBackend::Redis => Some(Arc::new(
+            Redis::new_and_run(&config.redis)
+                .await
+                .expect("There was an error initializing Redis"),
+        )),
+        EventsChannelBackend::Postgres | EventsChannelBackend::InProcess => None,
+    };
+
+    let publisher = init_configured_events_channel_publisher(backend, redis.clone(), &db).await;
+    let subscriber = init_configured_events_channel_subscriber(backend, redis, &db);
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let received_clone = received.clone();
+    let recipient = to.to_string();
+    subscriber.subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event: Event| {
+        let received = received_clone.clone();
+        let recipient = recipient.clone();
+        async move {
+            if event.to == recipient {
+                received.lock().unwrap().push(event);
+            }
+        }
+    });
+
+    // Give some time to the subscription to be ready
+    actix_rt::time::sleep(Duration::from_millis(500)).await;
+
+    let from = "0xabcd000000000000000000000000000000000001";
+    publisher.publish(request_event(from, to)).await;
+    publisher.publish(request_event(from, to)).await;
+
+    actix_rt::time::sleep(Duration::from_millis(500)).await;
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0], request_event(from, to));
+}
+
+#[actix_web::test]
+async fn test_redis_channel_should_publish_and_receive_events() {
+    should_publish_and_receive_events(
+        EventsChannelBackend::Redis,
+        "0xabcd000000000000000000000000000000000101",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_postgres_channel_should_publish_and_receive_events() {
+    should_publish_and_receive_events(
+        EventsChannelBackend::Postgres,
+        "0xabcd000000000000000000000000000000000102",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_in_process_channel_should_publish_and_receive_events() {
+    should_publish_and_receive_events(
+        EventsChannelBackend::InProcess,
+        "0xabcd000000000000000000000000000000000103",
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn test_in_process_channel_should_only_deliver_to_the_same_channel_name() {
+    let channel = InProcessChannel::new(16);
+    let publisher = channel.publisher("ANOTHER_CHANNEL");
+    let subscriber = channel.subscriber();
+
+    let received = Arc::new(Mutex::new(vec![]));
+    let received_clone = received.clone();
+    subscriber.subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event: Event| {
+        let received = received_clone.clone();
+        async move {
+            received.lock().unwrap().push(event);
+        }
+    });
+
+    let from = "0xabcd000000000000000000000000000000000001";
+    let to = "0xabcd000000000000000000000000000000000104";
+    publisher.publish(request_event(from, to)).await;
+
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+    assert!(received.lock().unwrap().is_empty());
+}
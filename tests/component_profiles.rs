@@ -0,0 +1,180 @@
+mod common;
+
+pub use common::*;
+
+use std::sync::Arc;
+
+use social_service::{
+    components::{
+        configuration::RedisConfig,
+        profiles::{LambdasProfileProvider, ProfilesComponent, PROFILES_URI},
+        redis::Redis,
+    },
+    domain::address::Address,
+};
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const FACE_IMAGE: &str = "https://images.decentraland.org/face.png";
+
+async fn create_profiles_component(lambdas_url: String) -> ProfilesComponent {
+    let redis = Redis::new_and_run(&RedisConfig {
+        host: "0.0.0.0:6379".to_string(),
+    })
+    .await
+    .expect("There was an error initializing Redis");
+
+    ProfilesComponent::new(
+        Arc::new(LambdasProfileProvider::new(lambdas_url)),
+        Some(redis),
+        60,
+        60,
+    )
+}
+
+/// Random address so cached profiles from previous runs don't interfere.
+fn random_address() -> Address {
+    let hex = uuid::Uuid::new_v4().simple().to_string();
+    Address::parse(&format!("0x{hex}00000000")).expect("a valid address")
+}
+
+#[actix_web::test]
+async fn test_should_fetch_profiles_in_a_single_batch() {
+    let (address_a, address_b) = (random_address(), random_address());
+    let server = profiles_mock_server(vec![
+        (address_a.as_str(), "Pizarnik", FACE_IMAGE),
+        (address_b.as_str(), "Martha", FACE_IMAGE),
+    ])
+    .await;
+
+    let component = create_profiles_component(server.uri()).await;
+    let profiles = component
+        .get_profiles(&[address_a.clone(), address_b.clone()])
+        .await;
+
+    assert_eq!(profiles.len(), 2);
+    assert_eq!(profiles[&address_a].name.as_deref(), Some("Pizarnik"));
+    assert_eq!(profiles[&address_a].face_image.as_deref(), Some(FACE_IMAGE));
+    assert_eq!(profiles[&address_b].name.as_deref(), Some("Martha"));
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_should_serve_cached_profiles_without_calling_the_provider() {
+    let address = random_address();
+    let other_address = random_address();
+    let server = MockServer::start().await;
+
+    let response = serde_json::json!([{
+        "avatars": [{
+            "ethAddress": address.as_str(),
+            "name": "Pizarnik",
+            "avatar": { "snapshots": { "face256": FACE_IMAGE } }
+        }]
+    }]);
+
+    Mock::given(method("POST"))
+        .and(path(PROFILES_URI))
+        .and(body_json(serde_json::json!({ "ids": [address.as_str()] })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(response))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    Mock::given(method("POST"))
+        .and(path(PROFILES_URI))
+        .and(body_json(
+            serde_json::json!({ "ids": [other_address.as_str()] }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let component = create_profiles_component(server.uri()).await;
+
+    let first = component.get_profiles(&[address.clone()]).await;
+    // Only the address that wasn't cached should be requested to the provider
+    let second = component
+        .get_profiles(&[address.clone(), other_address.clone()])
+        .await;
+
+    assert_eq!(first, second);
+    assert_eq!(second[&address].name.as_deref(), Some("Pizarnik"));
+    assert!(!second.contains_key(&other_address));
+}
+
+#[actix_web::test]
+async fn test_should_cache_the_addresses_without_a_profile() {
+    let address = random_address();
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path(PROFILES_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([])))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let component = create_profiles_component(server.uri()).await;
+
+    let first = component.get_profiles(&[address.clone()]).await;
+    // The address was cached as missing, so the provider isn't called again
+    let second = component.get_profiles(&[address]).await;
+
+    assert!(first.is_empty());
+    assert!(second.is_empty());
+}
+
+#[actix_web::test]
+async fn test_should_cache_the_profiles_in_process_without_redis() {
+    let address = random_address();
+    let server = MockServer::start().await;
+
+    let response = serde_json::json!([{
+        "avatars": [{
+            "ethAddress": address.as_str(),
+            "name": "Pizarnik",
+            "avatar": { "snapshots": { "face256": FACE_IMAGE } }
+        }]
+    }]);
+
+    Mock::given(method("POST"))
+        .and(path(PROFILES_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(response))
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let component = ProfilesComponent::new(
+        Arc::new(LambdasProfileProvider::new(server.uri())),
+        None,
+        60,
+        60,
+    );
+
+    let first = component.get_profiles(&[address.clone()]).await;
+    let second = component.get_profiles(&[address.clone()]).await;
+
+    assert_eq!(first, second);
+    assert_eq!(second[&address].name.as_deref(), Some("Pizarnik"));
+}
+
+#[actix_web::test]
+async fn test_should_return_no_profiles_when_provider_fails() {
+    let address = random_address();
+    let server = MockServer::start().await;
+
+    Mock::given(method("POST"))
+        .and(path(PROFILES_URI))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let component = create_profiles_component(server.uri()).await;
+    let profiles = component.get_profiles(&[address]).await;
+
+    assert!(profiles.is_empty());
+}
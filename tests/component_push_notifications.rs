@@ -0,0 +1,231 @@
+use std::{sync::Arc, time::Duration};
+
+use social_service::{
+    components::{
+        configuration::RedisConfig,
+        database::DBRepositories,
+        push_notifications::{
+            HttpPushProvider, PushNotification, PushNotificationsComponent,
+            PUSH_NOTIFICATIONS_DISABLED, PUSH_NOTIFICATIONS_FEATURE,
+        },
+        redis::Redis,
+    },
+    domain::{address::Address, friendship_event::FriendshipEvent},
+    friendships::{
+        friendship_event_response::Body, DeleteResponse, FriendshipEventResponse, RequestResponse,
+        User,
+    },
+    notifications::Event,
+};
+use wiremock::{
+    matchers::{header, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const PUSH_PATH: &str = "/push";
+const PUSH_TOKEN: &str = "a-push-token";
+
+async fn push_mock_server() -> MockServer {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path(PUSH_PATH))
+        .and(header(
+            "authorization",
+            format!("Bearer {PUSH_TOKEN}").as_str(),
+        ))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn create_redis() -> Redis {
+    Redis::new_and_run(&RedisConfig {
+        host: "0.0.0.0:6379".to_string(),
+    })
+    .await
+    .expect("There was an error initializing Redis")
+}
+
+async fn create_push_notifications(
+    server: &MockServer,
+    repos: &DBRepositories,
+) -> PushNotificationsComponent {
+    create_push_notifications_with_redis(server, repos, create_redis().await)
+}
+
+fn create_push_notifications_with_redis(
+    server: &MockServer,
+    repos: &DBRepositories,
+    redis: Redis,
+) -> PushNotificationsComponent {
+    let provider = HttpPushProvider::new(
+        format!("{}{PUSH_PATH}", server.uri()),
+        Some(PUSH_TOKEN.to_string()),
+    );
+
+    PushNotificationsComponent::new(
+        Arc::new(provider),
+        repos.user_features.clone(),
+        Some(redis),
+        "test_key".to_string(),
+        Duration::from_millis(10),
+    )
+}
+
+/// Random address so the events of previous runs aren't taken as already delivered.
+fn random_address() -> String {
+    let hex = uuid::Uuid::new_v4().simple().to_string();
+    format!("0x{hex}00000000")
+}
+
+fn event(body: Body, from: &str, to: &str) -> Event {
+    Event {
+        friendship_event: Some(FriendshipEventResponse { body: Some(body) }),
+        from: from.to_string(),
+        to: to.to_string(),
+        id: uuid::Uuid::new_v4().to_string(),
+        origin: social_service::instance_id().to_string(),
+        notify_from: false,
+    }
+}
+
+fn request_event(from: &str, to: &str) -> Event {
+    let body = Body::Request(RequestResponse {
+        user: Some(User {
+            address: from.to_string(),
+            ..Default::default()
+        }),
+        created_at: chrono::Utc::now().timestamp(),
+        message: Some("Hi!".to_string()),
+    });
+
+    event(body, from, to)
+}
+
+#[actix_web::test]
+async fn test_should_push_the_request_to_an_offline_user() {
+    let server = push_mock_server().await;
+    let repos = DBRepositories::in_memory();
+    let push_notifications = create_push_notifications(&server, &repos).await;
+    let (from, to) = (random_address(), random_address());
+
+    push_notifications
+        .notify_if_offline(&request_event(&from, &to))
+        .await;
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let notification: PushNotification = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(
+        notification,
+        PushNotification {
+            event: FriendshipEvent::REQUEST,
+            from,
+            to,
+            message: Some("Hi!".to_string()),
+        }
+    );
+}
+
+#[actix_web::test]
+async fn test_should_not_push_an_event_delivered_live() {
+    let server = push_mock_server().await;
+    let repos = DBRepositories::in_memory();
+    let push_notifications = create_push_notifications(&server, &repos).await;
+    let event = request_event(&random_address(), &random_address());
+
+    // The instance holding the subscription of the recipient marks it within the grace period
+    push_notifications.mark_delivered_live(&event).await;
+    push_notifications.notify_if_offline(&event).await;
+
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_should_push_each_event_once() {
+    let server = push_mock_server().await;
+    let repos = DBRepositories::in_memory();
+    let push_notifications = create_push_notifications(&server, &repos).await;
+    let other_instance = create_push_notifications(&server, &repos).await;
+    let event = request_event(&random_address(), &random_address());
+
+    push_notifications.notify_if_offline(&event).await;
+    other_instance.notify_if_offline(&event).await;
+
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_should_only_push_the_events_published_here_while_redis_is_unavailable() {
+    let server = push_mock_server().await;
+    let repos = DBRepositories::in_memory();
+    let redis = create_redis().await;
+    let push_notifications = create_push_notifications_with_redis(&server, &repos, redis.clone());
+    redis.stop();
+
+    let published_here = request_event(&random_address(), &random_address());
+    let published_elsewhere = Event {
+        origin: uuid::Uuid::new_v4().to_string(),
+        ..request_event(&random_address(), &random_address())
+    };
+
+    push_notifications
+        .notify_if_offline(&published_elsewhere)
+        .await;
+    assert!(server.received_requests().await.unwrap().is_empty());
+
+    push_notifications.notify_if_offline(&published_here).await;
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_should_not_push_to_users_who_opted_out() {
+    let server = push_mock_server().await;
+    let repos = DBRepositories::in_memory();
+    let push_notifications = create_push_notifications(&server, &repos).await;
+    let (from, to) = (random_address(), random_address());
+
+    repos
+        .user_features
+        .set_user_feature(
+            &Address::parse(&to).unwrap(),
+            PUSH_NOTIFICATIONS_FEATURE,
+            PUSH_NOTIFICATIONS_DISABLED,
+        )
+        .await
+        .unwrap();
+
+    push_notifications
+        .notify_if_offline(&request_event(&from, &to))
+        .await;
+
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_should_only_push_requests_and_accepts() {
+    let server = push_mock_server().await;
+    let repos = DBRepositories::in_memory();
+    let push_notifications = create_push_notifications(&server, &repos).await;
+    let (from, to) = (random_address(), random_address());
+    let delete = event(
+        Body::Delete(DeleteResponse {
+            user: Some(User {
+                address: from.clone(),
+                ..Default::default()
+            }),
+        }),
+        &from,
+        &to,
+    );
+
+    assert!(!PushNotificationsComponent::is_pushed(&delete));
+    assert!(PushNotificationsComponent::is_pushed(&request_event(
+        &from, &to
+    )));
+
+    push_notifications.notify_if_offline(&delete).await;
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
@@ -0,0 +1,261 @@
+use social_service::{
+    components::{
+        configuration::RedisConfig,
+        redis::Redis,
+        session_tokens::{SessionTokensComponent, SigningKey},
+        users_cache::UserId,
+    },
+    domain::error::CommonError,
+};
+
+const ACCESS_TTL_SECONDS: u64 = 60;
+const REFRESH_TTL_SECONDS: u64 = 3600;
+const DEVICE_ID: &str = "A_DEVICE";
+
+async fn create_session_tokens(keys: Vec<SigningKey>) -> SessionTokensComponent {
+    create_session_tokens_with_ttl(keys, ACCESS_TTL_SECONDS).await
+}
+
+async fn create_session_tokens_with_ttl(
+    keys: Vec<SigningKey>,
+    access_ttl_seconds: u64,
+) -> SessionTokensComponent {
+    let redis = Redis::new_and_run(&RedisConfig {
+        host: "0.0.0.0:6379".to_string(),
+    })
+    .await
+    .expect("There was an error initializing Redis");
+
+    SessionTokensComponent::new(keys, access_ttl_seconds, REFRESH_TTL_SECONDS, Some(redis))
+}
+
+/// Random user so revoked sessions from previous runs don't interfere.
+fn random_user() -> UserId {
+    let hex = uuid::Uuid::new_v4().simple().to_string();
+    UserId {
+        social_id: format!("0x{hex}00000000"),
+        synapse_id: format!("@0x{hex}00000000:decentraland.org"),
+    }
+}
+
+#[actix_web::test]
+async fn test_should_verify_the_issued_access_token() {
+    let session_tokens = create_session_tokens(vec![SigningKey::new("a", "a-secret")]).await;
+    let user = random_user();
+
+    let tokens = session_tokens.issue(&user, DEVICE_ID);
+
+    assert_eq!(tokens.expires_in, ACCESS_TTL_SECONDS);
+    assert_eq!(
+        session_tokens
+            .verify_access_token(&tokens.access_token)
+            .await,
+        Ok(user)
+    );
+    // A refresh token can't be used to authenticate
+    assert!(session_tokens
+        .verify_access_token(&tokens.refresh_token)
+        .await
+        .is_err());
+}
+
+#[actix_web::test]
+async fn test_should_reject_tampered_and_expired_tokens() {
+    let session_tokens = create_session_tokens(vec![SigningKey::new("a", "a-secret")]).await;
+    let tokens = session_tokens.issue(&random_user(), DEVICE_ID);
+
+    let mut parts: Vec<&str> = tokens.access_token.split('.').collect();
+    let other_tokens = session_tokens.issue(&random_user(), DEVICE_ID);
+    parts[1] = other_tokens.access_token.split('.').nth(1).unwrap();
+    assert_eq!(
+        session_tokens.verify_access_token(&parts.join(".")).await,
+        Err(CommonError::Unauthorized("".to_owned()))
+    );
+
+    // Issued with a TTL of 0, so it's already expired
+    let expiring = create_session_tokens_with_ttl(vec![SigningKey::new("a", "a-secret")], 0).await;
+    let expired_tokens = expiring.issue(&random_user(), DEVICE_ID);
+    assert_eq!(
+        session_tokens
+            .verify_access_token(&expired_tokens.access_token)
+            .await,
+        Err(CommonError::Unauthorized("".to_owned()))
+    );
+}
+
+#[actix_web::test]
+async fn test_should_accept_the_tokens_of_rotated_keys_until_they_are_removed() {
+    let user = random_user();
+    let old_key = SigningKey::new("old", "an-old-secret");
+    let new_key = SigningKey::new("new", "a-new-secret");
+
+    let before_rotation = create_session_tokens(vec![old_key.clone()]).await;
+    let during_rotation = create_session_tokens(vec![new_key.clone(), old_key]).await;
+    let after_rotation = create_session_tokens(vec![new_key]).await;
+
+    let old_tokens = before_rotation.issue(&user, DEVICE_ID);
+    assert!(during_rotation
+        .verify_access_token(&old_tokens.access_token)
+        .await
+        .is_ok());
+    assert!(after_rotation
+        .verify_access_token(&old_tokens.access_token)
+        .await
+        .is_err());
+
+    let new_tokens = during_rotation.issue(&user, DEVICE_ID);
+    assert!(after_rotation
+        .verify_access_token(&new_tokens.access_token)
+        .await
+        .is_ok());
+}
+
+#[actix_web::test]
+async fn test_should_refresh_until_the_sessions_are_revoked() {
+    let session_tokens = create_session_tokens(vec![SigningKey::new("a", "a-secret")]).await;
+    let user = random_user();
+    let tokens = session_tokens.issue(&user, DEVICE_ID);
+
+    let refreshed = session_tokens.refresh(&tokens.refresh_token).await.unwrap();
+    assert_eq!(
+        session_tokens
+            .verify_access_token(&refreshed.access_token)
+            .await,
+        Ok(user.clone())
+    );
+    assert!(session_tokens.refresh(&tokens.access_token).await.is_err());
+
+    session_tokens.revoke(&user.social_id).await.unwrap();
+
+    assert_eq!(
+        session_tokens.refresh(&refreshed.refresh_token).await.err(),
+        Some(CommonError::Unauthorized("".to_owned()))
+    );
+    assert_eq!(
+        session_tokens
+            .verify_access_token(&refreshed.access_token)
+            .await,
+        Err(CommonError::Unauthorized("".to_owned()))
+    );
+}
+
+#[actix_web::test]
+async fn test_should_exchange_a_refresh_token_only_once() {
+    let session_tokens = create_session_tokens(vec![SigningKey::new("a", "a-secret")]).await;
+    let tokens = session_tokens.issue(&random_user(), DEVICE_ID);
+
+    let refreshed = session_tokens.refresh(&tokens.refresh_token).await.unwrap();
+    assert_ne!(refreshed.refresh_token, tokens.refresh_token);
+
+    assert_eq!(
+        session_tokens.refresh(&tokens.refresh_token).await.err(),
+        Some(CommonError::Unauthorized("".to_owned()))
+    );
+    // The new refresh token replaces the used one
+    assert!(session_tokens
+        .refresh(&refreshed.refresh_token)
+        .await
+        .is_ok());
+}
+
+#[actix_web::test]
+async fn test_should_check_the_revocations_of_other_instances_on_refresh() {
+    let instance = create_session_tokens(vec![SigningKey::new("a", "a-secret")]).await;
+    let other_instance = create_session_tokens(vec![SigningKey::new("a", "a-secret")]).await;
+    let user = random_user();
+    let tokens = instance.issue(&user, DEVICE_ID);
+    assert!(instance
+        .verify_access_token(&tokens.access_token)
+        .await
+        .is_ok());
+
+    other_instance.revoke(&user.social_id).await.unwrap();
+
+    assert!(instance.refresh(&tokens.refresh_token).await.is_err());
+    // The access tokens use the revocations cached by the instance until they expire
+    assert!(instance
+        .verify_access_token(&tokens.access_token)
+        .await
+        .is_ok());
+    assert!(other_instance
+        .verify_access_token(&tokens.access_token)
+        .await
+        .is_err());
+}
+
+#[actix_web::test]
+async fn test_should_not_refresh_a_logged_out_session() {
+    let session_tokens = create_session_tokens(vec![SigningKey::new("a", "a-secret")]).await;
+    let user = random_user();
+    let tokens = session_tokens.issue(&user, DEVICE_ID);
+    let other_device_tokens = session_tokens.issue(&user, "ANOTHER_DEVICE");
+
+    session_tokens
+        .revoke_session(&user.social_id, DEVICE_ID)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        session_tokens.refresh(&tokens.refresh_token).await.err(),
+        Some(CommonError::Unauthorized("".to_owned()))
+    );
+    assert!(session_tokens
+        .verify_access_token(&tokens.access_token)
+        .await
+        .is_err());
+    // The sessions of the other devices of the user are still valid
+    assert!(session_tokens
+        .refresh(&other_device_tokens.refresh_token)
+        .await
+        .is_ok());
+}
+
+#[actix_web::test]
+async fn test_should_revoke_the_sessions_without_redis() {
+    let session_tokens = SessionTokensComponent::new(
+        vec![SigningKey::new("a", "a-secret")],
+        ACCESS_TTL_SECONDS,
+        REFRESH_TTL_SECONDS,
+        None,
+    );
+    let user = random_user();
+    let tokens = session_tokens.issue(&user, DEVICE_ID);
+    let other_device_tokens = session_tokens.issue(&user, "ANOTHER_DEVICE");
+
+    session_tokens
+        .revoke_session(&user.social_id, DEVICE_ID)
+        .await
+        .unwrap();
+
+    assert!(session_tokens.refresh(&tokens.refresh_token).await.is_err());
+    assert!(session_tokens
+        .refresh(&other_device_tokens.refresh_token)
+        .await
+        .is_ok());
+
+    session_tokens.revoke(&user.social_id).await.unwrap();
+
+    assert!(session_tokens
+        .verify_access_token(&other_device_tokens.access_token)
+        .await
+        .is_err());
+}
+
+#[actix_web::test]
+async fn test_should_exchange_a_refresh_token_only_once_without_redis() {
+    let session_tokens = SessionTokensComponent::new(
+        vec![SigningKey::new("a", "a-secret")],
+        ACCESS_TTL_SECONDS,
+        REFRESH_TTL_SECONDS,
+        None,
+    );
+    let tokens = session_tokens.issue(&random_user(), DEVICE_ID);
+
+    let refreshed = session_tokens.refresh(&tokens.refresh_token).await.unwrap();
+
+    assert!(session_tokens.refresh(&tokens.refresh_token).await.is_err());
+    assert!(session_tokens
+        .refresh(&refreshed.refresh_token)
+        .await
+        .is_ok());
+}
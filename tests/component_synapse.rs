@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use serde_json::json;
+use social_service::{
+    components::{
+        circuit_breaker::CircuitState,
+        health::Healthy,
+        synapse::{SynapseClientOptions, SynapseComponent, VERSION_URI, WHO_AM_I_URI},
+    },
+    domain::error::CommonError,
+};
+use wiremock::{
+    matchers::{body_json, method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+fn options(max_retries: u32, circuit_breaker_failure_threshold: u32) -> SynapseClientOptions {
+    SynapseClientOptions {
+        max_retries,
+        retry_base_delay: Duration::from_millis(1),
+        circuit_breaker_failure_threshold,
+        circuit_breaker_open_duration: Duration::from_secs(60),
+        ..Default::default()
+    }
+}
+
+async fn failing_synapse_server(expected_calls: u64) -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path(WHO_AM_I_URI))
+        .respond_with(ResponseTemplate::new(502))
+        .expect(expected_calls)
+        .mount(&server)
+        .await;
+
+    server
+}
+
+#[actix_web::test]
+async fn should_retry_idempotent_requests_on_server_errors() {
+    let server = failing_synapse_server(3).await;
+    let synapse = SynapseComponent::with_options(server.uri(), options(2, 10));
+
+    let result = synapse.who_am_i("a_token").await;
+
+    assert_eq!(result.err(), Some(CommonError::Unknown("".to_owned())));
+    assert_eq!(synapse.circuit_state(), CircuitState::Closed);
+}
+
+#[actix_web::test]
+async fn should_fail_fast_once_the_circuit_is_open() {
+    // The first call retries until the breaker opens, the second one never reaches Synapse
+    let server = failing_synapse_server(2).await;
+    let synapse = SynapseComponent::with_options(server.uri(), options(5, 2));
+
+    let result = synapse.who_am_i("a_token").await;
+    assert_eq!(
+        result.err(),
+        Some(CommonError::ServiceUnavailable("".to_owned()))
+    );
+
+    let result = synapse.who_am_i("a_token").await;
+    assert_eq!(
+        result.err(),
+        Some(CommonError::ServiceUnavailable("".to_owned()))
+    );
+
+    assert_eq!(synapse.circuit_state(), CircuitState::Open);
+    assert!(!synapse.is_healthy().await);
+}
+
+#[actix_web::test]
+async fn should_share_the_circuit_breaker_between_clones() {
+    let server = failing_synapse_server(1).await;
+    let synapse = SynapseComponent::with_options(server.uri(), options(0, 1));
+    let clone = synapse.clone();
+
+    let _ = synapse.who_am_i("a_token").await;
+    let result = clone.get_version().await;
+
+    assert_eq!(
+        result.err(),
+        Some(CommonError::ServiceUnavailable("".to_owned()))
+    );
+}
+
+#[actix_web::test]
+async fn should_keep_the_circuit_closed_on_client_errors() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(VERSION_URI))
+        .respond_with(ResponseTemplate::new(404).set_body_json(serde_json::json!({
+            "errcode": "M_NOT_FOUND"
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let synapse = SynapseComponent::with_options(server.uri(), options(2, 1));
+
+    let result = synapse.get_version().await;
+
+    assert!(result.is_err());
+    assert_eq!(synapse.circuit_state(), CircuitState::Closed);
+    assert!(synapse.is_healthy().await);
+}
+
+#[actix_web::test]
+async fn should_invite_the_service_user_and_leave_it_out_of_the_members() {
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/_matrix/client/r0/createRoom"))
+        .and(body_json(json!({
+            "room_alias_name": "a+b",
+            "preset": "trusted_private_chat",
+            "invite": ["@b:decentraland.org", "@friendships:decentraland.org"],
+            "is_direct": true,
+        })))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": "a_room" })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/_matrix/client/r0/rooms/a_room/members"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": ["@a:decentraland.org", "@b:decentraland.org", "@friendships:decentraland.org"]
+                .iter()
+                .map(|user| json!({ "state_key": user, "user_id": user, "room_id": "a_room", "type": "m.room.member" }))
+                .collect::<Vec<_>>()
+        })))
+        .mount(&server)
+        .await;
+    let synapse = SynapseComponent::new(server.uri())
+        .with_service_user("@friendships:decentraland.org".to_string());
+
+    synapse
+        .create_private_room("a_token", vec!["@b:decentraland.org"], "a+b")
+        .await
+        .unwrap();
+    let members = synapse.get_room_members("a_token", "a_room").await.unwrap();
+
+    let members: Vec<_> = members
+        .chunk
+        .iter()
+        .map(|member| member.state_key.as_str())
+        .collect();
+    assert_eq!(members, ["@a:decentraland.org", "@b:decentraland.org"]);
+}
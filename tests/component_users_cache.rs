@@ -17,7 +17,7 @@ async fn create_users_cache_component() -> UsersCacheComponent {
     .await
     .expect("There was an error initializing Redis");
 
-    UsersCacheComponent::new(redis, TEST_KEY.to_string())
+    UsersCacheComponent::new(Some(redis), TEST_KEY.to_string())
 }
 
 #[actix_web::test]
@@ -34,7 +34,7 @@ async fn test_should_return_no_connection_available() -> Result<(), String> {
     // When redis is closed, adding a user should return an error
     redis.stop();
 
-    let mut user_cache_component = UsersCacheComponent::new(redis, TEST_KEY.to_string());
+    let user_cache_component = UsersCacheComponent::new(Some(redis), TEST_KEY.to_string());
     let res = user_cache_component
         .add_user(token, user_id, user_id, None)
         .await;
@@ -53,7 +53,7 @@ async fn test_should_return_no_connection_available() -> Result<(), String> {
 
 #[actix_web::test]
 async fn test_can_store_and_get_user() {
-    let mut component = create_users_cache_component().await;
+    let component = create_users_cache_component().await;
 
     let user_id = "my user id";
     let token = "an example token";
@@ -82,7 +82,7 @@ async fn test_can_store_and_get_user() {
 
 #[actix_web::test]
 async fn test_obtain_expired_key_returns_none() {
-    let mut component = create_users_cache_component().await;
+    let component = create_users_cache_component().await;
 
     let user_id = "my expiring id";
     let token = "an example token that expires";
@@ -108,3 +108,105 @@ async fn test_obtain_expired_key_returns_none() {
         }
     }
 }
+
+#[actix_web::test]
+async fn test_invalidated_token_is_not_cached_anymore() {
+    let component = create_users_cache_component().await;
+
+    let user_id = "my invalidated id";
+    let token = "an example token to invalidate";
+
+    component
+        .add_user(token, user_id, user_id, None)
+        .await
+        .unwrap();
+    assert!(component.get_user(token).await.is_ok());
+
+    component.invalidate_token(token).await.unwrap();
+
+    let res = component.get_user(token).await;
+    assert!(res.unwrap_err().contains("(response was nil)"));
+}
+
+#[actix_web::test]
+async fn test_local_tier_serves_the_user_without_redis() {
+    let redis = Redis::new_and_run(&RedisConfig {
+        host: "0.0.0.0:6379".to_string(),
+    })
+    .await
+    .expect("Failed starting Redis");
+    let component = UsersCacheComponent::new(Some(redis.clone()), TEST_KEY.to_string())
+        .with_local_cache(10, Duration::from_secs(60));
+
+    let user_id = "my local id";
+    let token = "an example token kept in process";
+
+    component
+        .add_user(token, user_id, user_id, None)
+        .await
+        .unwrap();
+
+    // Once Redis is down the user is still served by the in-process tier
+    redis.stop();
+
+    assert_eq!(
+        component.get_user(token).await.unwrap(),
+        UserId {
+            social_id: user_id.to_string(),
+            synapse_id: user_id.to_string()
+        }
+    );
+}
+
+#[actix_web::test]
+async fn test_revoke_user_tokens_drops_every_token_of_the_user() {
+    let component = create_users_cache_component().await;
+
+    let user_id = "0xrevoked";
+    let other_user_id = "0xnotrevoked";
+    let tokens = ["a revoked token", "another revoked token"];
+    let other_token = "a token that is not revoked";
+
+    for token in tokens {
+        component
+            .add_user(token, user_id, user_id, None)
+            .await
+            .unwrap();
+    }
+    component
+        .add_user(other_token, other_user_id, other_user_id, None)
+        .await
+        .unwrap();
+
+    assert_eq!(component.revoke_user_tokens(user_id).await.unwrap(), 2);
+
+    for token in tokens {
+        assert!(component.get_user(token).await.is_err());
+    }
+    assert!(component.get_user(other_token).await.is_ok());
+    assert_eq!(component.revoke_user_tokens(user_id).await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn test_should_keep_the_users_in_process_without_redis() {
+    let component = UsersCacheComponent::new(None, TEST_KEY.to_string())
+        .with_local_cache(10, Duration::from_secs(60));
+
+    let user_id = "0xwithoutredis";
+    let token = "a token kept in process";
+
+    component
+        .add_user(token, user_id, user_id, None)
+        .await
+        .unwrap();
+    assert_eq!(
+        component.get_user(token).await.unwrap(),
+        UserId {
+            social_id: user_id.to_string(),
+            synapse_id: user_id.to_string()
+        }
+    );
+
+    assert_eq!(component.revoke_user_tokens(user_id).await.unwrap(), 1);
+    assert!(component.get_user(token).await.is_err());
+}
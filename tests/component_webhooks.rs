@@ -0,0 +1,327 @@
+use std::time::Duration;
+
+use social_service::{
+    components::{
+        configuration::RedisConfig,
+        database::DBRepositories,
+        redis::Redis,
+        users_cache::hash_with_key,
+        webhooks::{WebhookPayload, WebhooksComponent, DELIVERY_HEADER, SIGNATURE_HEADER},
+    },
+    domain::friendship_event::FriendshipEvent,
+    entities::webhook_deliveries::{DeliveryStatus, NewWebhookDelivery},
+    friendships::{
+        friendship_event_response::Body, AcceptResponse, FriendshipEventResponse, RequestResponse,
+        User,
+    },
+    notifications::Event,
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, Request, ResponseTemplate,
+};
+
+const SIGNING_KEY: &str = "a-signing-key";
+const WEBHOOK_PATH: &str = "/friendship-events";
+
+async fn create_redis() -> Redis {
+    Redis::new_and_run(&RedisConfig {
+        host: "0.0.0.0:6379".to_string(),
+    })
+    .await
+    .expect("There was an error initializing Redis")
+}
+
+async fn create_webhooks(server: &MockServer, repos: &DBRepositories) -> WebhooksComponent {
+    create_webhooks_with_redis(server, repos, create_redis().await)
+}
+
+fn create_webhooks_with_redis(
+    server: &MockServer,
+    repos: &DBRepositories,
+    redis: Redis,
+) -> WebhooksComponent {
+    WebhooksComponent::new(
+        vec![format!("{}{WEBHOOK_PATH}", server.uri())],
+        SIGNING_KEY.to_string(),
+        vec![FriendshipEvent::ACCEPT, FriendshipEvent::DELETE],
+        repos.webhook_deliveries.clone(),
+        Some(redis),
+    )
+    .with_retries(3, Duration::from_millis(10))
+}
+
+/// Random address so the events of previous runs aren't taken as already delivered.
+fn random_address() -> String {
+    let hex = uuid::Uuid::new_v4().simple().to_string();
+    format!("0x{hex}00000000")
+}
+
+fn event(body: Body, from: &str, to: &str) -> Event {
+    Event {
+        friendship_event: Some(FriendshipEventResponse { body: Some(body) }),
+        from: from.to_string(),
+        to: to.to_string(),
+        id: uuid::Uuid::new_v4().to_string(),
+        origin: social_service::instance_id().to_string(),
+        notify_from: false,
+    }
+}
+
+fn accept_event(from: &str, to: &str) -> Event {
+    let body = Body::Accept(AcceptResponse {
+        user: Some(User {
+            address: from.to_string(),
+            ..Default::default()
+        }),
+    });
+
+    event(body, from, to)
+}
+
+fn header(request: &Request, name: &str) -> Option<String> {
+    request
+        .headers
+        .iter()
+        .find(|(header, _)| header.as_str().eq_ignore_ascii_case(name))
+        .map(|(_, values)| values.last().as_str().to_string())
+}
+
+async fn mount_webhook(server: &MockServer, status: u16) {
+    server.reset().await;
+    Mock::given(method("POST"))
+        .and(path(WEBHOOK_PATH))
+        .respond_with(ResponseTemplate::new(status))
+        .mount(server)
+        .await;
+}
+
+#[actix_web::test]
+async fn test_should_post_the_signed_event_and_persist_the_delivery() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 200).await;
+    let repos = DBRepositories::in_memory();
+    let webhooks = create_webhooks(&server, &repos).await;
+    let (from, to) = (random_address(), random_address());
+
+    webhooks.dispatch(&accept_event(&from, &to)).await;
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    let body = String::from_utf8(requests[0].body.clone()).unwrap();
+    assert_eq!(
+        header(&requests[0], SIGNATURE_HEADER),
+        Some(hash_with_key(&body, SIGNING_KEY))
+    );
+
+    let payload: WebhookPayload = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload.event, FriendshipEvent::ACCEPT);
+    assert_eq!(payload.from, from);
+    assert_eq!(payload.to, to);
+
+    let deliveries = repos
+        .webhook_deliveries
+        .get_by_status(DeliveryStatus::Delivered, 10)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].event, "accept");
+    assert_eq!(deliveries[0].attempts, 1);
+    assert_eq!(deliveries[0].last_status_code, Some(200));
+    assert_eq!(
+        header(&requests[0], DELIVERY_HEADER),
+        Some(deliveries[0].id.to_string())
+    );
+}
+
+#[actix_web::test]
+async fn test_should_skip_the_events_not_configured() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 200).await;
+    let repos = DBRepositories::in_memory();
+    let webhooks = create_webhooks(&server, &repos).await;
+    let (from, to) = (random_address(), random_address());
+
+    let request = Body::Request(RequestResponse {
+        user: Some(User {
+            address: from.clone(),
+            ..Default::default()
+        }),
+        created_at: 0,
+        message: None,
+    });
+    webhooks.dispatch(&event(request, &from, &to)).await;
+
+    assert!(server.received_requests().await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_should_deliver_each_event_once() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 200).await;
+    let repos = DBRepositories::in_memory();
+    let webhooks = create_webhooks(&server, &repos).await;
+    let other_instance = create_webhooks(&server, &repos).await;
+    let event = accept_event(&random_address(), &random_address());
+
+    webhooks.dispatch(&event).await;
+    other_instance.dispatch(&event).await;
+
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_should_deliver_each_event_once_while_redis_is_unavailable() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 200).await;
+    let repos = DBRepositories::in_memory();
+    let redis = create_redis().await;
+    redis.stop();
+    let webhooks = create_webhooks_with_redis(&server, &repos, redis.clone());
+    let other_instance = create_webhooks_with_redis(&server, &repos, redis);
+    let event = accept_event(&random_address(), &random_address());
+
+    webhooks.dispatch(&event).await;
+    other_instance.dispatch(&event).await;
+
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_should_resume_the_stale_pending_deliveries() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 200).await;
+    let repos = DBRepositories::in_memory();
+    let webhooks = create_webhooks(&server, &repos).await;
+    let url = format!("{}{WEBHOOK_PATH}", server.uri());
+
+    // Left behind by an instance that stopped after its first attempt
+    let id = repos
+        .webhook_deliveries
+        .create(NewWebhookDelivery {
+            event_id: &uuid::Uuid::new_v4().to_string(),
+            url: &url,
+            event: "accept",
+            from_address: "0xa",
+            to_address: "0xb",
+            payload: "{}",
+            signature: &hash_with_key("{}", SIGNING_KEY),
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    repos
+        .webhook_deliveries
+        .record_attempt(id, DeliveryStatus::Pending, Some(503), Some("Unavailable"))
+        .await
+        .unwrap();
+
+    // Still being attempted
+    webhooks.resume_pending(Duration::from_secs(60)).await;
+    assert!(server.received_requests().await.unwrap().is_empty());
+
+    webhooks.resume_pending(Duration::ZERO).await;
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(header(&requests[0], DELIVERY_HEADER), Some(id.to_string()));
+
+    let delivery = repos.webhook_deliveries.get(id).await.unwrap().unwrap();
+    assert_eq!(delivery.status, DeliveryStatus::Delivered);
+    assert_eq!(delivery.attempts, 2);
+}
+
+#[actix_web::test]
+async fn test_should_retry_and_replay_a_failed_delivery() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 503).await;
+    let repos = DBRepositories::in_memory();
+    let webhooks = create_webhooks(&server, &repos).await;
+
+    webhooks
+        .dispatch(&accept_event(&random_address(), &random_address()))
+        .await;
+
+    let requests = server.received_requests().await.unwrap();
+    assert_eq!(requests.len(), 3);
+
+    let failed = repos
+        .webhook_deliveries
+        .get_by_status(DeliveryStatus::Failed, 10)
+        .await
+        .unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].attempts, 3);
+    assert_eq!(failed[0].last_status_code, Some(503));
+
+    mount_webhook(&server, 200).await;
+    let replayed = webhooks.replay(failed[0].id).await.unwrap().unwrap();
+    assert_eq!(replayed.status, DeliveryStatus::Delivered);
+    assert_eq!(replayed.attempts, 4);
+
+    // The replay carries the original payload and signature
+    let replay_request = &server.received_requests().await.unwrap()[0];
+    assert_eq!(replay_request.body, requests[0].body);
+    assert_eq!(
+        header(replay_request, SIGNATURE_HEADER),
+        header(&requests[0], SIGNATURE_HEADER)
+    );
+}
+
+#[actix_web::test]
+async fn test_should_replay_with_the_stored_signature_after_the_key_changes() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 400).await;
+    let repos = DBRepositories::in_memory();
+    let webhooks = create_webhooks(&server, &repos).await;
+
+    webhooks
+        .dispatch(&accept_event(&random_address(), &random_address()))
+        .await;
+    let original_signature = header(
+        &server.received_requests().await.unwrap()[0],
+        SIGNATURE_HEADER,
+    );
+
+    let failed = repos
+        .webhook_deliveries
+        .get_by_status(DeliveryStatus::Failed, 10)
+        .await
+        .unwrap();
+    assert_eq!(failed[0].signature, original_signature);
+
+    mount_webhook(&server, 200).await;
+    let rotated = WebhooksComponent::new(
+        vec![format!("{}{WEBHOOK_PATH}", server.uri())],
+        "a-rotated-signing-key".to_string(),
+        vec![FriendshipEvent::ACCEPT],
+        repos.webhook_deliveries.clone(),
+        Some(create_redis().await),
+    );
+    rotated.replay(failed[0].id).await.unwrap();
+
+    let replay_request = &server.received_requests().await.unwrap()[0];
+    assert_eq!(header(replay_request, SIGNATURE_HEADER), original_signature);
+}
+
+#[actix_web::test]
+async fn test_should_not_retry_a_rejected_delivery() {
+    let server = MockServer::start().await;
+    mount_webhook(&server, 400).await;
+    let repos = DBRepositories::in_memory();
+    let webhooks = create_webhooks(&server, &repos).await;
+
+    webhooks
+        .dispatch(&accept_event(&random_address(), &random_address()))
+        .await;
+
+    assert_eq!(server.received_requests().await.unwrap().len(), 1);
+
+    let failed = repos
+        .webhook_deliveries
+        .get_by_status(DeliveryStatus::Failed, 10)
+        .await
+        .unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].attempts, 1);
+}
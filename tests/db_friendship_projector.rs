@@ -0,0 +1,188 @@
+use social_service::{
+    components::database::DBRepositories,
+    db::friendship_projector::{rebuild_friendships_projection, ProjectionOptions},
+    domain::{address::Address, friendship_event::FriendshipEvent},
+    entities::friendships::Friendship,
+};
+use sqlx::types::Uuid;
+
+const USER_A: &str = "0x00000000000000000000000000000000000000aa";
+const USER_B: &str = "0x00000000000000000000000000000000000000bb";
+const USER_C: &str = "0x00000000000000000000000000000000000000cc";
+
+async fn create_friendship(
+    repos: &DBRepositories,
+    (user_1, user_2): (&str, &str),
+    is_active: bool,
+    history: &[(FriendshipEvent, &str)],
+) -> Uuid {
+    let (user_1, user_2) = (
+        Address::parse(user_1).unwrap(),
+        Address::parse(user_2).unwrap(),
+    );
+    let (id, _) = repos
+        .friendships
+        .create_new_friendships((&user_1, &user_2), is_active, "a_room_id", None)
+        .await;
+    let id = id.unwrap();
+
+    for (event, acting_user) in history {
+        let event = serde_json::to_string(event).unwrap();
+        let acting_user = Address::parse(acting_user).unwrap();
+        repos
+            .friendship_history
+            .create(id, &event, &acting_user, None, None)
+            .await
+            .0
+            .unwrap();
+    }
+
+    id
+}
+
+async fn get_friendship(repos: &DBRepositories, user_1: &str, user_2: &str) -> Friendship {
+    let (user_1, user_2) = (
+        Address::parse(user_1).unwrap(),
+        Address::parse(user_2).unwrap(),
+    );
+    let (friendship, _) = repos
+        .friendships
+        .get_friendship((&user_1, &user_2), None)
+        .await;
+
+    friendship.unwrap().unwrap()
+}
+
+fn options(apply: bool) -> ProjectionOptions {
+    ProjectionOptions {
+        batch_size: 1,
+        apply,
+    }
+}
+
+#[actix_web::test]
+async fn should_report_the_drifted_friendships_without_changing_them_on_a_dry_run() {
+    let repos = DBRepositories::in_memory();
+    create_friendship(
+        &repos,
+        (USER_A, USER_B),
+        true,
+        &[
+            (FriendshipEvent::REQUEST, USER_A),
+            (FriendshipEvent::ACCEPT, USER_B),
+        ],
+    )
+    .await;
+    let drifted = create_friendship(
+        &repos,
+        (USER_A, USER_C),
+        false,
+        &[
+            (FriendshipEvent::REQUEST, USER_C),
+            (FriendshipEvent::ACCEPT, USER_A),
+        ],
+    )
+    .await;
+
+    let report = rebuild_friendships_projection(&repos, &options(false))
+        .await
+        .unwrap();
+
+    assert_eq!(report.scanned, 2);
+    assert_eq!(report.drifted, 1);
+    assert_eq!(report.rebuilt, 0);
+    assert_eq!(report.friendships.len(), 1);
+    assert_eq!(report.friendships[0].friendship_id, drifted);
+    assert!(!get_friendship(&repos, USER_A, USER_C).await.is_active);
+}
+
+#[actix_web::test]
+async fn should_rebuild_the_drifted_friendships_in_apply_mode() {
+    let repos = DBRepositories::in_memory();
+    create_friendship(
+        &repos,
+        (USER_A, USER_B),
+        true,
+        &[
+            (FriendshipEvent::REQUEST, USER_A),
+            (FriendshipEvent::ACCEPT, USER_B),
+            (FriendshipEvent::DELETE, USER_A),
+        ],
+    )
+    .await;
+
+    let report = rebuild_friendships_projection(&repos, &options(true))
+        .await
+        .unwrap();
+
+    assert_eq!(report.drifted, 1);
+    assert_eq!(report.rebuilt, 1);
+    assert!(report.friendships[0].rebuilt);
+
+    let friendship = get_friendship(&repos, USER_A, USER_B).await;
+    assert!(!friendship.is_active);
+    assert!(friendship.friends_since.is_none());
+
+    let (last_history, _) = repos
+        .friendship_history
+        .get_last_history_for_friendship(friendship.id, None)
+        .await;
+    assert_eq!(
+        friendship.updated_at,
+        last_history.unwrap().unwrap().timestamp
+    );
+}
+
+#[actix_web::test]
+async fn should_report_the_invalid_sequences() {
+    let repos = DBRepositories::in_memory();
+    create_friendship(
+        &repos,
+        (USER_A, USER_B),
+        false,
+        &[
+            (FriendshipEvent::REQUEST, USER_A),
+            // The requester can't accept their own request
+            (FriendshipEvent::ACCEPT, USER_A),
+        ],
+    )
+    .await;
+
+    let report = rebuild_friendships_projection(&repos, &options(true))
+        .await
+        .unwrap();
+
+    assert_eq!(report.drifted, 0);
+    assert_eq!(report.friendships.len(), 1);
+    assert!(!report.friendships[0].drifted);
+    assert_eq!(report.friendships[0].invalid_events.len(), 1);
+    assert_eq!(report.friendships[0].invalid_events[0].acting_user, USER_A);
+}
+
+#[actix_web::test]
+async fn should_not_rebuild_the_friendships_with_invalid_events() {
+    let repos = DBRepositories::in_memory();
+    create_friendship(
+        &repos,
+        (USER_A, USER_B),
+        true,
+        &[
+            (FriendshipEvent::REQUEST, USER_A),
+            (FriendshipEvent::ACCEPT, USER_B),
+            // Only the requester can cancel a request
+            (FriendshipEvent::CANCEL, USER_B),
+            (FriendshipEvent::DELETE, USER_B),
+        ],
+    )
+    .await;
+
+    let report = rebuild_friendships_projection(&repos, &options(true))
+        .await
+        .unwrap();
+
+    assert_eq!(report.drifted, 1);
+    assert_eq!(report.rebuilt, 0);
+    assert!(!report.friendships[0].rebuilt);
+    assert_eq!(report.friendships[0].invalid_events.len(), 1);
+    assert!(get_friendship(&repos, USER_A, USER_B).await.is_active);
+}
@@ -1,16 +1,35 @@
 mod common;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
 
 pub use common::*;
 
 use actix_web::{
     test,
     web::{self, Data},
-    App, HttpMessage, HttpResponse,
+    App, HttpMessage, HttpRequest, HttpResponse,
 };
 use social_service::{
-    api::middlewares::check_auth::CheckAuthToken,
-    components::{app::AppComponents, users_cache::UserId},
+    api::{
+        app::{configure_routes, ROUTE_NAMES, ROUTE_POLICIES},
+        middlewares::{
+            bearer_token::ADMIN_ROUTES_PREFIX,
+            check_auth::{AuthScope, CheckAuthToken},
+        },
+    },
+    components::{
+        app::AppComponents,
+        synapse::{WhoAmIResponse, WHO_AM_I_URI},
+        users_cache::{get_user_id_from_token, UserId},
+    },
+    domain::error::CommonError,
+};
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
 };
 
 #[actix_web::test]
@@ -43,14 +62,12 @@ async fn should_not_call_synapse_when_token_available_in_redis() {
     let components = AppComponents::new(Some(config.clone())).await;
     components
         .users_cache
-        .lock()
-        .await
         .add_user(token, user_id, user_id, None)
         .await
         .expect("can add user");
 
     let app_data = Data::new(components);
-    let opts = vec!["/need-auth".to_string()];
+    let opts = [("/need-auth", AuthScope::User)];
     // unit app to unit test middleware
     let app = actix_web::test::init_service(
         App::new()
@@ -84,7 +101,7 @@ async fn should_not_call_synapse_when_token_available_in_redis() {
 #[actix_web::test]
 async fn should_call_synapse_when_token_not_available_in_redis_and_store_a_clean_user_id_into_redis(
 ) {
-    let user_id_synapse = "@0xb:decentraland.org";
+    let user_id_synapse = "@0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb:decentraland.org";
     let token = "a_random_token_";
 
     let mut token_to_user_id: HashMap<String, String> = HashMap::new();
@@ -99,7 +116,7 @@ async fn should_call_synapse_when_token_not_available_in_redis_and_store_a_clean
     let header = ("authorization", format!("Bearer {token}"));
 
     let req = actix_web::test::TestRequest::get()
-        .uri("/v1/friendships/0xb")
+        .uri("/v1/friendships/0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb")
         .insert_header(header)
         .to_request();
 
@@ -111,3 +128,224 @@ async fn should_call_synapse_when_token_not_available_in_redis_and_store_a_clean
     // Check that the id sent to synapse has the matrix format
     assert_eq!(ctx_user_id.unwrap().synapse_id, user_id_synapse)
 }
+
+#[actix_web::test]
+async fn should_call_synapse_once_for_concurrent_requests_with_the_same_token() {
+    let user_id_synapse = "@0xcccccccccccccccccccccccccccccccccccccccc:decentraland.org";
+    // Random token so a user cached by a previous run doesn't skip the call
+    let token = format!("a_concurrent_token_{}", uuid::Uuid::new_v4());
+
+    let synapse_server = create_synapse_mock_server().await;
+    Mock::given(method("GET"))
+        .and(path(WHO_AM_I_URI))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(WhoAmIResponse {
+                    user_id: user_id_synapse.to_string(),
+                    social_user_id: None,
+                    device_id: None,
+                })
+                .set_delay(Duration::from_millis(200)),
+        )
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = synapse_server.uri();
+    let components = Arc::new(AppComponents::new(Some(config)).await);
+
+    let lookups = (0..5).map(|_| {
+        let components = components.clone();
+        let token = token.clone();
+        tokio::spawn(async move {
+            get_user_id_from_token(
+                components.synapse.clone(),
+                components.users_cache.clone(),
+                &token,
+            )
+            .await
+        })
+    });
+
+    for lookup in futures_util::future::join_all(lookups).await {
+        assert_eq!(lookup.unwrap().unwrap().synapse_id, user_id_synapse);
+    }
+
+    synapse_server.verify().await;
+}
+
+#[actix_web::test]
+async fn should_not_call_synapse_again_for_a_recently_rejected_token() {
+    let token = format!("a_rejected_token_{}", uuid::Uuid::new_v4());
+
+    let synapse_server = create_synapse_mock_server().await;
+    Mock::given(method("GET"))
+        .and(path(WHO_AM_I_URI))
+        .respond_with(
+            ResponseTemplate::new(401)
+                .set_body_json(HashMap::from([("errcode", "M_UNKNOWN_TOKEN")])),
+        )
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = synapse_server.uri();
+    let components = AppComponents::new(Some(config)).await;
+
+    for _ in 0..2 {
+        let user_id = get_user_id_from_token(
+            components.synapse.clone(),
+            components.users_cache.clone(),
+            &token,
+        )
+        .await;
+        assert!(matches!(user_id, Err(CommonError::Unauthorized(_))));
+    }
+
+    synapse_server.verify().await;
+}
+
+#[actix_web::test]
+async fn should_verify_session_tokens_without_calling_synapse() {
+    let user_id = "0xdddddddddddddddddddddddddddddddddddddddd";
+
+    let synapse_server = mock_server_expect_no_calls().await;
+    let mut config = get_configuration().await;
+    config.synapse.url = synapse_server.uri();
+    config.session_signing_keys = "a-key:a-secret".to_string();
+
+    let components = AppComponents::new(Some(config)).await;
+    let session = components
+        .session_tokens
+        .as_ref()
+        .expect("session tokens to be enabled")
+        .issue(
+            &UserId {
+                social_id: user_id.to_string(),
+                synapse_id: format!("@{user_id}:decentraland.org"),
+            },
+            "A_DEVICE",
+        );
+
+    let app_data = Data::new(components);
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(app_data)
+            .wrap(CheckAuthToken::new([("/need-auth", AuthScope::User)]))
+            .route("/need-auth", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::get()
+        .uri("/need-auth")
+        .insert_header(("authorization", format!("Bearer {}", session.access_token)))
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+
+    let ctx_user_id = resp
+        .request()
+        .extensions()
+        .get::<UserId>()
+        .map(|u| u.social_id.clone());
+    assert_eq!(resp.status(), 200);
+    assert_eq!(ctx_user_id.unwrap(), user_id);
+
+    synapse_server.verify().await;
+}
+
+#[actix_web::test]
+async fn should_require_a_token_on_v1_routes_without_a_policy() {
+    let synapse_server = mock_server_expect_no_calls().await;
+    let mut config = get_configuration().await;
+    config.synapse.url = synapse_server.uri();
+
+    let app_data = Data::new(AppComponents::new(Some(config)).await);
+    let app = actix_web::test::init_service(
+        App::new()
+            .app_data(app_data)
+            .wrap(CheckAuthToken::new([("/public", AuthScope::Public)]))
+            .route("/public", web::get().to(HttpResponse::Ok))
+            .route("/v1/forgotten", web::get().to(HttpResponse::Ok)),
+    )
+    .await;
+
+    let req = actix_web::test::TestRequest::get()
+        .uri("/public")
+        .to_request();
+    let resp = actix_web::test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    // A malformed header is rejected as a missing token
+    for header in [None, Some("Bearer"), Some("Bearer ")] {
+        let mut req = actix_web::test::TestRequest::get().uri("/v1/forgotten");
+        if let Some(header) = header {
+            req = req.insert_header(("authorization", header));
+        }
+        let resp = actix_web::test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 400);
+    }
+}
+
+/// Patterns of the routes registered in the router. Actix doesn't list them, so each one is
+/// resolved from its name on a router without components.
+async fn registered_routes() -> HashSet<String> {
+    let app = test::init_service(
+        App::new()
+            .configure(configure_routes)
+            .default_service(web::to(|req: HttpRequest| async move {
+                let routes: Vec<String> = ROUTE_NAMES
+                    .iter()
+                    .map(|name| {
+                        // Extra values are ignored, so any route can be built with them
+                        req.url_for(name, ["0x1", "0x2", "0x3"])
+                            .ok()
+                            .and_then(|url| req.resource_map().match_pattern(url.path()))
+                            .unwrap_or_else(|| format!("unresolved route {name}"))
+                    })
+                    .collect();
+                HttpResponse::Ok().json(routes)
+            })),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri("/unknown-route").to_request();
+    let routes: Vec<String> = test::call_and_read_body_json(&app, req).await;
+
+    routes.into_iter().collect()
+}
+
+#[actix_web::test]
+async fn test_every_route_has_an_auth_policy() {
+    let routes = registered_routes().await;
+    assert!(!routes.is_empty(), "No routes were read from the router");
+
+    let policies: HashMap<&str, AuthScope> = ROUTE_POLICIES.into_iter().collect();
+    assert_eq!(policies.len(), ROUTE_POLICIES.len(), "Duplicated policies");
+
+    let missing: Vec<_> = routes
+        .iter()
+        .filter(|route| !policies.contains_key(route.as_str()))
+        .collect();
+    assert!(missing.is_empty(), "Routes without a policy: {missing:?}");
+
+    let unknown: Vec<_> = policies
+        .keys()
+        .filter(|route| !routes.contains(**route))
+        .collect();
+    assert!(
+        unknown.is_empty(),
+        "Policies of unknown routes: {unknown:?}"
+    );
+
+    // The admin token is only checked under the prefix, an admin route outside of it would be open
+    for route in &routes {
+        let scope = policies[route.as_str()];
+        assert_eq!(
+            route.starts_with(ADMIN_ROUTES_PREFIX),
+            scope == AuthScope::Admin,
+            "Route {route} has the {scope:?} scope"
+        );
+    }
+}
@@ -13,7 +13,10 @@ use social_service::{
     api::app::get_app_router,
     components::{
         app::AppComponents,
-        synapse::{AuthChain, LoginIdentifier, SynapseLoginRequest, SynapseLoginResponse},
+        synapse::{
+            AuthChain, LoginIdentifier, SynapseLoginRequest, SynapseLoginResponse, WhoAmIResponse,
+            LOGOUT_URI, WHO_AM_I_URI,
+        },
         users_cache::UserId,
     },
 };
@@ -94,8 +97,7 @@ async fn should_be_200_and_has_user_in_cache() {
     assert!(response.status().is_success());
 
     // Test if all happenned correctly
-    let mut users_cache = app_data.users_cache.lock().await;
-    let user = users_cache.get_user("0xA1_TOKEN").await;
+    let user = app_data.users_cache.get_user("0xA1_TOKEN").await;
     assert!(user.is_ok());
     let user = user.unwrap();
     assert_eq!(
@@ -164,8 +166,101 @@ async fn should_be_500_and_not_user_in_cache() {
     let response = test::call_service(&app, req).await;
 
     // Test if all happenned correctly
-    let mut users_cache = app_data.users_cache.lock().await;
-    let user = users_cache.get_user("0xB1_TOKEN").await;
+    let user = app_data.users_cache.get_user("0xB1_TOKEN").await;
     assert!(user.is_err());
     assert!(response.status().is_server_error())
 }
+
+#[actix_web::test]
+async fn should_logout_and_drop_the_token_from_the_cache() {
+    let token = "0xC1_TOKEN";
+    let synapse_server = create_synapse_mock_server().await;
+
+    Mock::given(method("POST"))
+        .and(path(LOGOUT_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(HashMap::<String, String>::new()))
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = synapse_server.uri();
+
+    let app_data = Data::new(AppComponents::new(Some(config)).await);
+    app_data
+        .users_cache
+        .add_user(token, "0xC1", "0xC1", None)
+        .await
+        .unwrap();
+
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let req = test::TestRequest::post()
+        .uri(LOGOUT_URI)
+        .insert_header(("authorization", format!("Bearer {token}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert!(response.status().is_success());
+    assert!(app_data.users_cache.get_user(token).await.is_err());
+}
+
+#[actix_web::test]
+async fn should_revoke_the_session_of_the_device_on_logout() {
+    let token = "0xC2_TOKEN";
+    let social_id = format!("0x{}00000000", uuid::Uuid::new_v4().simple());
+    let synapse_id = format!("@{social_id}:decentraland.org");
+    let synapse_server = create_synapse_mock_server().await;
+
+    Mock::given(method("GET"))
+        .and(path(WHO_AM_I_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(WhoAmIResponse {
+            user_id: synapse_id.clone(),
+            social_user_id: None,
+            device_id: Some("A_DEVICE".to_string()),
+        }))
+        .mount(&synapse_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path(LOGOUT_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(HashMap::<String, String>::new()))
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = synapse_server.uri();
+    config.session_signing_keys = "a-key:a-secret".to_string();
+
+    let app_data = Data::new(AppComponents::new(Some(config)).await);
+    let session_tokens = app_data
+        .session_tokens
+        .clone()
+        .expect("session tokens to be enabled");
+    let user_id = UserId {
+        social_id,
+        synapse_id,
+    };
+    let session = session_tokens.issue(&user_id, "A_DEVICE");
+    let other_session = session_tokens.issue(&user_id, "ANOTHER_DEVICE");
+
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let req = test::TestRequest::post()
+        .uri(LOGOUT_URI)
+        .insert_header(("authorization", format!("Bearer {token}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+
+    assert!(response.status().is_success());
+    assert!(session_tokens
+        .refresh(&session.refresh_token)
+        .await
+        .is_err());
+    assert!(session_tokens
+        .refresh(&other_session.refresh_token)
+        .await
+        .is_ok());
+}
@@ -0,0 +1,411 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use actix_http::StatusCode;
+use actix_web::{test, web::Data};
+use dcl_http_prom_metrics::HttpMetricsCollectorBuilder;
+use social_service::{
+    api::{
+        app::get_app_router,
+        routes::admin::{
+            events::ADMIN_ACTING_USER,
+            types::{
+                AdminFriendshipsResponse, AdminRequestsResponse, AdminWebhookDeliveriesResponse,
+                AdminWebhookDelivery, PurgeUserResponse, RevokeUserTokensResponse,
+            },
+        },
+    },
+    components::{
+        app::AppComponents,
+        notifications::{
+            init_events_channel_subscriber, ChannelSubscriber, EVENT_UPDATES_CHANNEL_NAME,
+        },
+        redis::Redis,
+    },
+    domain::{address::Address, friendship_event::FriendshipEvent},
+    entities::webhook_deliveries::{DeliveryStatus, NewWebhookDelivery},
+    friendships::{friendship_event_response::Body, AcceptResponse, FriendshipEventResponse, User},
+    notifications::Event,
+};
+
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::v1::friendships::utils::add_friendship;
+use crate::common::*;
+
+const ADMIN_TOKEN: &str = "admin-token";
+
+async fn get_admin_app_data() -> Data<AppComponents> {
+    let mut config = get_configuration().await;
+    config.admin_bearer_token = ADMIN_TOKEN.to_string();
+
+    Data::new(AppComponents::new(Some(config)).await)
+}
+
+/// Collects the events published to the friendship events channel that are sent to `to`.
+async fn subscribe_to_events(to: &str) -> Arc<Mutex<Vec<Event>>> {
+    let config = get_configuration().await;
+    let redis = Redis::new_and_run(&config.redis)
+        .await
+        .expect("There was an error initializing Redis");
+    let subscriber = init_events_channel_subscriber(Arc::new(redis));
+
+    let events = Arc::new(Mutex::new(vec![]));
+    let events_clone = events.clone();
+    let to = to.to_string();
+    subscriber.subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event: Event| {
+        let events = events_clone.clone();
+        let to = to.clone();
+        async move {
+            if event.to == to {
+                events.lock().unwrap().push(event);
+            }
+        }
+    });
+
+    // Give some time to the subscription to be ready
+    actix_rt::time::sleep(Duration::from_millis(500)).await;
+
+    events
+}
+
+#[actix_web::test]
+async fn test_admin_routes_should_fail_401_without_a_valid_token() {
+    let app_data = get_admin_app_data().await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let uri = "/admin/v1/users/0xabcd000000000000000000000000000000000001/friendships";
+
+    let req = test::TestRequest::get().uri(uri).to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri(uri)
+        .append_header(("authorization", "Bearer not-the-admin-token"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_admin_should_list_user_friendships_and_pending_requests() {
+    let user = "0xabcd000000000000000000000000000000000001";
+    let friend = "0xabcd000000000000000000000000000000000002";
+    let requester = "0xabcd000000000000000000000000000000000003";
+
+    let app_data = get_admin_app_data().await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    add_friendship(&app_data.db, (user, friend), true).await;
+    let request_id = add_friendship(&app_data.db, (user, requester), false).await;
+    app_data
+        .db
+        .get_repos()
+        .as_ref()
+        .unwrap()
+        .friendship_history
+        .create(
+            request_id,
+            &serde_json::to_string(&FriendshipEvent::REQUEST).unwrap(),
+            &Address::parse(requester).unwrap(),
+            None,
+            None,
+        )
+        .await
+        .0
+        .unwrap();
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/v1/users/{user}/friendships"))
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let friendships: AdminFriendshipsResponse = test::read_body_json(response).await;
+    assert_eq!(friendships.friendships.len(), 2);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/admin/v1/users/{user}/requests"))
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let requests: AdminRequestsResponse = test::read_body_json(response).await;
+    assert!(requests.outgoing.is_empty());
+    assert_eq!(requests.incoming.len(), 1);
+    assert_eq!(requests.incoming[0].from, requester);
+    assert_eq!(requests.incoming[0].to, user);
+}
+
+#[actix_web::test]
+async fn test_admin_should_force_delete_a_friendship() {
+    let user = "0xabcd000000000000000000000000000000000011";
+    let friend = "0xabcd000000000000000000000000000000000012";
+
+    let app_data = get_admin_app_data().await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let friendship_id = add_friendship(&app_data.db, (user, friend), true).await;
+    let user_events = subscribe_to_events(user).await;
+    let friend_events = subscribe_to_events(friend).await;
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/admin/v1/friendships/{user}/{friend}"))
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let repos = app_data.db.get_repos().as_ref().unwrap();
+    let friendship = repos
+        .friendships
+        .get_friendship(
+            (
+                &Address::parse(user).unwrap(),
+                &Address::parse(friend).unwrap(),
+            ),
+            None,
+        )
+        .await
+        .0
+        .unwrap()
+        .unwrap();
+    assert!(!friendship.is_active);
+
+    let last_history = repos
+        .friendship_history
+        .get_last_history_for_friendship(friendship_id, None)
+        .await
+        .0
+        .unwrap()
+        .unwrap();
+    assert_eq!(last_history.event, FriendshipEvent::DELETE);
+    assert_eq!(last_history.acting_user, ADMIN_ACTING_USER);
+    assert_eq!(last_history.metadata.unwrap().forced_by_admin, Some(true));
+
+    actix_rt::time::sleep(Duration::from_millis(500)).await;
+    let friend_events = friend_events.lock().unwrap();
+    assert_eq!(friend_events.len(), 1);
+    assert_eq!(friend_events[0].from, user);
+    assert!(matches!(
+        friend_events[0].friendship_event.as_ref().unwrap().body,
+        Some(Body::Delete(_))
+    ));
+    // A single event is published, the subscriptions of the user receive it mirrored
+    assert!(friend_events[0].notify_from);
+    assert!(user_events.lock().unwrap().is_empty());
+
+    // There's nothing left to remove
+    let req = test::TestRequest::delete()
+        .uri(&format!("/admin/v1/friendships/{user}/{friend}"))
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_admin_should_purge_a_user() {
+    let banned = "0xabcd000000000000000000000000000000000021";
+    let friend = "0xabcd000000000000000000000000000000000022";
+    let other_friend = "0xabcd000000000000000000000000000000000023";
+
+    let app_data = get_admin_app_data().await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    add_friendship(&app_data.db, (banned, friend), true).await;
+    add_friendship(&app_data.db, (banned, other_friend), false).await;
+    add_friendship(&app_data.db, (friend, other_friend), true).await;
+    let friend_events = subscribe_to_events(friend).await;
+
+    let repos = app_data.db.get_repos().as_ref().unwrap();
+    let banned_payload =
+        format!(r#"{{"event":"accept","from":"{friend}","to":"{banned}","timestamp":1}}"#);
+    let banned_delivery = repos
+        .webhook_deliveries
+        .create(NewWebhookDelivery {
+            event_id: &uuid::Uuid::new_v4().to_string(),
+            url: "http://webhook.test",
+            event: "accept",
+            from_address: friend,
+            to_address: banned,
+            payload: &banned_payload,
+            signature: "a-signature",
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    let other_payload =
+        format!(r#"{{"event":"accept","from":"{friend}","to":"{other_friend}","timestamp":1}}"#);
+    let other_delivery = repos
+        .webhook_deliveries
+        .create(NewWebhookDelivery {
+            event_id: &uuid::Uuid::new_v4().to_string(),
+            url: "http://webhook.test",
+            event: "accept",
+            from_address: friend,
+            to_address: other_friend,
+            payload: &other_payload,
+            signature: "a-signature",
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/admin/v1/users/{banned}"))
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let purge: PurgeUserResponse = test::read_body_json(response).await;
+    assert_eq!(purge.friendships_removed, 2);
+    assert_eq!(purge.events_published, 1);
+
+    let (banned_friendships, _) = repos
+        .friendships
+        .get_user_friends(&Address::parse(banned).unwrap(), false, None, None)
+        .await;
+    assert!(banned_friendships.unwrap().is_empty());
+
+    // Other friendships are kept
+    let (friend_friendships, _) = repos
+        .friendships
+        .get_user_friends(&Address::parse(friend).unwrap(), true, None, None)
+        .await;
+    assert_eq!(friend_friendships.unwrap().len(), 1);
+
+    // Only the webhook deliveries of the purged user are removed
+    let deliveries = &repos.webhook_deliveries;
+    assert!(deliveries.get(banned_delivery).await.unwrap().is_none());
+    assert!(deliveries.get(other_delivery).await.unwrap().is_some());
+
+    actix_rt::time::sleep(Duration::from_millis(500)).await;
+    let friend_events = friend_events.lock().unwrap();
+    assert_eq!(friend_events.len(), 1);
+    assert_eq!(friend_events[0].from, banned);
+}
+
+#[actix_web::test]
+async fn test_admin_should_revoke_the_cached_tokens_of_a_user() {
+    let user = "0xabcd000000000000000000000000000000000031";
+    let tokens = ["revoked_token_1", "revoked_token_2"];
+
+    let app_data = get_admin_app_data().await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    for token in tokens {
+        app_data
+            .users_cache
+            .add_user(token, user, user, None)
+            .await
+            .unwrap();
+    }
+
+    let req = test::TestRequest::delete()
+        .uri(&format!("/admin/v1/users/{user}/tokens"))
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let revoke: RevokeUserTokensResponse = test::read_body_json(response).await;
+    assert_eq!(revoke.tokens_revoked, 2);
+
+    for token in tokens {
+        assert!(app_data.users_cache.get_user(token).await.is_err());
+    }
+}
+
+#[actix_web::test]
+async fn test_admin_should_list_and_replay_failed_webhook_deliveries() {
+    let user = "0xabcd000000000000000000000000000000000041";
+    let friend = "0xabcd000000000000000000000000000000000042";
+
+    let server = MockServer::start().await;
+    Mock::given(method("POST"))
+        .and(path("/events"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    let mut config = get_configuration().await;
+    config.admin_bearer_token = ADMIN_TOKEN.to_string();
+    config.webhooks_urls = format!("{}/events", server.uri());
+    config.webhooks_signing_key = "a-signing-key".to_string();
+    config.webhooks_max_attempts = 1;
+    let app_data = Data::new(AppComponents::new(Some(config)).await);
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    app_data
+        .webhooks
+        .as_ref()
+        .unwrap()
+        .dispatch(&Event {
+            friendship_event: Some(FriendshipEventResponse {
+                body: Some(Body::Accept(AcceptResponse {
+                    user: Some(User {
+                        address: user.to_string(),
+                        ..Default::default()
+                    }),
+                })),
+            }),
+            from: user.to_string(),
+            to: friend.to_string(),
+            id: uuid::Uuid::new_v4().to_string(),
+            origin: social_service::instance_id().to_string(),
+            notify_from: false,
+        })
+        .await;
+
+    let req = test::TestRequest::get()
+        .uri("/admin/v1/webhooks/deliveries?status=failed")
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response: AdminWebhookDeliveriesResponse = test::read_body_json(response).await;
+    let failed = response
+        .deliveries
+        .into_iter()
+        .find(|delivery| delivery.payload.contains(friend))
+        .expect("the failed delivery to be listed");
+    assert_eq!(failed.attempts, 1);
+
+    server.reset().await;
+    Mock::given(method("POST"))
+        .and(path("/events"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&server)
+        .await;
+
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/admin/v1/webhooks/deliveries/{}/replay",
+            failed.id
+        ))
+        .append_header(("authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let replayed: AdminWebhookDelivery = test::read_body_json(response).await;
+    assert_eq!(replayed.status, DeliveryStatus::Delivered);
+    assert_eq!(replayed.attempts, 2);
+}
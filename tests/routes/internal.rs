@@ -0,0 +1,154 @@
+use actix_http::StatusCode;
+use actix_web::{test, web::Data};
+use dcl_http_prom_metrics::HttpMetricsCollectorBuilder;
+use social_service::{
+    api::{
+        app::get_app_router,
+        routes::internal::types::{
+            AddressPair, AreFriendsRequest, AreFriendsResponse, MutualFriendsCountResponse,
+            ServiceFriendsResponse,
+        },
+    },
+    components::app::AppComponents,
+};
+
+use super::v1::friendships::utils::add_friendship;
+use crate::common::*;
+
+const SERVICE_KEY: &str = "a-service-key";
+
+async fn get_internal_app_data(requests_per_minute: u64) -> Data<AppComponents> {
+    let mut config = get_configuration().await;
+    config.service_keys = format!("comms:{SERVICE_KEY}");
+    config.service_requests_per_minute = requests_per_minute;
+
+    Data::new(AppComponents::new(Some(config)).await)
+}
+
+#[actix_web::test]
+async fn test_internal_routes_should_fail_401_without_a_valid_service_key() {
+    let app_data = get_internal_app_data(100).await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let uri = "/internal/v1/users/0xabcd000000000000000000000000000000000101/friends";
+
+    let req = test::TestRequest::get().uri(uri).to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri(uri)
+        .append_header(("authorization", "Bearer not-a-service-key"))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_internal_routes_should_answer_friendship_queries() {
+    let user = "0xabcd000000000000000000000000000000000111";
+    let friend = "0xabcd000000000000000000000000000000000112";
+    let other = "0xabcd000000000000000000000000000000000113";
+    let stranger = "0xabcd000000000000000000000000000000000114";
+
+    let app_data = get_internal_app_data(100).await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    add_friendship(&app_data.db, (user, friend), true).await;
+    add_friendship(&app_data.db, (other, friend), true).await;
+    add_friendship(&app_data.db, (user, stranger), false).await;
+
+    let req = test::TestRequest::post()
+        .uri("/internal/v1/friendships/check")
+        .append_header(("authorization", format!("Bearer {SERVICE_KEY}")))
+        .set_json(AreFriendsRequest {
+            pairs: vec![
+                AddressPair {
+                    address_1: user.to_string(),
+                    address_2: friend.to_string(),
+                },
+                AddressPair {
+                    address_1: user.to_string(),
+                    address_2: stranger.to_string(),
+                },
+                AddressPair {
+                    address_1: user.to_string(),
+                    address_2: other.to_string(),
+                },
+            ],
+        })
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response: AreFriendsResponse = test::read_body_json(response).await;
+    let are_friends: Vec<bool> = response
+        .results
+        .iter()
+        .map(|result| result.are_friends)
+        .collect();
+    assert_eq!(are_friends, vec![true, false, false]);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/internal/v1/users/{user}/friends"))
+        .append_header(("authorization", format!("Bearer {SERVICE_KEY}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response: ServiceFriendsResponse = test::read_body_json(response).await;
+    assert_eq!(response.friends, vec![friend.to_string()]);
+
+    let req = test::TestRequest::get()
+        .uri(&format!("/internal/v1/users/{user}/mutuals/{other}/count"))
+        .append_header(("authorization", format!("Bearer {SERVICE_KEY}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response: MutualFriendsCountResponse = test::read_body_json(response).await;
+    assert_eq!(response.count, 1);
+}
+
+#[actix_web::test]
+async fn test_internal_routes_should_reject_too_many_pairs() {
+    let app_data = get_internal_app_data(100).await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let pairs = (0..101)
+        .map(|i| AddressPair {
+            address_1: format!("0xabcd{i:036}"),
+            address_2: "0xabcd000000000000000000000000000000000121".to_string(),
+        })
+        .collect();
+
+    let req = test::TestRequest::post()
+        .uri("/internal/v1/friendships/check")
+        .append_header(("authorization", format!("Bearer {SERVICE_KEY}")))
+        .set_json(AreFriendsRequest { pairs })
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_internal_routes_should_rate_limit_each_service() {
+    let app_data = get_internal_app_data(1).await;
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let uri = "/internal/v1/users/0xabcd000000000000000000000000000000000131/friends";
+    let mut statuses = vec![];
+    for _ in 0..3 {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .append_header(("authorization", format!("Bearer {SERVICE_KEY}")))
+            .to_request();
+        statuses.push(test::call_service(&app, req).await.status());
+    }
+
+    assert!(statuses.contains(&StatusCode::TOO_MANY_REQUESTS));
+}
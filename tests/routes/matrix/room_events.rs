@@ -10,11 +10,13 @@ mod tests {
     use social_service::{
         api::routes::synapse::room_events::{RoomEventRequestBody, RoomEventResponse},
         components::{
+            app::AppComponents,
+            configuration::Config,
             database::DBRepositories,
             synapse::{RoomMember, RoomMembersResponse},
         },
-        domain::friendship_event::FriendshipEvent,
-        entities::friendships::{Friendship, FriendshipRepositoryImplementation},
+        domain::{address::Address, friendship_event::FriendshipEvent},
+        entities::friendships::Friendship,
     };
     use uuid::Uuid;
     use wiremock::{
@@ -42,6 +44,7 @@ mod tests {
                     state_key: room_members.0,
                     social_user_id: None,
                     user_id: "".to_string(),
+                    content: None,
                 },
                 RoomMember {
                     room_id: "a_room_id".to_string(),
@@ -49,6 +52,7 @@ mod tests {
                     state_key: room_members.1,
                     social_user_id: None,
                     user_id: "".to_string(),
+                    content: None,
                 },
             ],
         };
@@ -88,13 +92,13 @@ mod tests {
 
     // TODO!: Implement a function that returns tokens to prevent collision between tests
     const USER_A: TestUser = TestUser {
-        user_id: "@LALA",
-        social_user_id: "LALA",
+        user_id: "@0xAbCdEf0000000000000000000000000000000001",
+        social_user_id: "0xAbCdEf0000000000000000000000000000000001",
         token: "LALA-1",
     };
     const USER_B: TestUser = TestUser {
-        user_id: "@LELE",
-        social_user_id: "LELE",
+        user_id: "@0x1234560000000000000000000000000000000002",
+        social_user_id: "0x1234560000000000000000000000000000000002",
         token: "LELE-2",
     };
 
@@ -321,6 +325,65 @@ mod tests {
         .await;
     }
 
+    #[actix_web::test]
+    async fn test_friendship_lifecycle_request_accept_delete_in_memory() {
+        let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+        token_to_user_id.insert(USER_A.token.to_string(), USER_A.user_id.to_string());
+        token_to_user_id.insert(USER_B.token.to_string(), USER_B.user_id.to_string());
+
+        let synapse_server = get_synapse_mocked_server_with_room(
+            token_to_user_id,
+            (USER_A.user_id.to_string(), USER_B.user_id.to_string()),
+        )
+        .await;
+
+        // No test database is created, the repositories live in memory
+        let mut config = Config::new().expect("Couldn't read the configuration file");
+        config.synapse.url = synapse_server.uri();
+        let components = AppComponents::new_in_memory(Some(config.clone())).await;
+        let repos = components.db.get_repos().clone().unwrap();
+
+        let app = actix_web::test::init_service(get_app(config, Some(components)).await).await;
+
+        // user A request user B
+        let req = get_request(USER_A.token, FriendshipEvent::REQUEST, None);
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        // user B accept user A
+        let req = get_request(USER_B.token, FriendshipEvent::ACCEPT, None);
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        assert_and_get_friendship_from_db(
+            &repos,
+            (USER_A.social_user_id, USER_B.social_user_id),
+            true,
+        )
+        .await;
+
+        // user A delete user B
+        let req = get_request(USER_A.token, FriendshipEvent::DELETE, None);
+        let resp = actix_web::test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+
+        let result = assert_and_get_friendship_from_db(
+            &repos,
+            (USER_A.social_user_id, USER_B.social_user_id),
+            false,
+        )
+        .await;
+
+        assert_last_history_from_db(
+            &repos,
+            result.id,
+            USER_A.social_user_id,
+            FriendshipEvent::DELETE,
+            None,
+        )
+        .await;
+    }
+
     #[actix_web::test]
     async fn test_friendship_lifecycle_request_request_should_400() {
         let mut token_to_user_id: HashMap<String, String> = HashMap::new();
@@ -417,9 +480,13 @@ mod tests {
         addresses: (&str, &str),
         is_active: bool,
     ) -> Friendship {
+        let addresses = (
+            Address::parse(addresses.0).unwrap(),
+            Address::parse(addresses.1).unwrap(),
+        );
         let result = repos
             .friendships
-            .get_friendship(addresses, None)
+            .get_friendship((&addresses.0, &addresses.1), None)
             .await
             .0
             .unwrap()
@@ -445,7 +512,10 @@ mod tests {
             .unwrap()
             .unwrap();
 
-        assert_eq!(result.acting_user, expected_acting_user);
+        assert_eq!(
+            result.acting_user,
+            Address::parse(expected_acting_user).unwrap().as_str()
+        );
         assert_eq!(result.event, event_type);
         let message_body = result
             .metadata
@@ -1,2 +1,4 @@
+pub mod admin;
+pub mod internal;
 pub mod matrix;
 pub mod v1;
@@ -0,0 +1,221 @@
+use std::collections::HashMap;
+
+use actix_http::StatusCode;
+use actix_web::{test, web::Data};
+use dcl_http_prom_metrics::HttpMetricsCollectorBuilder;
+use serde_json::json;
+use social_service::{
+    api::{
+        app::get_app_router,
+        routes::v1::friendships::types::{
+            BulkFriendshipUpdateRequest, BulkFriendshipUpdateResponse,
+        },
+    },
+    components::{app::AppComponents, configuration::EventsChannelBackend},
+    domain::address::Address,
+    ws::{
+        app::{init_ws_components, SocialContext},
+        service::bulk_friendship_updates::BulkFriendshipAction,
+    },
+};
+use wiremock::{
+    matchers::{method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+use super::utils::add_friendship;
+use crate::common::*;
+
+/// Mounts the Synapse calls of accepting the requests, failing to store the event in `failing_room`.
+async fn mount_accept_mocks(server: &MockServer, rooms: &[String], failing_room: &str) {
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/joined_rooms$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "joined_rooms": rooms })))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(server)
+        .await;
+    // The first mounted mock is the one answering when both match
+    Mock::given(method("PUT"))
+        .and(path_regex(format!(
+            r"^/_matrix/client/r0/rooms/{failing_room}/(state|send)/.+"
+        )))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.+/(state|send)/.+"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "an_event" })))
+        .mount(server)
+        .await;
+}
+
+#[actix_web::test]
+async fn test_bulk_accept_stores_each_update_on_its_own() {
+    let user = "0xabcd000000000000000000000000000000000051";
+    let requesters = [
+        "0xabcd000000000000000000000000000000000052",
+        "0xabcd000000000000000000000000000000000053",
+        "0xabcd000000000000000000000000000000000054",
+    ];
+    let token = "bulk-accept-token";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    token_to_user_id.insert(token.to_string(), user.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let rooms: Vec<String> = requesters
+        .iter()
+        .map(|requester| format!("room_id_{requester}_{user}"))
+        .collect();
+    // The update with the second requester fails after the previous one was stored
+    mount_accept_mocks(&mock_server, &rooms, &rooms[1]).await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+    config.events_channel = EventsChannelBackend::InProcess;
+
+    let app_data = Data::new(AppComponents::new(Some(config.clone())).await);
+    let ws_components = init_ws_components(config, &app_data.db).await;
+    let social_context = Data::new(SocialContext::new(&app_data, ws_components));
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(
+        get_app_router(&app_data, &http_metrics_collector).app_data(social_context),
+    )
+    .await;
+
+    let repos = app_data.db.get_repos().as_ref().unwrap();
+    for requester in requesters {
+        let request_id = add_friendship(&app_data.db, (requester, user), false).await;
+        repos
+            .friendship_history
+            .create(
+                request_id,
+                "\"request\"",
+                &Address::parse(requester).unwrap(),
+                None,
+                None,
+            )
+            .await
+            .0
+            .unwrap();
+    }
+
+    let req = test::TestRequest::post()
+        .uri("/v1/friendships/bulk")
+        .append_header(("authorization", format!("Bearer {token}")))
+        .set_json(BulkFriendshipUpdateRequest {
+            action: BulkFriendshipAction::AcceptAll,
+            users: Some(requesters.map(str::to_string).to_vec()),
+        })
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response: BulkFriendshipUpdateResponse = test::read_body_json(response).await;
+    let users: Vec<&str> = response
+        .results
+        .iter()
+        .map(|result| result.user.as_str())
+        .collect();
+    assert_eq!(users, requesters.to_vec());
+    assert!(response.results[0].error.is_none());
+    assert!(response.results[1].error.is_some());
+    assert!(response.results[2].error.is_none());
+
+    let user = Address::parse(user).unwrap();
+    for (requester, is_active) in requesters.iter().zip([true, false, true]) {
+        let (friendship, _) = repos
+            .friendships
+            .get_friendship((&user, &Address::parse(requester).unwrap()), None)
+            .await;
+        assert_eq!(friendship.unwrap().unwrap().is_active, is_active);
+    }
+
+    // The failed update was rolled back, so the request is still pending
+    let (pending, _) = repos
+        .friendship_history
+        .get_user_pending_request_events(&user, None)
+        .await;
+    let pending = pending.unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].acting_user, requesters[1]);
+}
+
+#[actix_web::test]
+async fn test_bulk_update_reports_the_invalid_users() {
+    let user = "0xabcd000000000000000000000000000000000061";
+    let requester = "0xABCD000000000000000000000000000000000062";
+    let token = "bulk-invalid-users-token";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    token_to_user_id.insert(token.to_string(), user.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let requester_address = Address::parse(requester).unwrap();
+    let room = format!("room_id_{requester_address}_{user}");
+    mount_accept_mocks(&mock_server, &[room], "no_room").await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+    config.events_channel = EventsChannelBackend::InProcess;
+
+    let app_data = Data::new(AppComponents::new(Some(config.clone())).await);
+    let ws_components = init_ws_components(config, &app_data.db).await;
+    let social_context = Data::new(SocialContext::new(&app_data, ws_components));
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(
+        get_app_router(&app_data, &http_metrics_collector).app_data(social_context),
+    )
+    .await;
+
+    let request_id = add_friendship(&app_data.db, (requester_address.as_str(), user), false).await;
+    app_data
+        .db
+        .get_repos()
+        .as_ref()
+        .unwrap()
+        .friendship_history
+        .create(request_id, "\"request\"", &requester_address, None, None)
+        .await
+        .0
+        .unwrap();
+
+    // The same user in another case is only updated once
+    let req = test::TestRequest::post()
+        .uri("/v1/friendships/bulk")
+        .append_header(("authorization", format!("Bearer {token}")))
+        .set_json(BulkFriendshipUpdateRequest {
+            action: BulkFriendshipAction::AcceptAll,
+            users: Some(vec![
+                "not-an-address".to_string(),
+                requester.to_string(),
+                requester_address.to_string(),
+            ]),
+        })
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response: BulkFriendshipUpdateResponse = test::read_body_json(response).await;
+    assert_eq!(response.results.len(), 2);
+    assert_eq!(response.results[0].user, "not-an-address");
+    assert_eq!(
+        response.results[0].error.as_ref().map(|error| error.code),
+        Some(400)
+    );
+    assert_eq!(response.results[1].user, requester_address.as_str());
+    assert!(response.results[1].error.is_none());
+}
@@ -5,7 +5,7 @@ use actix_web::{test, web::Data};
 use dcl_http_prom_metrics::HttpMetricsCollectorBuilder;
 use social_service::{
     api::{app::get_app_router, routes::v1::friendships::types::FriendshipsResponse},
-    components::{app::AppComponents, database::DatabaseComponentImplementation},
+    components::app::AppComponents,
 };
 
 use super::utils::add_friendship;
@@ -14,8 +14,8 @@ use crate::common::*;
 // Get friendships/me should return list of friends
 #[actix_web::test]
 async fn test_get_friendships_me_when_active() {
-    let user_id = "a_User_id";
-    let other_user_id = "other_useR_id";
+    let user_id = "0xAbCd000000000000000000000000000000000001";
+    let other_user_id = "0xEf01000000000000000000000000000000000002";
 
     let token = "my-token";
 
@@ -56,14 +56,14 @@ async fn test_get_friendships_me_when_active() {
         .first()
         .expect("at least one friend")
         .address;
-    assert_eq!(friend_address, other_user_id);
+    assert_eq!(friend_address, &other_user_id.to_lowercase());
 }
 
 // Get friends should return list of friends
 #[actix_web::test]
 async fn test_get_friends_when_active() {
-    let user_id = "a_User_id";
-    let other_user_id = "other_useR_id";
+    let user_id = "0xAbCd000000000000000000000000000000000001";
+    let other_user_id = "0xEf01000000000000000000000000000000000002";
 
     let token = "my-token";
 
@@ -85,7 +85,7 @@ async fn test_get_friends_when_active() {
 
     add_friendship(&app_data.db, (user_id, other_user_id), true).await;
 
-    let url = "/v1/friendships/a_user_Id/".to_string();
+    let url = "/v1/friendships/0xABCD000000000000000000000000000000000001/".to_string();
 
     let header = ("authorization", format!("Bearer {token}"));
     let req = test::TestRequest::get()
@@ -104,14 +104,14 @@ async fn test_get_friends_when_active() {
         .first()
         .expect("at least one friend")
         .address;
-    assert_eq!(friend_address, other_user_id);
+    assert_eq!(friend_address, &other_user_id.to_lowercase());
 }
 
 // Get friends should return empty when non-active
 #[actix_web::test]
 async fn test_get_friends_when_inactive() {
-    let user_id = "a_User_id";
-    let other_user_id = "other_useR_id";
+    let user_id = "0xAbCd000000000000000000000000000000000001";
+    let other_user_id = "0xEf01000000000000000000000000000000000002";
 
     let token = "my-token";
 
@@ -133,7 +133,7 @@ async fn test_get_friends_when_inactive() {
 
     add_friendship(&app_data.db, (user_id, other_user_id), false).await;
 
-    let url = "/v1/friendships/a_user_Id".to_string();
+    let url = "/v1/friendships/0xabcd000000000000000000000000000000000001".to_string();
 
     let header = ("authorization", format!("Bearer {token}"));
     let req = test::TestRequest::get()
@@ -152,7 +152,7 @@ async fn test_get_friends_when_inactive() {
 
 #[actix_web::test]
 async fn should_return_forbidden_when_requester_asks_for_different_user() {
-    let user_id = "a_user_id";
+    let user_id = "0xabcd000000000000000000000000000000000001";
 
     let mut token_to_user_id: HashMap<String, String> = HashMap::new();
     let token = "my-token";
@@ -167,7 +167,7 @@ async fn should_return_forbidden_when_requester_asks_for_different_user() {
 
     let app = test::init_service(get_app(config, Some(components)).await).await;
 
-    let url = "/v1/friendships/other_user_id";
+    let url = "/v1/friendships/0xef01000000000000000000000000000000000002";
 
     let header = ("authorization", format!("Bearer {token}"));
     let req = test::TestRequest::get()
@@ -182,7 +182,7 @@ async fn should_return_forbidden_when_requester_asks_for_different_user() {
 
 #[actix_web::test]
 async fn test_get_user_friends_database_error_should_return_unknown_error() {
-    let user_id = "a_user_id";
+    let user_id = "0xabcd000000000000000000000000000000000001";
     let mut token_to_user_id: HashMap<String, String> = HashMap::new();
     let token = "my-token";
 
@@ -217,9 +217,9 @@ async fn test_get_user_friends_database_error_should_return_unknown_error() {
 
 #[actix_web::test]
 async fn test_get_user_friends_should_return_the_address_list() {
-    let user_id = "a_uSer_id";
-    let other_user = "b_another_Id";
-    let other_user_2 = "b_another_Id_2";
+    let user_id = "0xaBcD000000000000000000000000000000000001";
+    let other_user = "0xBeeF000000000000000000000000000000000002";
+    let other_user_2 = "0xBeeF000000000000000000000000000000000003";
 
     let mut token_to_user_id: HashMap<String, String> = HashMap::new();
     let token = "my-token";
@@ -242,7 +242,7 @@ async fn test_get_user_friends_should_return_the_address_list() {
     add_friendship(&app_data.db, (user_id, other_user), true).await;
     add_friendship(&app_data.db, (user_id, other_user_2), true).await;
 
-    let url = "/v1/friendships/a_uSer_ID".to_string();
+    let url = "/v1/friendships/0xABCD000000000000000000000000000000000001".to_string();
 
     let header = ("authorization", format!("Bearer {token}"));
     let req = test::TestRequest::get()
@@ -261,6 +261,364 @@ async fn test_get_user_friends_should_return_the_address_list() {
         .iter()
         .map(|friendship| friendship.address.as_str())
         .collect();
-    assert!(addresses.contains(&other_user));
-    assert!(addresses.contains(&other_user_2));
+    assert!(addresses.contains(&other_user.to_lowercase().as_str()));
+    assert!(addresses.contains(&other_user_2.to_lowercase().as_str()));
+}
+
+#[actix_web::test]
+async fn should_return_bad_request_when_user_id_is_not_an_address() {
+    let user_id = "0xabcd000000000000000000000000000000000001";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    let token = "my-token";
+    token_to_user_id.insert(token.to_string(), user_id.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+
+    let components = AppComponents::new(Some(config.clone())).await;
+
+    let app = test::init_service(get_app(config, Some(components)).await).await;
+
+    let url = "/v1/friendships/not_an_address";
+
+    let header = ("authorization", format!("Bearer {token}"));
+    let req = test::TestRequest::get()
+        .uri(url)
+        .append_header(header)
+        .to_request();
+
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_get_user_friends_should_be_enriched_with_profiles() {
+    let user_id = "0xaBcD000000000000000000000000000000000001";
+    let other_user = "0xC0FFEE0000000000000000000000000000000002";
+    let other_user_2 = "0xC0FFEE0000000000000000000000000000000003";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    let token = "my-token";
+
+    token_to_user_id.insert(token.to_string(), user_id.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let profiles_server = profiles_mock_server(vec![(
+        other_user,
+        "Pizarnik",
+        "https://images.decentraland.org/pizarnik/face.png",
+    )])
+    .await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+    config.profiles.url = profiles_server.uri();
+
+    let app_components = AppComponents::new(Some(config)).await;
+    let app_data = Data::new(app_components);
+
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+
+    let router = get_app_router(&app_data, &http_metrics_collector);
+
+    let app = test::init_service(router).await;
+
+    add_friendship(&app_data.db, (user_id, other_user), true).await;
+    add_friendship(&app_data.db, (user_id, other_user_2), true).await;
+
+    let url = "/v1/friendships/me".to_string();
+
+    let header = ("authorization", format!("Bearer {token}"));
+    let req = test::TestRequest::get()
+        .uri(url.as_str())
+        .append_header(header)
+        .to_request();
+
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let friendships_response: FriendshipsResponse = test::read_body_json(response).await;
+    let friends = &friendships_response.friendships;
+    assert_eq!(friends.len(), 2);
+    let find_friend = |address: &str| {
+        friends
+            .iter()
+            .find(|friend| friend.address == address.to_lowercase())
+            .expect("to be a friend")
+    };
+
+    let enriched_friend = find_friend(other_user);
+    assert_eq!(enriched_friend.name.as_deref(), Some("Pizarnik"));
+    assert_eq!(
+        enriched_friend.face_image.as_deref(),
+        Some("https://images.decentraland.org/pizarnik/face.png")
+    );
+
+    let friend_without_profile = find_friend(other_user_2);
+    assert!(friend_without_profile.name.is_none());
+    assert!(friend_without_profile.face_image.is_none());
+}
+
+#[actix_web::test]
+async fn test_get_user_friends_should_filter_by_address_prefix() {
+    let user_id = "0xaBcD000000000000000000000000000000000001";
+    let other_user = "0xBeeF000000000000000000000000000000000002";
+    let other_user_2 = "0xCafe000000000000000000000000000000000003";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    let token = "my-token";
+
+    token_to_user_id.insert(token.to_string(), user_id.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+
+    let app_components = AppComponents::new(Some(config)).await;
+    let app_data = Data::new(app_components);
+
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+
+    let router = get_app_router(&app_data, &http_metrics_collector);
+
+    let app = test::init_service(router).await;
+
+    add_friendship(&app_data.db, (user_id, other_user), true).await;
+    add_friendship(&app_data.db, (user_id, other_user_2), true).await;
+
+    for search in ["0xBEE", "bee"] {
+        let url = format!("/v1/friendships/me?search={search}");
+
+        let header = ("authorization", format!("Bearer {token}"));
+        let req = test::TestRequest::get()
+            .uri(url.as_str())
+            .append_header(header)
+            .to_request();
+
+        let response = test::call_service(&app, req).await;
+
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let friendships_response: FriendshipsResponse = test::read_body_json(response).await;
+        let addresses: Vec<&str> = friendships_response
+            .friendships
+            .iter()
+            .map(|friendship| friendship.address.as_str())
+            .collect();
+        assert_eq!(addresses, vec![other_user.to_lowercase().as_str()]);
+    }
+}
+
+#[actix_web::test]
+async fn test_get_user_friends_should_filter_by_display_name() {
+    let user_id = "0xaBcD000000000000000000000000000000000001";
+    let other_user = "0xD00D000000000000000000000000000000000002";
+    let other_user_2 = "0xD00D000000000000000000000000000000000003";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    let token = "my-token";
+
+    token_to_user_id.insert(token.to_string(), user_id.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let profiles_server = profiles_mock_server(vec![
+        (
+            other_user,
+            "Alejandra",
+            "https://images.decentraland.org/a.png",
+        ),
+        (
+            other_user_2,
+            "Martha",
+            "https://images.decentraland.org/m.png",
+        ),
+    ])
+    .await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+    config.profiles.url = profiles_server.uri();
+
+    let app_components = AppComponents::new(Some(config)).await;
+    let app_data = Data::new(app_components);
+
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+
+    let router = get_app_router(&app_data, &http_metrics_collector);
+
+    let app = test::init_service(router).await;
+
+    add_friendship(&app_data.db, (user_id, other_user), true).await;
+    add_friendship(&app_data.db, (user_id, other_user_2), true).await;
+
+    let url = "/v1/friendships/me?search=mar".to_string();
+
+    let header = ("authorization", format!("Bearer {token}"));
+    let req = test::TestRequest::get()
+        .uri(url.as_str())
+        .append_header(header)
+        .to_request();
+
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let friendships_response: FriendshipsResponse = test::read_body_json(response).await;
+    let friends = &friendships_response.friendships;
+    assert_eq!(friends.len(), 1);
+    assert_eq!(friends[0].address, other_user_2.to_lowercase());
+    assert_eq!(friends[0].name.as_deref(), Some("Martha"));
+}
+
+#[actix_web::test]
+async fn test_get_user_friends_should_filter_by_address_prefix_with_profiles() {
+    let user_id = "0xaBcD000000000000000000000000000000000001";
+    let other_user = "0xBeeF000000000000000000000000000000000002";
+    let other_user_2 = "0xCafe000000000000000000000000000000000003";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    let token = "my-token";
+
+    token_to_user_id.insert(token.to_string(), user_id.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let profiles_server = profiles_mock_server(vec![
+        (
+            other_user,
+            "Martha",
+            "https://images.decentraland.org/m.png",
+        ),
+        (
+            other_user_2,
+            "Beethoven",
+            "https://images.decentraland.org/b.png",
+        ),
+    ])
+    .await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+    config.profiles.url = profiles_server.uri();
+
+    let app_components = AppComponents::new(Some(config)).await;
+    let app_data = Data::new(app_components);
+
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+
+    let router = get_app_router(&app_data, &http_metrics_collector);
+
+    let app = test::init_service(router).await;
+
+    add_friendship(&app_data.db, (user_id, other_user), true).await;
+    add_friendship(&app_data.db, (user_id, other_user_2), true).await;
+
+    let url = "/v1/friendships/me?search=bee".to_string();
+
+    let header = ("authorization", format!("Bearer {token}"));
+    let req = test::TestRequest::get()
+        .uri(url.as_str())
+        .append_header(header)
+        .to_request();
+
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // One matches by address and the other one by name
+    let friendships_response: FriendshipsResponse = test::read_body_json(response).await;
+    let mut addresses: Vec<&str> = friendships_response
+        .friendships
+        .iter()
+        .map(|friendship| friendship.address.as_str())
+        .collect();
+    addresses.sort();
+    assert_eq!(
+        addresses,
+        vec![
+            other_user.to_lowercase().as_str(),
+            other_user_2.to_lowercase().as_str()
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn should_return_bad_request_when_search_is_empty() {
+    let user_id = "0xabcd000000000000000000000000000000000001";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    let token = "my-token";
+    token_to_user_id.insert(token.to_string(), user_id.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+
+    let components = AppComponents::new(Some(config.clone())).await;
+
+    let app = test::init_service(get_app(config, Some(components)).await).await;
+
+    let url = "/v1/friendships/me?search=";
+
+    let header = ("authorization", format!("Bearer {token}"));
+    let req = test::TestRequest::get()
+        .uri(url)
+        .append_header(header)
+        .to_request();
+
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_get_user_friends_should_include_friends_since_and_sort_by_recency() {
+    let user_id = "0xaBcD000000000000000000000000000000000001";
+    let older_friend = "0xFeed000000000000000000000000000000000002";
+    let newer_friend = "0xFeed000000000000000000000000000000000003";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    let token = "my-token";
+
+    token_to_user_id.insert(token.to_string(), user_id.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+
+    let app_components = AppComponents::new(Some(config)).await;
+    let app_data = Data::new(app_components);
+
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+
+    let router = get_app_router(&app_data, &http_metrics_collector);
+
+    let app = test::init_service(router).await;
+
+    add_friendship(&app_data.db, (user_id, older_friend), true).await;
+    // Timestamps have second precision in responses
+    actix_rt::time::sleep(std::time::Duration::from_millis(1100)).await;
+    add_friendship(&app_data.db, (user_id, newer_friend), true).await;
+
+    let url = "/v1/friendships/me?sort=recent".to_string();
+
+    let header = ("authorization", format!("Bearer {token}"));
+    let req = test::TestRequest::get()
+        .uri(url.as_str())
+        .append_header(header)
+        .to_request();
+
+    let response = test::call_service(&app, req).await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let friendships_response: FriendshipsResponse = test::read_body_json(response).await;
+    let friends = &friendships_response.friendships;
+    assert_eq!(friends.len(), 2);
+    assert_eq!(friends[0].address, newer_friend.to_lowercase());
+    assert_eq!(friends[1].address, older_friend.to_lowercase());
+    assert!(friends[0].friends_since > friends[1].friends_since);
+    assert!(friends[1].friends_since.is_some());
 }
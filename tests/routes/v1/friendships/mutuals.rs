@@ -18,12 +18,12 @@ use crate::common::*;
 // Get friends should return list of friends
 #[actix_web::test]
 async fn test_get_mutual_friends() {
-    let user_id_a = "user-A";
-    let user_id_b = "useR-b";
-    let user_id_c = "user-c";
-    let user_id_d = "user-D";
-    let user_id_e = "user-e";
-    let user_id_f = "user-f";
+    let user_id_a = "0xAAAA000000000000000000000000000000000001";
+    let user_id_b = "0xbbbb000000000000000000000000000000000002";
+    let user_id_c = "0xcccc000000000000000000000000000000000003";
+    let user_id_d = "0xDDDD000000000000000000000000000000000004";
+    let user_id_e = "0xeeee000000000000000000000000000000000005";
+    let user_id_f = "0xffff000000000000000000000000000000000006";
 
     let token = "token-user-a";
 
@@ -59,7 +59,7 @@ async fn test_get_mutual_friends() {
     add_friendship(&app_data.db, (user_id_f, user_id_a), true).await;
     add_friendship(&app_data.db, (user_id_f, user_id_b), true).await;
 
-    let url = "/v1/friendships/user-b/mutuals".to_string();
+    let url = "/v1/friendships/0xBBBB000000000000000000000000000000000002/mutuals".to_string();
 
     let header = ("authorization", format!("Bearer {token}"));
     let req = test::TestRequest::get()
@@ -77,15 +77,19 @@ async fn test_get_mutual_friends() {
     assert_eq!(mutual_friends_addresses.len(), 4);
 
     assert!(mutual_friends_addresses.contains(&FriendshipFriend {
-        address: user_id_c.to_string()
+        address: user_id_c.to_lowercase(),
+        ..Default::default()
     }));
     assert!(mutual_friends_addresses.contains(&FriendshipFriend {
-        address: user_id_d.to_string()
+        address: user_id_d.to_lowercase(),
+        ..Default::default()
     }));
     assert!(mutual_friends_addresses.contains(&FriendshipFriend {
-        address: user_id_e.to_string()
+        address: user_id_e.to_lowercase(),
+        ..Default::default()
     }));
     assert!(mutual_friends_addresses.contains(&FriendshipFriend {
-        address: user_id_f.to_string()
+        address: user_id_f.to_lowercase(),
+        ..Default::default()
     }));
 }
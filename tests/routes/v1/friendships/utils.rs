@@ -1,19 +1,21 @@
-use social_service::components::database::DatabaseComponent;
-use social_service::entities::friendships::FriendshipRepositoryImplementation;
+use social_service::components::database::DatabaseComponentImplementation;
+use social_service::domain::address::Address;
 
 use uuid::Uuid;
 
 pub async fn add_friendship(
-    db: &DatabaseComponent,
+    db: &dyn DatabaseComponentImplementation,
     friendship: (&str, &str),
     is_active: bool,
 ) -> Uuid {
     let synapse_room_id = format!("room_id_{}_{}", friendship.0, friendship.1);
-    db.db_repos
+    let address_1 = Address::parse(friendship.0).expect("a valid address");
+    let address_2 = Address::parse(friendship.1).expect("a valid address");
+    db.get_repos()
         .as_ref()
         .expect("repos to be present")
         .friendships
-        .create_new_friendships(friendship, is_active, &synapse_room_id, None)
+        .create_new_friendships((&address_1, &address_2), is_active, &synapse_room_id, None)
         .await
         .0
         .expect("can create friendship")
@@ -1 +1,2 @@
 pub mod friendships;
+pub mod users;
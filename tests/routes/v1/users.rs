@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use actix_http::StatusCode;
+use actix_web::{test, web::Data};
+use dcl_http_prom_metrics::HttpMetricsCollectorBuilder;
+use social_service::{
+    api::{
+        app::get_app_router,
+        routes::v1::users::types::{NotificationSettings, UserDataExport},
+    },
+    components::app::AppComponents,
+    domain::address::Address,
+    entities::friendship_history::FriendshipMetadata,
+};
+
+use super::friendships::utils::add_friendship;
+use crate::common::*;
+
+#[actix_web::test]
+async fn test_export_user_data() {
+    let user = "0xabcd000000000000000000000000000000000031";
+    let friend = "0xabcd000000000000000000000000000000000032";
+    let requester = "0xabcd000000000000000000000000000000000033";
+    let token = "export-user-data-token";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    token_to_user_id.insert(token.to_string(), user.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+
+    let app_data = Data::new(AppComponents::new(Some(config)).await);
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let repos = app_data.db.get_repos().as_ref().unwrap();
+    add_friendship(&app_data.db, (user, friend), true).await;
+    let request_id = add_friendship(&app_data.db, (requester, user), false).await;
+    repos
+        .friendship_history
+        .create(
+            request_id,
+            "\"request\"",
+            &Address::parse(requester).unwrap(),
+            Some(sqlx::types::Json(FriendshipMetadata {
+                message: Some("Hi!".to_string()),
+                synapse_room_id: None,
+                migrated_from_synapse: None,
+                forced_by_admin: None,
+            })),
+            None,
+        )
+        .await
+        .0
+        .unwrap();
+    repos
+        .user_features
+        .create(&Address::parse(user).unwrap(), "exposure_level", "public")
+        .await
+        .unwrap();
+
+    let req = test::TestRequest::get()
+        .uri("/v1/me/data")
+        .append_header(("authorization", format!("Bearer {token}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let content_disposition = response
+        .headers()
+        .get("Content-Disposition")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(content_disposition.starts_with("attachment"));
+
+    let export: UserDataExport = test::read_body_json(response).await;
+    assert_eq!(export.address, user);
+    assert_eq!(export.friendships.len(), 2);
+
+    assert_eq!(export.friendship_history.len(), 1);
+    let request = &export.friendship_history[0];
+    assert_eq!(request.event, "request");
+    assert_eq!(request.acting_user, requester);
+    assert_eq!(request.message.as_deref(), Some("Hi!"));
+
+    assert_eq!(export.features.len(), 1);
+    assert_eq!(export.features[0].name, "exposure_level");
+    assert_eq!(export.features[0].value, "public");
+}
+
+#[actix_web::test]
+async fn test_erase_user_data() {
+    let user = "0xabcd000000000000000000000000000000000041";
+    let friend = "0xabcd000000000000000000000000000000000042";
+    let other_friend = "0xabcd000000000000000000000000000000000043";
+    let token = "erase-user-data-token";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    token_to_user_id.insert(token.to_string(), user.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+
+    let app_data = Data::new(AppComponents::new(Some(config)).await);
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let repos = app_data.db.get_repos().as_ref().unwrap();
+    add_friendship(&app_data.db, (user, friend), true).await;
+    add_friendship(&app_data.db, (friend, other_friend), true).await;
+    repos
+        .user_features
+        .create(&Address::parse(user).unwrap(), "exposure_level", "public")
+        .await
+        .unwrap();
+
+    let req = test::TestRequest::delete()
+        .uri("/v1/me/data")
+        .append_header(("authorization", format!("Bearer {token}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    let (user_friendships, _) = repos
+        .friendships
+        .get_user_friends(&Address::parse(user).unwrap(), false, None, None)
+        .await;
+    assert!(user_friendships.unwrap().is_empty());
+
+    let features = repos
+        .user_features
+        .get_all_user_features(&Address::parse(user).unwrap())
+        .await
+        .unwrap();
+    assert!(features.is_none());
+
+    // The friend keeps the rest of their friendships
+    let (friend_friendships, _) = repos
+        .friendships
+        .get_user_friends(&Address::parse(friend).unwrap(), true, None, None)
+        .await;
+    assert_eq!(friend_friendships.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_user_data_requires_authorization_header() {
+    let app_data = Data::new(AppComponents::new(Some(get_configuration().await)).await);
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let req = test::TestRequest::get().uri("/v1/me/data").to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_opt_out_of_push_notifications() {
+    let user = "0xabcd000000000000000000000000000000000051";
+    let token = "notification-settings-token";
+
+    let mut token_to_user_id: HashMap<String, String> = HashMap::new();
+    token_to_user_id.insert(token.to_string(), user.to_string());
+
+    let mock_server = who_am_i_synapse_mock_server(token_to_user_id).await;
+    let mut config = get_configuration().await;
+    config.synapse.url = mock_server.uri();
+
+    let app_data = Data::new(AppComponents::new(Some(config)).await);
+    let http_metrics_collector = Data::new(HttpMetricsCollectorBuilder::default().build());
+    let app = test::init_service(get_app_router(&app_data, &http_metrics_collector)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/v1/me/notifications")
+        .append_header(("authorization", format!("Bearer {token}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let settings: NotificationSettings = test::read_body_json(response).await;
+    assert!(settings.push_enabled);
+
+    for push_enabled in [false, true, false] {
+        let req = test::TestRequest::put()
+            .uri("/v1/me/notifications")
+            .append_header(("authorization", format!("Bearer {token}")))
+            .set_json(NotificationSettings { push_enabled })
+            .to_request();
+        let response = test::call_service(&app, req).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/v1/me/notifications")
+        .append_header(("authorization", format!("Bearer {token}")))
+        .to_request();
+    let response = test::call_service(&app, req).await;
+    let settings: NotificationSettings = test::read_body_json(response).await;
+    assert!(!settings.push_enabled);
+}
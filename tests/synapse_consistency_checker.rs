@@ -0,0 +1,225 @@
+use serde_json::json;
+use social_service::{
+    components::{database::DBRepositories, synapse::SynapseComponent},
+    domain::{address::Address, friendship_event::FriendshipEvent},
+    synapse::consistency_checker::{ConsistencyCheckOptions, ConsistencyChecker, Mismatch},
+};
+use sqlx::types::Uuid;
+use wiremock::{
+    matchers::{method, path},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const USER_A: &str = "0x00000000000000000000000000000000000000aa";
+const USER_B: &str = "0x00000000000000000000000000000000000000bb";
+const USER_C: &str = "0x00000000000000000000000000000000000000cc";
+
+/// Mounts a room where only the given users joined and whose friendship event is `room_event`.
+async fn mount_room(server: &MockServer, room_id: &str, members: &[&str], room_event: &str) {
+    Mock::given(method("GET"))
+        .and(path(format!("/_matrix/client/r0/rooms/{room_id}/members")))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": members.iter().map(|user| json!({
+                "state_key": format!("@{user}:decentraland.org"),
+                "user_id": format!("@{user}:decentraland.org"),
+                "room_id": room_id,
+                "type": "m.room.member",
+                "content": { "membership": "join" },
+            })).collect::<Vec<_>>()
+        })))
+        .mount(server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(format!(
+            "/_matrix/client/r0/rooms/{room_id}/state/org.decentraland.friendship"
+        )))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "type": room_event })))
+        .mount(server)
+        .await;
+}
+
+async fn mount_missing_room(server: &MockServer, room_id: &str) {
+    Mock::given(method("GET"))
+        .and(path(format!("/_matrix/client/r0/rooms/{room_id}/members")))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "User not in room"
+        })))
+        .mount(server)
+        .await;
+}
+
+async fn create_friendship(
+    repos: &DBRepositories,
+    (acting_user, second_user): (&str, &str),
+    is_active: bool,
+    room_id: &str,
+    event: FriendshipEvent,
+) -> Uuid {
+    let (acting_user, second_user) = (
+        Address::parse(acting_user).unwrap(),
+        Address::parse(second_user).unwrap(),
+    );
+    let (id, _) = repos
+        .friendships
+        .create_new_friendships((&acting_user, &second_user), is_active, room_id, None)
+        .await;
+    let id = id.unwrap();
+
+    let event = serde_json::to_string(&event).unwrap();
+    repos
+        .friendship_history
+        .create(id, &event, &acting_user, None, None)
+        .await
+        .0
+        .unwrap();
+
+    id
+}
+
+fn checker(server: &MockServer, repos: &DBRepositories, apply: bool) -> ConsistencyChecker {
+    ConsistencyChecker::new(
+        SynapseComponent::new(server.uri()),
+        repos.clone(),
+        "a_service_token".to_string(),
+        ConsistencyCheckOptions {
+            batch_size: 1,
+            apply,
+        },
+    )
+}
+
+#[actix_web::test]
+async fn should_report_the_mismatches_without_changing_anything_on_a_dry_run() {
+    let server = MockServer::start().await;
+    let repos = DBRepositories::in_memory();
+
+    mount_room(&server, "consistent_room", &[USER_A, USER_B], "accept").await;
+    create_friendship(
+        &repos,
+        (USER_B, USER_A),
+        true,
+        "consistent_room",
+        FriendshipEvent::ACCEPT,
+    )
+    .await;
+
+    mount_room(&server, "drifted_room", &[USER_A], "request").await;
+    let drifted = create_friendship(
+        &repos,
+        (USER_C, USER_A),
+        false,
+        "drifted_room",
+        FriendshipEvent::ACCEPT,
+    )
+    .await;
+
+    let report = checker(&server, &repos, false).run().await.unwrap();
+
+    assert_eq!(report.scanned, 2);
+    assert_eq!(report.failed, 0);
+    assert_eq!(report.friendships.len(), 1);
+
+    let friendship = &report.friendships[0];
+    assert_eq!(friendship.friendship_id, drifted);
+    assert_eq!(
+        friendship.mismatches,
+        vec![
+            Mismatch::IsActive { expected: true },
+            Mismatch::MissingMember {
+                address: USER_C.to_string()
+            },
+            Mismatch::RoomEvent {
+                database: FriendshipEvent::ACCEPT,
+                synapse: Some(FriendshipEvent::REQUEST)
+            },
+        ]
+    );
+    assert!(friendship.repaired.is_empty());
+
+    let (user_a, user_c) = (
+        Address::parse(USER_A).unwrap(),
+        Address::parse(USER_C).unwrap(),
+    );
+    let (friendship, _) = repos
+        .friendships
+        .get_friendship((&user_a, &user_c), None)
+        .await;
+    assert!(!friendship.unwrap().unwrap().is_active);
+}
+
+#[actix_web::test]
+async fn should_repair_the_database_side_in_apply_mode() {
+    let server = MockServer::start().await;
+    let repos = DBRepositories::in_memory();
+    let (user_a, user_b, user_c) = (
+        Address::parse(USER_A).unwrap(),
+        Address::parse(USER_B).unwrap(),
+        Address::parse(USER_C).unwrap(),
+    );
+
+    // Deleted, but still flagged as active
+    mount_room(&server, "deleted_room", &[USER_A, USER_B], "delete").await;
+    create_friendship(
+        &repos,
+        (USER_A, USER_B),
+        true,
+        "deleted_room",
+        FriendshipEvent::DELETE,
+    )
+    .await;
+
+    // Rejected, and its room is gone
+    mount_missing_room(&server, "gone_room").await;
+    create_friendship(
+        &repos,
+        (USER_C, USER_A),
+        false,
+        "gone_room",
+        FriendshipEvent::REJECT,
+    )
+    .await;
+
+    let report = checker(&server, &repos, true).run().await.unwrap();
+
+    assert_eq!(report.friendships.len(), 2);
+    for friendship in &report.friendships {
+        assert_eq!(friendship.mismatches, friendship.repaired);
+    }
+
+    let (deleted, _) = repos
+        .friendships
+        .get_friendship((&user_a, &user_b), None)
+        .await;
+    assert!(!deleted.unwrap().unwrap().is_active);
+
+    let (rejected, _) = repos
+        .friendships
+        .get_friendship((&user_a, &user_c), None)
+        .await;
+    assert_eq!(rejected.unwrap().unwrap().synapse_room_id, None);
+}
+
+#[actix_web::test]
+async fn should_not_unlink_the_missing_room_of_an_ongoing_friendship() {
+    let server = MockServer::start().await;
+    let repos = DBRepositories::in_memory();
+
+    mount_missing_room(&server, "gone_room").await;
+    create_friendship(
+        &repos,
+        (USER_A, USER_B),
+        false,
+        "gone_room",
+        FriendshipEvent::REQUEST,
+    )
+    .await;
+
+    let report = checker(&server, &repos, true).run().await.unwrap();
+
+    assert_eq!(
+        report.friendships[0].mismatches,
+        vec![Mismatch::RoomNotFound]
+    );
+    assert!(report.friendships[0].repaired.is_empty());
+}
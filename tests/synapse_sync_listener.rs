@@ -0,0 +1,363 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde_json::{json, Value};
+use social_service::{
+    components::{
+        configuration::{Config, EventsChannelBackend},
+        database::{DBRepositories, DatabaseComponentImplementation, InMemoryDatabaseComponent},
+        notifications::{ChannelSubscriber, EVENT_UPDATES_CHANNEL_NAME},
+        synapse::{SynapseComponent, SYNC_URI, WHO_AM_I_URI},
+    },
+    domain::{address::Address, friendship_event::FriendshipEvent},
+    notifications::Event,
+    synapse::sync_listener::{SynapseSyncListener, SYNC_LISTENER_NAME},
+    ws::app::init_ws_components,
+};
+use wiremock::{
+    matchers::{method, path, path_regex, query_param},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const USER_A: &str = "0x00000000000000000000000000000000000000aa";
+const USER_B: &str = "0x00000000000000000000000000000000000000bb";
+const ROOM_ID: &str = "!a_room:decentraland.org";
+
+fn friendship_event(event_id: &str, sender: &str, event: &str) -> Value {
+    json!({
+        "event_id": event_id,
+        "type": "org.decentraland.friendship",
+        "sender": format!("@{sender}:decentraland.org"),
+        "origin_server_ts": chrono::Utc::now().timestamp_millis() + 60_000,
+        "content": { "type": event },
+    })
+}
+
+fn sync_response(next_batch: &str, events: Vec<Value>) -> Value {
+    json!({
+        "next_batch": next_batch,
+        "rooms": {
+            "join": {
+                ROOM_ID: { "timeline": { "events": events } }
+            }
+        }
+    })
+}
+
+async fn synapse_mock_server() -> MockServer {
+    let server = synapse_mock_server_without_members().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.+/members$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "chunk": [USER_A, USER_B, "friendships"].iter().map(|user| json!({
+                "state_key": format!("@{user}:decentraland.org"),
+                "user_id": format!("@{user}:decentraland.org"),
+                "room_id": ROOM_ID,
+                "type": "m.room.member",
+            })).collect::<Vec<_>>()
+        })))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn synapse_mock_server_without_members() -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path(WHO_AM_I_URI))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(json!({ "user_id": "@friendships:decentraland.org" })),
+        )
+        .mount(&server)
+        .await;
+
+    server
+}
+
+async fn mount_sync(server: &MockServer, since: &str, response: Value) {
+    Mock::given(method("GET"))
+        .and(path(SYNC_URI))
+        .and(query_param("since", since))
+        .respond_with(ResponseTemplate::new(200).set_body_json(response))
+        .expect(1)
+        .mount(server)
+        .await;
+}
+
+fn listener(
+    server: &MockServer,
+    db: &Arc<dyn DatabaseComponentImplementation>,
+) -> SynapseSyncListener {
+    SynapseSyncListener::new(
+        SynapseComponent::new(server.uri()),
+        db.clone(),
+        "a_service_token".to_string(),
+        Duration::from_millis(10),
+    )
+}
+
+fn repos(db: &Arc<dyn DatabaseComponentImplementation>) -> DBRepositories {
+    db.get_repos().clone().unwrap()
+}
+
+async fn next_batch(db: &Arc<dyn DatabaseComponentImplementation>) -> Option<String> {
+    repos(db)
+        .sync_state
+        .get_next_batch(SYNC_LISTENER_NAME)
+        .await
+        .unwrap()
+}
+
+async fn last_event(
+    db: &Arc<dyn DatabaseComponentImplementation>,
+) -> Option<(FriendshipEvent, String)> {
+    let repos = repos(db);
+    let (user_a, user_b) = (
+        Address::parse(USER_A).unwrap(),
+        Address::parse(USER_B).unwrap(),
+    );
+
+    let (friendship, _) = repos
+        .friendships
+        .get_friendship((&user_a, &user_b), None)
+        .await;
+    let friendship = friendship.unwrap()?;
+
+    let (history, _) = repos
+        .friendship_history
+        .get_last_history_for_friendship(friendship.id, None)
+        .await;
+
+    history
+        .unwrap()
+        .map(|history| (history.event, history.acting_user))
+}
+
+#[actix_web::test]
+async fn should_only_store_the_token_on_the_first_sync() {
+    let server = synapse_mock_server().await;
+    Mock::given(method("GET"))
+        .and(path(SYNC_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(sync_response(
+            "s1",
+            vec![friendship_event("$request", USER_A, "request")],
+        )))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+
+    listener(&server, &db).sync_once().await.unwrap();
+
+    assert_eq!(next_batch(&db).await, Some("s1".to_string()));
+    assert_eq!(last_event(&db).await, None);
+}
+
+#[actix_web::test]
+async fn should_apply_the_friendship_events_after_the_stored_token() {
+    let server = synapse_mock_server().await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+    repos(&db)
+        .sync_state
+        .save_next_batch(SYNC_LISTENER_NAME, "s1")
+        .await
+        .unwrap();
+    mount_sync(
+        &server,
+        "s1",
+        sync_response(
+            "s2",
+            vec![
+                friendship_event("$request", USER_A, "request"),
+                friendship_event("$accept", USER_B, "accept"),
+            ],
+        ),
+    )
+    .await;
+
+    listener(&server, &db).sync_once().await.unwrap();
+
+    assert_eq!(next_batch(&db).await, Some("s2".to_string()));
+    assert_eq!(
+        last_event(&db).await,
+        Some((FriendshipEvent::ACCEPT, USER_B.to_string()))
+    );
+}
+
+#[actix_web::test]
+async fn should_resume_from_the_token_of_the_previous_sync() {
+    let server = synapse_mock_server().await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+    repos(&db)
+        .sync_state
+        .save_next_batch(SYNC_LISTENER_NAME, "s1")
+        .await
+        .unwrap();
+    mount_sync(
+        &server,
+        "s1",
+        sync_response("s2", vec![friendship_event("$request", USER_A, "request")]),
+    )
+    .await;
+    mount_sync(
+        &server,
+        "s2",
+        sync_response("s3", vec![friendship_event("$cancel", USER_A, "cancel")]),
+    )
+    .await;
+
+    // A new listener, as after a restart
+    listener(&server, &db).sync_once().await.unwrap();
+    listener(&server, &db).sync_once().await.unwrap();
+
+    assert_eq!(next_batch(&db).await, Some("s3".to_string()));
+    assert_eq!(
+        last_event(&db).await,
+        Some((FriendshipEvent::CANCEL, USER_A.to_string()))
+    );
+}
+
+#[actix_web::test]
+async fn should_skip_invalid_events() {
+    let server = synapse_mock_server().await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+    repos(&db)
+        .sync_state
+        .save_next_batch(SYNC_LISTENER_NAME, "s1")
+        .await
+        .unwrap();
+    mount_sync(
+        &server,
+        "s1",
+        sync_response(
+            "s2",
+            vec![
+                friendship_event("$request", USER_A, "request"),
+                // Only the other user can accept the request
+                friendship_event("$accept", USER_A, "accept"),
+            ],
+        ),
+    )
+    .await;
+
+    listener(&server, &db).sync_once().await.unwrap();
+
+    assert_eq!(next_batch(&db).await, Some("s2".to_string()));
+    assert_eq!(
+        last_event(&db).await,
+        Some((FriendshipEvent::REQUEST, USER_A.to_string()))
+    );
+}
+
+#[actix_web::test]
+async fn should_retry_from_the_same_token_when_an_event_fails_for_a_while() {
+    let server = synapse_mock_server_without_members().await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.+/members$"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+    repos(&db)
+        .sync_state
+        .save_next_batch(SYNC_LISTENER_NAME, "s1")
+        .await
+        .unwrap();
+    mount_sync(
+        &server,
+        "s1",
+        sync_response("s2", vec![friendship_event("$request", USER_A, "request")]),
+    )
+    .await;
+
+    let result = listener(&server, &db).sync_once().await;
+
+    assert!(result.is_err());
+    assert_eq!(next_batch(&db).await, Some("s1".to_string()));
+    assert_eq!(last_event(&db).await, None);
+}
+
+#[actix_web::test]
+async fn should_join_the_rooms_the_service_account_is_invited_to() {
+    let server = synapse_mock_server().await;
+    Mock::given(method("GET"))
+        .and(path(SYNC_URI))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "next_batch": "s1",
+            "rooms": { "invite": { ROOM_ID: { "invite_state": { "events": [] } } } }
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/.+/join$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": ROOM_ID })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+
+    listener(&server, &db).sync_once().await.unwrap();
+
+    assert_eq!(next_batch(&db).await, Some("s1".to_string()));
+}
+
+#[actix_web::test]
+async fn should_publish_the_applied_events() {
+    let server = synapse_mock_server().await;
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+    repos(&db)
+        .sync_state
+        .save_next_batch(SYNC_LISTENER_NAME, "s1")
+        .await
+        .unwrap();
+    mount_sync(
+        &server,
+        "s1",
+        sync_response("s2", vec![friendship_event("$request", USER_A, "request")]),
+    )
+    .await;
+
+    let mut config = Config::new().expect("Couldn't read the configuration file");
+    config.events_channel = EventsChannelBackend::InProcess;
+    let ws_components = init_ws_components(config, &db).await;
+    let received = Arc::new(Mutex::new(vec![]));
+    let received_clone = received.clone();
+    ws_components
+        .events_subscriber
+        .subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event: Event| {
+            let received = received_clone.clone();
+            async move {
+                received.lock().unwrap().push(event);
+            }
+        });
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+    listener(&server, &db)
+        .with_events_publisher(
+            ws_components.events_publisher.clone(),
+            ws_components.metrics.clone(),
+        )
+        .sync_once()
+        .await
+        .unwrap();
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    assert_eq!(received[0].from, USER_A);
+    assert_eq!(received[0].to, USER_B);
+}
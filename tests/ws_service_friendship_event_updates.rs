@@ -0,0 +1,814 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use serde_json::json;
+use social_service::{
+    components::{
+        configuration::{Config, EventsChannelBackend},
+        database::{DatabaseComponentImplementation, InMemoryDatabaseComponent},
+        notifications::{ChannelSubscriber, EVENT_UPDATES_CHANNEL_NAME},
+        redis::Redis,
+        synapse::SynapseComponent,
+        users_cache::UsersCacheComponent,
+    },
+    domain::{
+        address::Address,
+        error::CommonError,
+        event::EventPayload,
+        friendship_event::FriendshipEvent,
+        friendship_limits::{FriendshipLimits, MAX_OUTGOING_REQUESTS_FEATURE},
+    },
+    notifications::Event,
+    synapse::synapse_handler::{clean_up_synapse_room, RoomUser},
+    ws::{
+        app::{init_ws_components, ConfigRpcServer, SocialContext},
+        service::{
+            bulk_friendship_updates::{
+                handle_bulk_friendship_update, BulkFriendshipAction, BulkTarget,
+            },
+            friendship_event_updates::handle_friendship_update,
+        },
+    },
+};
+use wiremock::{
+    matchers::{body_json, header, method, path_regex},
+    Mock, MockServer, ResponseTemplate,
+};
+
+const USER_A: &str = "0x00000000000000000000000000000000000000aa";
+const USER_B: &str = "0x00000000000000000000000000000000000000bb";
+const USER_C: &str = "0x00000000000000000000000000000000000000cc";
+const USER_D: &str = "0x00000000000000000000000000000000000000dd";
+const USER_E: &str = "0x00000000000000000000000000000000000000ee";
+const ROOM_ID: &str = "a_room_id";
+
+/// User A cleaning up with their token, while user B has none
+const ROOM_USERS: [RoomUser; 2] = [
+    RoomUser {
+        address: USER_A,
+        token: Some("a_token"),
+    },
+    RoomUser {
+        address: USER_B,
+        token: None,
+    },
+];
+
+async fn synapse_mock_server() -> MockServer {
+    let server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/directory/room/.+"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "room_id": ROOM_ID, "servers": [] })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/joined_rooms$"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "joined_rooms": [ROOM_ID] })),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/a_room_id/join$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": ROOM_ID })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/rooms/a_room_id/(state|send)/.+",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "an_event" })))
+        .mount(&server)
+        .await;
+
+    server
+}
+
+/// Mounts the calls done to clean up `a_room_id`, each one answering with the given response.
+async fn mount_room_cleanup_mocks(
+    server: &MockServer,
+    response: ResponseTemplate,
+    expected_calls: u64,
+) {
+    let calls = [
+        ("POST", r"^/_matrix/client/r0/rooms/a_room_id/kick$"),
+        ("POST", r"^/_matrix/client/r0/rooms/a_room_id/leave$"),
+        ("POST", r"^/_matrix/client/r0/rooms/a_room_id/forget$"),
+        ("DELETE", r"^/_matrix/client/r0/directory/room/.+"),
+    ];
+
+    for (http_method, path) in calls {
+        Mock::given(method(http_method))
+            .and(path_regex(path))
+            .respond_with(response.clone())
+            .expect(expected_calls)
+            .mount(server)
+            .await;
+    }
+}
+
+async fn in_memory_social_context(synapse_url: String) -> Arc<SocialContext> {
+    in_memory_social_context_with(synapse_url, |_| {}).await
+}
+
+async fn in_memory_social_context_with(
+    synapse_url: String,
+    configure: impl FnOnce(&mut Config),
+) -> Arc<SocialContext> {
+    let mut config = Config::new().expect("Couldn't read the configuration file");
+    config.synapse.url = synapse_url;
+    configure(&mut config);
+    config.events_channel = EventsChannelBackend::InProcess;
+
+    let db: Arc<dyn DatabaseComponentImplementation> =
+        Arc::new(InMemoryDatabaseComponent::default());
+    let ws_components = init_ws_components(config.clone(), &db).await;
+    let redis = Redis::new_and_run(&config.redis)
+        .await
+        .expect("There was an error initializing Redis");
+
+    Arc::new(SocialContext {
+        synapse: SynapseComponent::new(config.synapse.url.clone()),
+        db,
+        users_cache: Arc::new(UsersCacheComponent::new(
+            Some(redis),
+            config.cache_hashing_key.clone(),
+        )),
+        friends_cache: None,
+        session_tokens: None,
+        push_notifications: None,
+        profiles: None,
+        config: ConfigRpcServer {
+            rpc_server: config.rpc_server.clone(),
+            wkc_metrics_bearer_token: config.wkc_metrics_bearer_token.clone(),
+            room_cleanup: config.synapse.room_cleanup,
+            synapse_admin_access_token: config.synapse_admin_access_token.clone(),
+            friendship_limits: FriendshipLimits {
+                max_friends: config.friendships_max_friends,
+                max_outgoing_requests: config.friendships_max_outgoing_requests,
+            },
+        },
+        events_publisher: ws_components.events_publisher,
+        events_subscriber: ws_components.events_subscriber,
+        friendships_events_generators: ws_components.friendships_events_generators,
+        transport_context: ws_components.transport_context,
+        friends_stream_page_size: config.friends_stream_page_size,
+        metrics: ws_components.metrics,
+    })
+}
+
+fn event(friendship_event: FriendshipEvent, second_user: &str) -> EventPayload {
+    EventPayload {
+        friendship_event,
+        second_user: second_user.to_string(),
+        request_event_message_body: None,
+    }
+}
+
+#[actix_web::test]
+async fn should_go_through_the_friendship_lifecycle_without_a_database() {
+    let synapse_server = synapse_mock_server().await;
+    let context = in_memory_social_context(synapse_server.uri()).await;
+    let user_a = Address::parse(USER_A).unwrap();
+    let user_b = Address::parse(USER_B).unwrap();
+
+    let steps = [
+        (FriendshipEvent::REQUEST, &user_a, &user_b, false),
+        (FriendshipEvent::ACCEPT, &user_b, &user_a, true),
+        (FriendshipEvent::DELETE, &user_a, &user_b, false),
+    ];
+
+    for (friendship_event, acting_user, second_user, is_active) in steps {
+        let response = handle_friendship_update(
+            "a_token".to_string(),
+            event(friendship_event, second_user.as_str()),
+            context.clone(),
+            acting_user.clone(),
+        )
+        .await
+        .unwrap_or_else(|_| panic!("{friendship_event:?} to be handled"));
+        assert_eq!(response.user_id, second_user.as_str());
+
+        let repos = context.db.get_repos().as_ref().unwrap();
+        let (friendship, _) = repos
+            .friendships
+            .get_friendship((&user_a, &user_b), None)
+            .await;
+        let friendship = friendship.unwrap().expect("the friendship to be stored");
+        assert_eq!(friendship.is_active, is_active);
+
+        let (history, _) = repos
+            .friendship_history
+            .get_last_history_for_friendship(friendship.id, None)
+            .await;
+        let history = history.unwrap().expect("the event to be in the history");
+        assert_eq!(history.event, friendship_event);
+        assert_eq!(history.acting_user, acting_user.as_str());
+    }
+}
+
+#[actix_web::test]
+async fn should_reject_an_invalid_transition_without_a_database() {
+    let synapse_server = synapse_mock_server().await;
+    let context = in_memory_social_context(synapse_server.uri()).await;
+    let user_a = Address::parse(USER_A).unwrap();
+
+    // There is no friendship to accept
+    let result = handle_friendship_update(
+        "a_token".to_string(),
+        event(FriendshipEvent::ACCEPT, USER_B),
+        context.clone(),
+        user_a.clone(),
+    )
+    .await;
+
+    assert!(result.is_err());
+
+    let user_b = Address::parse(USER_B).unwrap();
+    let (friendship, _) = context
+        .db
+        .get_repos()
+        .as_ref()
+        .unwrap()
+        .friendships
+        .get_friendship((&user_a, &user_b), None)
+        .await;
+    assert!(friendship.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn should_clean_up_the_room_when_the_friendship_is_deleted() {
+    let synapse_server = synapse_mock_server().await;
+    mount_room_cleanup_mocks(
+        &synapse_server,
+        ResponseTemplate::new(200).set_body_json(json!({})),
+        1,
+    )
+    .await;
+    let context = in_memory_social_context(synapse_server.uri()).await;
+    let user_a = Address::parse(USER_A).unwrap();
+    let user_b = Address::parse(USER_B).unwrap();
+
+    let steps = [
+        (FriendshipEvent::REQUEST, &user_a, &user_b),
+        (FriendshipEvent::ACCEPT, &user_b, &user_a),
+        (FriendshipEvent::DELETE, &user_a, &user_b),
+    ];
+    for (friendship_event, acting_user, second_user) in steps {
+        handle_friendship_update(
+            "a_token".to_string(),
+            event(friendship_event, second_user.as_str()),
+            context.clone(),
+            acting_user.clone(),
+        )
+        .await
+        .unwrap_or_else(|_| panic!("{friendship_event:?} to be handled"));
+    }
+
+    // The room is cleaned up in the background
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+    let (friendship, _) = context
+        .db
+        .get_repos()
+        .as_ref()
+        .unwrap()
+        .friendships
+        .get_friendship((&user_a, &user_b), None)
+        .await;
+    assert_eq!(friendship.unwrap().unwrap().synapse_room_id, None);
+}
+
+#[actix_web::test]
+async fn should_clean_up_the_room_for_both_users_with_the_admin_token() {
+    let synapse_server = synapse_mock_server().await;
+    let calls = [
+        ("POST", r"^/_matrix/client/r0/rooms/a_room_id/kick$", 0),
+        ("POST", r"^/_matrix/client/r0/rooms/a_room_id/leave$", 2),
+        ("POST", r"^/_matrix/client/r0/rooms/a_room_id/forget$", 2),
+        ("DELETE", r"^/_matrix/client/r0/directory/room/.+", 1),
+    ];
+    for (http_method, path, expected_calls) in calls {
+        Mock::given(method(http_method))
+            .and(path_regex(path))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+            .expect(expected_calls)
+            .mount(&synapse_server)
+            .await;
+    }
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_synapse/admin/v1/users/.+/login$"))
+        .and(header("Authorization", "Bearer admin_token"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "access_token": "b_token" })),
+        )
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/logout$"))
+        .and(header("Authorization", "Bearer b_token"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    // The room is pruned from the direct chats of user B too
+    let first_user_synapse_id = format!("@{USER_A}:decentraland.zone");
+    Mock::given(method("GET"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .and(header("Authorization", "Bearer b_token"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ first_user_synapse_id: [ROOM_ID] })),
+        )
+        .with_priority(1)
+        .mount(&synapse_server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .and(header("Authorization", "Bearer b_token"))
+        .and(body_json(json!({})))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .with_priority(1)
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    let context = in_memory_social_context_with(synapse_server.uri(), |config| {
+        config.synapse_admin_access_token = "admin_token".to_string();
+    })
+    .await;
+    let user_a = Address::parse(USER_A).unwrap();
+    let user_b = Address::parse(USER_B).unwrap();
+
+    let steps = [
+        (FriendshipEvent::REQUEST, &user_a, &user_b),
+        (FriendshipEvent::ACCEPT, &user_b, &user_a),
+        (FriendshipEvent::DELETE, &user_a, &user_b),
+    ];
+    for (friendship_event, acting_user, second_user) in steps {
+        handle_friendship_update(
+            "a_token".to_string(),
+            event(friendship_event, second_user.as_str()),
+            context.clone(),
+            acting_user.clone(),
+        )
+        .await
+        .unwrap_or_else(|_| panic!("{friendship_event:?} to be handled"));
+    }
+
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+    let (friendship, _) = context
+        .db
+        .get_repos()
+        .as_ref()
+        .unwrap()
+        .friendships
+        .get_friendship((&user_a, &user_b), None)
+        .await;
+    assert_eq!(friendship.unwrap().unwrap().synapse_room_id, None);
+}
+
+#[actix_web::test]
+async fn should_create_a_new_room_when_requesting_after_a_clean_up() {
+    let synapse_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/directory/room/.+"))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({ "errcode": "M_NOT_FOUND" })))
+        .mount(&synapse_server)
+        .await;
+    Mock::given(method("POST"))
+        .and(path_regex(r"^/_matrix/client/r0/createRoom$"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "room_id": "a_new_room" })))
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path_regex(r"^/_matrix/client/r0/joined_rooms$"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(json!({ "joined_rooms": ["a_new_room"] })),
+        )
+        .mount(&synapse_server)
+        .await;
+    Mock::given(path_regex(
+        r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+    ))
+    .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+    .mount(&synapse_server)
+    .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(r"^/_matrix/client/r0/rooms/a_new_room/state/.+"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "event_id": "an_event" })))
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    let context = in_memory_social_context(synapse_server.uri()).await;
+    let user_a = Address::parse(USER_A).unwrap();
+    let user_b = Address::parse(USER_B).unwrap();
+
+    // A friendship that was deleted and whose room was cleaned up
+    let repos = context.db.get_repos().as_ref().unwrap();
+    let (friendship_id, _) = repos
+        .friendships
+        .create_new_friendships((&user_a, &user_b), false, ROOM_ID, None)
+        .await;
+    let friendship_id = friendship_id.unwrap();
+    let (result, _) = repos
+        .friendships
+        .update_synapse_room_id(&friendship_id, None, None)
+        .await;
+    result.unwrap();
+    let (result, _) = repos
+        .friendship_history
+        .create(friendship_id, "\"delete\"", &user_b, None, None)
+        .await;
+    result.unwrap();
+
+    handle_friendship_update(
+        "a_token".to_string(),
+        event(FriendshipEvent::REQUEST, USER_B),
+        context.clone(),
+        user_a.clone(),
+    )
+    .await
+    .unwrap_or_else(|_| panic!("the request to be handled"));
+
+    let (friendship, _) = repos
+        .friendships
+        .get_friendship((&user_a, &user_b), None)
+        .await;
+    assert_eq!(
+        friendship.unwrap().unwrap().synapse_room_id.as_deref(),
+        Some("a_new_room")
+    );
+}
+
+#[actix_web::test]
+async fn should_leave_the_room_and_prune_the_direct_chats() {
+    let synapse_server = MockServer::start().await;
+    mount_room_cleanup_mocks(
+        &synapse_server,
+        ResponseTemplate::new(200).set_body_json(json!({})),
+        1,
+    )
+    .await;
+
+    let second_user_synapse_id = format!("@{USER_B}:decentraland.zone");
+    let other_user_synapse_id = format!("@{USER_C}:decentraland.zone");
+    Mock::given(method("GET"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            second_user_synapse_id: [ROOM_ID],
+            other_user_synapse_id.clone(): ["another_room"],
+        })))
+        .mount(&synapse_server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .and(body_json(
+            json!({ other_user_synapse_id: ["another_room"] }),
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(1)
+        .mount(&synapse_server)
+        .await;
+
+    let synapse = SynapseComponent::new(synapse_server.uri());
+    let result = clean_up_synapse_room(ROOM_ID, ROOM_USERS, &synapse).await;
+
+    assert!(result.is_ok());
+}
+
+#[actix_web::test]
+async fn should_clean_up_a_room_that_was_already_cleaned_up() {
+    let synapse_server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .mount(&synapse_server)
+        .await;
+    Mock::given(method("PUT"))
+        .and(path_regex(
+            r"^/_matrix/client/r0/user/.+/account_data/m.direct$",
+        ))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({})))
+        .expect(0)
+        .mount(&synapse_server)
+        .await;
+    mount_room_cleanup_mocks(
+        &synapse_server,
+        ResponseTemplate::new(403).set_body_json(json!({
+            "errcode": "M_FORBIDDEN",
+            "error": "User is not in the room"
+        })),
+        1,
+    )
+    .await;
+
+    let synapse = SynapseComponent::new(synapse_server.uri());
+    let result = clean_up_synapse_room(ROOM_ID, ROOM_USERS, &synapse).await;
+
+    assert!(result.is_ok());
+}
+
+fn assert_limit_reached(result: Result<impl std::fmt::Debug, CommonError>, reason: &str) {
+    match result {
+        Err(CommonError::Forbidden(message)) => assert!(
+            message.starts_with(reason),
+            "{message} to start with {reason}"
+        ),
+        other => panic!("{other:?} to be forbidden by {reason}"),
+    }
+}
+
+#[actix_web::test]
+async fn should_reject_a_request_over_the_outgoing_requests_limit() {
+    let synapse_server = synapse_mock_server().await;
+    let context = in_memory_social_context_with(synapse_server.uri(), |config| {
+        config.friendships_max_outgoing_requests = 1;
+    })
+    .await;
+    let user_a = Address::parse(USER_A).unwrap();
+
+    handle_friendship_update(
+        "a_token".to_string(),
+        event(FriendshipEvent::REQUEST, USER_B),
+        context.clone(),
+        user_a.clone(),
+    )
+    .await
+    .expect("the first request to be handled");
+
+    let result = handle_friendship_update(
+        "a_token".to_string(),
+        event(FriendshipEvent::REQUEST, USER_C),
+        context.clone(),
+        user_a.clone(),
+    )
+    .await
+    .map(|response| response.user_id);
+    assert_limit_reached(result, "OUTGOING_REQUESTS_LIMIT_REACHED");
+
+    let repos = context.db.get_repos().as_ref().unwrap();
+    let user_c = Address::parse(USER_C).unwrap();
+    let (friendship, _) = repos
+        .friendships
+        .get_friendship((&user_a, &user_c), None)
+        .await;
+    assert!(friendship.unwrap().is_none());
+
+    // Partners and creators get a higher limit through their features
+    repos
+        .user_features
+        .set_user_feature(&user_a, MAX_OUTGOING_REQUESTS_FEATURE, "2")
+        .await
+        .unwrap();
+
+    handle_friendship_update(
+        "a_token".to_string(),
+        event(FriendshipEvent::REQUEST, USER_C),
+        context.clone(),
+        user_a,
+    )
+    .await
+    .expect("the request to be handled with the higher limit");
+}
+
+#[actix_web::test]
+async fn should_reject_accepting_a_request_over_the_friends_limit() {
+    let synapse_server = synapse_mock_server().await;
+    let context = in_memory_social_context_with(synapse_server.uri(), |config| {
+        config.friendships_max_friends = 1;
+    })
+    .await;
+    let user_a = Address::parse(USER_A).unwrap();
+    let user_b = Address::parse(USER_B).unwrap();
+    let user_c = Address::parse(USER_C).unwrap();
+
+    let steps = [
+        (FriendshipEvent::REQUEST, &user_c, &user_a),
+        (FriendshipEvent::REQUEST, &user_a, &user_b),
+        (FriendshipEvent::ACCEPT, &user_b, &user_a),
+    ];
+    for (friendship_event, acting_user, second_user) in steps {
+        handle_friendship_update(
+            "a_token".to_string(),
+            event(friendship_event, second_user.as_str()),
+            context.clone(),
+            acting_user.clone(),
+        )
+        .await
+        .unwrap_or_else(|_| panic!("{friendship_event:?} to be handled"));
+    }
+
+    // A already has as many friends as allowed
+    let result = handle_friendship_update(
+        "a_token".to_string(),
+        event(FriendshipEvent::ACCEPT, USER_C),
+        context.clone(),
+        user_a.clone(),
+    )
+    .await
+    .map(|response| response.user_id);
+    assert_limit_reached(result, "FRIENDS_LIMIT_REACHED");
+
+    // The request is still pending, so it can be rejected
+    handle_friendship_update(
+        "a_token".to_string(),
+        event(FriendshipEvent::REJECT, USER_C),
+        context.clone(),
+        user_a,
+    )
+    .await
+    .expect("the request to be rejected");
+}
+
+/// Collects the events published by the given user, the in-process channel is shared by the tests.
+async fn published_events_from(context: &SocialContext, from: &str) -> Arc<Mutex<Vec<Event>>> {
+    let received = Arc::new(Mutex::new(vec![]));
+    let received_clone = received.clone();
+    let from = from.to_string();
+    context
+        .events_subscriber
+        .subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event: Event| {
+            let received = received_clone.clone();
+            let from = from.clone();
+            async move {
+                if event.from == from {
+                    received.lock().unwrap().push(event);
+                }
+            }
+        });
+
+    // Give some time to the subscription to be ready
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+    received
+}
+
+async fn send_update(
+    context: &Arc<SocialContext>,
+    friendship_event: FriendshipEvent,
+    acting_user: &str,
+    second_user: &str,
+) {
+    handle_friendship_update(
+        "a_token".to_string(),
+        event(friendship_event, second_user),
+        context.clone(),
+        Address::parse(acting_user).unwrap(),
+    )
+    .await
+    .unwrap_or_else(|_| panic!("{friendship_event:?} to be handled"));
+}
+
+#[actix_web::test]
+async fn should_accept_all_the_incoming_requests() {
+    let synapse_server = synapse_mock_server().await;
+    let context = in_memory_social_context(synapse_server.uri()).await;
+    let published = published_events_from(&context, USER_E).await;
+
+    send_update(&context, FriendshipEvent::REQUEST, USER_B, USER_E).await;
+    send_update(&context, FriendshipEvent::REQUEST, USER_D, USER_E).await;
+    send_update(&context, FriendshipEvent::REQUEST, USER_E, USER_C).await;
+
+    let results = handle_bulk_friendship_update(
+        "a_token".to_string(),
+        BulkFriendshipAction::AcceptAll,
+        BulkTarget::All,
+        context.clone(),
+        Address::parse(USER_E).unwrap(),
+    )
+    .await
+    .expect("the bulk update to be handled");
+
+    let users: Vec<&str> = results.iter().map(|item| item.user.as_str()).collect();
+    assert_eq!(users, vec![USER_B, USER_D]);
+    assert!(results.iter().all(|item| item.result.is_ok()));
+
+    let repos = context.db.get_repos().as_ref().unwrap();
+    let user_e = Address::parse(USER_E).unwrap();
+    for (user, is_active) in [(USER_B, true), (USER_D, true), (USER_C, false)] {
+        let (friendship, _) = repos
+            .friendships
+            .get_friendship((&user_e, &Address::parse(user).unwrap()), None)
+            .await;
+        assert_eq!(friendship.unwrap().unwrap().is_active, is_active);
+    }
+
+    // The outgoing request isn't answered by accepting all
+    let pending = repos
+        .friendship_history
+        .get_user_pending_request_events(&user_e, None)
+        .await
+        .0
+        .unwrap();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].acting_user, USER_E);
+
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+    let mut recipients: Vec<String> = published
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|event| event.to.clone())
+        .collect();
+    recipients.sort();
+    assert_eq!(recipients, vec![USER_B, USER_D]);
+}
+
+#[actix_web::test]
+async fn should_cancel_each_outgoing_request_independently() {
+    let synapse_server = synapse_mock_server().await;
+    let context = in_memory_social_context(synapse_server.uri()).await;
+    let published = published_events_from(&context, USER_D).await;
+
+    send_update(&context, FriendshipEvent::REQUEST, USER_D, USER_B).await;
+    send_update(&context, FriendshipEvent::REQUEST, USER_D, USER_C).await;
+
+    // There is no request to E, and B is repeated
+    let users = [USER_B, USER_E, USER_B, USER_C]
+        .map(str::to_string)
+        .to_vec();
+    let results = handle_bulk_friendship_update(
+        "a_token".to_string(),
+        BulkFriendshipAction::CancelAllOutgoing,
+        BulkTarget::Users(users),
+        context.clone(),
+        Address::parse(USER_D).unwrap(),
+    )
+    .await
+    .expect("the bulk update to be handled");
+
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].user, USER_B);
+    assert!(results[0].result.is_ok());
+    assert_eq!(results[1].user, USER_E);
+    assert!(results[1].result.is_err());
+    assert_eq!(results[2].user, USER_C);
+    assert!(results[2].result.is_ok());
+
+    let pending = context
+        .db
+        .get_repos()
+        .as_ref()
+        .unwrap()
+        .friendship_history
+        .get_user_pending_request_events(&Address::parse(USER_D).unwrap(), None)
+        .await
+        .0
+        .unwrap();
+    assert!(pending.is_empty());
+
+    actix_rt::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(published.lock().unwrap().len(), 2);
+}
+
+#[actix_web::test]
+async fn should_reject_a_bulk_update_over_the_users_limit() {
+    let synapse_server = synapse_mock_server().await;
+    let context = in_memory_social_context(synapse_server.uri()).await;
+
+    let users = (0..101).map(|i| format!("0x{i:040x}")).collect();
+    let result = handle_bulk_friendship_update(
+        "a_token".to_string(),
+        BulkFriendshipAction::RejectAll,
+        BulkTarget::Users(users),
+        context,
+        Address::parse(USER_A).unwrap(),
+    )
+    .await;
+
+    assert!(matches!(result, Err(CommonError::BadRequest(_))));
+}
@@ -1,10 +1,21 @@
 #[cfg(test)]
 mod tests {
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
     use chrono::NaiveDateTime;
     use social_service::{
+        components::notifications::{
+            ChannelSubscriber, EventsChannelPublisher, InProcessChannel, EVENT_UPDATES_CHANNEL_NAME,
+        },
         domain::{
-            event::EventResponse, friendship_event::FriendshipEvent,
-            friendship_event_validator::validate_new_event, friendship_status::FriendshipStatus,
+            address::Address,
+            event::EventResponse,
+            friendship_event::{FriendshipEvent, ADMIN_ACTING_USER},
+            friendship_event_validator::validate_new_event,
+            friendship_status::FriendshipStatus,
             friendship_status_calculator::get_new_friendship_status,
         },
         entities::friendship_history::{
@@ -14,22 +25,34 @@ mod tests {
             friendship_event_payload::Body, friendship_event_response, CancelPayload,
             FriendshipEventPayload, Payload, RequestPayload, UpdateFriendshipPayload, User,
         },
-        ws::service::mapper::event::{
-            event_response_as_update_response, friendship_requests_as_request_events_response,
-            update_request_as_event_payload,
+        notifications::Event,
+        ws::{
+            metrics::Metrics,
+            service::{
+                friendship_event_updates::publish_friendship_update,
+                mapper::event::{
+                    event_response_as_update_response,
+                    friendship_requests_as_request_events_response,
+                    update_request_as_event_payload,
+                },
+            },
         },
     };
     use uuid::Uuid;
 
+    const PIZARNIK: &str = "0x0000000000000000000000000000000000000001";
+    const MARTHA: &str = "0x0000000000000000000000000000000000000002";
+    const PEDRO: &str = "0x0000000000000000000000000000000000000003";
+
     #[test]
     fn test_friendship_requests_as_request_events() {
         // Database mock response
         let requests: Vec<FriendshipRequestEvent> = generate_request_events();
 
         // Authenticated user
-        let user_id: String = "Pizarnik".to_owned();
+        let address = Address::parse(PIZARNIK).unwrap();
 
-        let result = friendship_requests_as_request_events_response(requests, user_id)
+        let result = friendship_requests_as_request_events_response(requests, &address)
             .response
             .unwrap();
 
@@ -42,7 +65,7 @@ mod tests {
                         let first_request = outgoing.items.get(0);
                         match first_request {
                             Some(req) => {
-                                assert_eq!(req.user.as_ref().unwrap().address, "PedroL");
+                                assert_eq!(req.user.as_ref().unwrap().address, PEDRO);
                                 assert!(req.created_at > 0);
                                 assert!(req.message.is_none());
                             }
@@ -59,7 +82,7 @@ mod tests {
                         let first_request = incoming.items.get(0);
                         match first_request {
                             Some(req) => {
-                                assert_eq!(req.user.as_ref().unwrap().address, "Martha");
+                                assert_eq!(req.user.as_ref().unwrap().address, MARTHA);
                                 assert!(req.created_at > 0);
                                 assert_eq!(req.message.as_ref().unwrap(), "Hey, let's be friends!");
                             }
@@ -84,6 +107,7 @@ mod tests {
                 message: Some("Let's be friends!".to_owned()),
                 user: Some(User {
                     address: "Pizarnik".to_owned(),
+                    ..Default::default()
                 }),
             }),
             "Pizarnik".to_owned(),
@@ -102,6 +126,7 @@ mod tests {
             Body::Cancel(CancelPayload {
                 user: Some(User {
                     address: "Pizarnik".to_owned(),
+                    ..Default::default()
                 }),
             }),
             "Pizarnik".to_owned(),
@@ -129,6 +154,7 @@ mod tests {
                 message: Some("Let's be friends!".to_owned()),
                 user: Some(User {
                     address: "Pizarnik".to_owned(),
+                    ..Default::default()
                 }),
             }),
             "Pizarnik".to_owned(),
@@ -207,6 +233,19 @@ mod tests {
         ));
         let new_event = FriendshipEvent::REQUEST;
         assert!(validate_new_event("Sussana", &last_recorded_history, new_event).is_err());
+
+        // Case 5: Users can't act as the admin
+        assert!(validate_new_event(ADMIN_ACTING_USER, &None, FriendshipEvent::REQUEST).is_err());
+
+        // Case 6: Any user can go on after an event forced by an admin
+        let last_recorded_history = Some(generate_friendship_history(
+            FriendshipEvent::DELETE,
+            ADMIN_ACTING_USER,
+            "2022-04-12 09:30:00",
+        ));
+        assert!(
+            validate_new_event("Sussana", &last_recorded_history, FriendshipEvent::REQUEST).is_ok()
+        );
     }
 
     #[test]
@@ -242,26 +281,87 @@ mod tests {
         assert_eq!(result, FriendshipStatus::NotFriends);
     }
 
+    #[actix_web::test]
+    async fn test_publish_friendship_update_notifies_the_other_user() {
+        let channel = InProcessChannel::new(16);
+        let publisher =
+            EventsChannelPublisher::InProcess(channel.publisher(EVENT_UPDATES_CHANNEL_NAME));
+
+        let published = Arc::new(Mutex::new(vec![]));
+        let published_clone = published.clone();
+        channel
+            .subscriber()
+            .subscribe(EVENT_UPDATES_CHANNEL_NAME, move |event: Event| {
+                let published = published_clone.clone();
+                async move {
+                    published.lock().unwrap().push(event);
+                }
+            });
+
+        let event = FriendshipEventPayload {
+            body: Some(Body::Request(RequestPayload {
+                message: Some("Let's be friends!".to_owned()),
+                user: Some(User {
+                    address: MARTHA.to_owned(),
+                    ..Default::default()
+                }),
+            })),
+        };
+        publish_friendship_update(
+            &publisher,
+            None,
+            event,
+            &Address::parse(PIZARNIK).unwrap(),
+            1681291800,
+            &Metrics::new(),
+        )
+        .await;
+
+        actix_rt::time::sleep(Duration::from_millis(100)).await;
+
+        let published = published.lock().unwrap();
+        assert_eq!(published.len(), 1);
+        assert_eq!(published[0].from, PIZARNIK);
+        assert_eq!(published[0].to, MARTHA);
+
+        let body = published[0]
+            .friendship_event
+            .as_ref()
+            .unwrap()
+            .body
+            .as_ref()
+            .unwrap();
+        match body {
+            friendship_event_response::Body::Request(request) => {
+                assert_eq!(request.user.as_ref().unwrap().address, PIZARNIK);
+                assert_eq!(request.message.as_deref(), Some("Let's be friends!"));
+                assert_eq!(request.created_at, 1681291800);
+            }
+            _ => unreachable!("Expected a request event"),
+        }
+    }
+
     fn generate_request_events() -> Vec<FriendshipRequestEvent> {
         let timestamp_str = "2022-04-12 09:30:00";
         let timestamp = NaiveDateTime::parse_from_str(timestamp_str, "%Y-%m-%d %H:%M:%S").unwrap();
 
         vec![
             FriendshipRequestEvent {
-                acting_user: "Martha".to_owned(),
-                address_1: "Martha".to_owned(),
-                address_2: "Pizarnik".to_owned(),
+                acting_user: MARTHA.to_owned(),
+                address_1: PIZARNIK.to_owned(),
+                address_2: MARTHA.to_owned(),
                 timestamp,
                 metadata: Some(sqlx::types::Json(FriendshipMetadata {
                     message: Some("Hey, let's be friends!".to_owned()),
                     synapse_room_id: None,
                     migrated_from_synapse: None,
+                    forced_by_admin: None,
                 })),
             },
             FriendshipRequestEvent {
-                acting_user: "Pizarnik".to_owned(),
-                address_1: "PedroL".to_owned(),
-                address_2: "Pizarnik".to_owned(),
+                acting_user: PIZARNIK.to_owned(),
+                address_1: PIZARNIK.to_owned(),
+                address_2: PEDRO.to_owned(),
                 timestamp,
                 metadata: None,
             },